@@ -0,0 +1,93 @@
+use std::{
+    env,
+    io::{Cursor, Read},
+    sync::Arc,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use file_downloader::{DownloaderBuilder, FetchError, FileDownloader, Response};
+
+// Mirrors how a network stream is consumed: a plain `Read` without buffering.
+struct Unbuffered(Cursor<Arc<[u8]>>);
+
+impl Read for Unbuffered {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+// Serves the same body on every fetch, announcing its length or not.
+struct BodyFetcher {
+    body: Arc<[u8]>,
+    sized: bool,
+}
+
+impl FileDownloader for BodyFetcher {
+    fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+        let response = Response::new(200)
+            .with_header("Content-Type", "application/octet-stream")
+            .with_reader(Unbuffered(Cursor::new(Arc::clone(&self.body))));
+
+        Ok(match self.sized {
+            true => response.with_header("Content-Length", &self.body.len().to_string()),
+            false => response,
+        })
+    }
+}
+
+fn byte_by_byte(data: &Arc<[u8]>) -> Vec<u8> {
+    #[allow(clippy::unbuffered_bytes)]
+    Unbuffered(Cursor::new(Arc::clone(data)))
+        .bytes()
+        .collect::<Result<Vec<u8>, _>>()
+        .unwrap()
+}
+
+fn body_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("body_read");
+
+    for size in [1024 * 1024, 10 * 1024 * 1024] {
+        let data: Arc<[u8]> = vec![0xAB; size].into();
+
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("bytes_collect", size), &data, |b, data| {
+            b.iter(|| byte_by_byte(data))
+        });
+
+        for sized in [false, true] {
+            let name = match sized {
+                true => "download_sized",
+                false => "download_unsized",
+            };
+
+            let dir = env::temp_dir().join(format!("file-downloader-bench-{name}"));
+
+            let fetcher = BodyFetcher {
+                body: Arc::clone(&data),
+                sized,
+            };
+
+            let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher).build();
+
+            group.bench_function(BenchmarkId::new(name, size), |b| {
+                b.iter(|| {
+                    let download = downloader.download("https://example.com/blob.bin").unwrap();
+
+                    assert_eq!(download.metadata.size, Some(size as u64));
+                })
+            });
+
+            downloader.clear_cache();
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = body_read
+}
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use file_downloader::{CachePolicy, DownloaderBuilder, FetchError, FileDownloader, Response};
+
+// Serves the same small icon for every URL; after the first request each is a
+// cache hit, so what is measured is reading the hit back.
+struct IconFetcher;
+
+impl FileDownloader for IconFetcher {
+    fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+        Ok(Response::ok(
+            vec![0x42; 32 * 1024],
+            Some("image/png".to_string()),
+        ))
+    }
+}
+
+fn memory_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memory_cache");
+
+    let urls: Vec<String> = (0..100)
+        .map(|index| format!("https://example.com/icons/{index}.png"))
+        .collect();
+
+    for (name, memory) in [("disk", None), ("memory", Some(16 * 1024 * 1024))] {
+        let builder = DownloaderBuilder::with_fetcher(
+            std::env::temp_dir().join(format!("file-downloader-bench-memory-cache-{name}")),
+            IconFetcher,
+        )
+        .cache_policy(CachePolicy::CacheFirst);
+
+        let downloader = match memory {
+            Some(max_bytes) => builder.with_memory_cache(max_bytes),
+            None => builder,
+        }
+        .build();
+
+        for url in &urls {
+            downloader.download(url).unwrap();
+        }
+
+        group.bench_with_input(BenchmarkId::new("hit_bytes", name), &downloader, |b, d| {
+            b.iter(|| {
+                for url in &urls {
+                    d.download(url).unwrap().bytes().unwrap();
+                }
+            })
+        });
+
+        downloader.clear_cache();
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = memory_cache
+}
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use file_downloader::{DownloaderBuilder, FetchError, FileDownloader, Response};
+
+// Counts allocations so the benchmark can show what building a request
+// costs besides time.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// Answers every HEAD at once, so the request-building path is all there is.
+struct HeadFetcher;
+
+impl FileDownloader for HeadFetcher {
+    fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+        Ok(Response::new(200))
+    }
+
+    fn head(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+        Ok(Response::new(200).with_header("Content-Length", "1"))
+    }
+}
+
+fn request_headers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("request_headers");
+
+    let urls = ["https://example.com/a.png"];
+
+    for count in [0, 10] {
+        let downloader = (0..count)
+            .fold(
+                DownloaderBuilder::with_fetcher(
+                    std::env::temp_dir().join("file-downloader-bench-request-headers"),
+                    HeadFetcher,
+                ),
+                |builder, index| builder.header(&format!("X-Default-{index}"), "value"),
+            )
+            .build();
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+
+        downloader.validate_urls(&urls);
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        // Default headers are shared, not copied into every request.
+        println!("{count} default headers: {allocations} allocations per request");
+
+        group.bench_with_input(BenchmarkId::new("probe", count), &downloader, |b, d| {
+            b.iter(|| d.validate_urls(&urls))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = request_headers
+}
+criterion_main!(benches);
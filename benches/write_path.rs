@@ -0,0 +1,85 @@
+use std::{
+    env,
+    io::{Cursor, Read},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use file_downloader::{DownloaderBuilder, FetchError, FileDownloader, Response};
+
+const BODY_SIZE: usize = 50 * 1024 * 1024;
+
+// Counts every byte pulled from the body so the benchmark can assert the
+// write path consumes it exactly once.
+struct CountingReader {
+    inner: Cursor<Arc<[u8]>>,
+    read: Arc<AtomicU64>,
+}
+
+impl Read for CountingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+struct StreamingFetcher {
+    body: Arc<[u8]>,
+    read: Arc<AtomicU64>,
+}
+
+impl FileDownloader for StreamingFetcher {
+    fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+        let reader = CountingReader {
+            inner: Cursor::new(Arc::clone(&self.body)),
+            read: Arc::clone(&self.read),
+        };
+
+        Ok(Response::new(200)
+            .with_header("Content-Type", "application/octet-stream")
+            .with_reader(reader))
+    }
+}
+
+fn write_path(c: &mut Criterion) {
+    let read = Arc::new(AtomicU64::new(0));
+
+    let fetcher = StreamingFetcher {
+        body: vec![0x5A; BODY_SIZE].into(),
+        read: Arc::clone(&read),
+    };
+
+    let dir = env::temp_dir().join("file-downloader-bench-write-path");
+
+    let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher).build();
+
+    let mut group = c.benchmark_group("write_path");
+
+    group.throughput(Throughput::Bytes(BODY_SIZE as u64));
+
+    group.bench_function("download_50mb", |b| {
+        b.iter(|| {
+            read.store(0, Ordering::Relaxed);
+
+            let download = downloader.download("https://example.com/blob.bin").unwrap();
+
+            assert_eq!(read.load(Ordering::Relaxed), BODY_SIZE as u64);
+            assert_eq!(download.metadata.size, Some(BODY_SIZE as u64));
+        })
+    });
+
+    group.finish();
+
+    downloader.clear_cache();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = write_path
+}
+criterion_main!(benches);
@@ -0,0 +1,362 @@
+use std::{
+    io::{self, IsTerminal},
+    time::Duration,
+};
+
+use crate::{BatchOptions, CancellationToken, DownloadError, Downloader, Outcome};
+
+pub const USAGE: &str = "\
+usage: file-downloader [--failures-out <path>] [--retry-from <path>] <url>...
+       file-downloader --stdout [--force] <url>
+       file-downloader --watch <seconds> <url>";
+
+// Where downloads are cached, relative to the working directory.
+const CACHE_DIR: &str = "images";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    // Downloads the URLs, or those a failures report lists, into the cache.
+    Batch {
+        urls: Vec<String>,
+        failures_out: Option<String>,
+        retry_from: Option<String>,
+    },
+    // Streams one body to stdout, so the output can be piped into other
+    // tools.
+    Stdout {
+        url: String,
+        force: bool,
+    },
+    // Downloads one URL again every `interval`, a line per cycle, until
+    // Ctrl-C.
+    Watch {
+        url: String,
+        interval: Duration,
+    },
+}
+
+// The arguments after the program name. Errors are messages for stderr, the
+// usage being wrong.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut failures_out = None;
+
+    let mut retry_from = None;
+
+    let mut stdout = false;
+
+    let mut force = false;
+
+    let mut watch = None;
+
+    let mut urls = Vec::new();
+
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--failures-out" => failures_out = Some(expect_value(&arg, args.next())?),
+            "--retry-from" => retry_from = Some(expect_value(&arg, args.next())?),
+            "--stdout" => stdout = true,
+            "--force" => force = true,
+            "--watch" => watch = Some(expect_seconds(&arg, args.next())?),
+            _ => urls.push(arg),
+        }
+    }
+
+    if stdout {
+        return Ok(Command::Stdout {
+            url: single_url("--stdout", urls)?,
+            force,
+        });
+    }
+
+    if let Some(interval) = watch {
+        return Ok(Command::Watch {
+            url: single_url("--watch", urls)?,
+            interval,
+        });
+    }
+
+    if urls.is_empty() && retry_from.is_none() {
+        return Err(USAGE.to_string());
+    }
+
+    Ok(Command::Batch {
+        urls,
+        failures_out,
+        retry_from,
+    })
+}
+
+// Runs the command line and returns the process's exit code.
+pub fn run(args: impl IntoIterator<Item = String>) -> i32 {
+    match parse_args(args) {
+        Ok(Command::Help) => {
+            println!("{USAGE}");
+            0
+        }
+        Ok(Command::Batch {
+            urls,
+            failures_out,
+            retry_from,
+        }) => download_batch(&urls, failures_out.as_deref(), retry_from.as_deref()),
+        Ok(Command::Stdout { url, force }) => download_to_stdout(&url, force),
+        Ok(Command::Watch { url, interval }) => watch_url(&url, interval),
+        Err(message) => {
+            eprintln!("{message}");
+            2
+        }
+    }
+}
+
+// 2 for a URL that cannot be fetched, 3 when it is missing, 4 for transport
+// failures, 5 for local I/O and 1 for anything else. Exhausted retries exit
+// as their last attempt.
+pub fn exit_code(error: &DownloadError) -> i32 {
+    match error {
+        DownloadError::InvalidUrl(_) => 2,
+        DownloadError::NotFound => 3,
+        DownloadError::NetworkError { .. }
+        | DownloadError::InvalidBody
+        | DownloadError::TruncatedBody { .. }
+        | DownloadError::HttpStatus(_)
+        | DownloadError::Dns(_)
+        | DownloadError::Connect(_)
+        | DownloadError::Tls(_)
+        | DownloadError::Timeout
+        | DownloadError::CircuitOpen { .. }
+        | DownloadError::RateLimited { .. }
+        | DownloadError::DeadlineExceeded { .. } => 4,
+        DownloadError::Io(_) | DownloadError::Writer(_) => 5,
+        DownloadError::RetriesExhausted { .. } => exit_code(error.last_error()),
+        _ => 1,
+    }
+}
+
+fn download_batch(urls: &[String], failures_out: Option<&str>, retry_from: Option<&str>) -> i32 {
+    let downloader = Downloader::new(CACHE_DIR);
+
+    let batch = match retry_from {
+        Some(report) => match downloader.retry_from_report(report) {
+            Ok(batch) => batch,
+            Err(error) => {
+                eprintln!("Error reading {report}: {error}");
+                return 1;
+            }
+        },
+        None => downloader.download_all(urls, BatchOptions::default()),
+    };
+
+    downloader.flush_maintenance();
+
+    for (_, download) in &batch.results {
+        println!("Downloaded file: {:?}", download);
+    }
+
+    if let Some(path) = failures_out {
+        if let Err(error) = batch.write_failures(path) {
+            eprintln!("Error writing {path}: {error}");
+            return 1;
+        }
+    }
+
+    0
+}
+
+fn download_to_stdout(url: &str, force: bool) -> i32 {
+    if io::stdout().is_terminal() && !force {
+        eprintln!("Refusing to write to a terminal, pass --force to do it anyway");
+        return 2;
+    }
+
+    // A read-only cache is never created, so nothing is written to disk.
+    let downloader = Downloader::builder(CACHE_DIR).read_only(true).build();
+
+    match downloader.download_into(url, &mut io::stdout().lock()) {
+        Ok(_) => 0,
+        Err(error) => {
+            eprintln!("Error downloading {url}: {error}");
+            exit_code(&error)
+        }
+    }
+}
+
+fn watch_url(url: &str, interval: Duration) -> i32 {
+    let token = CancellationToken::new();
+
+    let handler = token.clone();
+
+    if let Err(error) = ctrlc::set_handler(move || handler.cancel()) {
+        eprintln!("Error installing the Ctrl-C handler: {error}");
+        return 1;
+    }
+
+    let downloader = Downloader::builder(CACHE_DIR)
+        .cancellation_token(token)
+        .build();
+
+    downloader.watch(url, interval, |result| match result {
+        Ok(Outcome::NotModified(download)) => println!("Unchanged: {:?}", download.file),
+        Ok(Outcome::Stale(download)) => println!("Stale copy: {:?}", download.file),
+        Ok(outcome) => println!("Downloaded file: {:?}", outcome.into_download().file),
+        Err(error) => eprintln!("Error downloading {url}: {error}"),
+    });
+
+    downloader.flush_maintenance();
+
+    0
+}
+
+fn single_url(flag: &str, urls: Vec<String>) -> Result<String, String> {
+    match <[String; 1]>::try_from(urls) {
+        Ok([url]) => Ok(url),
+        Err(_) => Err(format!("{flag} takes exactly one url")),
+    }
+}
+
+fn expect_seconds(flag: &str, value: Option<String>) -> Result<Duration, String> {
+    match value.as_deref().map(str::parse) {
+        Some(Ok(seconds)) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+        _ => Err(format!("{flag} needs a number of seconds")),
+    }
+}
+
+fn expect_value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{flag} needs a path"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use super::{exit_code, parse_args, run, Command, USAGE};
+    use crate::{AttemptRecord, DownloadError, UrlProblem};
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_arguments_are_parsed_into_commands() {
+        let url = "https://example.com/logo.png";
+
+        let cases = [
+            (vec!["--help"], Ok(Command::Help)),
+            (vec!["--stdout", url, "-h"], Ok(Command::Help)),
+            (
+                vec![url, "--failures-out", "failed.json"],
+                Ok(Command::Batch {
+                    urls: vec![url.to_string()],
+                    failures_out: Some("failed.json".to_string()),
+                    retry_from: None,
+                }),
+            ),
+            (
+                vec!["--retry-from", "failed.json"],
+                Ok(Command::Batch {
+                    urls: Vec::new(),
+                    failures_out: None,
+                    retry_from: Some("failed.json".to_string()),
+                }),
+            ),
+            (
+                vec!["--force", "--stdout", url],
+                Ok(Command::Stdout {
+                    url: url.to_string(),
+                    force: true,
+                }),
+            ),
+            (
+                vec!["--watch", "30", url],
+                Ok(Command::Watch {
+                    url: url.to_string(),
+                    interval: Duration::from_secs(30),
+                }),
+            ),
+            (vec![], Err(USAGE.to_string())),
+            (
+                vec!["--stdout", url, url],
+                Err("--stdout takes exactly one url".to_string()),
+            ),
+            (
+                vec!["--watch", "0", url],
+                Err("--watch needs a number of seconds".to_string()),
+            ),
+            (
+                vec!["--watch", "30"],
+                Err("--watch takes exactly one url".to_string()),
+            ),
+            (
+                vec![url, "--failures-out"],
+                Err("--failures-out needs a path".to_string()),
+            ),
+        ];
+
+        for (args, expected) in cases {
+            // Act
+
+            let parsed = parse(&args);
+
+            // Assert
+
+            assert_eq!(parsed, expected, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn test_errors_map_to_exit_codes() {
+        let attempt = |error| AttemptRecord {
+            url: "https://example.com/logo.png".to_string(),
+            attempt: 1,
+            error,
+            at: std::time::SystemTime::UNIX_EPOCH,
+            duration: Duration::ZERO,
+        };
+
+        let cases = [
+            (DownloadError::InvalidUrl(UrlProblem::RelativeUrl), 2),
+            (DownloadError::NotFound, 3),
+            (
+                DownloadError::TruncatedBody {
+                    received: 1,
+                    kind: io::ErrorKind::UnexpectedEof,
+                },
+                4,
+            ),
+            (DownloadError::Io("disk".to_string()), 5),
+            (DownloadError::CorruptImage, 1),
+            (
+                DownloadError::RetriesExhausted {
+                    attempts: vec![
+                        attempt(DownloadError::InvalidBody),
+                        attempt(DownloadError::NotFound),
+                    ],
+                },
+                3,
+            ),
+        ];
+
+        for (error, expected) in cases {
+            // Act
+
+            let code = exit_code(&error);
+
+            // Assert
+
+            assert_eq!(code, expected, "{error:?}");
+        }
+    }
+
+    #[test]
+    fn test_usage_errors_exit_with_2() {
+        // Act
+
+        let code = run(["--watch".to_string()]);
+
+        // Assert
+
+        assert_eq!(code, 2);
+    }
+}
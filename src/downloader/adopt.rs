@@ -0,0 +1,293 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    cache_key::{CacheKey, KeyEncoding},
+    manifest::{self, ManifestEntry},
+    sidecar::{self, SIDECAR_SUFFIX},
+    sniff,
+    tee::BodySummary,
+    Downloader, FileDownloader, Storage, UReqFetcher,
+};
+
+// What `adopt` found in a directory. Paths are sorted.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AdoptReport {
+    // Files named like cache entries, now in the manifest.
+    pub recognized: Vec<PathBuf>,
+    // Files the cache does not name, left alone.
+    pub unrecognized: Vec<PathBuf>,
+    // Entries whose sidecar could not be read or disagrees with the file,
+    // left out of the manifest.
+    pub corrupt: Vec<PathBuf>,
+}
+
+impl Downloader<UReqFetcher> {
+    // Takes an existing directory, such as assets shipped with an app, as a
+    // cache. Unlike `new`, a missing directory is an error.
+    pub fn adopt(path: impl AsRef<Path>) -> io::Result<(Self, AdoptReport)> {
+        let path = path.as_ref();
+
+        if !fs::metadata(path)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{} is not a directory", path.display()),
+            ));
+        }
+
+        let downloader = Self::builder(path).build();
+
+        let report = downloader.adopt_files()?;
+
+        Ok((downloader, report))
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Rebuilds the manifest entries of the files already in the cache. Files
+    // named `<key>.<extension>` are entries, of the URL their sidecar names
+    // or of an unknown one; entries the manifest knows are kept as they are.
+    pub fn adopt_files(&self) -> io::Result<AdoptReport> {
+        if self.config.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "a read-only cache cannot adopt files",
+            ));
+        }
+
+        let mut names = self.storage.list()?;
+
+        names.sort();
+
+        let mut report = AdoptReport::default();
+
+        for name in &names {
+            let file = self.locate(name);
+
+            // Sidecars are adopted with their data file.
+            if let Some(data) = name.strip_suffix(SIDECAR_SUFFIX) {
+                if !names.iter().any(|name| name == data) {
+                    report.unrecognized.push(file);
+                }
+
+                continue;
+            }
+
+            let key = name.rsplit('/').next().unwrap_or(name);
+
+            let key = match key.split_once('.') {
+                // Thumbnails are named after their entry.
+                Some((key, _)) if !key.contains("_thumb") => key,
+                _ => {
+                    report.unrecognized.push(file);
+
+                    continue;
+                }
+            };
+
+            // Keyers may use any of the characters names can hold.
+            let encoding = match self.config.keyer {
+                Some(_) => KeyEncoding::Base64Url,
+                None => self.config.key_encoding,
+            };
+
+            if !CacheKey::is_well_formed(key, encoding) {
+                report.unrecognized.push(file);
+
+                continue;
+            }
+
+            if self
+                .manifest
+                .get(key)
+                .is_some_and(|meta| meta.file == *name)
+            {
+                report.recognized.push(file);
+
+                continue;
+            }
+
+            match self.adopted_entry(name, &file) {
+                Some(entry) => {
+                    self.manifest.insert(key, entry);
+
+                    report.recognized.push(file);
+                }
+                None => report.corrupt.push(file),
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn adopted_entry(&self, name: &str, file: &Path) -> Option<ManifestEntry> {
+        // Streamed, as shipped assets can be larger than is worth holding.
+        let summary = BodySummary::of_reader(&mut fs::File::open(file).ok()?).ok()?;
+
+        let sidecar = match sidecar::read(file) {
+            Ok(sidecar) => Some(sidecar),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(_) => return None,
+        };
+
+        if let Some(sha256) = sidecar.as_ref().and_then(|sidecar| sidecar.sha256.as_ref()) {
+            if *sha256 != summary.sha256 {
+                return None;
+            }
+        }
+
+        let fetched_at = match &sidecar {
+            Some(sidecar) => sidecar.fetched_at,
+            None => manifest::unix_secs(fs::metadata(file).ok()?.modified().ok()?),
+        };
+
+        let mime = sidecar
+            .as_ref()
+            .and_then(|sidecar| sidecar.mime.clone())
+            .or_else(|| sniff::mime_from_magic(&summary.head).map(str::to_string));
+
+        Some(ManifestEntry {
+            url: sidecar.map(|sidecar| sidecar.source).unwrap_or_default(),
+            file: name.to_string(),
+            fetched_at,
+            size: Some(summary.size),
+            sha256: Some(summary.sha256),
+            mime,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::ErrorKind};
+
+    use crate::downloader::{
+        cache_key::CacheKey,
+        fetcher::MockFetcher,
+        fixtures,
+        sidecar::{self, Sidecar},
+        tee, testing, Downloader, DownloaderBuilder,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn sidecar(sha256: &str) -> Sidecar {
+        Sidecar {
+            source: URL.to_string(),
+            fetched_at: 1_700_000_000,
+            status: 200,
+            headers: Vec::new(),
+            sha256: Some(sha256.to_string()),
+            mime: Some("image/png".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_populated_directories_are_adopted() {
+        let dir = testing::cache_dir("adopt_populated");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let sha256 = tee::BodySummary::of(fixtures::PNG).sha256;
+
+        let logo = dir.join(format!("{}.png", CacheKey::from_url(URL).as_str()));
+
+        let orphan = dir.join(format!(
+            "{}.dat",
+            CacheKey::from_url("https://example.com/orphan").as_str()
+        ));
+
+        let tampered = dir.join(format!(
+            "{}.png",
+            CacheKey::from_url("https://example.com/tampered.png").as_str()
+        ));
+
+        fs::write(&logo, fixtures::PNG).unwrap();
+        sidecar::write(&logo, &sidecar(&sha256)).unwrap();
+
+        fs::write(&orphan, b"seeded").unwrap();
+
+        fs::write(&tampered, b"not the same bytes").unwrap();
+        sidecar::write(&tampered, &sidecar(&sha256)).unwrap();
+
+        for junk in ["notes.txt", "IMG_0001.jpg", "README"] {
+            fs::write(dir.join(junk), b"junk").unwrap();
+        }
+
+        // Act
+
+        let (downloader, report) = Downloader::adopt(&dir).unwrap();
+
+        downloader.flush_maintenance();
+
+        // Assert
+
+        let mut recognized = vec![logo.clone(), orphan];
+
+        recognized.sort();
+
+        assert_eq!(report.recognized, recognized);
+        assert_eq!(report.corrupt, [tampered]);
+        assert_eq!(
+            report.unrecognized,
+            ["IMG_0001.jpg", "README", "notes.txt"].map(|name| dir.join(name))
+        );
+
+        let cached = downloader.cached(URL).unwrap();
+
+        assert_eq!(cached.file, logo);
+        assert_eq!(cached.metadata.sha256, Some(sha256));
+        assert_eq!(cached.metadata.mime.as_deref(), Some("image/png"));
+        assert!(dir.join("notes.txt").exists());
+        assert!(fs::read_to_string(dir.join("manifest.json"))
+            .unwrap()
+            .contains(URL));
+    }
+
+    #[test]
+    fn test_missing_directories_are_not_adopted() {
+        let dir = testing::cache_dir("adopt_missing");
+
+        // Act
+
+        let error = Downloader::adopt(&dir).err().unwrap();
+
+        // Assert
+
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_read_only_caches_do_not_adopt_files() {
+        let dir = testing::cache_dir("adopt_read_only");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(
+            dir.join(format!("{}.png", CacheKey::from_url(URL).as_str())),
+            fixtures::PNG,
+        )
+        .unwrap();
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![]))
+            .read_only(true)
+            .build();
+
+        // Act
+
+        let error = downloader.adopt_files().unwrap_err();
+
+        // Assert
+
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert!(!dir.join("manifest.json").exists());
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use flate2::read::GzDecoder;
+use tar::EntryType;
+use zip::ZipArchive;
+
+use super::{
+    partial::{self, PartialFile},
+    Download, DownloadError, Downloader, FileDownloader, Storage,
+};
+
+const ZIP_MAGIC: &[u8] = b"PK";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    // Counted from the bytes actually inflated, not the sizes the archive
+    // announces.
+    pub max_total_size: u64,
+    pub max_entries: usize,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: 1 << 30,
+            max_entries: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Extraction {
+    pub download: Download,
+    pub files: Vec<PathBuf>,
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Supports zip and tar.gz. Entries escaping `dest_dir` fail the whole
+    // extraction, links inside tarballs are skipped.
+    pub fn download_and_extract(
+        &self,
+        url: &str,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<Extraction, DownloadError> {
+        let download = self.download(url)?;
+
+        let files = extract(
+            &download.file,
+            dest_dir.as_ref(),
+            self.config.archive_limits,
+            self.config.clock.now(),
+        )?;
+
+        Ok(Extraction { download, files })
+    }
+}
+
+fn extract(
+    archive: &Path,
+    dest: &Path,
+    limits: ArchiveLimits,
+    modified: SystemTime,
+) -> Result<Vec<PathBuf>, DownloadError> {
+    let mut file = File::open(archive).map_err(io_error)?;
+
+    let mut magic = [0; 2];
+    file.read_exact(&mut magic)
+        .map_err(|_| DownloadError::InvalidArchive)?;
+
+    file.rewind().map_err(io_error)?;
+
+    let file = BufReader::new(file);
+
+    fs::create_dir_all(dest).map_err(io_error)?;
+
+    let mut extractor = Extractor {
+        dest,
+        limits,
+        modified,
+        entries: 0,
+        written: 0,
+        files: Vec::new(),
+    };
+
+    let result = match &magic[..] {
+        ZIP_MAGIC => extractor.zip(file),
+        GZIP_MAGIC => extractor.tar(GzDecoder::new(file)),
+        _ => Err(DownloadError::InvalidArchive),
+    };
+
+    // A rejected archive leaves nothing behind.
+    if result.is_err() {
+        for file in &extractor.files {
+            let _ = fs::remove_file(file);
+        }
+    }
+
+    result.map(|()| extractor.files)
+}
+
+struct Extractor<'a> {
+    dest: &'a Path,
+    limits: ArchiveLimits,
+    modified: SystemTime,
+    entries: usize,
+    written: u64,
+    files: Vec<PathBuf>,
+}
+
+impl Extractor<'_> {
+    fn zip(&mut self, reader: impl Read + Seek) -> Result<(), DownloadError> {
+        let mut archive = ZipArchive::new(reader).map_err(|_| DownloadError::InvalidArchive)?;
+
+        if archive.len() > self.limits.max_entries {
+            return Err(DownloadError::ArchiveTooLarge);
+        }
+
+        for index in 0..archive.len() {
+            let mut entry = archive
+                .by_index(index)
+                .map_err(|_| DownloadError::InvalidArchive)?;
+
+            let name = entry
+                .name()
+                .map_err(|_| DownloadError::InvalidArchive)?
+                .into_owned();
+
+            let target = self.target(Path::new(&name))?;
+
+            if entry.is_dir() {
+                fs::create_dir_all(target).map_err(io_error)?;
+            } else if !entry.is_symlink() {
+                self.write(target, &mut entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tar(&mut self, reader: impl Read) -> Result<(), DownloadError> {
+        let mut archive = tar::Archive::new(reader);
+
+        let entries = archive
+            .entries()
+            .map_err(|_| DownloadError::InvalidArchive)?;
+
+        for entry in entries {
+            let mut entry = entry.map_err(|_| DownloadError::InvalidArchive)?;
+
+            let name = entry
+                .path()
+                .map_err(|_| DownloadError::InvalidArchive)?
+                .into_owned();
+
+            let target = self.target(&name)?;
+
+            match entry.header().entry_type() {
+                EntryType::Directory => fs::create_dir_all(target).map_err(io_error)?,
+                EntryType::Regular | EntryType::Continuous => self.write(target, &mut entry)?,
+                // Links, devices and fifos could point outside the
+                // destination or block the reader.
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn target(&mut self, name: &Path) -> Result<PathBuf, DownloadError> {
+        self.entries += 1;
+
+        if self.entries > self.limits.max_entries {
+            return Err(DownloadError::ArchiveTooLarge);
+        }
+
+        enclosed(name)
+            .map(|path| self.dest.join(path))
+            .ok_or_else(|| DownloadError::UnsafeArchiveEntry(name.display().to_string()))
+    }
+
+    fn write(&mut self, target: PathBuf, entry: &mut impl Read) -> Result<(), DownloadError> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+
+        let mut partial = PartialFile::create(partial::staging_path(&target)).map_err(io_error)?;
+
+        let mut buffer = [0; 8192];
+
+        loop {
+            let read = entry
+                .read(&mut buffer)
+                .map_err(|_| DownloadError::InvalidArchive)?;
+
+            if read == 0 {
+                break;
+            }
+
+            self.written += read as u64;
+
+            if self.written > self.limits.max_total_size {
+                return Err(DownloadError::ArchiveTooLarge);
+            }
+
+            partial.write_all(&buffer[..read]).map_err(io_error)?;
+        }
+
+        partial.commit(&target, self.modified).map_err(io_error)?;
+
+        self.files.push(target);
+
+        Ok(())
+    }
+}
+
+// The entry path relative to the destination, or None when it is absolute or
+// climbs above it.
+fn enclosed(name: &Path) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !path.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(path)
+}
+
+fn io_error(error: io::Error) -> DownloadError {
+    DownloadError::Io(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Cursor, Write},
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+    use tar::{EntryType, Header};
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::ArchiveLimits;
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, Downloader, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/bundle";
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for (name, content) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    // Headers are filled by hand because the tar builder refuses the unsafe
+    // names these fixtures need.
+    fn tar_gz(entries: &[(&str, EntryType, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+
+        for (name, entry_type, content) in entries {
+            let mut header = Header::new_gnu();
+
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            header.set_size(content.len() as u64);
+
+            if entry_type.is_symlink() {
+                header.set_link_name("/etc/passwd").unwrap();
+            }
+
+            header.set_cksum();
+
+            builder.append(&header, *content).unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn downloader(name: &str, body: Vec<u8>) -> Downloader<MockFetcher> {
+        DownloaderBuilder::with_fetcher(
+            testing::cache_dir(name),
+            MockFetcher::new(vec![Response::ok(body, None)]),
+        )
+        .build()
+    }
+
+    #[test]
+    fn test_zip_extracted_into_dest() {
+        let body = zip(&[("readme.txt", b"hello"), ("data/rows.csv", b"a,b\n1,2\n")]);
+
+        let downloader = downloader("archive_zip", body);
+
+        let dest = testing::cache_dir("archive_zip_dest");
+
+        // Act
+
+        let extraction = downloader.download_and_extract(URL, &dest).unwrap();
+
+        // Assert
+
+        assert_eq!(
+            extraction.files,
+            vec![dest.join("readme.txt"), dest.join("data/rows.csv")]
+        );
+        assert_eq!(fs::read(dest.join("data/rows.csv")).unwrap(), b"a,b\n1,2\n");
+        assert!(extraction.download.file.exists());
+    }
+
+    #[test]
+    fn test_tar_gz_skips_links() {
+        let body = tar_gz(&[
+            ("./", EntryType::Directory, b""),
+            ("docs/", EntryType::Directory, b""),
+            ("docs/guide.md", EntryType::Regular, b"# Guide"),
+            ("docs/passwd", EntryType::Symlink, b""),
+        ]);
+
+        let downloader = downloader("archive_tar", body);
+
+        let dest = testing::cache_dir("archive_tar_dest");
+
+        // Act
+
+        let extraction = downloader.download_and_extract(URL, &dest).unwrap();
+
+        // Assert
+
+        assert_eq!(extraction.files, vec![dest.join("docs/guide.md")]);
+        assert_eq!(fs::read(dest.join("docs/guide.md")).unwrap(), b"# Guide");
+        assert!(fs::symlink_metadata(dest.join("docs/passwd")).is_err());
+    }
+
+    #[test]
+    fn test_entries_escaping_dest_are_rejected() {
+        let cases = [
+            zip(&[("ok.txt", b"fine"), ("../evil.txt", b"pwned")]),
+            tar_gz(&[
+                ("ok.txt", EntryType::Regular, b"fine"),
+                ("nested/../../evil.txt", EntryType::Regular, b"pwned"),
+            ]),
+            zip(&[("/tmp/evil.txt", b"pwned")]),
+        ];
+
+        for (index, body) in cases.into_iter().enumerate() {
+            let downloader = downloader("archive_slip", body);
+
+            let root = testing::cache_dir("archive_slip_dest");
+
+            let dest = root.join("dest");
+
+            // Act
+
+            let error = downloader.download_and_extract(URL, &dest).unwrap_err();
+
+            // Assert
+
+            assert!(
+                matches!(error, DownloadError::UnsafeArchiveEntry(_)),
+                "case {index}: {error:?}"
+            );
+            assert!(!root.join("evil.txt").exists());
+            assert!(!dest.join("ok.txt").exists());
+        }
+    }
+
+    #[test]
+    fn test_limits_guard_against_bombs() {
+        let bomb = vec![0; 1 << 20];
+
+        let cases = [
+            (
+                zip(&[("zeros.bin", &bomb)]),
+                ArchiveLimits {
+                    max_total_size: 1 << 16,
+                    ..Default::default()
+                },
+            ),
+            (
+                tar_gz(&[("zeros.bin", EntryType::Regular, &bomb)]),
+                ArchiveLimits {
+                    max_total_size: 1 << 16,
+                    ..Default::default()
+                },
+            ),
+            (
+                tar_gz(&[
+                    ("a", EntryType::Regular, b"a"),
+                    ("b", EntryType::Regular, b"b"),
+                    ("c", EntryType::Regular, b"c"),
+                ]),
+                ArchiveLimits {
+                    max_entries: 2,
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        for (index, (body, limits)) in cases.into_iter().enumerate() {
+            let downloader = DownloaderBuilder::with_fetcher(
+                testing::cache_dir("archive_bomb"),
+                MockFetcher::new(vec![Response::ok(body, None)]),
+            )
+            .archive_limits(limits)
+            .build();
+
+            let dest = testing::cache_dir("archive_bomb_dest");
+
+            // Act
+
+            let error = downloader.download_and_extract(URL, &dest).unwrap_err();
+
+            // Assert
+
+            assert_eq!(error, DownloadError::ArchiveTooLarge, "case {index}");
+            assert_eq!(fs::read_dir(&dest).unwrap().count(), 0, "case {index}");
+        }
+    }
+
+    #[test]
+    fn test_non_archives_are_invalid() {
+        let downloader = downloader("archive_invalid", b"plain text".to_vec());
+
+        // Act
+
+        let error = downloader
+            .download_and_extract(URL, testing::cache_dir("archive_invalid_dest"))
+            .unwrap_err();
+
+        // Assert
+
+        assert_eq!(error, DownloadError::InvalidArchive);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use super::DownloadError;
+
+// One failed fetch of a download that was tried more than once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttemptRecord {
+    pub url: String,
+    // Counts from 1.
+    pub attempt: u32,
+    pub error: DownloadError,
+    pub at: SystemTime,
+    pub duration: Duration,
+}
+
+impl DownloadError {
+    // Every failed attempt in order, empty when the download was not retried.
+    pub fn attempts(&self) -> &[AttemptRecord] {
+        match self {
+            Self::RetriesExhausted { attempts } | Self::DeadlineExceeded { attempts, .. } => {
+                attempts
+            }
+            _ => &[],
+        }
+    }
+
+    // What the last attempt failed with, this error itself when there was
+    // only one.
+    pub fn last_error(&self) -> &DownloadError {
+        match self.attempts().last() {
+            Some(record) => record.error.last_error(),
+            None => self,
+        }
+    }
+}
+
+// "3 attempts over 12.4s, last error: ..."
+pub(crate) fn summarize(f: &mut fmt::Formatter<'_>, attempts: &[AttemptRecord]) -> fmt::Result {
+    let (Some(first), Some(last)) = (attempts.first(), attempts.last()) else {
+        return f.write_str("no attempts");
+    };
+
+    let elapsed = last.at.duration_since(first.at).unwrap_or_default() + last.duration;
+
+    write!(
+        f,
+        "{} attempts over {:.1}s, last error: {}",
+        attempts.len(),
+        elapsed.as_secs_f64(),
+        last.error
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use super::AttemptRecord;
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, Clock, DownloadError, DownloaderBuilder,
+        FetchError, FileDownloader, Response,
+    };
+
+    // Takes `step` of the fake clock for every response it hands out.
+    struct SlowFetcher {
+        inner: MockFetcher,
+        clock: FakeClock,
+        step: Duration,
+    }
+
+    impl FileDownloader for SlowFetcher {
+        fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+            self.clock.advance(self.step);
+
+            self.inner.fetch(url, headers)
+        }
+    }
+
+    #[test]
+    fn test_failed_attempts_are_recorded() {
+        let url = "https://example.com/flaky.png";
+
+        let clock = FakeClock::new();
+
+        let start = clock.now();
+
+        let fetcher = SlowFetcher {
+            inner: MockFetcher::new(vec![
+                Response::invalid_body(),
+                Response::invalid_body(),
+                Response::not_found(),
+            ]),
+            clock: clock.clone(),
+            step: Duration::from_millis(4_100),
+        };
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("attempts"), fetcher)
+            .clock(clock)
+            .build();
+
+        // Act
+
+        let error = downloader.download(url).unwrap_err();
+
+        // Assert
+
+        let truncated = DownloadError::TruncatedBody {
+            received: 0,
+            kind: io::ErrorKind::UnexpectedEof,
+        };
+
+        let record = |attempt: u32, error| AttemptRecord {
+            url: url.to_string(),
+            attempt,
+            error,
+            at: start + Duration::from_millis(4_100) * (attempt - 1),
+            duration: Duration::from_millis(4_100),
+        };
+
+        assert_eq!(
+            error.attempts(),
+            [
+                record(1, truncated.clone()),
+                record(2, truncated),
+                record(3, DownloadError::NotFound),
+            ]
+        );
+        assert_eq!(error.last_error(), &DownloadError::NotFound);
+        assert_eq!(
+            error.to_string(),
+            "3 attempts over 12.3s, last error: not found"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    collections::HashMap,
+    iter,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use super::{
+    budget, parallel, Download, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Storage,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchOptions {
+    // Stop handing out URLs after the first failure. Downloads already in
+    // flight still finish and are reported.
+    pub fail_fast: bool,
+    // Report results in input order rather than as they complete.
+    pub preserve_order: bool,
+    // 0 uses the downloader's `max_concurrency`.
+    pub max_concurrency: usize,
+    // Once the batch downloaded this many bytes, the URLs not yet started
+    // fail with `DownloadError::BudgetExceeded`. Cache hits are free.
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            fail_fast: false,
+            preserve_order: true,
+            max_concurrency: 0,
+            max_total_bytes: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    // The index of each attempted URL in the input, next to its result. URLs
+    // skipped by `fail_fast` or a cancelled token are missing.
+    pub results: Vec<(usize, Result<Download, DownloadError>)>,
+    // The index of the failure that stopped a `fail_fast` batch.
+    pub stopped_at: Option<usize>,
+    // URLs that repeated an earlier one and were given its result instead of
+    // being fetched again.
+    pub coalesced: usize,
+    // Every input URL as given, next to how many batches attempted it.
+    pub(crate) sources: Vec<(String, u32)>,
+}
+
+// What `download_all_with` reports while a batch runs.
+#[derive(Debug)]
+pub enum BatchEvent<'a> {
+    // The input index and result of an item, repeats of a URL included.
+    ItemFinished(usize, &'a Result<Download, DownloadError>),
+    // Sent after the items of every completed download.
+    Progress {
+        done: usize,
+        total: usize,
+        failed: usize,
+    },
+}
+
+impl BatchResult {
+    pub fn error(&self) -> Option<&DownloadError> {
+        let stopped_at = self.stopped_at?;
+
+        self.results
+            .iter()
+            .find(|(index, _)| *index == stopped_at)
+            .and_then(|(_, result)| result.as_ref().err())
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    pub fn download_all<U>(&self, urls: &[U], options: BatchOptions) -> BatchResult
+    where
+        U: IntoDownloadUrl + Sync,
+    {
+        self.download_all_with(urls, options, |_| {})
+    }
+
+    // Like `download_all`, reporting items as they finish. `on_event` runs on
+    // the worker threads, one call at a time, so progress counts only grow,
+    // and every call returns before the batch does.
+    pub fn download_all_with<U, F>(
+        &self,
+        urls: &[U],
+        options: BatchOptions,
+        on_event: F,
+    ) -> BatchResult
+    where
+        U: IntoDownloadUrl + Sync,
+        F: Fn(BatchEvent<'_>) + Send + Sync,
+    {
+        // Failing fast must not cancel the downloader's own token.
+        let token = self.config.cancellation_token.child();
+
+        let workers = match options.max_concurrency {
+            0 => self.config.max_concurrency,
+            workers => workers,
+        };
+
+        let (unique, repeats) = coalesce(urls);
+
+        let batch = Mutex::new(BatchResult::default());
+
+        // Only updated under the batch lock.
+        let failed = AtomicUsize::new(0);
+
+        let spent = AtomicU64::new(0);
+
+        parallel::for_each(&unique, workers, &token, |_, &index| {
+            let result = match options.max_total_bytes {
+                Some(limit) if spent.load(Ordering::SeqCst) >= limit => {
+                    Err(DownloadError::BudgetExceeded { limit })
+                }
+                _ => self.download_outcome(&urls[index]).map(|outcome| {
+                    spent.fetch_add(budget::downloaded_bytes(&outcome), Ordering::SeqCst);
+
+                    outcome.into_download()
+                }),
+            };
+
+            let mut batch = batch.lock().unwrap();
+
+            if result.is_err() && options.fail_fast && batch.stopped_at.is_none() {
+                batch.stopped_at = Some(index);
+
+                token.cancel();
+            }
+
+            let indices: Vec<_> = iter::once(index)
+                .chain(repeats.get(&index).into_iter().flatten().copied())
+                .collect();
+
+            for &index in &indices {
+                on_event(BatchEvent::ItemFinished(index, &result));
+            }
+
+            if result.is_err() {
+                failed.fetch_add(indices.len(), Ordering::Relaxed);
+            }
+
+            for &repeat in &indices[1..] {
+                batch.results.push((repeat, result.clone()));
+            }
+
+            batch.results.push((index, result));
+
+            on_event(BatchEvent::Progress {
+                done: batch.results.len(),
+                total: urls.len(),
+                failed: failed.load(Ordering::Relaxed),
+            });
+        });
+
+        let mut batch = batch.into_inner().unwrap();
+
+        batch.coalesced = repeats.values().map(Vec::len).sum();
+
+        batch.sources = urls
+            .iter()
+            .map(|url| (url.as_str().to_string(), 1))
+            .collect();
+
+        if options.preserve_order {
+            batch.results.sort_by_key(|(index, _)| *index);
+        }
+
+        batch
+    }
+}
+
+// The first index of every distinct URL, and the later indices repeating
+// each of them. URLs that do not parse are never coalesced.
+fn coalesce<U: IntoDownloadUrl>(urls: &[U]) -> (Vec<usize>, HashMap<usize, Vec<usize>>) {
+    let mut first = HashMap::new();
+
+    let mut unique = Vec::new();
+
+    let mut repeats: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    for (index, url) in urls.iter().enumerate() {
+        let Ok(url) = url.to_download_url() else {
+            unique.push(index);
+            continue;
+        };
+
+        match first.get(url.as_str()) {
+            Some(&original) => repeats.entry(original).or_default().push(index),
+            None => {
+                first.insert(url.as_str().to_string(), index);
+                unique.push(index);
+            }
+        }
+    }
+
+    (unique, repeats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use std::sync::Mutex;
+
+    use super::{BatchEvent, BatchOptions};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, Downloader, DownloaderBuilder, Response,
+        UrlProblem,
+    };
+
+    // The third URL fails without reaching the fetcher, whatever order the
+    // workers pick the others up in.
+    const URLS: [&str; 5] = [
+        "https://example.com/0.png",
+        "https://example.com/1.png",
+        "not a url",
+        "https://example.com/3.png",
+        "https://example.com/4.png",
+    ];
+
+    fn downloader(name: &str) -> Downloader<MockFetcher> {
+        let responses = (0..4)
+            .map(|_| Response::ok(b"image".to_vec(), Some("image/png".to_string())))
+            .collect();
+
+        let fetcher = MockFetcher::new(responses).with_delay(Duration::from_millis(20));
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), fetcher).build()
+    }
+
+    #[test]
+    fn test_fail_fast_stops_at_the_first_failure() {
+        let downloader = downloader("batch_fail_fast");
+
+        let options = BatchOptions {
+            fail_fast: true,
+            max_concurrency: 2,
+            ..Default::default()
+        };
+
+        // Act
+
+        let batch = downloader.download_all(&URLS, options);
+
+        // Assert
+
+        let indices: Vec<_> = batch.results.iter().map(|(index, _)| *index).collect();
+
+        assert_eq!(batch.stopped_at, Some(2));
+        assert_eq!(
+            batch.error(),
+            Some(&DownloadError::InvalidUrl(UrlProblem::RelativeUrl))
+        );
+        assert_eq!(indices[..3], [0, 1, 2]);
+        assert!(!indices.contains(&4), "{indices:?}");
+        assert!(downloader.fetcher().calls() <= 3);
+        assert!(!downloader.cancellation_token().is_cancelled());
+    }
+
+    #[test]
+    fn test_results_follow_input_order() {
+        let downloader = downloader("batch_ordered");
+
+        let options = BatchOptions {
+            max_concurrency: 3,
+            ..Default::default()
+        };
+
+        // Act
+
+        let batch = downloader.download_all(&URLS, options);
+
+        // Assert
+
+        let indices: Vec<_> = batch.results.iter().map(|(index, _)| *index).collect();
+
+        assert_eq!(indices, [0, 1, 2, 3, 4]);
+        assert_eq!(
+            batch.results[2].1,
+            Err(DownloadError::InvalidUrl(UrlProblem::RelativeUrl))
+        );
+        assert_eq!(batch.stopped_at, None);
+        assert_eq!(downloader.fetcher().calls(), 4);
+    }
+
+    #[test]
+    fn test_events_report_every_item_before_returning() {
+        let mut urls = URLS.map(str::to_string).to_vec();
+
+        urls.push(URLS[0].to_string());
+
+        let downloader = downloader("batch_events");
+
+        // Items finished so far, and the progress events seen.
+        let seen = Mutex::new((Vec::new(), Vec::new()));
+
+        let options = BatchOptions {
+            max_concurrency: 3,
+            ..Default::default()
+        };
+
+        // Act
+
+        let batch = downloader.download_all_with(&urls, options, |event| {
+            let (items, progress) = &mut *seen.lock().unwrap();
+
+            match event {
+                BatchEvent::ItemFinished(index, result) => items.push((index, result.is_ok())),
+                BatchEvent::Progress {
+                    done,
+                    total,
+                    failed,
+                } => {
+                    assert_eq!(done, items.len(), "items come before their progress");
+
+                    progress.push((done, total, failed));
+                }
+            }
+        });
+
+        // Assert
+
+        let (mut items, progress) = seen.into_inner().unwrap();
+
+        items.sort();
+
+        assert_eq!(
+            items,
+            [
+                (0, true),
+                (1, true),
+                (2, false),
+                (3, true),
+                (4, true),
+                (5, true)
+            ]
+        );
+        assert_eq!(progress.len(), 5, "one per download, the repeat shares one");
+        assert!(progress.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(progress.last(), Some(&(6, 6, 1)));
+        assert_eq!(batch.results.len(), 6);
+    }
+
+    #[test]
+    fn test_repeated_urls_are_fetched_once() {
+        let urls = ["a.png", "b.png", "a.png", "a.png", "c.png"]
+            .map(|name| format!("https://example.com/{name}"));
+
+        let downloader = downloader("batch_coalesced");
+
+        // Act
+
+        let batch = downloader.download_all(&urls, BatchOptions::default());
+
+        // Assert
+
+        let files: Vec<_> = batch
+            .results
+            .iter()
+            .map(|(index, result)| (*index, result.as_ref().unwrap().file.clone()))
+            .collect();
+
+        assert_eq!(downloader.fetcher().calls(), 3);
+        assert_eq!(batch.coalesced, 2);
+        assert_eq!(
+            files.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4]
+        );
+        assert_eq!(files[2].1, files[0].1);
+        assert_eq!(files[3].1, files[0].1);
+        assert_ne!(files[1].1, files[0].1);
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{manifest, partial, DownloadError, Downloader, FileDownloader, Outcome, Storage};
+
+pub(crate) const BUDGET_FILE: &str = "budget.json";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Spent {
+    // Days since the unix epoch, in UTC.
+    day: u64,
+    bytes: u64,
+}
+
+// The bytes downloaded on the current UTC day, kept in the cache directory so
+// restarts do not reset it.
+pub(crate) struct DailyBudget {
+    // None for a directory that is not written to.
+    path: Option<PathBuf>,
+    limit: u64,
+    spent: Mutex<Spent>,
+}
+
+impl DailyBudget {
+    // A counter that cannot be read counts from zero rather than blocking
+    // every download.
+    pub fn load(dir: &Path, limit: u64) -> Self {
+        let path = dir.join(BUDGET_FILE);
+
+        let spent = fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            path: Some(path),
+            limit,
+            spent: Mutex::new(spent),
+        }
+    }
+
+    // Counts on from the directory's count, spending only in memory.
+    pub fn load_read_only(dir: &Path, limit: u64) -> Self {
+        Self {
+            path: None,
+            ..Self::load(dir, limit)
+        }
+    }
+
+    pub fn check(&self, now: SystemTime) -> Result<(), DownloadError> {
+        let spent = self.spent.lock().unwrap();
+
+        if spent.day == day(now) && spent.bytes >= self.limit {
+            return Err(DownloadError::BudgetExceeded { limit: self.limit });
+        }
+
+        Ok(())
+    }
+
+    pub fn charge(&self, bytes: u64, now: SystemTime) {
+        let mut spent = self.spent.lock().unwrap();
+
+        let today = day(now);
+
+        if spent.day != today {
+            *spent = Spent {
+                day: today,
+                bytes: 0,
+            };
+        }
+
+        spent.bytes += bytes;
+
+        // Losing the counter only loosens the budget for the day.
+        let _ = self.save(&spent);
+    }
+
+    // Written aside and renamed, so a crash leaves the previous count.
+    fn save(&self, spent: &Spent) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let content = serde_json::to_vec(spent)?;
+
+        let partial = path.with_extension("json.part");
+
+        partial::recreating_parent(&partial, || fs::write(&partial, &content))?;
+
+        fs::rename(&partial, path)
+    }
+}
+
+// The bytes that came over the network for an outcome.
+pub(crate) fn downloaded_bytes(outcome: &Outcome) -> u64 {
+    match outcome {
+        Outcome::Downloaded(download) | Outcome::Unchanged(download) => {
+            download.metadata.size.unwrap_or(0)
+        }
+        Outcome::NotModified(_) | Outcome::CacheHit(_) | Outcome::Stale(_) => 0,
+    }
+}
+
+fn day(now: SystemTime) -> u64 {
+    manifest::unix_secs(now) / 86_400
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    pub(crate) fn check_budget(&self) -> Result<(), DownloadError> {
+        match &self.daily_budget {
+            Some(budget) => budget.check(self.config.clock.now()),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn charge_budget(&self, outcome: &Outcome) {
+        if let Some(budget) = &self.daily_budget {
+            budget.charge(downloaded_bytes(outcome), self.config.clock.now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::BUDGET_FILE;
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, BatchOptions, CachePolicy, DownloadError,
+        DownloaderBuilder, Response,
+    };
+
+    fn body() -> Response {
+        Response::ok(vec![0; 100], Some("image/png".to_string()))
+    }
+
+    fn url(name: &str) -> String {
+        format!("https://example.com/{name}.png")
+    }
+
+    #[test]
+    fn test_batches_stop_once_their_budget_is_spent() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("budget_batch"),
+            MockFetcher::new((0..4).map(|_| body()).collect()),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        downloader.download(&url("cached")).unwrap();
+
+        let urls = ["cached", "a", "b", "c", "d"].map(url);
+
+        let options = BatchOptions {
+            max_total_bytes: Some(250),
+            max_concurrency: 1,
+            ..Default::default()
+        };
+
+        // Act
+
+        let batch = downloader.download_all(&urls, options);
+
+        // Assert
+
+        let results: Vec<_> = batch
+            .results
+            .iter()
+            .map(|(_, result)| result.as_ref().err().cloned())
+            .collect();
+
+        assert_eq!(
+            results,
+            [
+                None,
+                None,
+                None,
+                None,
+                Some(DownloadError::BudgetExceeded { limit: 250 })
+            ]
+        );
+        assert_eq!(downloader.fetcher().calls(), 4, "the hit was free");
+    }
+
+    #[test]
+    fn test_daily_budget_persists_and_resets_each_day() {
+        let dir = testing::cache_dir("budget_daily");
+
+        let clock = FakeClock::new();
+
+        let downloader = |responses: usize| {
+            DownloaderBuilder::with_fetcher(
+                &dir,
+                MockFetcher::new((0..responses).map(|_| body()).collect()),
+            )
+            .clock(clock.clone())
+            .cache_policy(CachePolicy::CacheFirst)
+            .with_daily_byte_budget(150)
+            .build()
+        };
+
+        let first = downloader(2);
+
+        // Act
+
+        let spent = ["a", "b", "c"].map(|name| first.download(&url(name)).err());
+
+        let hit = first.download(&url("a"));
+
+        let restarted = downloader(1);
+
+        let after_restart = restarted.download(&url("c")).unwrap_err();
+
+        clock.advance(Duration::from_secs(86_400));
+
+        let next_day = restarted.download(&url("c"));
+
+        // Assert
+
+        assert_eq!(
+            spent,
+            [
+                None,
+                None,
+                Some(DownloadError::BudgetExceeded { limit: 150 })
+            ]
+        );
+        assert!(hit.is_ok());
+        assert_eq!(after_restart, DownloadError::BudgetExceeded { limit: 150 });
+        assert!(next_day.is_ok());
+        assert_eq!(restarted.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_read_only_caches_keep_to_the_budget_without_saving_it() {
+        let dir = testing::cache_dir("budget_read_only");
+
+        let writer = DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![body()]))
+            .with_daily_byte_budget(150)
+            .build();
+
+        writer.download(&url("a")).unwrap();
+
+        let saved = std::fs::read(dir.join(BUDGET_FILE)).unwrap();
+
+        let reader = DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![body(), body()]))
+            .read_only(true)
+            .with_daily_byte_budget(150)
+            .build();
+
+        // Act
+
+        let within = reader.download_temp(&url("b")).map(drop);
+
+        let over = reader.download_temp(&url("c")).map(drop);
+
+        // Assert
+
+        assert_eq!(within, Ok(()));
+        assert_eq!(over, Err(DownloadError::BudgetExceeded { limit: 150 }));
+        assert_eq!(reader.fetcher().calls(), 1);
+        assert_eq!(std::fs::read(dir.join(BUDGET_FILE)).unwrap(), saved);
+    }
+}
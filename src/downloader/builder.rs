@@ -0,0 +1,859 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+#[cfg(feature = "archives")]
+use super::archive::ArchiveLimits;
+#[cfg(feature = "image")]
+use super::images::{AnimatedPolicy, ImageOptions, ThumbSpec, VerifyLevel};
+use super::{
+    budget::DailyBudget,
+    cache_key::{HashAlgo, KeyEncoding},
+    circuit_breaker::{CircuitBreaker, CircuitBreakerConfig},
+    clock::{Clock, SystemClock},
+    connections::ConnectionLimiter,
+    deadline::Deadline,
+    fetcher::UReqFetcher,
+    format,
+    host_policy::HostPolicy,
+    maintenance::Maintenance,
+    manifest::Manifest,
+    memory_cache::MemoryCache,
+    partition::Partitioner,
+    rate_limit::RateLimitWait,
+    refresher::Refresher,
+    retry_scheduler::{RetryScheduler, RetryThrottle},
+    space::{FsSpace, SpaceProvider},
+    storage::{FsStorage, Storage},
+    stream::Tap,
+    CacheKeyer, CachePolicy, CancellationToken, DownloadError, Downloader, FileDownloader, NameBy,
+    Observer, OverwritePolicy, RefererPolicy,
+};
+#[cfg(feature = "image")]
+use image::ImageFormat;
+
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+const DEFAULT_BODY_RETRIES: u32 = 2;
+
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub clock: Arc<dyn Clock>,
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    pub host_policy: HostPolicy,
+    // Shared, as every request and per-call configuration reads them.
+    pub headers: Arc<[(String, String)]>,
+    pub referer: Option<RefererPolicy>,
+    // Domains and the `Referer` their hosts get instead.
+    pub referer_overrides: Vec<(String, RefererPolicy)>,
+    pub origin: Option<RefererPolicy>,
+    pub max_concurrency: usize,
+    pub max_connections_per_host: Option<usize>,
+    pub max_total_connections: Option<usize>,
+    pub retry_throttle: Option<RetryThrottle>,
+    // Set per call by `DownloadOptions::deadline`.
+    pub deadline: Option<Deadline>,
+    // Set per call by `download_into_cached`.
+    pub tap: Option<Tap>,
+    // Set per call by `download_temp`: bodies are stored in this directory
+    // instead of the cache, which is neither read nor written.
+    pub target: Option<PathBuf>,
+    pub cancellation_token: CancellationToken,
+    pub cache_policy: CachePolicy,
+    pub ttl: Option<Duration>,
+    pub observer: Option<Arc<dyn Observer>>,
+    pub persist_dir: Option<PathBuf>,
+    pub overwrite_policy: OverwritePolicy,
+    pub name_by: NameBy,
+    pub write_sidecars: bool,
+    pub strip_metadata: bool,
+    pub reject_html: bool,
+    pub space: Arc<dyn SpaceProvider>,
+    pub space_margin: u64,
+    pub file_mode: Option<u32>,
+    pub preserve_mtime: bool,
+    pub read_only: bool,
+    pub hash_algo: HashAlgo,
+    pub key_on_final_url: bool,
+    pub key_encoding: KeyEncoding,
+    pub keyer: Option<Arc<dyn CacheKeyer>>,
+    pub vary_headers: Vec<String>,
+    pub learn_vary: bool,
+    pub body_retries: u32,
+    pub keep_partial_bodies: bool,
+    pub negative_ttl: Option<Duration>,
+    pub negative_statuses: Vec<u16>,
+    pub partitioner: Partitioner,
+    pub rate_limit_wait: Option<RateLimitWait>,
+    pub strict_extension: bool,
+    pub strict_content_type: bool,
+    pub strict_sniff: bool,
+    pub daily_byte_budget: Option<u64>,
+    pub verify_server_digests: bool,
+    pub memory_cache: Option<u64>,
+    pub prefer_declared_mime: bool,
+    pub stale_on_not_found: bool,
+    pub max_entries_per_host: Option<usize>,
+    pub migrate_cache_format: bool,
+    #[cfg(feature = "image")]
+    pub image: ImageOptions,
+    #[cfg(feature = "archives")]
+    pub archive_limits: ArchiveLimits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clock: Arc::new(SystemClock),
+            circuit_breaker: None,
+            host_policy: HostPolicy::default(),
+            headers: Arc::from([]),
+            referer: None,
+            referer_overrides: Vec::new(),
+            origin: None,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            max_connections_per_host: None,
+            max_total_connections: None,
+            retry_throttle: None,
+            deadline: None,
+            cancellation_token: CancellationToken::new(),
+            tap: None,
+            target: None,
+            cache_policy: CachePolicy::default(),
+            ttl: None,
+            observer: None,
+            persist_dir: None,
+            overwrite_policy: OverwritePolicy::default(),
+            name_by: NameBy::default(),
+            write_sidecars: false,
+            strip_metadata: false,
+            reject_html: false,
+            space: Arc::new(FsSpace),
+            space_margin: 0,
+            file_mode: None,
+            preserve_mtime: false,
+            read_only: false,
+            hash_algo: HashAlgo::default(),
+            key_on_final_url: false,
+            key_encoding: KeyEncoding::default(),
+            keyer: None,
+            vary_headers: Vec::new(),
+            learn_vary: false,
+            body_retries: DEFAULT_BODY_RETRIES,
+            keep_partial_bodies: false,
+            negative_ttl: None,
+            negative_statuses: vec![404],
+            partitioner: Partitioner::None,
+            rate_limit_wait: None,
+            strict_extension: false,
+            strict_content_type: false,
+            strict_sniff: false,
+            daily_byte_budget: None,
+            verify_server_digests: false,
+            memory_cache: None,
+            prefer_declared_mime: false,
+            stale_on_not_found: false,
+            max_entries_per_host: None,
+            migrate_cache_format: false,
+            #[cfg(feature = "image")]
+            image: ImageOptions::default(),
+            #[cfg(feature = "archives")]
+            archive_limits: ArchiveLimits::default(),
+        }
+    }
+}
+
+pub struct DownloaderBuilder<T: FileDownloader, S: Storage = FsStorage> {
+    path: PathBuf,
+    fetcher: T,
+    storage: S,
+    overlay: Option<PathBuf>,
+    config: Config,
+}
+
+impl DownloaderBuilder<UReqFetcher> {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self::with_fetcher(path, UReqFetcher::new())
+    }
+}
+
+impl<T> DownloaderBuilder<T>
+where
+    T: FileDownloader,
+{
+    pub fn with_fetcher(path: impl AsRef<Path>, fetcher: T) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            fetcher,
+            storage: FsStorage::new(path),
+            overlay: None,
+            config: Config::default(),
+        }
+    }
+}
+
+impl<T, S> DownloaderBuilder<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Entries are kept as files in the cache directory unless a storage is
+    // given.
+    pub fn storage<R: Storage>(self, storage: R) -> DownloaderBuilder<T, R> {
+        DownloaderBuilder {
+            path: self.path,
+            fetcher: self.fetcher,
+            storage,
+            overlay: self.overlay,
+            config: self.config,
+        }
+    }
+
+    // Serves what the cache directory already holds and never writes to it,
+    // nor creates it. Misses fail with `DownloadError::NotCached` unless an
+    // overlay is configured. Fetches that still go out, such as
+    // `download_temp`, keep to the configured limits.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.config.read_only = read_only;
+        self
+    }
+
+    // A writable directory for what a read-only cache misses, looked up after
+    // it.
+    pub fn overlay(mut self, path: impl AsRef<Path>) -> Self {
+        self.overlay = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // Names entries by this hash of their URL. Entries stored under another
+    // scheme are not found, so changing it on a populated cache starts over.
+    pub fn hash_algo(mut self, algo: HashAlgo) -> Self {
+        self.config.hash_algo = algo;
+        self
+    }
+
+    pub fn key_encoding(mut self, encoding: KeyEncoding) -> Self {
+        self.config.key_encoding = encoding;
+        self
+    }
+
+    // Names entries by the keyer's keys instead. Like changing the hash,
+    // entries stored under other names are not found.
+    pub fn cache_keyer(mut self, keyer: impl CacheKeyer + 'static) -> Self {
+        self.config.keyer = Some(Arc::new(keyer));
+        self
+    }
+
+    // Writes new entries into a subdirectory of the cache chosen per entry.
+    pub fn partition_by(mut self, partitioner: Partitioner) -> Self {
+        self.config.partitioner = partitioner;
+        self
+    }
+
+    // Stores redirected downloads under the URL they ended at, so short links
+    // to the same target share its entry.
+    pub fn key_on_final_url(mut self, key_on_final_url: bool) -> Self {
+        self.config.key_on_final_url = key_on_final_url;
+        self
+    }
+
+    // Request headers whose values select a different entry for the same
+    // URL, such as `Accept-Language` or `Authorization`.
+    pub fn vary_on(mut self, names: &[&str]) -> Self {
+        self.config
+            .vary_headers
+            .extend(names.iter().map(|name| name.to_ascii_lowercase()));
+        self
+    }
+
+    // Also varies on the headers a URL's responses name in `Vary`, once one
+    // has been seen.
+    pub fn learn_vary(mut self, learn_vary: bool) -> Self {
+        self.config.learn_vary = learn_vary;
+        self
+    }
+
+    // A body that fails while being read is fetched again up to `retries`
+    // times, 2 unless set.
+    pub fn retry_invalid_bodies(mut self, retries: u32) -> Self {
+        self.config.body_retries = retries;
+        self
+    }
+
+    // Keeps what a failed body delivered as `<key>.part` in the cache
+    // directory, for resuming it, instead of removing it. The next attempt at
+    // the URL starts that file over.
+    pub fn keep_partial_bodies(mut self, keep: bool) -> Self {
+        self.config.keep_partial_bodies = keep;
+        self
+    }
+
+    // Remembers a URL answering 404 for `ttl`, failing downloads of it with
+    // the same error meanwhile instead of asking again.
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.config.negative_ttl = Some(ttl);
+        self
+    }
+
+    // The statuses `negative_ttl` remembers, 404 unless set. Only 4xx are
+    // ever remembered.
+    pub fn negative_statuses(mut self, statuses: &[u16]) -> Self {
+        self.config.negative_statuses = statuses.to_vec();
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.config.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn circuit_breaker(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.config.circuit_breaker = Some(CircuitBreakerConfig::new(failure_threshold, cooldown));
+        self
+    }
+
+    // Once a host has `max` entries, writing another evicts its least
+    // recently written or served one. Other hosts' entries are not touched.
+    pub fn with_max_entries_per_host(mut self, max: usize) -> Self {
+        self.config.max_entries_per_host = Some(max);
+        self
+    }
+
+    // Keeps up to `max_bytes` of recently served files in memory, so hits on
+    // them read no disk in `Download::bytes`. The files stay on disk.
+    pub fn with_memory_cache(mut self, max_bytes: u64) -> Self {
+        self.config.memory_cache = Some(max_bytes);
+        self
+    }
+
+    // Bodies sent with `Digest` or `Content-MD5` headers are checked against
+    // them, failing with `DownloadError::ChecksumMismatch`. Off unless set.
+    pub fn verify_server_digests(mut self, verify: bool) -> Self {
+        self.config.verify_server_digests = verify;
+        self
+    }
+
+    // At most `bytes` are downloaded per UTC day, counted across restarts in
+    // the cache directory. Past it downloads fail with
+    // `DownloadError::BudgetExceeded`; cache hits still succeed.
+    pub fn with_daily_byte_budget(mut self, bytes: u64) -> Self {
+        self.config.daily_byte_budget = Some(bytes);
+        self
+    }
+
+    // Rate limited responses asking to retry within `max_wait` are waited out
+    // and fetched again, up to `max_waits` times per download. Longer waits
+    // fail with `DownloadError::RateLimited`, and open the host's circuit
+    // until then when a circuit breaker is configured.
+    pub fn wait_on_rate_limit(mut self, max_wait: Duration, max_waits: u32) -> Self {
+        self.config.rate_limit_wait = Some(RateLimitWait {
+            max_wait,
+            max_waits,
+        });
+        self
+    }
+
+    // Only these hosts are contacted, `*.example.com` covering the domain and
+    // its subdomains. Other hosts fail with `DownloadError::Forbidden`.
+    pub fn allow_hosts(mut self, patterns: &[&str]) -> Self {
+        self.config.host_policy.allow(patterns);
+        self
+    }
+
+    // Takes precedence over `allow_hosts`.
+    pub fn deny_hosts(mut self, patterns: &[&str]) -> Self {
+        self.config.host_policy.deny(patterns);
+        self
+    }
+
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.config.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    // Caps the connections open at once to any one host, however many
+    // workers are running. A connection is held until its body is read.
+    pub fn max_connections_per_host(mut self, max_connections: usize) -> Self {
+        self.config.max_connections_per_host = Some(max_connections);
+        self
+    }
+
+    pub fn max_total_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_total_connections = Some(max_connections);
+        self
+    }
+
+    // Retries to one host start at least `min_interval` apart, first come
+    // first served, with no more than `max_concurrent` running at once.
+    // Shared by every download of this downloader, first attempts excluded.
+    pub fn throttle_retries(mut self, min_interval: Duration, max_concurrent: usize) -> Self {
+        self.config.retry_throttle = Some(RetryThrottle {
+            min_interval,
+            max_concurrent,
+        });
+        self
+    }
+
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.config.cancellation_token = token;
+        self
+    }
+
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.config.cache_policy = cache_policy;
+        self
+    }
+
+    // A 404 means the URL is gone, so `NetworkFirstFallbackStale` reports it
+    // unless set, when the cached copy is served like on other failures.
+    pub fn stale_on_not_found(mut self, stale: bool) -> Self {
+        self.config.stale_on_not_found = stale;
+        self
+    }
+
+    // Sent with every request. Headers the downloader sets itself, such as
+    // validators, take precedence.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let mut headers = self.config.headers.to_vec();
+
+        headers.push((name.to_string(), value.to_string()));
+
+        self.config.headers = headers.into();
+        self
+    }
+
+    // The `Referer` of every request, unless a header of that name is set.
+    pub fn with_referer(mut self, policy: impl Into<RefererPolicy>) -> Self {
+        self.config.referer = Some(policy.into());
+        self
+    }
+
+    // Replaces `with_referer` for `domain` and its subdomains.
+    pub fn referer_for_host(mut self, domain: &str, policy: impl Into<RefererPolicy>) -> Self {
+        // Mapped like URL hosts, so IDNs match their punycode.
+        let domain = domain.trim_end_matches('.');
+
+        let domain = match url::Host::parse(domain) {
+            Ok(url::Host::Domain(domain)) => domain,
+            _ => domain.to_lowercase(),
+        };
+
+        self.config.referer_overrides.push((domain, policy.into()));
+        self
+    }
+
+    // The `Origin` of every request, unless a header of that name is set.
+    pub fn with_origin(mut self, policy: impl Into<RefererPolicy>) -> Self {
+        self.config.origin = Some(policy.into());
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.config.ttl = Some(ttl);
+        self
+    }
+
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.config.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn overwrite_policy(mut self, overwrite_policy: OverwritePolicy) -> Self {
+        self.config.overwrite_policy = overwrite_policy;
+        self
+    }
+
+    pub fn name_by(mut self, name_by: NameBy) -> Self {
+        self.config.name_by = name_by;
+        self
+    }
+
+    pub fn write_sidecars(mut self, write_sidecars: bool) -> Self {
+        self.config.write_sidecars = write_sidecars;
+        self
+    }
+
+    // Removes EXIF, XMP and ICC data from JPEG, PNG and WebP bodies.
+    pub fn strip_metadata(mut self, strip_metadata: bool) -> Self {
+        self.config.strip_metadata = strip_metadata;
+        self
+    }
+
+    // Fail with `DownloadError::UnsupportedContent` instead of storing an HTML
+    // body, usually an error or login page served in place of the file.
+    pub fn reject_html(mut self, reject_html: bool) -> Self {
+        self.config.reject_html = reject_html;
+        self
+    }
+
+    // Every strict toggle at once, so nothing ambiguous lands in the cache.
+    pub fn strict(self, strict: bool) -> Self {
+        self.strict_extension(strict)
+            .strict_content_type(strict)
+            .strict_sniff(strict)
+    }
+
+    // Fail with `DownloadError::UnknownExtension` instead of naming the entry
+    // `.dat`.
+    pub fn strict_extension(mut self, strict: bool) -> Self {
+        self.config.strict_extension = strict;
+        self
+    }
+
+    // Fail with `DownloadError::MissingContentType` instead of sniffing the
+    // body of a response that announced no type.
+    pub fn strict_content_type(mut self, strict: bool) -> Self {
+        self.config.strict_content_type = strict;
+        self
+    }
+
+    // Fail with `DownloadError::SniffFailed` when the type has to be sniffed
+    // from the body and no signature matches.
+    pub fn strict_sniff(mut self, strict: bool) -> Self {
+        self.config.strict_sniff = strict;
+        self
+    }
+
+    // Bodies whose signature contradicts their `Content-Type` are named and
+    // typed after the signature unless set, when the header wins as it used
+    // to. The mismatch is recorded either way.
+    pub fn prefer_declared_mime(mut self, prefer: bool) -> Self {
+        self.config.prefer_declared_mime = prefer;
+        self
+    }
+
+    pub fn space_provider(mut self, space: impl SpaceProvider + 'static) -> Self {
+        self.config.space = Arc::new(space);
+        self
+    }
+
+    // Free space to keep on the cache filesystem after a download.
+    pub fn space_margin(mut self, bytes: u64) -> Self {
+        self.config.space_margin = bytes;
+        self
+    }
+
+    // Unix permission bits for stored files, ignored on other platforms.
+    pub fn file_mode(mut self, mode: u32) -> Self {
+        self.config.file_mode = Some(mode);
+        self
+    }
+
+    // Stored files get the server's `Last-Modified` as their mtime.
+    pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.config.preserve_mtime = preserve_mtime;
+        self
+    }
+
+    #[cfg(feature = "image")]
+    pub fn verify_images(mut self, level: VerifyLevel) -> Self {
+        self.config.image.verify = level;
+        self
+    }
+
+    // SVG bodies cannot be decoded, so verification rejects them with
+    // `DownloadError::UnsupportedContent` unless they are allowed unchecked.
+    #[cfg(feature = "image")]
+    pub fn allow_svg(mut self, allow_svg: bool) -> Self {
+        self.config.image.allow_svg = allow_svg;
+        self
+    }
+
+    // Images of a format this build recognizes but cannot decode fail
+    // verification with `DownloadError::DecoderUnavailable` unless they are
+    // accepted unchecked.
+    #[cfg(feature = "image")]
+    pub fn accept_undecodable_images(mut self, accept: bool) -> Self {
+        self.config.image.accept_undecodable = accept;
+        self
+    }
+
+    // Applies to every decode the downloader performs.
+    #[cfg(feature = "image")]
+    pub fn max_image_pixels(mut self, max_pixels: u64) -> Self {
+        self.config.image.max_pixels = max_pixels;
+        self
+    }
+
+    #[cfg(feature = "image")]
+    pub fn with_thumbnails(mut self, spec: ThumbSpec) -> Self {
+        self.config.image.thumbnails = Some(spec);
+        self
+    }
+
+    // Also turns on animation detection, reported as `metadata.animated`.
+    #[cfg(feature = "image")]
+    pub fn animated_policy(mut self, policy: AnimatedPolicy) -> Self {
+        self.config.image.animated = Some(policy);
+        self
+    }
+
+    #[cfg(feature = "image")]
+    pub fn first_frame_format(mut self, format: ImageFormat) -> Self {
+        self.config.image.first_frame_format = Some(format);
+        self
+    }
+
+    // Ask for AVIF and WebP first. With verification on, a body that does not
+    // decode is fetched once more asking for PNG or JPEG.
+    #[cfg(feature = "image")]
+    pub fn negotiate_image_formats(mut self, negotiate: bool) -> Self {
+        self.config.image.negotiate = negotiate;
+        self
+    }
+
+    #[cfg(feature = "image")]
+    pub fn retry_corrupt_images(mut self, retries: u32) -> Self {
+        self.config.image.retries = retries;
+        self
+    }
+
+    #[cfg(feature = "archives")]
+    pub fn archive_limits(mut self, limits: ArchiveLimits) -> Self {
+        self.config.archive_limits = limits;
+        self
+    }
+
+    // Downloaded files are moved into `dir` instead of staying in the cache.
+    // Needs a storage on the local filesystem.
+    pub fn persist_to(mut self, dir: impl AsRef<Path>) -> Self {
+        self.config.persist_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    // Directories in an older format are upgraded when opened, as
+    // `Downloader::migrate` would, instead of used as they are.
+    pub fn migrate_cache_format(mut self, migrate: bool) -> Self {
+        self.config.migrate_cache_format = migrate;
+        self
+    }
+
+    // A directory written by a newer version, or one that cannot be created
+    // or written, still gives a downloader, whose downloads all fail with
+    // the reason. So does `persist_to` with a storage not on disk.
+    pub fn build(self) -> Downloader<T, S> {
+        let (mut downloader, opened) = self.open_checked();
+
+        downloader.unusable = opened.err();
+
+        downloader
+    }
+
+    // Like `build`, but a directory written by a newer version, or whose
+    // format cannot be recorded or migrated, is an error.
+    pub fn try_build(self) -> Result<Downloader<T, S>, DownloadError> {
+        let (downloader, opened) = self.open_checked();
+
+        opened.map(|()| downloader)
+    }
+
+    fn open_checked(mut self) -> (Downloader<T, S>, Result<(), DownloadError>) {
+        let mut opened = format::check(&self.path);
+
+        // Persisting moves files, which only storages on disk have.
+        if opened.is_ok() && self.config.persist_dir.is_some() && self.storage.path("").is_none() {
+            opened = Err(DownloadError::Io(
+                "persist_to needs a storage on the local filesystem".to_string(),
+            ));
+        }
+
+        if opened.is_ok() && !self.config.read_only {
+            opened = Downloader::<T>::create_path(&self.path)
+                .map(drop)
+                .map_err(|error| DownloadError::Io(error.to_string()));
+        }
+
+        // The other directories written to are created up front, so that
+        // they fail the same way.
+        let others = [self.config.persist_dir.as_ref(), self.overlay.as_ref()];
+
+        for dir in others.into_iter().flatten() {
+            if opened.is_ok() {
+                opened = Downloader::<T>::create_path(dir)
+                    .map(drop)
+                    .map_err(|error| DownloadError::Io(format!("{}: {error}", dir.display())));
+            }
+        }
+
+        // Nothing writes to a directory that cannot be used.
+        if opened.is_err() {
+            self.config.read_only = true;
+            self.overlay = None;
+        }
+
+        let read_only = self.config.read_only;
+
+        let migrate = self.config.migrate_cache_format;
+
+        let downloader = self.open();
+
+        if !read_only {
+            opened = downloader.open_format(migrate);
+        }
+
+        (downloader, opened)
+    }
+
+    fn open(self) -> Downloader<T, S> {
+        let fetcher = Arc::new(self.fetcher);
+
+        if !self.config.read_only {
+            return Self::assemble(&self.path, fetcher, self.storage, self.config);
+        }
+
+        let overlay = self.overlay.map(|dir| {
+            let config = Config {
+                read_only: false,
+                ..self.config.clone()
+            };
+
+            let storage = FsStorage::new(&dir);
+
+            Box::new(DownloaderBuilder::assemble(
+                &dir,
+                Arc::clone(&fetcher),
+                storage,
+                config,
+            ))
+        });
+
+        let path = std::path::absolute(&self.path)
+            .unwrap_or_else(|_| panic!("Error resolving path: {:?}", self.path));
+
+        // Fetches into a target still go out, under the overlay's limits
+        // when there is one.
+        let limiters = match &overlay {
+            Some(overlay) => Limiters::of(overlay),
+            None => Limiters::new(&path, &self.config),
+        };
+
+        let maintenance = Arc::new(Maintenance::default());
+
+        Downloader {
+            fetcher,
+            storage: Arc::new(self.storage),
+            manifest: Arc::new(Manifest::load(&path, Arc::clone(&maintenance))),
+            listing: Arc::default(),
+            maintenance,
+            path,
+            memory_cache: self
+                .config
+                .memory_cache
+                .map(|max| Arc::new(MemoryCache::new(max))),
+            config: Arc::new(self.config),
+            circuit_breaker: limiters.circuit_breaker,
+            daily_budget: limiters.daily_budget,
+            connections: limiters.connections,
+            retry_scheduler: limiters.retry_scheduler,
+            refresher: None,
+            overlay,
+            unusable: None,
+        }
+    }
+
+    pub(crate) fn assemble(
+        path: &Path,
+        fetcher: Arc<T>,
+        storage: S,
+        mut config: Config,
+    ) -> Downloader<T, S> {
+        // Created by `open_checked`, which reports when they cannot be.
+        let path = Downloader::<T>::create_path(path).unwrap_or_else(|_| path.to_path_buf());
+
+        if let Some(dir) = config.persist_dir.take() {
+            config.persist_dir = Some(Downloader::<T>::create_path(&dir).unwrap_or(dir));
+        }
+
+        let limiters = Limiters::new(&path, &config);
+
+        let memory_cache = config
+            .memory_cache
+            .map(|max_bytes| Arc::new(MemoryCache::new(max_bytes)));
+
+        let maintenance = Arc::new(Maintenance::default());
+
+        let manifest = Arc::new(Manifest::load(&path, Arc::clone(&maintenance)));
+
+        let storage = Arc::new(storage);
+
+        let mut downloader = Downloader {
+            fetcher,
+            storage,
+            manifest,
+            listing: Arc::default(),
+            maintenance,
+            path,
+            config: Arc::new(config),
+            circuit_breaker: limiters.circuit_breaker,
+            daily_budget: limiters.daily_budget,
+            memory_cache,
+            connections: limiters.connections,
+            retry_scheduler: limiters.retry_scheduler,
+            refresher: None,
+            overlay: None,
+            unusable: None,
+        };
+
+        if downloader.config.cache_policy == CachePolicy::StaleWhileRevalidate {
+            downloader.refresher = Some(Arc::new(Refresher::spawn(downloader.detached())));
+        }
+
+        downloader
+    }
+}
+
+// What a downloader holds back its fetches with.
+struct Limiters {
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    daily_budget: Option<Arc<DailyBudget>>,
+    connections: Option<Arc<ConnectionLimiter>>,
+    retry_scheduler: Option<Arc<RetryScheduler>>,
+}
+
+impl Limiters {
+    // A read-only cache directory keeps its count of the day's bytes as it
+    // was.
+    fn new(path: &Path, config: &Config) -> Self {
+        let circuit_breaker = config
+            .circuit_breaker
+            .map(|config| Arc::new(CircuitBreaker::new(config)));
+
+        let daily_budget = config.daily_byte_budget.map(|limit| {
+            Arc::new(match config.read_only {
+                true => DailyBudget::load_read_only(path, limit),
+                false => DailyBudget::load(path, limit),
+            })
+        });
+
+        let connections = (config.max_connections_per_host.is_some()
+            || config.max_total_connections.is_some())
+        .then(|| {
+            Arc::new(ConnectionLimiter::new(
+                config.max_connections_per_host,
+                config.max_total_connections,
+            ))
+        });
+
+        let retry_scheduler = config
+            .retry_throttle
+            .map(|throttle| Arc::new(RetryScheduler::new(throttle)));
+
+        Self {
+            circuit_breaker,
+            daily_budget,
+            connections,
+            retry_scheduler,
+        }
+    }
+
+    fn of<T: FileDownloader, S: Storage>(downloader: &Downloader<T, S>) -> Self {
+        Self {
+            circuit_breaker: downloader.circuit_breaker.clone(),
+            daily_budget: downloader.daily_budget.clone(),
+            connections: downloader.connections.clone(),
+            retry_scheduler: downloader.retry_scheduler.clone(),
+        }
+    }
+}
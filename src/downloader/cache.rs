@@ -0,0 +1,1696 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use url::Url;
+
+#[cfg(feature = "image")]
+use super::images::{self, VerifyLevel};
+use super::{
+    cache_control::{self, CacheDirectives},
+    cache_key::CacheKey,
+    extension, filename, headers,
+    manifest::{self, ManifestEntry},
+    overwrite_policy::OverwritePolicy,
+    partial::{self, PartialFile},
+    partition,
+    response::MAX_PREALLOCATION,
+    server_digest::ServerDigest,
+    sidecar::{self, SIDECAR_SUFFIX},
+    sniff,
+    storage::Backing,
+    strip::{self, StripOutcome},
+    tee::{self, BodySummary, CopyError, TeeWriter},
+    Body, Download, DownloadError, DownloadMetadata, Downloader, FileDownloader, IntoDownloadUrl,
+    NameBy, Storage, PARTIAL_SUFFIX,
+};
+
+// Below this, preallocating costs a syscall for no real gain.
+const PREALLOCATE_MIN: u64 = 64 * 1024;
+
+// The names in storage by key, listed once for lookups the manifest cannot
+// answer. Entries stored since are in the manifest, and names that went away
+// are checked for.
+#[derive(Default)]
+pub(crate) struct Listing(OnceLock<HashMap<String, Vec<String>>>);
+
+impl Listing {
+    fn named(&self, storage: &impl Storage, key: &str) -> &[String] {
+        let names = self.0.get_or_init(|| {
+            let mut names: HashMap<String, Vec<String>> = HashMap::new();
+
+            for name in storage.list().unwrap_or_default() {
+                if name.ends_with(PARTIAL_SUFFIX) || name.ends_with(SIDECAR_SUFFIX) {
+                    continue;
+                }
+
+                if let Some((stem, _)) = unpartitioned(&name).split_once('.') {
+                    names.entry(stem.to_string()).or_default().push(name);
+                }
+            }
+
+            for named in names.values_mut() {
+                named.sort();
+            }
+
+            names
+        });
+
+        names.get(unpartitioned(key)).map_or(&[], Vec::as_slice)
+    }
+}
+
+// `is_entry` is false when the stored file is not the URL's cache entry, so
+// the manifest must not be updated to describe it. `written` is false when an
+// existing file was kept instead of the new body. `on_disk` is false when the
+// storage keeps entries somewhere other than the local filesystem.
+pub(crate) struct Stored {
+    pub file: PathBuf,
+    pub metadata: DownloadMetadata,
+    pub is_entry: bool,
+    pub written: bool,
+    // The body matched the entry's checksum, so the entry was kept as is.
+    pub unchanged: bool,
+    pub on_disk: bool,
+    pub thumbnail: Option<PathBuf>,
+}
+
+// A body yet to be streamed into a partial file, or one already written to
+// one, which is committed as is.
+pub(crate) enum BodySource {
+    Streamed(Body),
+    Staged(PartialFile),
+}
+
+#[derive(Debug)]
+pub(crate) enum StoreError {
+    Read { received: u64, kind: io::ErrorKind },
+    Write(io::Error),
+    AlreadyExists,
+    UnsupportedContent,
+    Full { written: u64 },
+    Rejected(DownloadError),
+}
+
+impl From<CopyError> for StoreError {
+    fn from(error: CopyError) -> Self {
+        match error {
+            CopyError::Read { received, kind } => Self::Read { received, kind },
+            CopyError::Write(error) => Self::Write(error),
+        }
+    }
+}
+
+struct ProcessedImage<'a> {
+    mime: Option<&'a str>,
+    #[cfg(feature = "image")]
+    decoded: Option<image::DynamicImage>,
+    #[cfg(feature = "image")]
+    animated: Option<bool>,
+}
+
+pub(crate) struct CachedEntry {
+    pub file: PathBuf,
+    pub meta: Option<ManifestEntry>,
+    pub backing: Backing,
+}
+
+impl CachedEntry {
+    pub fn metadata(&self) -> DownloadMetadata {
+        let extension = self
+            .file
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned());
+
+        let size = self
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.size)
+            .or_else(|| match self.backing.0 {
+                Some(_) => None,
+                None => fs::metadata(&self.file).ok().map(|metadata| metadata.len()),
+            });
+
+        DownloadMetadata {
+            size,
+            sha256: self.meta.as_ref().and_then(|meta| meta.sha256.clone()),
+            mime: self.meta.as_ref().and_then(|meta| meta.mime.clone()),
+            extension,
+            ..Default::default()
+        }
+    }
+
+    pub fn download(&self, url: &Url) -> Download {
+        self.download_from(url.as_str())
+    }
+
+    pub fn download_from(&self, source: &str) -> Download {
+        let mut download =
+            Download::with_metadata(source.to_string(), self.file.clone(), self.metadata());
+
+        download.thumbnail = self.thumbnail();
+        download.backing = self.backing.clone();
+
+        if let Some(meta) = &self.meta {
+            download.redirects = meta.redirects.clone();
+            download.user_meta = meta.user_meta.clone();
+        }
+
+        download
+    }
+
+    pub fn thumbnail(&self) -> Option<PathBuf> {
+        if self.backing.0.is_some() {
+            return None;
+        }
+
+        #[cfg(feature = "image")]
+        return images::find_thumbnail(&self.file);
+
+        #[cfg(not(feature = "image"))]
+        None
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    pub(crate) fn cached_entry(&self, url: &Url) -> Option<CachedEntry> {
+        let key = self.entry_key(url.as_str());
+
+        let meta = self.manifest.get(&key);
+
+        // Several `<key>.*` files can exist after the extension changed; the
+        // one the manifest names wins, then the first by name. Aliases
+        // recorded by `key_on_final_url` name another URL's entry.
+        let named = meta
+            .as_ref()
+            .map(|meta| meta.file.as_str())
+            .filter(|file| !file.is_empty() && self.storage.exists(file));
+
+        let file = match named {
+            Some(name) => self.locate(name),
+            None => self
+                .listing
+                .named(&*self.storage, &key)
+                .iter()
+                .find(|name| self.storage.exists(name))
+                .map(|name| self.locate(name))?,
+        };
+
+        if meta.as_ref().is_some_and(|meta| meta.no_store) {
+            return None;
+        }
+
+        Some(CachedEntry {
+            backing: self.backing(&file),
+            file,
+            meta,
+        })
+    }
+
+    // The key of the variant the configured request headers select. Requests
+    // carrying none of the significant headers use the plain URL key, so
+    // entries stored before any were configured are still found.
+    pub(crate) fn entry_key(&self, url: &str) -> CacheKey {
+        let variant = self.variant(url);
+
+        if variant.is_empty() {
+            return self.get_hash(url, "");
+        }
+
+        let variant: String = variant
+            .iter()
+            .map(|(name, value)| format!("\n{name}: {value}"))
+            .collect();
+
+        self.get_hash(url, &variant)
+    }
+
+    // The significant request headers sent for `url`, by lowercase name.
+    fn variant(&self, url: &str) -> Vec<(String, String)> {
+        let learned = self
+            .config
+            .learn_vary
+            .then(|| self.manifest.get(&self.get_hash(url, "")))
+            .flatten()
+            .map(|meta| meta.vary)
+            .unwrap_or_default();
+
+        let mut names: Vec<_> = self
+            .config
+            .vary_headers
+            .iter()
+            .cloned()
+            .chain(learned)
+            .collect();
+
+        names.sort();
+        names.dedup();
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let value = headers::find(&self.config.headers, &name)?
+                    .trim()
+                    .to_string();
+
+                Some((name, value))
+            })
+            .collect()
+    }
+
+    // Remembers the request headers named by a response's `Vary`, under the
+    // plain URL key, before the response is stored.
+    pub(crate) fn learn_vary(&self, url: &str, response_headers: &[(String, String)]) {
+        let Some(vary) = headers::find(response_headers, "Vary") else {
+            return;
+        };
+
+        let key = self.get_hash(url, "");
+
+        let mut entry = self.manifest.get(&key).unwrap_or_else(|| ManifestEntry {
+            url: url.to_string(),
+            ..Default::default()
+        });
+
+        let names: Vec<_> = vary
+            .split(',')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty() && name != "*" && !entry.vary.contains(name))
+            .collect();
+
+        if names.is_empty() {
+            return;
+        }
+
+        entry.vary.extend(names);
+        entry.vary.sort();
+
+        self.manifest.insert(&key, entry);
+    }
+
+    // Whether `cached` would find the URL. Never touches the network.
+    pub fn is_cached(&self, url: impl IntoDownloadUrl) -> bool {
+        let Ok(url) = url.to_download_url() else {
+            return false;
+        };
+
+        self.cached_entry(&url).is_some()
+            || self
+                .overlay
+                .as_ref()
+                .is_some_and(|overlay| overlay.is_cached(url.as_ref()))
+    }
+
+    // The entry a cache hit would serve, whether or not it is still fresh.
+    pub fn cached(&self, url: impl IntoDownloadUrl) -> Option<Download> {
+        let url = url.to_download_url().ok()?;
+
+        match self.cached_entry(&url) {
+            Some(entry) => Some(self.with_remote_url(entry.download(&url))),
+            None => self.overlay.as_ref()?.cached(url.as_ref()),
+        }
+    }
+
+    pub(crate) fn cached_file(&self, url: &Url) -> Option<PathBuf> {
+        self.cached_entry(url).map(|entry| entry.file)
+    }
+
+    // Entries are matched by key in any partition.
+    pub(crate) fn entries_named(&self, key: &str) -> Vec<PathBuf> {
+        let Ok(names) = self.storage.list() else {
+            return Vec::new();
+        };
+
+        let key = unpartitioned(key);
+
+        names
+            .iter()
+            .filter(|name| !name.ends_with(PARTIAL_SUFFIX) && !name.ends_with(SIDECAR_SUFFIX))
+            .filter(|name| {
+                unpartitioned(name)
+                    .split_once('.')
+                    .is_some_and(|(stem, _)| stem == key)
+            })
+            .map(|name| self.locate(name))
+            .collect()
+    }
+
+    // Where `download` stores the URL, without fetching it. The extension is
+    // only known once the body is, so until the entry exists it is guessed
+    // from the URL path.
+    pub fn target_path_for(&self, url: impl IntoDownloadUrl) -> Result<PathBuf, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        if let Some(entry) = self.cached_entry(&url) {
+            return Ok(entry.file);
+        }
+
+        let extension = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .and_then(|segment| segment.split_once('.'))
+            .and_then(|(_, extension)| extension::sanitize_extension(extension))
+            .unwrap_or_else(|| "dat".to_string());
+
+        let key = self.entry_key(url.as_str());
+
+        // Persisted files are moved out of their partition.
+        Ok(match &self.config.persist_dir {
+            Some(dir) => dir.join(format!("{}.{}", key, extension)),
+            None => self.locate(&format!("{}.{}", self.partitioned(&url, &key), extension)),
+        })
+    }
+
+    // Entries on disk are located by their path, others by their name in the
+    // storage.
+    pub(crate) fn locate(&self, name: &str) -> PathBuf {
+        self.storage
+            .path(name)
+            .unwrap_or_else(|| PathBuf::from(name))
+    }
+
+    // The inverse of `locate`: entries in a partition are named
+    // `<partition>/<file>`.
+    pub(crate) fn storage_name(&self, path: &Path) -> String {
+        let base = file_name(path);
+
+        path.parent()
+            .and_then(Path::file_name)
+            .map(|partition| format!("{}/{}", partition.to_string_lossy(), base))
+            .filter(|name| self.locate(name) == path)
+            .unwrap_or(base)
+    }
+
+    // Where new entries for `url` go, `<partition>/<key>` when partitioned.
+    pub(crate) fn partitioned(&self, url: &Url, key: &str) -> String {
+        match self
+            .config
+            .partitioner
+            .partition(url, self.config.clock.now())
+        {
+            Some(partition) => format!("{partition}/{key}"),
+            None => key.to_string(),
+        }
+    }
+
+    // Server directives win; the configured TTL applies only when the
+    // response carried neither `max-age` nor `Expires`.
+    pub(crate) fn is_fresh(&self, entry: &CachedEntry) -> bool {
+        let now = self.config.clock.now();
+
+        let fetched_at = match &entry.meta {
+            Some(meta) if meta.no_cache => return false,
+
+            Some(meta) => {
+                if let Some(expires_at) = meta.expires_at() {
+                    return now < expires_at;
+                }
+
+                meta.fetched_at()
+            }
+
+            // Outside the filesystem nothing else tells when it was fetched.
+            None if entry.backing.0.is_some() => return false,
+
+            // Without a manifest, as when the directory was copied from
+            // elsewhere, a sidecar still records when the body was fetched.
+            // The file's mtime is a last resort since `preserve_mtime` sets
+            // it to the server's `Last-Modified`.
+            None => match sidecar::read(&entry.file) {
+                Ok(sidecar) => manifest::from_unix_secs(sidecar.fetched_at),
+                Err(_) => {
+                    let modified =
+                        fs::metadata(&entry.file).and_then(|metadata| metadata.modified());
+
+                    let Ok(modified) = modified else {
+                        return false;
+                    };
+
+                    modified
+                }
+            },
+        };
+
+        self.config.ttl.is_none_or(|ttl| fetched_at + ttl > now)
+    }
+
+    pub(crate) fn must_revalidate(&self, entry: &CachedEntry) -> bool {
+        entry.meta.as_ref().is_some_and(|meta| meta.no_cache)
+    }
+
+    pub(crate) fn validators(&self, entry: Option<&CachedEntry>) -> Vec<(String, String)> {
+        let Some(meta) = entry.and_then(|entry| entry.meta.as_ref()) else {
+            return Vec::new();
+        };
+
+        let mut validators = Vec::new();
+
+        if let Some(etag) = &meta.etag {
+            validators.push(("If-None-Match".to_string(), etag.clone()));
+        }
+
+        if let Some(last_modified) = &meta.last_modified {
+            validators.push(("If-Modified-Since".to_string(), last_modified.clone()));
+        }
+
+        validators
+    }
+
+    pub(crate) fn record_entry(
+        &self,
+        url: &str,
+        download: &Download,
+        response_headers: &[(String, String)],
+        previous: Option<&ManifestEntry>,
+    ) {
+        let key = self.entry_key(url);
+
+        let now = self.config.clock.now();
+
+        let directives = CacheDirectives::from_headers(response_headers);
+
+        // Bodies that must not be stored are only recorded so the file left
+        // for the caller is never served; none of the response is kept.
+        if directives.no_store {
+            return self.manifest.insert(
+                &key,
+                ManifestEntry {
+                    url: url.to_string(),
+                    file: self.storage_name(&download.file),
+                    fetched_at: manifest::unix_secs(now),
+                    no_store: true,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let header = |name: &str| headers::find(response_headers, name).map(str::to_string);
+
+        let entry = ManifestEntry {
+            url: url.to_string(),
+            file: self.storage_name(&download.file),
+            fetched_at: manifest::unix_secs(now),
+            expires_at: cache_control::expires_at(response_headers, now).map(manifest::unix_secs),
+            no_cache: directives.no_cache,
+            no_store: directives.no_store,
+            etag: header("ETag").or_else(|| previous.and_then(|meta| meta.etag.clone())),
+            last_modified: header("Last-Modified")
+                .or_else(|| previous.and_then(|meta| meta.last_modified.clone())),
+            size: download.metadata.size,
+            sha256: download.metadata.sha256.clone(),
+            mime: download.metadata.mime.clone(),
+            redirects: download.redirects.clone(),
+            host: manifest::host_of(url),
+            used_at: Some(manifest::unix_secs(now)),
+            filename: headers::find(response_headers, "Content-Disposition")
+                .and_then(filename::disposition_filename)
+                .or_else(|| previous.and_then(|meta| meta.filename.clone())),
+            vary: self
+                .manifest
+                .get(&key)
+                .map(|meta| meta.vary)
+                .unwrap_or_default(),
+            renamed_from: previous
+                .map(|meta| meta.renamed_from.clone())
+                .unwrap_or_default(),
+            variant: self.variant(url),
+            negative: None,
+            user_meta: self.stored_user_meta(url),
+        };
+
+        let host = entry.host.clone();
+
+        let file = entry.file.clone();
+
+        let replaced = self.manifest.get(&key);
+
+        self.manifest.insert(&key, entry);
+
+        // Content-named files are shared, so one is removed along with the
+        // last entry naming it.
+        if let Some(replaced) = replaced.filter(|replaced| {
+            self.config.name_by == NameBy::ContentHash
+                && !replaced.file.is_empty()
+                && replaced.file != file
+                && !self.manifest.names_file(&replaced.file)
+        }) {
+            self.remove_entry(&self.locate(&replaced.file));
+        }
+
+        if let Some(host) = host {
+            self.limit_host_entries(&key, &host);
+        }
+    }
+
+    // The body streams once into a partial file next to the entry, so readers
+    // of a cached entry never observe a half-written file while it is being
+    // refreshed. It is renamed once the extension is known.
+    pub(crate) fn store_body(
+        &self,
+        key: &str,
+        body: BodySource,
+        headers: &[(String, String)],
+        size_hint: Option<u64>,
+        modified: Option<SystemTime>,
+        overwrite: OverwritePolicy,
+    ) -> Result<Stored, StoreError> {
+        let mime = headers::find(headers, "Content-Type");
+
+        if self.config.strict_content_type && mime.is_none() {
+            return Err(StoreError::Rejected(DownloadError::MissingContentType));
+        }
+
+        let kept = self.path.join(format!("{}{}", key, PARTIAL_SUFFIX));
+
+        let (mut partial, mut summary) = match body {
+            BodySource::Streamed(body) => self.stage_body(key, body, size_hint, &kept)?,
+            BodySource::Staged(mut partial) => {
+                partial.rewind().map_err(StoreError::Write)?;
+
+                let summary = BodySummary::of_reader(&mut partial).map_err(StoreError::Write)?;
+
+                (partial, summary)
+            }
+        };
+
+        if self.config.verify_server_digests {
+            if let Some(digest) = ServerDigest::from_headers(headers) {
+                digest.check(&summary, &mut partial)?;
+            }
+        }
+
+        if self.config.reject_html && is_html(mime, &summary.head) {
+            return Err(StoreError::UnsupportedContent);
+        }
+
+        // Opt-in, so the extra pass over the body only costs when enabled.
+        let stripped = if self.config.strip_metadata {
+            Some(self.strip_partial(&mut partial, &mut summary)?)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "image")]
+        let image = self.process_image(&mut partial, &mut summary, mime)?;
+
+        #[cfg(not(feature = "image"))]
+        let image = ProcessedImage { mime };
+
+        let declared = image.mime.and_then(sniff::normalize_mime);
+
+        // Servers mislabel bodies; only a signature is trusted over the header.
+        let detected = sniff::mime_from_magic(&summary.head).filter(|detected| {
+            declared
+                .as_deref()
+                .is_some_and(|declared| declared != *detected)
+        });
+
+        let mime = match detected {
+            Some(detected) if !self.config.prefer_declared_mime => Some(detected),
+            _ => image.mime,
+        };
+
+        let extension = self
+            .entry_extension(mime, &summary.head)
+            .map_err(StoreError::Rejected)?;
+
+        let entry_name = match self.config.name_by {
+            NameBy::UrlHash => format!("{}.{}", key, extension),
+            NameBy::ContentHash => format!(
+                "{}{}.{}",
+                &key[..key.len() - unpartitioned(key).len()],
+                summary.sha256,
+                extension
+            ),
+        };
+
+        let existing = || CachedEntry {
+            meta: self
+                .manifest
+                .get(unpartitioned(key))
+                .filter(|meta| meta.file == entry_name),
+            file: self.locate(&entry_name),
+            backing: self.backing(&self.locate(&entry_name)),
+        };
+
+        let name = match overwrite {
+            _ if self.config.target.is_some() => entry_name.clone(),
+            _ if !self.storage.exists(&entry_name) => entry_name.clone(),
+            // A file of that name holds these very bytes.
+            _ if self.config.name_by == NameBy::ContentHash => entry_name.clone(),
+            // Rewriting identical bytes would only churn the file's mtime.
+            OverwritePolicy::Overwrite => {
+                let entry = existing();
+
+                let sha256 = entry.meta.as_ref().and_then(|meta| meta.sha256.as_ref());
+
+                if sha256 != Some(&summary.sha256) {
+                    entry_name.clone()
+                } else {
+                    return Ok(Stored {
+                        metadata: entry.metadata(),
+                        thumbnail: entry.thumbnail(),
+                        on_disk: self.storage.path(&entry_name).is_some(),
+                        file: entry.file,
+                        is_entry: true,
+                        written: false,
+                        unchanged: true,
+                    });
+                }
+            }
+            OverwritePolicy::Skip => {
+                let entry = existing();
+
+                return Ok(Stored {
+                    metadata: entry.metadata(),
+                    thumbnail: entry.thumbnail(),
+                    on_disk: self.storage.path(&entry_name).is_some(),
+                    file: entry.file,
+                    is_entry: false,
+                    written: false,
+                    unchanged: false,
+                });
+            }
+            OverwritePolicy::Error => return Err(StoreError::AlreadyExists),
+            OverwritePolicy::Rename => self.free_name(key, &extension),
+        };
+
+        let modified = modified.unwrap_or_else(|| self.config.clock.now());
+
+        let file_path = self
+            .commit(partial, &name, modified)
+            .map_err(StoreError::Write)?;
+
+        // What an earlier attempt kept is superseded by the whole body.
+        if self.config.keep_partial_bodies {
+            let _ = fs::remove_file(&kept);
+        }
+
+        let on_disk = self.config.target.is_some() || self.storage.path(&name).is_some();
+
+        if overwrite == OverwritePolicy::Overwrite && self.config.target.is_none() {
+            for sibling in self.entries_named(key) {
+                if sibling != file_path {
+                    self.remove_entry(&sibling);
+                }
+            }
+        }
+
+        #[cfg(feature = "image")]
+        let thumbnail = (on_disk && !CacheDirectives::from_headers(headers).no_store)
+            .then(|| {
+                self.config
+                    .image
+                    .thumbnail(&file_path, image.decoded, self.config.clock.now())
+            })
+            .flatten();
+
+        #[cfg(not(feature = "image"))]
+        let thumbnail = None;
+
+        let metadata = DownloadMetadata {
+            size: Some(summary.size),
+            sha256: Some(summary.sha256),
+            extension: Some(extension),
+            stripped,
+            #[cfg(feature = "image")]
+            animated: image.animated,
+            #[cfg(not(feature = "image"))]
+            animated: None,
+            mime: mime
+                .and_then(sniff::normalize_mime)
+                .or_else(|| sniff::mime_from_magic(&summary.head).map(str::to_string)),
+            declared_mime: detected.and(declared),
+            detected_format: detected.map(str::to_string),
+        };
+
+        Ok(Stored {
+            is_entry: name == entry_name && self.config.target.is_none(),
+            file: file_path,
+            metadata,
+            written: true,
+            unchanged: false,
+            on_disk,
+            thumbnail,
+        })
+    }
+
+    // Streams `body` into a partial file next to the entry `key` names.
+    fn stage_body(
+        &self,
+        key: &str,
+        body: Body,
+        size_hint: Option<u64>,
+        kept: &Path,
+    ) -> Result<(PartialFile, BodySummary), StoreError> {
+        let mut partial = PartialFile::create(self.staging_path(key)).map_err(StoreError::Write)?;
+
+        // Servers may announce more than they send, so the file is cut back to
+        // what was written.
+        let preallocated = size_hint
+            .filter(|size| *size >= PREALLOCATE_MIN)
+            .map(|size| size.min(MAX_PREALLOCATION));
+
+        if let Some(size) = preallocated {
+            match partial.preallocate(size) {
+                Err(error) if error.kind() == io::ErrorKind::StorageFull => {
+                    return Err(StoreError::Full { written: 0 })
+                }
+                // Filesystems without preallocation still take the write.
+                _ => {}
+            }
+        }
+
+        let body = match &self.config.tap {
+            Some(tap) => tap.wrap(body),
+            None => body,
+        };
+
+        let mut tee = TeeWriter::new(partial);
+
+        if let Err(error) = tee::copy_body(body, &mut tee) {
+            return Err(match error {
+                CopyError::Write(error) if error.kind() == io::ErrorKind::StorageFull => {
+                    StoreError::Full {
+                        written: tee.written(),
+                    }
+                }
+                CopyError::Read { received, kind } if self.config.keep_partial_bodies => {
+                    let (mut partial, _) = tee.finish();
+
+                    if let Err(error) = partial
+                        .set_len(received)
+                        .and_then(|()| partial.keep_as(kept))
+                    {
+                        return Err(StoreError::Write(error));
+                    }
+
+                    StoreError::Read { received, kind }
+                }
+                error => error.into(),
+            });
+        }
+
+        let (mut partial, summary) = tee.finish();
+
+        if preallocated.is_some() {
+            partial.set_len(summary.size).map_err(StoreError::Write)?;
+        }
+
+        Ok((partial, summary))
+    }
+
+    // Verified before the commit so a corrupt body never replaces a good
+    // entry; dropping the partial file removes it.
+    #[cfg(feature = "image")]
+    fn process_image<'a>(
+        &self,
+        partial: &mut PartialFile,
+        summary: &mut BodySummary,
+        mime: Option<&'a str>,
+    ) -> Result<ProcessedImage<'a>, StoreError> {
+        let options = &self.config.image;
+
+        // Vector images cannot be verified by decoding them.
+        if options.verify != VerifyLevel::None && is_svg(mime, &summary.head) {
+            if !options.allow_svg {
+                return Err(StoreError::UnsupportedContent);
+            }
+
+            return Ok(ProcessedImage {
+                mime,
+                decoded: None,
+                animated: None,
+            });
+        }
+
+        let decoded = options
+            .verify(partial.path())
+            .map_err(StoreError::Rejected)?;
+
+        let inspection = options
+            .inspect_animation(partial.path())
+            .map_err(StoreError::Rejected)?;
+
+        let mut image = ProcessedImage {
+            mime,
+            decoded,
+            animated: inspection.as_ref().map(|inspection| inspection.animated),
+        };
+
+        if let Some((bytes, format)) = inspection.and_then(|inspection| inspection.first_frame) {
+            partial.rewrite(&bytes).map_err(StoreError::Write)?;
+
+            *summary = BodySummary::of(&bytes);
+
+            // The announced type no longer describes the body.
+            image.mime = Some(format.to_mime_type());
+        }
+
+        Ok(image)
+    }
+
+    fn strip_partial(
+        &self,
+        partial: &mut PartialFile,
+        summary: &mut BodySummary,
+    ) -> Result<StripOutcome, StoreError> {
+        let bytes = partial.read_all().map_err(StoreError::Write)?;
+
+        let (outcome, stripped) = strip::strip(&bytes);
+
+        if let Some(stripped) = stripped {
+            partial.rewrite(&stripped).map_err(StoreError::Write)?;
+
+            *summary = BodySummary::of(&stripped);
+        }
+
+        Ok(outcome)
+    }
+
+    // Storages on disk get the staged file renamed into place, others have it
+    // streamed in.
+    fn commit(
+        &self,
+        mut partial: PartialFile,
+        name: &str,
+        modified: SystemTime,
+    ) -> io::Result<PathBuf> {
+        let path = match &self.config.target {
+            Some(target) => Some(target.join(unpartitioned(name))),
+            None => self.storage.path(name),
+        };
+
+        match path {
+            Some(path) => {
+                if let Some(mode) = self.config.file_mode {
+                    partial.set_mode(mode)?;
+                }
+
+                partial.commit(&path, modified)?;
+
+                if let Some(dir) = path
+                    .parent()
+                    .filter(|_| self.config.target.is_none() && name.contains('/'))
+                {
+                    partition::mark(dir)?;
+                }
+
+                Ok(path)
+            }
+            None => {
+                partial.rewind()?;
+
+                self.storage.put(name, &mut partial)?;
+
+                Ok(PathBuf::from(name))
+            }
+        }
+    }
+
+    // Where the body of the entry `key` is staged: next to it, or in the
+    // directory `download_temp` fetches into.
+    pub(crate) fn staging_path(&self, key: &str) -> PathBuf {
+        match &self.config.target {
+            Some(target) => partial::staging_path(&target.join(unpartitioned(key))),
+            None => partial::staging_path(&self.path.join(key)),
+        }
+    }
+
+    fn free_name(&self, key: &str, extension: &str) -> String {
+        (1..)
+            .map(|counter| format!("{}-{}.{}", key, counter, extension))
+            .find(|name| !self.storage.exists(name))
+            .unwrap()
+    }
+
+    // A data file, its sidecar and its thumbnail are removed as a unit.
+    pub(crate) fn remove_entry(&self, file: &Path) {
+        let name = self.storage_name(file);
+
+        let _ = self.storage.delete(&name);
+
+        if self.storage.path(&name).is_some() {
+            let _ = sidecar::remove_sidecar(file);
+
+            #[cfg(feature = "image")]
+            images::remove_thumbnails(file);
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn unpartitioned(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+fn is_html(mime: Option<&str>, head: &[u8]) -> bool {
+    announces(mime, "text/html") || sniff::is_html(head)
+}
+
+#[cfg(feature = "image")]
+fn is_svg(mime: Option<&str>, head: &[u8]) -> bool {
+    announces(mime, "image/svg+xml") || sniff::is_svg(head)
+}
+
+fn announces(mime: Option<&str>, essence: &str) -> bool {
+    mime.is_some_and(|mime| {
+        mime.split(';')
+            .next()
+            .is_some_and(|announced| announced.trim().eq_ignore_ascii_case(essence))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, Read},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, fixtures, sidecar, testing, CachePolicy,
+        DownloadError, DownloadOptions, DownloaderBuilder, FsStorage, MemoryStorage, Observer,
+        Outcome, Response, Storage, StoredFile, UrlProblem,
+    };
+
+    // Counts how often the directory is listed.
+    struct Listed {
+        inner: FsStorage,
+        lists: Arc<AtomicUsize>,
+    }
+
+    impl Storage for Listed {
+        fn put(&self, name: &str, reader: &mut dyn Read) -> io::Result<StoredFile> {
+            self.inner.put(name, reader)
+        }
+
+        fn exists(&self, name: &str) -> bool {
+            self.inner.exists(name)
+        }
+
+        fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+            self.inner.open(name)
+        }
+
+        fn delete(&self, name: &str) -> io::Result<()> {
+            self.inner.delete(name)
+        }
+
+        fn list(&self) -> io::Result<Vec<String>> {
+            self.lists.fetch_add(1, Ordering::Relaxed);
+
+            self.inner.list()
+        }
+
+        fn path(&self, name: &str) -> Option<PathBuf> {
+            self.inner.path(name)
+        }
+    }
+
+    // Entries in the cache directory, and in memory.
+    fn storages(name: &str) -> [Box<dyn Storage>; 2] {
+        [
+            Box::new(FsStorage::new(testing::cache_dir(name))),
+            Box::new(MemoryStorage::new()),
+        ]
+    }
+
+    fn png_response(body: &str) -> Response {
+        Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()))
+    }
+
+    fn language(value: &str) -> DownloadOptions {
+        DownloadOptions::new().header("Accept-Language", value)
+    }
+
+    #[test]
+    fn test_significant_headers_keep_variants_apart() {
+        for storage in storages("vary_configured") {
+            let url = "https://example.com/greeting.png";
+
+            let fetcher = MockFetcher::new(vec![png_response("hello"), png_response("hallo")]);
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("vary_configured"), fetcher)
+                    .storage(storage)
+                    .cache_policy(CachePolicy::CacheFirst)
+                    .vary_on(&["Accept-Language"])
+                    .build();
+
+            let english = downloader.download_with(url, &language("en")).unwrap();
+
+            // Act
+
+            let german = downloader.download_with(url, &language("de")).unwrap();
+
+            let cached = downloader.download_with(url, &language("en")).unwrap();
+
+            // Assert
+
+            assert_ne!(english.file, german.file);
+            assert_eq!(english.bytes().unwrap(), b"hello");
+            assert_eq!(german.bytes().unwrap(), b"hallo");
+            assert_eq!(cached.file, english.file);
+            assert_eq!(downloader.fetcher().calls(), 2);
+        }
+    }
+
+    #[test]
+    fn test_vary_response_header_is_learned() {
+        for storage in storages("vary_learned") {
+            let url = "https://example.com/greeting.png";
+
+            let fetcher = MockFetcher::new(vec![
+                png_response("hello").with_header("Vary", "Accept-Encoding, Accept-Language"),
+                png_response("hallo").with_header("Vary", "Accept-Encoding, Accept-Language"),
+            ]);
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("vary_learned"), fetcher)
+                    .storage(storage)
+                    .cache_policy(CachePolicy::CacheFirst)
+                    .learn_vary(true)
+                    .build();
+
+            let english = downloader.download_with(url, &language("en")).unwrap();
+
+            // Act
+
+            let german = downloader.download_with(url, &language("de")).unwrap();
+
+            let cached = downloader.download_with(url, &language("de")).unwrap();
+
+            // Assert
+
+            assert_ne!(english.file, german.file);
+            assert_eq!(german.bytes().unwrap(), b"hallo");
+            assert_eq!(cached.file, german.file);
+            assert_eq!(downloader.fetcher().calls(), 2);
+        }
+    }
+
+    #[test]
+    fn test_max_age_overrides_ttl() {
+        for storage in storages("max_age") {
+            let url = "https://example.com/max-age.png";
+
+            let clock = FakeClock::new();
+
+            let fetcher = MockFetcher::new(vec![
+                png_response("v1").with_header("Cache-Control", "public, max-age=60"),
+                png_response("v2"),
+                png_response("v3"),
+            ]);
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("max_age"), fetcher)
+                    .storage(storage)
+                    .clock(clock.clone())
+                    .cache_policy(CachePolicy::CacheFirst)
+                    .ttl(Duration::from_secs(10))
+                    .build();
+
+            downloader.download(url).unwrap();
+
+            // Act & Assert: max-age keeps the entry fresh beyond the TTL
+
+            clock.advance(Duration::from_secs(30));
+            downloader.download(url).unwrap();
+            assert_eq!(downloader.fetcher().calls(), 1);
+
+            clock.advance(Duration::from_secs(31));
+            let download = downloader.download(url).unwrap();
+            assert_eq!(downloader.fetcher().calls(), 2);
+            assert_eq!(download.bytes().unwrap(), b"v2");
+
+            // Without directives the configured TTL applies
+
+            clock.advance(Duration::from_secs(5));
+            downloader.download(url).unwrap();
+            assert_eq!(downloader.fetcher().calls(), 2);
+
+            clock.advance(Duration::from_secs(6));
+            let download = downloader.download(url).unwrap();
+            assert_eq!(downloader.fetcher().calls(), 3);
+            assert_eq!(download.bytes().unwrap(), b"v3");
+        }
+    }
+
+    #[test]
+    fn test_copied_cache_keeps_its_fetch_time() {
+        let url = "https://example.com/copied.png";
+
+        let clock = FakeClock::new();
+
+        let dir = testing::cache_dir("copied_cache");
+
+        let build = |responses| {
+            DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(responses))
+                .clock(clock.clone())
+                .cache_policy(CachePolicy::CacheFirst)
+                .ttl(Duration::from_secs(60))
+                .write_sidecars(true)
+                .preserve_mtime(true)
+                .build()
+        };
+
+        build(vec![png_response("v1").with_header(
+            "Last-Modified",
+            "Wed, 21 Oct 2015 07:28:00 GMT",
+        )])
+        .download(url)
+        .unwrap();
+
+        fs::remove_file(dir.join("manifest.json")).unwrap();
+
+        let copied = build(vec![png_response("v2")]);
+
+        // Act
+
+        clock.advance(Duration::from_secs(30));
+
+        let fresh = copied.download(url).unwrap().bytes().unwrap();
+
+        clock.advance(Duration::from_secs(31));
+
+        let stale = copied.download(url).unwrap().bytes().unwrap();
+
+        // Assert
+
+        assert_eq!(fresh, b"v1");
+        assert_eq!(stale, b"v2");
+        assert_eq!(copied.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_no_store_is_never_served_from_cache() {
+        for storage in storages("no_store") {
+            let url = "https://example.com/no-store.png";
+
+            let fetcher = MockFetcher::new(vec![
+                png_response("v1").with_header("Cache-Control", "no-store"),
+                png_response("v2"),
+            ]);
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("no_store"), fetcher)
+                    .storage(storage)
+                    .cache_policy(CachePolicy::CacheFirst)
+                    .build();
+
+            // Act
+
+            downloader.download(url).unwrap();
+
+            let download = downloader.download(url).unwrap();
+
+            // Assert
+
+            assert_eq!(downloader.fetcher().calls(), 2);
+            assert_eq!(download.bytes().unwrap(), b"v2");
+        }
+    }
+
+    #[test]
+    fn test_no_store_responses_keep_nothing_beyond_the_file() {
+        let url = "https://example.com/no-store.png";
+
+        let fetcher = MockFetcher::new(vec![png_response("v1")
+            .with_header("Cache-Control", "no-store")
+            .with_header("ETag", "\"v1\"")]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("no_store_kept"), fetcher)
+                .write_sidecars(true)
+                .build();
+
+        // Act
+
+        let download = downloader.download(url).unwrap();
+
+        // Assert
+
+        let entry = downloader
+            .manifest
+            .get(downloader.entry_key(url).as_str())
+            .unwrap();
+
+        assert!(entry.no_store);
+        assert_eq!(entry.etag, None);
+        assert_eq!(entry.sha256, None);
+        assert_eq!(download.bytes().unwrap(), b"v1");
+        assert!(!sidecar::sidecar_path(&download.file).exists());
+    }
+
+    #[test]
+    fn test_no_cache_revalidates_on_every_access() {
+        for storage in storages("no_cache") {
+            let url = "https://example.com/no-cache.png";
+
+            let fetcher = MockFetcher::new(vec![
+                png_response("v1")
+                    .with_header("Cache-Control", "no-cache")
+                    .with_header("ETag", "\"v1\""),
+                Response::not_modified().with_header("Cache-Control", "no-cache"),
+                Response::not_modified(),
+            ]);
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("no_cache"), fetcher)
+                    .storage(storage)
+                    .cache_policy(CachePolicy::StaleWhileRevalidate)
+                    .build();
+
+            let first = downloader.download(url).unwrap();
+
+            // Act
+
+            let second = downloader.download(url).unwrap();
+
+            let third = downloader.download(url).unwrap();
+
+            // Assert
+
+            let conditional = vec![("If-None-Match".to_string(), "\"v1\"".to_string())];
+
+            assert_eq!(downloader.fetcher().calls(), 3);
+            assert_eq!(downloader.fetcher().request_headers(0), vec![]);
+            assert_eq!(downloader.fetcher().request_headers(1), conditional);
+            assert_eq!(downloader.fetcher().request_headers(2), conditional);
+            assert_eq!(second, first);
+            assert_eq!(third, first);
+            assert_eq!(third.bytes().unwrap(), b"v1");
+        }
+    }
+
+    #[test]
+    fn test_target_path_matches_download() {
+        for storage in storages("target_path") {
+            let named = "https://example.com/logo.PNG?size=2";
+
+            let unnamed = "https://example.com/photos/";
+
+            let fetcher = MockFetcher::new(vec![png_response("logo"), png_response("photos")]);
+
+            let dir = testing::cache_dir("target_path");
+
+            let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher)
+                .storage(storage)
+                .build();
+
+            // Act
+
+            let before = (
+                downloader.target_path_for(named).unwrap(),
+                downloader.target_path_for(unnamed).unwrap(),
+            );
+
+            let downloads = (
+                downloader.download(named).unwrap(),
+                downloader.download(unnamed).unwrap(),
+            );
+
+            // Assert
+
+            assert_eq!(downloader.path(), fs::canonicalize(&dir).unwrap());
+            assert_eq!(before.0, downloads.0.file);
+            assert_eq!(before.1.extension().unwrap(), "dat");
+            assert_eq!(
+                downloader.target_path_for(unnamed).unwrap(),
+                downloads.1.file
+            );
+            assert_eq!(
+                downloader.target_path_for("not a url"),
+                Err(DownloadError::InvalidUrl(UrlProblem::RelativeUrl))
+            );
+        }
+    }
+
+    #[test]
+    fn test_lookups_list_the_storage_once() {
+        let dir = testing::cache_dir("lookup_listing");
+
+        let url = |name: &str| format!("https://example.com/{name}.png");
+
+        let writer = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![png_response("a"), png_response("b")]),
+        )
+        .build();
+
+        let unrecorded = writer.download(&url("a")).unwrap();
+
+        let removed = writer.download(&url("b")).unwrap();
+
+        writer.flush_maintenance();
+
+        fs::remove_file(dir.join("manifest.json")).unwrap();
+
+        let lists = Arc::new(AtomicUsize::new(0));
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![png_response("c")]))
+                .cache_policy(CachePolicy::CacheFirst)
+                .storage(Listed {
+                    inner: FsStorage::new(&dir),
+                    lists: Arc::clone(&lists),
+                })
+                .build();
+
+        // Act
+
+        let found = downloader.cached(url("a"));
+
+        let listed = lists.load(Ordering::Relaxed);
+
+        fs::remove_file(&removed.file).unwrap();
+
+        let recorded = downloader.download(&url("c")).unwrap();
+
+        let written = lists.load(Ordering::Relaxed);
+
+        let probes = (0..10)
+            .filter(|_| downloader.is_cached(url("c")) && !downloader.is_cached(url("d")))
+            .count();
+
+        // Assert
+
+        assert_eq!(found.map(|found| found.file), Some(unrecorded.file));
+        assert!(!downloader.is_cached(url("b")));
+        assert_eq!(downloader.cached(url("c")), Some(recorded));
+        assert_eq!(probes, 10);
+        assert_eq!(listed, 1);
+        assert_eq!(
+            lists.load(Ordering::Relaxed),
+            written,
+            "lookups do not list"
+        );
+    }
+
+    #[test]
+    fn test_cached_probe_agrees_with_cache_hits() {
+        for storage in storages("cached_probe") {
+            let url = "https://example.com/logo.png";
+
+            let fetcher = MockFetcher::new(vec![png_response("logo")]);
+
+            let dir = testing::cache_dir("cached_probe");
+
+            let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher)
+                .storage(storage)
+                .cache_policy(CachePolicy::CacheFirst)
+                .build();
+
+            let download = downloader.download(url).unwrap();
+
+            // A leftover of the same key that sorts before the entry.
+            let stem = download.file.file_stem().unwrap().to_string_lossy();
+
+            fs::write(dir.join(format!("{stem}.bmp")), "stale").unwrap();
+
+            // Act
+
+            let probed = downloader.cached(url);
+
+            let hit = downloader.download_checked(url).unwrap();
+
+            // Assert
+
+            assert!(downloader.is_cached(url));
+            assert!(!downloader.is_cached("https://example.com/other.png"));
+            assert_eq!(downloader.cached("not a url"), None);
+            assert_eq!(probed.as_ref(), Some(&download));
+            assert_eq!(hit, Outcome::CacheHit(download));
+            assert_eq!(downloader.fetcher().calls(), 1);
+        }
+    }
+
+    #[test]
+    fn test_overstated_content_length_is_truncated() {
+        for storage in storages("preallocate") {
+            let body = vec![7; 100_000];
+
+            let fetcher = MockFetcher::new(vec![
+                Response::ok(body.clone(), None).with_header("Content-Length", "1000000")
+            ]);
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("preallocate"), fetcher)
+                    .storage(storage)
+                    .build();
+
+            // Act
+
+            let download = downloader
+                .download("https://example.com/large.bin")
+                .unwrap();
+
+            // Assert
+
+            assert_eq!(download.len().unwrap(), 100_000);
+            assert_eq!(download.metadata.size, Some(100_000));
+            assert_eq!(download.bytes().unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn test_preserve_mtime_uses_last_modified() {
+        let fetcher = MockFetcher::new(vec![
+            png_response("v1").with_header("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            png_response("v2"),
+        ]);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("mtime"), fetcher)
+            .preserve_mtime(true)
+            .build();
+
+        // Act
+
+        let dated = downloader
+            .download("https://example.com/dated.png")
+            .unwrap();
+
+        let undated = downloader
+            .download("https://example.com/undated.png")
+            .unwrap();
+
+        // Assert
+
+        let modified = |file| fs::metadata(file).unwrap().modified().unwrap();
+
+        assert_eq!(
+            modified(&dated.file),
+            UNIX_EPOCH + Duration::from_secs(1_445_412_480)
+        );
+        assert!(modified(&undated.file) > UNIX_EPOCH + Duration::from_secs(1_445_412_480));
+    }
+
+    fn listing(dir: &std::path::Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+
+        names.sort();
+
+        names
+    }
+
+    #[test]
+    fn test_read_only_cache_serves_hits_and_never_writes() {
+        let warm = "https://example.com/warm.png";
+
+        let dir = testing::cache_dir("read_only");
+
+        let overlay = testing::cache_dir("read_only_overlay");
+
+        let writer =
+            DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![png_response("v1")]))
+                .build();
+
+        let cached = writer.download(warm).unwrap();
+
+        writer.flush_maintenance();
+
+        // Checked by listing the directory, permissions not binding root.
+        let before = listing(&dir);
+
+        let missing = testing::cache_dir("read_only_missing");
+
+        let strict = DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![]))
+            .read_only(true)
+            .build();
+
+        let layered =
+            DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![png_response("v2")]))
+                .read_only(true)
+                .overlay(&overlay)
+                .cache_policy(CachePolicy::CacheFirst)
+                .build();
+
+        // Act
+
+        let hit = strict.download_checked(warm).unwrap();
+
+        let miss = strict.download("https://example.com/cold.png");
+
+        let fetched = layered.download("https://example.com/cold.png").unwrap();
+
+        let refetched = layered
+            .download_checked("https://example.com/cold.png")
+            .unwrap();
+
+        let unbuilt = DownloaderBuilder::with_fetcher(&missing, MockFetcher::new(vec![]))
+            .read_only(true)
+            .build();
+
+        // Assert
+
+        assert!(matches!(hit, Outcome::CacheHit(download) if download == cached));
+        assert_eq!(miss.unwrap_err(), DownloadError::NotCached);
+        assert!(fetched
+            .file
+            .starts_with(fs::canonicalize(&overlay).unwrap()));
+        assert!(matches!(refetched, Outcome::CacheHit(_)));
+        assert_eq!(strict.fetcher().calls(), 0);
+        assert_eq!(layered.fetcher().calls(), 1);
+        assert_eq!(listing(&dir), before);
+        assert!(!missing.exists(), "{:?}", unbuilt.path());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_mode_ignores_umask() {
+        use std::os::unix::fs::PermissionsExt;
+
+        for mode in [0o644, 0o600] {
+            let fetcher = MockFetcher::new(vec![png_response("v1")]);
+
+            let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("mode"), fetcher)
+                .file_mode(mode)
+                .build();
+
+            // Act
+
+            let download = downloader.download("https://example.com/mode.png").unwrap();
+
+            // Assert
+
+            let permissions = fs::metadata(&download.file).unwrap().permissions();
+
+            assert_eq!(permissions.mode() & 0o777, mode);
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Mismatches(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl Observer for Mismatches {
+        fn on_content_mismatch(&self, _url: &str, declared: &str, detected: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((declared.to_string(), detected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_mislabelled_bodies_follow_their_signature() {
+        for storage in storages("mislabelled") {
+            let mismatches = Mismatches::default();
+
+            let downloader = DownloaderBuilder::with_fetcher(
+                testing::cache_dir("mislabelled"),
+                MockFetcher::new(vec![
+                    Response::ok(fixtures::JPEG.to_vec(), Some("image/png".to_string())),
+                    Response::ok(fixtures::PNG.to_vec(), Some("image/jpeg".to_string())),
+                    Response::ok(fixtures::PNG.to_vec(), Some("image/png".to_string())),
+                ]),
+            )
+            .storage(storage)
+            .observer(mismatches.clone())
+            .build();
+
+            // Act
+
+            let jpeg = downloader.download("https://example.com/a.png").unwrap();
+
+            let png = downloader.download("https://example.com/b.jpg").unwrap();
+
+            let honest = downloader.download("https://example.com/c.png").unwrap();
+
+            // Assert
+
+            assert_eq!(jpeg.file.extension().unwrap(), "jpeg");
+            assert_eq!(jpeg.mime(), Some("image/jpeg"));
+            assert_eq!(jpeg.metadata.declared_mime.as_deref(), Some("image/png"));
+            assert_eq!(jpeg.metadata.detected_format.as_deref(), Some("image/jpeg"));
+            assert_eq!(png.file.extension().unwrap(), "png");
+            assert_eq!(png.metadata.declared_mime.as_deref(), Some("image/jpeg"));
+            assert_eq!(honest.metadata.declared_mime, None);
+            assert_eq!(honest.metadata.detected_format, None);
+            assert_eq!(
+                *mismatches.0.lock().unwrap(),
+                [
+                    ("image/png".to_string(), "image/jpeg".to_string()),
+                    ("image/jpeg".to_string(), "image/png".to_string())
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_declared_types_can_keep_priority() {
+        for storage in storages("mislabelled_declared") {
+            let downloader = DownloaderBuilder::with_fetcher(
+                testing::cache_dir("mislabelled_declared"),
+                MockFetcher::new(vec![Response::ok(
+                    fixtures::JPEG.to_vec(),
+                    Some("image/png".to_string()),
+                )]),
+            )
+            .storage(storage)
+            .prefer_declared_mime(true)
+            .build();
+
+            // Act
+
+            let download = downloader.download("https://example.com/a.png").unwrap();
+
+            // Assert
+
+            assert_eq!(download.file.extension().unwrap(), "png");
+            assert_eq!(download.mime(), Some("image/png"));
+            assert_eq!(
+                download.metadata.declared_mime.as_deref(),
+                Some("image/png")
+            );
+            assert_eq!(
+                download.metadata.detected_format.as_deref(),
+                Some("image/jpeg")
+            );
+        }
+    }
+}
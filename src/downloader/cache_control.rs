@@ -0,0 +1,177 @@
+use std::time::{Duration, SystemTime};
+
+use super::headers;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CacheDirectives {
+    pub max_age: Option<u64>,
+    pub no_store: bool,
+    pub no_cache: bool,
+}
+
+impl CacheDirectives {
+    pub fn parse(header: &str) -> Self {
+        header
+            .split(',')
+            .map(str::trim)
+            .filter(|directive| !directive.is_empty())
+            .fold(Self::default(), |mut directives, directive| {
+                let (name, value) = match directive.split_once('=') {
+                    Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                    None => (directive, None),
+                };
+
+                match name.to_ascii_lowercase().as_str() {
+                    "no-store" => directives.no_store = true,
+                    "no-cache" => directives.no_cache = true,
+                    "max-age" => {
+                        if let Some(max_age) = value.and_then(|value| value.parse().ok()) {
+                            directives.max_age = Some(max_age);
+                        }
+                    }
+                    _ => {}
+                }
+
+                directives
+            })
+    }
+
+    pub fn from_headers(headers: &[(String, String)]) -> Self {
+        headers::find(headers, "Cache-Control")
+            .map(Self::parse)
+            .unwrap_or_default()
+    }
+}
+
+// `max-age` takes precedence over `Expires`. An `Expires` value that is not a
+// valid HTTP date means "already expired". The lifetime is measured on the
+// server's clock: `Expires` counts from `Date`, and the time the response
+// already spent in caches (`Age`) is used up, so a skewed local clock does
+// not move the result. Only an `Expires` without `Date` is taken as is.
+pub(crate) fn expires_at(
+    headers: &[(String, String)],
+    fetched_at: SystemTime,
+) -> Option<SystemTime> {
+    let directives = CacheDirectives::from_headers(headers);
+
+    let http_date =
+        |name| headers::find(headers, name).map(|value| httpdate::parse_http_date(value).ok());
+
+    let lifetime = match directives.max_age {
+        Some(max_age) => Duration::from_secs(max_age),
+        None => match (http_date("Expires")?, http_date("Date").flatten()) {
+            (None, _) => return Some(SystemTime::UNIX_EPOCH),
+            (Some(expires), None) => return Some(expires),
+            (Some(expires), Some(date)) => expires.duration_since(date).unwrap_or_default(),
+        },
+    };
+
+    let age = headers::find(headers, "Age")
+        .and_then(|age| age.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default();
+
+    Some(fetched_at + lifetime.saturating_sub(age))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{expires_at, CacheDirectives};
+
+    #[test]
+    fn test_parse_directives() {
+        let cases = [
+            ("max-age=60", Some(60), false, false),
+            ("public, max-age=3600", Some(3600), false, false),
+            ("max-age=0", Some(0), false, false),
+            ("MAX-AGE = 10", Some(10), false, false),
+            ("max-age=\"120\"", Some(120), false, false),
+            ("no-store", None, true, false),
+            ("no-cache", None, false, true),
+            ("no-cache, max-age=60", Some(60), false, true),
+            ("private, no-store, no-cache", None, true, true),
+            ("max-age=abc", None, false, false),
+            ("max-age=-5", None, false, false),
+            ("max-age", None, false, false),
+            (",,", None, false, false),
+            ("", None, false, false),
+        ];
+
+        for (header, max_age, no_store, no_cache) in cases {
+            let directives = CacheDirectives::parse(header);
+
+            assert_eq!(
+                directives,
+                CacheDirectives {
+                    max_age,
+                    no_store,
+                    no_cache
+                },
+                "Cache-Control: {header}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_expires_at() {
+        let fetched_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let expires = httpdate::fmt_http_date(fetched_at + Duration::from_secs(30));
+
+        let header = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+        let cases = [
+            (vec![], None),
+            (
+                vec![header("Cache-Control", "max-age=60")],
+                Some(fetched_at + Duration::from_secs(60)),
+            ),
+            (
+                vec![header("Expires", &expires)],
+                Some(fetched_at + Duration::from_secs(30)),
+            ),
+            (
+                vec![
+                    header("Cache-Control", "max-age=0"),
+                    header("Expires", &expires),
+                ],
+                Some(fetched_at),
+            ),
+            (vec![header("Expires", "0")], Some(SystemTime::UNIX_EPOCH)),
+            (
+                vec![
+                    header(
+                        "Date",
+                        &httpdate::fmt_http_date(fetched_at - Duration::from_secs(3600)),
+                    ),
+                    header(
+                        "Expires",
+                        &httpdate::fmt_http_date(fetched_at - Duration::from_secs(3570)),
+                    ),
+                ],
+                Some(fetched_at + Duration::from_secs(30)),
+            ),
+            (
+                vec![header("Cache-Control", "max-age=60"), header("Age", "20")],
+                Some(fetched_at + Duration::from_secs(40)),
+            ),
+            (
+                vec![header("Cache-Control", "max-age=60"), header("Age", "90")],
+                Some(fetched_at),
+            ),
+            (
+                vec![
+                    header("Cache-Control", "max-age=oops"),
+                    header("Expires", &expires),
+                ],
+                Some(fetched_at + Duration::from_secs(30)),
+            ),
+        ];
+
+        for (headers, expected) in cases {
+            assert_eq!(expires_at(&headers, fetched_at), expected, "{headers:?}");
+        }
+    }
+}
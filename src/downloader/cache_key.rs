@@ -0,0 +1,321 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh64::xxh64;
+
+// A 256-bit digest has at most 78 decimal digits.
+const MAX_LEN: usize = 78;
+
+const DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// How entry names are derived from URLs. Changing it on an existing cache
+// directory leaves the entries stored under the old names unreachable.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgo {
+    // The standard library's SipHash, what caches have always been named by.
+    #[default]
+    DefaultHasher,
+    XxHash64,
+    Sha256,
+    // The first bytes of the SHA-256 digest, between 1 and 32 of them.
+    Sha256Truncated(usize),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEncoding {
+    #[default]
+    Decimal,
+    Hex,
+    Base36,
+    // Mixes cases, so two names may collide on case-insensitive file systems.
+    Base64Url,
+}
+
+// The encoded url hash, kept on the stack because it is computed at least
+// once for every download and cache lookup.
+#[derive(Clone, Copy)]
+pub(crate) struct CacheKey {
+    chars: [u8; MAX_LEN],
+    len: usize,
+}
+
+impl CacheKey {
+    #[cfg(test)]
+    pub fn from_url(url: &str) -> Self {
+        Self::new(url, HashAlgo::default(), KeyEncoding::default())
+    }
+
+    pub fn new(url: &str, algo: HashAlgo, encoding: KeyEncoding) -> Self {
+        let mut digest = [0; 32];
+
+        let len = match algo {
+            HashAlgo::DefaultHasher => {
+                let mut hasher = DefaultHasher::new();
+                url.hash(&mut hasher);
+
+                digest[..8].copy_from_slice(&hasher.finish().to_be_bytes());
+                8
+            }
+            HashAlgo::XxHash64 => {
+                digest[..8].copy_from_slice(&xxh64(url.as_bytes(), 0).to_be_bytes());
+                8
+            }
+            HashAlgo::Sha256 => {
+                digest.copy_from_slice(&Sha256::digest(url.as_bytes()));
+                32
+            }
+            HashAlgo::Sha256Truncated(len) => {
+                digest.copy_from_slice(&Sha256::digest(url.as_bytes()));
+                len.clamp(1, 32)
+            }
+        };
+
+        let mut key = Self {
+            chars: [0; MAX_LEN],
+            len: 0,
+        };
+
+        let digest = &digest[..len];
+
+        match encoding {
+            KeyEncoding::Decimal => key.push_radix(digest, 10),
+            KeyEncoding::Base36 => key.push_radix(digest, 36),
+            KeyEncoding::Hex => {
+                for byte in digest {
+                    key.push(DIGITS[(byte >> 4) as usize]);
+                    key.push(DIGITS[(byte & 0xf) as usize]);
+                }
+            }
+            KeyEncoding::Base64Url => {
+                for chunk in digest.chunks(3) {
+                    let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| {
+                        bits | (*byte as u32) << (16 - 8 * index)
+                    });
+
+                    for index in 0..=chunk.len() {
+                        key.push(BASE64_URL[(bits >> (18 - 6 * index) & 0x3f) as usize]);
+                    }
+                }
+            }
+        }
+
+        key
+    }
+
+    // A key a `CacheKeyer` chose, kept when it is a valid name.
+    pub fn from_key(key: &str, algo: HashAlgo, encoding: KeyEncoding) -> Self {
+        if !Self::is_well_formed(key, KeyEncoding::Base64Url) {
+            return Self::new(key, algo, encoding);
+        }
+
+        let mut chars = [0; MAX_LEN];
+
+        chars[..key.len()].copy_from_slice(key.as_bytes());
+
+        Self {
+            chars,
+            len: key.len(),
+        }
+    }
+
+    // Whether `name` could be a key in `encoding`, without knowing its URL.
+    pub fn is_well_formed(name: &str, encoding: KeyEncoding) -> bool {
+        let alphabet: &[u8] = match encoding {
+            KeyEncoding::Decimal => &DIGITS[..10],
+            KeyEncoding::Hex => &DIGITS[..16],
+            KeyEncoding::Base36 => DIGITS,
+            KeyEncoding::Base64Url => BASE64_URL,
+        };
+
+        (1..=MAX_LEN).contains(&name.len()) && name.bytes().all(|byte| alphabet.contains(&byte))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII is ever written into the buffer.
+        std::str::from_utf8(&self.chars[..self.len]).unwrap()
+    }
+
+    fn push(&mut self, char: u8) {
+        self.chars[self.len] = char;
+        self.len += 1;
+    }
+
+    // The digest as a big-endian number, without leading zeros.
+    fn push_radix(&mut self, digest: &[u8], radix: u32) {
+        let mut number = [0; 32];
+
+        let number = &mut number[..digest.len()];
+
+        number.copy_from_slice(digest);
+
+        loop {
+            let mut remainder = 0;
+
+            for digit in number.iter_mut() {
+                let value = remainder << 8 | *digit as u32;
+
+                *digit = (value / radix) as u8;
+                remainder = value % radix;
+            }
+
+            self.push(DIGITS[remainder as usize]);
+
+            if number.iter().all(|digit| *digit == 0) {
+                break;
+            }
+        }
+
+        self.chars[..self.len].reverse();
+    }
+}
+
+impl Deref for CacheKey {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    use super::{CacheKey, HashAlgo, KeyEncoding};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, CachePolicy, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    #[test]
+    fn test_key_matches_decimal_hash() {
+        let mut hasher = DefaultHasher::new();
+        URL.hash(&mut hasher);
+
+        // Act
+
+        let key = CacheKey::from_url(URL);
+
+        // Assert
+
+        assert_eq!(key.as_str(), hasher.finish().to_string());
+    }
+
+    #[test]
+    fn test_golden_keys() {
+        let key = |algo, encoding| CacheKey::new(URL, algo, encoding).to_string();
+
+        // Assert
+
+        assert_eq!(
+            key(HashAlgo::XxHash64, KeyEncoding::Decimal),
+            "17586115648827365903"
+        );
+        assert_eq!(
+            key(HashAlgo::XxHash64, KeyEncoding::Hex),
+            "f40e6f01e34b1a0f"
+        );
+        assert_eq!(
+            key(HashAlgo::XxHash64, KeyEncoding::Base36),
+            "3plzxppvxdlan"
+        );
+        assert_eq!(
+            key(HashAlgo::XxHash64, KeyEncoding::Base64Url),
+            "9A5vAeNLGg8"
+        );
+        assert_eq!(
+            key(HashAlgo::Sha256, KeyEncoding::Hex),
+            "adc2cf2dbcde0df0a108066c5f6f973453801b181e7fa92d392540a099f1fd8d"
+        );
+        assert_eq!(
+            key(HashAlgo::Sha256, KeyEncoding::Base36),
+            "4bwq1cys5pu9ozuvvr3byqrftc3um8yo8w6tinp51qal66luvh"
+        );
+        assert_eq!(
+            key(HashAlgo::Sha256, KeyEncoding::Base64Url),
+            "rcLPLbzeDfChCAZsX2-XNFOAGxgef6ktOSVAoJnx_Y0"
+        );
+        assert_eq!(
+            key(HashAlgo::Sha256, KeyEncoding::Decimal),
+            "78594321030053536154274576241045324134719642787134167236257233740573214637453"
+        );
+        assert_eq!(
+            key(HashAlgo::Sha256Truncated(8), KeyEncoding::Hex),
+            "adc2cf2dbcde0df0"
+        );
+        assert_eq!(key(HashAlgo::Sha256Truncated(0), KeyEncoding::Hex), "ad");
+    }
+
+    #[test]
+    fn test_key_does_not_allocate() {
+        // Act
+
+        let allocations = testing::count_allocations(|| {
+            let key = CacheKey::from_url(URL);
+            assert!(!key.is_empty());
+
+            let key = CacheKey::new(URL, HashAlgo::Sha256, KeyEncoding::Base36);
+            assert!(!key.is_empty());
+        });
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("cache_key_allocations"),
+            MockFetcher::new(Vec::new()),
+        )
+        .build();
+
+        let lookups = testing::count_allocations(|| {
+            let key = downloader.entry_key(URL);
+            assert!(!key.is_empty());
+        });
+
+        // Assert
+
+        assert_eq!(allocations, 0);
+        assert_eq!(lookups, 0);
+    }
+
+    #[test]
+    fn test_configured_scheme_names_and_finds_entries() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("cache_key_scheme"),
+            MockFetcher::new(vec![Response::ok(
+                b"image".to_vec(),
+                Some("image/png".to_string()),
+            )]),
+        )
+        .hash_algo(HashAlgo::XxHash64)
+        .key_encoding(KeyEncoding::Base36)
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let second = downloader.download(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(first.file.file_name().unwrap(), "3plzxppvxdlan.png");
+        assert_eq!(second, first);
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+}
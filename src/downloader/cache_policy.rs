@@ -0,0 +1,14 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    // Always fetch and overwrite the cached entry.
+    #[default]
+    NetworkOnly,
+    // Serve a cached entry while it is within the TTL, fetch otherwise.
+    CacheFirst,
+    // Serve any cached entry immediately and refresh expired ones in the
+    // background so the next call gets the fresh bytes.
+    StaleWhileRevalidate,
+    // Always fetch, but when the network or the server fails serve the
+    // cached entry instead, however old, as `Outcome::Stale`.
+    NetworkFirstFallbackStale,
+}
@@ -0,0 +1,104 @@
+use super::{cache::CachedEntry, Download, Downloader, FileDownloader, Storage};
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // An entry holding exactly these bytes, whichever URL they came from.
+    // `sha256` is the hex digest, in either case. Entries whose file was
+    // removed behind the cache's back are dropped from the index.
+    pub fn find_by_checksum(&self, sha256: &str) -> Option<Download> {
+        let sha256 = sha256.to_ascii_lowercase();
+
+        for key in self.manifest.keys_with_digest(&sha256) {
+            let Some(meta) = self.manifest.get(&key) else {
+                continue;
+            };
+
+            if meta.sha256.as_deref() != Some(sha256.as_str()) || meta.no_store {
+                continue;
+            }
+
+            if meta.file.is_empty() || !self.storage.exists(&meta.file) {
+                self.manifest.remove_digest(&sha256, &key);
+
+                continue;
+            }
+
+            // Entries adopted without a sidecar have no known source.
+            let source = meta.url.clone();
+
+            let file = self.locate(&meta.file);
+
+            let entry = CachedEntry {
+                backing: self.backing(&file),
+                file,
+                meta: Some(meta),
+            };
+
+            return Some(self.with_remote_url(entry.download_from(&source)));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::downloader::{
+        fetcher::MockFetcher, fixtures, tee, testing, CachePolicy, DownloaderBuilder, Response,
+    };
+
+    #[test]
+    fn test_entries_are_found_by_their_bytes() {
+        let png = || Response::ok(fixtures::PNG.to_vec(), Some("image/png".to_string()));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("checksum_lookup"),
+            MockFetcher::new(vec![png(), Response::ok(b"other".to_vec(), None), png()]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        let sha256 = tee::BodySummary::of(fixtures::PNG).sha256;
+
+        let first = downloader.download("https://example.com/a.png").unwrap();
+
+        downloader.download("https://example.com/other").unwrap();
+
+        let mirror = downloader
+            .download("https://mirror.example.com/a.png")
+            .unwrap();
+
+        // Act
+
+        let hit = downloader
+            .find_by_checksum(&sha256.to_ascii_uppercase())
+            .unwrap();
+
+        let hit_bytes = hit.bytes().unwrap();
+
+        let miss = downloader.find_by_checksum(&tee::BodySummary::of(b"absent").sha256);
+
+        fs::remove_file(&first.file).unwrap();
+
+        let after_first_removed = downloader.find_by_checksum(&sha256);
+
+        fs::remove_file(&mirror.file).unwrap();
+
+        let after_both_removed = downloader.find_by_checksum(&sha256);
+
+        // Assert
+
+        assert!([&first.file, &mirror.file].contains(&&hit.file));
+        assert_eq!(hit_bytes, fixtures::PNG);
+        assert_eq!(hit.metadata.sha256.as_deref(), Some(sha256.as_str()));
+        assert_eq!(miss, None);
+        assert_eq!(after_first_removed.unwrap().file, mirror.file);
+        assert_eq!(after_both_removed, None);
+        assert!(downloader.manifest.keys_with_digest(&sha256).is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{tee::TeeWriter, Downloader, FileDownloader, Storage};
+
+// What `verify_checksums` found. Paths are in the order the file lists them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ChecksumReport {
+    pub verified: Vec<PathBuf>,
+    pub mismatched: Vec<PathBuf>,
+    pub missing: Vec<PathBuf>,
+    // Lines that do not list a SHA-256, numbered from 1.
+    pub malformed: Vec<usize>,
+}
+
+impl ChecksumReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.malformed.is_empty()
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Writes a `SHA256SUMS` file that `sha256sum -c` accepts when run from
+    // the cache directory, one line per stored file, sorted by name. Digests
+    // the manifest lacks are computed. Returns how many files it lists.
+    pub fn write_checksums(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let names: BTreeSet<_> = self
+            .manifest
+            .entries()
+            .into_iter()
+            .filter(|(_, entry)| !entry.file.is_empty() && !entry.no_store)
+            .map(|(_, entry)| (entry.file, entry.sha256))
+            .collect();
+
+        let mut content = Vec::new();
+
+        let mut listed = 0;
+
+        for (name, sha256) in names {
+            if !self.storage.exists(&name) {
+                continue;
+            }
+
+            let sha256 = match sha256 {
+                Some(sha256) => sha256,
+                None => self.digest_of(&name)?,
+            };
+
+            content.extend(format_line(&sha256, name.as_bytes()));
+
+            listed += 1;
+        }
+
+        fs::write(path, content)?;
+
+        Ok(listed)
+    }
+
+    // Checks the stored files against a `SHA256SUMS` file, such as one
+    // `write_checksums` wrote. Names are relative to the cache directory.
+    pub fn verify_checksums(&self, path: impl AsRef<Path>) -> io::Result<ChecksumReport> {
+        let content = fs::read(path)?;
+
+        let mut report = ChecksumReport::default();
+
+        for (index, line) in content.split(|byte| *byte == b'\n').enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((expected, name)) = parse_line(line) else {
+                report.malformed.push(index + 1);
+
+                continue;
+            };
+
+            // Storage names are UTF-8, so no other name is stored.
+            let Some(name) = String::from_utf8(name.clone())
+                .ok()
+                .filter(|name| self.storage.exists(name))
+            else {
+                report.missing.push(self.path.join(path_from_bytes(&name)));
+
+                continue;
+            };
+
+            let file = self.locate(&name);
+
+            match self.digest_of(&name) {
+                Ok(actual) if actual.eq_ignore_ascii_case(&expected) => report.verified.push(file),
+                Ok(_) => report.mismatched.push(file),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => report.missing.push(file),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn digest_of(&self, name: &str) -> io::Result<String> {
+        let mut reader = self.storage.open(name)?;
+
+        let mut tee = TeeWriter::new(io::sink());
+
+        io::copy(&mut reader, &mut tee)?;
+
+        tee.flush()?;
+
+        Ok(tee.finish().1.sha256)
+    }
+}
+
+// `<hex>  <name>`. Like GNU sha256sum, a name holding a backslash, newline
+// or carriage return is escaped, and its line marked with a leading `\`.
+// Other bytes, spaces and invalid UTF-8 among them, are written as they are.
+pub(crate) fn format_line(sha256: &str, name: &[u8]) -> Vec<u8> {
+    let escaped = name
+        .iter()
+        .any(|byte| matches!(byte, b'\\' | b'\n' | b'\r'));
+
+    let mut line = Vec::with_capacity(sha256.len() + name.len() + 4);
+
+    if escaped {
+        line.push(b'\\');
+    }
+
+    line.extend_from_slice(sha256.as_bytes());
+    line.extend_from_slice(b"  ");
+
+    for byte in name {
+        match byte {
+            b'\\' => line.extend_from_slice(b"\\\\"),
+            b'\n' => line.extend_from_slice(b"\\n"),
+            b'\r' => line.extend_from_slice(b"\\r"),
+            byte => line.push(*byte),
+        }
+    }
+
+    line.push(b'\n');
+
+    line
+}
+
+// The digest and name of one line, without its newline, as `sha256sum -c`
+// reads them: the digest, a space, ` ` or `*` for the text or binary mode,
+// then the name. A trailing carriage return is dropped.
+pub(crate) fn parse_line(line: &[u8]) -> Option<(String, Vec<u8>)> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    let (escaped, line) = match line.strip_prefix(b"\\") {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    let (digest, rest) = line.split_at_checked(64)?;
+
+    if !digest.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    let name = match rest {
+        [b' ', b' ' | b'*', name @ ..] if !name.is_empty() => name,
+        _ => return None,
+    };
+
+    let name = match escaped {
+        true => unescape(name)?,
+        false => name.to_vec(),
+    };
+
+    Some((String::from_utf8(digest.to_vec()).ok()?, name))
+}
+
+fn unescape(name: &[u8]) -> Option<Vec<u8>> {
+    let mut unescaped = Vec::with_capacity(name.len());
+
+    let mut bytes = name.iter();
+
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\\' => unescaped.push(match bytes.next()? {
+                b'\\' => b'\\',
+                b'n' => b'\n',
+                b'r' => b'\r',
+                _ => return None,
+            }),
+            byte => unescaped.push(*byte),
+        }
+    }
+
+    Some(unescaped)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{format_line, parse_line, ChecksumReport};
+    use crate::downloader::{
+        fetcher::MockFetcher, fixtures, tee, testing, DownloaderBuilder, Response,
+    };
+
+    const EMPTY: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn test_lines_are_read_as_sha256sum_reads_them() {
+        let upper = EMPTY.to_ascii_uppercase();
+
+        let cases: [(Vec<u8>, Option<&[u8]>); 10] = [
+            (format!("{EMPTY}  a.png").into(), Some(b"a.png")),
+            (format!("{EMPTY} *a.png").into(), Some(b"a.png")),
+            (
+                format!("{EMPTY}  with  spaces .txt").into(),
+                Some(b"with  spaces .txt"),
+            ),
+            (format!("{upper}  a.png\r").into(), Some(b"a.png")),
+            (
+                format!("\\{EMPTY}  back\\\\slash").into(),
+                Some(b"back\\slash"),
+            ),
+            (
+                format!("\\{EMPTY}  two\\nlines").into(),
+                Some(b"two\nlines"),
+            ),
+            (
+                [format!("{EMPTY}  ").as_bytes(), &[0xff, 0xfe]].concat(),
+                Some(&[0xff, 0xfe]),
+            ),
+            (format!("{EMPTY} a.png").into(), None),
+            (format!("{}  a.png", &EMPTY[1..]).into(), None),
+            (format!("\\{EMPTY}  bad\\tescape").into(), None),
+        ];
+
+        for (line, expected) in cases {
+            // Act
+
+            let parsed = parse_line(&line);
+
+            // Assert
+
+            assert_eq!(
+                parsed.as_ref().map(|(_, name)| name.as_slice()),
+                expected,
+                "{:?}",
+                String::from_utf8_lossy(&line)
+            );
+        }
+    }
+
+    #[test]
+    fn test_names_round_trip_through_escaping() {
+        let names: [&[u8]; 5] = [
+            b"plain.png",
+            b"with space.png",
+            b"back\\slash",
+            b"line\nbreak\r",
+            &[b'x', 0xff, b'y'],
+        ];
+
+        for name in names {
+            // Act
+
+            let line = format_line(EMPTY, name);
+
+            // Assert
+
+            let (sha256, parsed) = parse_line(line.strip_suffix(b"\n").unwrap()).unwrap();
+
+            assert_eq!(sha256, EMPTY);
+            assert_eq!(parsed, name);
+            assert_eq!(line.iter().filter(|byte| **byte == b'\n').count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_checksum_files_round_trip() {
+        let dir = testing::cache_dir("checksum_file");
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![
+                Response::ok(fixtures::PNG.to_vec(), Some("image/png".to_string())),
+                Response::ok(b"notes".to_vec(), None),
+                Response::ok(b"gone".to_vec(), None),
+            ]),
+        )
+        .build();
+
+        let logo = downloader.download("https://example.com/logo.png").unwrap();
+        let notes = downloader.download("https://example.com/notes").unwrap();
+        let gone = downloader.download("https://example.com/gone").unwrap();
+
+        let sums = dir.with_extension("SHA256SUMS");
+
+        // Act
+
+        let listed = downloader.write_checksums(&sums).unwrap();
+
+        let intact = downloader.verify_checksums(&sums).unwrap();
+
+        fs::write(&notes.file, b"edited").unwrap();
+        fs::remove_file(&gone.file).unwrap();
+
+        let tampered = downloader.verify_checksums(&sums).unwrap();
+
+        // Assert
+
+        let content = fs::read_to_string(&sums).unwrap();
+
+        let line = format!(
+            "{}  {}\n",
+            tee::BodySummary::of(fixtures::PNG).sha256,
+            logo.file.file_name().unwrap().to_str().unwrap()
+        );
+
+        assert_eq!(listed, 3);
+        assert!(content.contains(&line));
+        assert!(intact.is_ok());
+        assert_eq!(intact.verified.len(), 3);
+        assert_eq!(
+            tampered,
+            ChecksumReport {
+                verified: vec![logo.file],
+                mismatched: vec![notes.file],
+                missing: vec![gone.file],
+                malformed: Vec::new(),
+            }
+        );
+    }
+}
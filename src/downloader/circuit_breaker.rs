@@ -0,0 +1,341 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircuitBreakerConfig {
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl CircuitBreakerConfig {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+        }
+    }
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(30))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    Closed,
+    Open { until: SystemTime },
+    HalfOpen { until: SystemTime },
+}
+
+#[derive(Debug)]
+struct HostCircuit {
+    consecutive_failures: u32,
+    state: CircuitState,
+}
+
+impl Default for HostCircuit {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: 0,
+            state: CircuitState::Closed,
+        }
+    }
+}
+
+// Hosts are tracked independently; a single mutex is enough since the
+// critical sections only touch a few integers.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    hosts: Mutex<HashMap<String, HostCircuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns `Err(retry_at)` when requests to `host` must fail fast, and
+    /// the probe of a half-open circuit when this request is it.
+    pub fn check<'a>(
+        &'a self,
+        host: &'a str,
+        now: SystemTime,
+    ) -> Result<Option<CircuitProbe<'a>>, SystemTime> {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        let Some(circuit) = hosts.get_mut(host) else {
+            return Ok(None);
+        };
+
+        match circuit.state {
+            CircuitState::Closed => Ok(None),
+
+            CircuitState::Open { until } if now >= until => {
+                circuit.state = CircuitState::HalfOpen { until };
+                Ok(Some(CircuitProbe {
+                    breaker: self,
+                    host,
+                    settled: false,
+                }))
+            }
+
+            CircuitState::Open { until } => Err(until),
+
+            // Only the probe that moved the circuit to half-open goes through.
+            CircuitState::HalfOpen { until } => Err(until),
+        }
+    }
+
+    pub fn record_success(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        hosts.remove(host);
+    }
+
+    pub fn record_failure(&self, host: &str, now: SystemTime) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        let circuit = hosts.entry(host.to_string()).or_default();
+
+        circuit.consecutive_failures += 1;
+
+        let half_open = matches!(circuit.state, CircuitState::HalfOpen { .. });
+
+        if half_open || circuit.consecutive_failures >= self.config.failure_threshold {
+            circuit.state = CircuitState::Open {
+                until: now + self.config.cooldown,
+            };
+        }
+    }
+
+    // Lets the next request probe a half-open circuit whose probe ended
+    // without an outcome.
+    fn abandon_probe(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if let Some(circuit) = hosts.get_mut(host) {
+            if let CircuitState::HalfOpen { until } = circuit.state {
+                circuit.state = CircuitState::Open { until };
+            }
+        }
+    }
+
+    // Opens the circuit until `until` whatever the failure count, for hosts
+    // that said when they will be back.
+    pub fn open_until(&self, host: &str, until: SystemTime) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        let circuit = hosts.entry(host.to_string()).or_default();
+
+        circuit.state = CircuitState::Open { until };
+    }
+
+    pub fn state(&self, host: &str) -> CircuitState {
+        let hosts = self.hosts.lock().unwrap();
+
+        hosts
+            .get(host)
+            .map(|circuit| circuit.state)
+            .unwrap_or(CircuitState::Closed)
+    }
+}
+
+// The request a half-open circuit let through. Dropped before its outcome
+// was recorded, such as when refused on a redirect, out of time for a
+// connection or unwinding, it hands the probe to the next request.
+pub(crate) struct CircuitProbe<'a> {
+    breaker: &'a CircuitBreaker,
+    host: &'a str,
+    settled: bool,
+}
+
+impl CircuitProbe<'_> {
+    pub fn settle(mut self) {
+        self.settled = true;
+    }
+}
+
+impl Drop for CircuitProbe<'_> {
+    fn drop(&mut self) {
+        if !self.settled {
+            self.breaker.abandon_probe(self.host);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+
+    use crate::downloader::{
+        clock::{Clock, FakeClock},
+        fetcher::MockFetcher,
+        testing, DownloadError, DownloaderBuilder, FetchError, Response,
+    };
+
+    #[test]
+    fn test_probes_ending_without_an_outcome_hand_over() {
+        let url = "https://down.example.com/image.png";
+
+        let clock = FakeClock::new();
+
+        let cooldown = Duration::from_secs(30);
+
+        let mut redirected = Response::ok(b"image".to_vec(), Some("image/png".to_string()));
+
+        redirected.redirects = vec![(302, "https://blocked.example/image.png".to_string())];
+
+        let fetcher = MockFetcher::scripted(vec![
+            Err(FetchError::connect("connection refused")),
+            Ok(redirected),
+            Ok(Response::ok(
+                b"image".to_vec(),
+                Some("image/png".to_string()),
+            )),
+        ]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("circuit_abandoned"), fetcher)
+                .clock(clock.clone())
+                .circuit_breaker(1, cooldown)
+                .deny_hosts(&["blocked.example"])
+                .build();
+
+        downloader.download(url).unwrap_err();
+
+        clock.advance(cooldown);
+
+        // Act
+
+        let refused = downloader.download(url);
+
+        let next = downloader.download(url);
+
+        // Assert
+
+        assert_eq!(
+            refused,
+            Err(DownloadError::Forbidden {
+                host: "blocked.example".to_string()
+            })
+        );
+        assert!(next.is_ok());
+        assert_eq!(
+            downloader.circuit_state("down.example.com"),
+            CircuitState::Closed
+        );
+    }
+
+    #[test]
+    fn test_circuit_goes_closed_open_half_open_closed() {
+        let url = "https://down.example.com/image.png";
+
+        let clock = FakeClock::new();
+
+        let cooldown = Duration::from_secs(30);
+
+        let fetcher = MockFetcher::scripted(vec![
+            Err(FetchError::connect("connection refused")),
+            Err(FetchError::connect("connection refused")),
+            Err(FetchError::timeout("probe timed out")),
+            Ok(Response::ok(
+                b"image".to_vec(),
+                Some("image/png".to_string()),
+            )),
+        ]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("circuit_breaker"), fetcher)
+                .clock(clock.clone())
+                .circuit_breaker(2, cooldown)
+                .build();
+
+        // Act & Assert: closed, failures accumulate
+
+        let refused = Err(DownloadError::Connect("connection refused".to_string()));
+
+        assert_eq!(downloader.download(url), refused);
+        assert_eq!(
+            downloader.circuit_state("down.example.com"),
+            CircuitState::Closed
+        );
+
+        assert_eq!(downloader.download(url), refused);
+
+        let retry_at = clock.now() + cooldown;
+
+        assert_eq!(
+            downloader.circuit_state("down.example.com"),
+            CircuitState::Open { until: retry_at }
+        );
+
+        // Open: fail fast without touching the fetcher
+
+        assert_eq!(
+            downloader.download(url),
+            Err(DownloadError::CircuitOpen {
+                host: "down.example.com".to_string(),
+                retry_at,
+            })
+        );
+        assert_eq!(downloader.fetcher().calls(), 2);
+
+        // Half-open: a failing probe re-opens the circuit
+
+        clock.advance(cooldown);
+
+        assert_eq!(downloader.download(url), Err(DownloadError::Timeout));
+        assert_eq!(downloader.fetcher().calls(), 3);
+        assert!(matches!(
+            downloader.download(url),
+            Err(DownloadError::CircuitOpen { .. })
+        ));
+
+        // Half-open: a successful probe closes it again
+
+        clock.advance(cooldown);
+
+        assert!(downloader.download(url).is_ok());
+        assert_eq!(
+            downloader.circuit_state("down.example.com"),
+            CircuitState::Closed
+        );
+
+        downloader.clear_cache();
+    }
+
+    #[test]
+    fn test_half_open_allows_a_single_probe() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig::new(1, Duration::from_secs(10)));
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        breaker.record_failure("example.com", now);
+
+        assert!(breaker.check("example.com", now).is_err());
+        assert!(breaker.check("other.com", now).is_ok());
+
+        let later = now + Duration::from_secs(10);
+
+        // Act
+
+        let probe = breaker.check("example.com", later);
+        let concurrent = breaker.check("example.com", later);
+
+        // Assert
+
+        assert!(probe.is_ok());
+        assert!(concurrent.is_err());
+    }
+}
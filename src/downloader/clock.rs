@@ -0,0 +1,64 @@
+use std::{
+    thread,
+    time::{Duration, SystemTime},
+};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    // Waits such as a server's `Retry-After` go through here, so a fake
+    // clock can skip them.
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(test)]
+pub use fake_clock::FakeClock;
+
+#[cfg(test)]
+mod fake_clock {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use super::Clock;
+
+    #[derive(Clone)]
+    pub struct FakeClock {
+        now: Arc<Mutex<SystemTime>>,
+    }
+
+    impl FakeClock {
+        pub fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(SystemTime::now())),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            let mut now = self.now.lock().unwrap();
+            *now += duration;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> SystemTime {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration);
+        }
+    }
+}
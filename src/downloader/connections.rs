@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{cancel::CANCEL_CHECK, retry_scheduler::GaveUp, Body, CancellationToken};
+
+#[derive(Debug, Default)]
+struct Open {
+    total: usize,
+    hosts: HashMap<String, usize>,
+}
+
+// Counts the connections open at once, overall and per host. A connection
+// stays open until its body has been read or dropped.
+#[derive(Debug)]
+pub(crate) struct ConnectionLimiter {
+    per_host: Option<usize>,
+    total: Option<usize>,
+    open: Mutex<Open>,
+    released: Condvar,
+}
+
+impl ConnectionLimiter {
+    pub fn new(per_host: Option<usize>, total: Option<usize>) -> Self {
+        Self {
+            per_host: per_host.map(|limit| limit.max(1)),
+            total: total.map(|limit| limit.max(1)),
+            open: Mutex::new(Open::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    // Blocks until both limits leave room for one more connection to `host`,
+    // for `within` at most or until `token` is cancelled. Slots held by
+    // bodies nobody reads free up only once they are dropped, so waits are
+    // cut into slices that check the token.
+    pub fn acquire(
+        self: &Arc<Self>,
+        host: &str,
+        within: Option<Duration>,
+        token: &CancellationToken,
+    ) -> Result<Connection, GaveUp> {
+        let give_up_at = within.map(|within| Instant::now() + within);
+
+        let mut open = self.open.lock().unwrap();
+
+        loop {
+            let to_host = open.hosts.get(host).copied().unwrap_or(0);
+
+            let full = self.total.is_some_and(|limit| open.total >= limit)
+                || self.per_host.is_some_and(|limit| to_host >= limit);
+
+            if !full {
+                break;
+            }
+
+            if token.is_cancelled() {
+                return Err(GaveUp::Cancelled);
+            }
+
+            let left = give_up_at.map(|at| at.saturating_duration_since(Instant::now()));
+
+            if left == Some(Duration::ZERO) {
+                return Err(GaveUp::Deadline);
+            }
+
+            let slice = left.map_or(CANCEL_CHECK, |left| left.min(CANCEL_CHECK));
+
+            open = self.released.wait_timeout(open, slice).unwrap().0;
+        }
+
+        open.total += 1;
+        *open.hosts.entry(host.to_string()).or_default() += 1;
+
+        Ok(Connection {
+            limiter: Arc::clone(self),
+            host: host.to_string(),
+        })
+    }
+
+    fn release(&self, host: &str) {
+        let mut open = self.open.lock().unwrap();
+
+        open.total -= 1;
+
+        if let Some(count) = open.hosts.get_mut(host) {
+            *count -= 1;
+
+            if *count == 0 {
+                open.hosts.remove(host);
+            }
+        }
+
+        self.released.notify_all();
+    }
+}
+
+// Releases its slot when dropped.
+pub(crate) struct Connection {
+    limiter: Arc<ConnectionLimiter>,
+    host: String,
+}
+
+impl Connection {
+    // Keeps the slot until a streamed body is finished with.
+    pub fn hold_until_read(self, body: Body) -> Body {
+        match body {
+            Body::Reader(reader) => Body::Reader(Box::new(HeldReader {
+                reader,
+                _connection: self,
+            })),
+            bytes => bytes,
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.limiter.release(&self.host);
+    }
+}
+
+struct HeldReader {
+    reader: Box<dyn Read + Send>,
+    _connection: Connection,
+}
+
+impl Read for HeldReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use url::Url;
+
+    use super::ConnectionLimiter;
+    use crate::downloader::{
+        retry_scheduler::GaveUp, testing, BatchOptions, CancellationToken, DownloaderBuilder,
+        FetchError, FileDownloader, Response,
+    };
+
+    // Records the most calls it has seen in flight at once, overall and per
+    // host.
+    #[derive(Default)]
+    struct SlowFetcher {
+        in_flight: Mutex<(usize, HashMap<String, usize>)>,
+        peaks: Mutex<(usize, HashMap<String, usize>)>,
+        fail_first: Mutex<bool>,
+    }
+
+    impl FileDownloader for SlowFetcher {
+        fn fetch(&self, url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+            let host = Url::parse(url).unwrap().host_str().unwrap().to_string();
+
+            {
+                let mut in_flight = self.in_flight.lock().unwrap();
+
+                in_flight.0 += 1;
+                *in_flight.1.entry(host.clone()).or_default() += 1;
+
+                let mut peaks = self.peaks.lock().unwrap();
+
+                peaks.0 = peaks.0.max(in_flight.0);
+
+                let peak = peaks.1.entry(host.clone()).or_default();
+                *peak = (*peak).max(in_flight.1[&host]);
+            }
+
+            thread::sleep(Duration::from_millis(20));
+
+            let mut in_flight = self.in_flight.lock().unwrap();
+
+            in_flight.0 -= 1;
+            *in_flight.1.get_mut(&host).unwrap() -= 1;
+
+            // A truncated PNG makes the first download retry.
+            let body = match std::mem::take(&mut *self.fail_first.lock().unwrap()) {
+                true => b"\x89PNG".to_vec(),
+                false => crate::downloader::fixtures::PNG.to_vec(),
+            };
+
+            Ok(Response::ok(body, Some("image/png".to_string())))
+        }
+    }
+
+    #[test]
+    fn test_connections_are_limited_per_host_and_overall() {
+        let fetcher = SlowFetcher {
+            fail_first: Mutex::new(true),
+            ..Default::default()
+        };
+
+        let builder =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("connection_limits"), fetcher)
+                .max_concurrency(16)
+                .max_connections_per_host(2)
+                .max_total_connections(3);
+
+        #[cfg(feature = "image")]
+        let builder = builder
+            .verify_images(crate::downloader::VerifyLevel::FullDecode)
+            .retry_corrupt_images(1);
+
+        let downloader = builder.build();
+
+        let urls: Vec<_> = (0..12)
+            .map(|index| format!("https://host{}.example/{index}.png", index % 2))
+            .collect();
+
+        // Act
+
+        let batch = downloader.download_all(&urls, BatchOptions::default());
+
+        // Assert
+
+        let peaks = downloader.fetcher().peaks.lock().unwrap();
+
+        assert!(batch.results.iter().all(|(_, result)| result.is_ok()));
+        assert!(peaks.0 <= 3, "{peaks:?}");
+        assert!(peaks.1.values().all(|peak| *peak <= 2), "{peaks:?}");
+    }
+
+    #[test]
+    fn test_waits_for_a_connection_are_bounded() {
+        let limiter = Arc::new(ConnectionLimiter::new(Some(1), None));
+
+        let token = CancellationToken::new();
+
+        let held = limiter.acquire("example.com", None, &token);
+
+        // Act
+
+        let to_host = limiter.acquire("example.com", Some(Duration::from_millis(20)), &token);
+
+        let to_other = limiter.acquire("example.org", Some(Duration::ZERO), &token);
+
+        // Assert
+
+        assert!(held.is_ok());
+        assert_eq!(to_host.err(), Some(GaveUp::Deadline));
+        assert!(to_other.is_ok());
+    }
+
+    #[test]
+    fn test_waits_for_a_connection_end_when_cancelled() {
+        let limiter = Arc::new(ConnectionLimiter::new(Some(1), None));
+
+        let token = CancellationToken::new();
+
+        let _held = limiter.acquire("example.com", None, &token).unwrap();
+
+        let cancel = {
+            let token = token.clone();
+
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+
+                token.cancel();
+            })
+        };
+
+        // Act
+
+        let waited = limiter.acquire("example.com", None, &token);
+
+        // Assert
+
+        cancel.join().unwrap();
+
+        assert_eq!(waited.err(), Some(GaveUp::Cancelled));
+    }
+}
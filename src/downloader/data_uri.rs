@@ -0,0 +1,248 @@
+use std::io::{self, Read, Write};
+
+use super::Download;
+
+// Files above this are refused by `to_data_uri`, as inlining them would
+// bloat whatever embeds them.
+pub const DATA_URI_LIMIT: u64 = 1024 * 1024;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl Download {
+    // `data:<mime>;base64,<body>`, for files up to `DATA_URI_LIMIT` bytes.
+    pub fn to_data_uri(&self) -> io::Result<String> {
+        self.to_data_uri_limited(DATA_URI_LIMIT)
+    }
+
+    pub fn to_data_uri_limited(&self, limit: u64) -> io::Result<String> {
+        let mut uri = Vec::new();
+
+        self.write_data_uri(&mut uri, limit)?;
+
+        Ok(String::from_utf8(uri).expect("data URIs are ASCII"))
+    }
+
+    // Encodes the file as it is read, without holding it in memory. Nothing
+    // is written when the file is larger than `limit`.
+    pub fn write_data_uri<W: Write>(&self, writer: &mut W, limit: u64) -> io::Result<()> {
+        let size = self.len()?;
+
+        if size > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!(
+                    "{} is {size} bytes, over the {limit} a data URI may inline",
+                    self.source
+                ),
+            ));
+        }
+
+        let mime = media_type(self.mime().unwrap_or("application/octet-stream"));
+
+        write!(writer, "data:{mime};base64,")?;
+
+        // A multiple of 3, so only the last chunk needs padding.
+        let mut file = self.stream()?;
+
+        let mut chunk = [0; 3 * 1024];
+
+        let mut encoded = Vec::with_capacity(chunk.len() / 3 * 4);
+
+        loop {
+            let read = read_full(&mut file, &mut chunk)?;
+
+            if read == 0 {
+                break;
+            }
+
+            encoded.clear();
+
+            encode(&chunk[..read], &mut encoded);
+
+            writer.write_all(&encoded)?;
+
+            if read < chunk.len() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// `text/plain; charset=utf-8` as `text/plain;charset=utf-8`, the way data
+// URIs spell media types.
+fn media_type(mime: &str) -> String {
+    mime.split(';')
+        .map(|part| match part.split_once('=') {
+            Some((name, value)) => format!("{}={}", name.trim(), value.trim()),
+            None => part.trim().to_string(),
+        })
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// Fills `buf` unless the reader ends first, so chunks only come up short at
+// the end of the file.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(filled)
+}
+
+fn encode(bytes: &[u8], out: &mut Vec<u8>) {
+    for group in bytes.chunks(3) {
+        let value = group.iter().enumerate().fold(0u32, |value, (index, byte)| {
+            value | (*byte as u32) << (16 - 8 * index)
+        });
+
+        for index in 0..4 {
+            let symbol = if index <= group.len() {
+                ALPHABET[(value >> (18 - 6 * index) & 0x3f) as usize]
+            } else {
+                b'='
+            };
+
+            out.push(symbol);
+        }
+    }
+}
+
+// Padding is optional. `None` for anything that is not base64.
+pub(crate) fn decode(encoded: &str) -> Option<Vec<u8>> {
+    let values = encoded
+        .trim_end_matches('=')
+        .bytes()
+        .map(|byte| {
+            let value = ALPHABET.iter().position(|symbol| *symbol == byte)?;
+
+            Some(value as u32)
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    // A lone symbol holds less than a byte.
+    if values.len() % 4 == 1 {
+        return None;
+    }
+
+    let bytes = values
+        .chunks(4)
+        .flat_map(|group| {
+            let value = group
+                .iter()
+                .enumerate()
+                .fold(0, |value, (index, bits)| value | bits << (18 - 6 * index));
+
+            (0..group.len() - 1).map(move |index| (value >> (16 - 8 * index)) as u8)
+        })
+        .collect();
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{decode, encode, media_type};
+    use crate::downloader::{fetcher::MockFetcher, fixtures, testing, DownloaderBuilder, Response};
+
+    #[test]
+    fn test_encode() {
+        let encoded = |bytes: &[u8]| {
+            let mut out = Vec::new();
+
+            encode(bytes, &mut out);
+
+            String::from_utf8(out).unwrap()
+        };
+
+        // Assert
+
+        assert_eq!(encoded(b""), "");
+        assert_eq!(encoded(b"f"), "Zg==");
+        assert_eq!(encoded(b"fo"), "Zm8=");
+        assert_eq!(encoded(b"foo"), "Zm9v");
+        assert_eq!(encoded(b"foobar"), "Zm9vYmFy");
+        assert_eq!(decode("Zm8").unwrap(), b"fo");
+        assert_eq!(decode("Zm9v!"), None);
+        assert_eq!(decode("Zm9vY"), None);
+    }
+
+    #[test]
+    fn test_media_types_drop_the_spaces_around_parameters() {
+        // Assert
+
+        assert_eq!(media_type("image/png"), "image/png");
+        assert_eq!(
+            media_type("text/plain; charset=utf-8"),
+            "text/plain;charset=utf-8"
+        );
+        assert_eq!(
+            media_type(" text/html ;charset = UTF-8; "),
+            "text/html;charset=UTF-8"
+        );
+    }
+
+    #[test]
+    fn test_data_uris_round_trip() {
+        let large: Vec<u8> = (0..10_000u32).map(|index| (index % 251) as u8).collect();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("data_uri"),
+            MockFetcher::new(vec![
+                Response::ok(fixtures::PNG.to_vec(), Some("image/png".to_string())),
+                Response::ok(large.clone(), None),
+                Response::ok(b"hello".to_vec(), Some("text/plain".to_string())),
+            ]),
+        )
+        .build();
+
+        let png = downloader.download("https://example.com/a.png").unwrap();
+
+        let blob = downloader.download("https://example.com/blob").unwrap();
+
+        let mut text = downloader
+            .download("https://example.com/hello.txt")
+            .unwrap();
+
+        text.metadata.mime = Some("text/plain; charset=utf-8".to_string());
+
+        // Act
+
+        let png_uri = png.to_data_uri().unwrap();
+
+        let mut blob_uri = Vec::new();
+
+        blob.write_data_uri(&mut blob_uri, 10_000).unwrap();
+
+        let too_large = blob.to_data_uri_limited(9_999).unwrap_err();
+
+        let text_uri = text.to_data_uri().unwrap();
+
+        // Assert
+
+        let png_body = png_uri.strip_prefix("data:image/png;base64,").unwrap();
+
+        let blob_uri = String::from_utf8(blob_uri).unwrap();
+
+        let blob_body = blob_uri
+            .strip_prefix("data:application/octet-stream;base64,")
+            .unwrap();
+
+        assert_eq!(decode(png_body).unwrap(), fixtures::PNG);
+        assert_eq!(decode(blob_body).unwrap(), large);
+        assert_eq!(too_large.kind(), ErrorKind::FileTooLarge);
+        assert_eq!(text_uri, "data:text/plain;charset=utf-8;base64,aGVsbG8=");
+    }
+}
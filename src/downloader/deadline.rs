@@ -0,0 +1,257 @@
+use std::time::{Duration, SystemTime};
+
+use super::{Downloader, FileDownloader, Storage};
+
+// The budget of one call, by the downloader's clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Deadline {
+    pub started: SystemTime,
+    pub budget: Duration,
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // What is left of the call's deadline, `None` without one.
+    pub(crate) fn remaining_budget(&self) -> Option<Duration> {
+        let deadline = self.config.deadline?;
+
+        let elapsed = self
+            .config
+            .clock
+            .now()
+            .duration_since(deadline.started)
+            .unwrap_or_default();
+
+        Some(deadline.budget.saturating_sub(elapsed))
+    }
+
+    // How long the call has run, once its deadline passed.
+    pub(crate) fn past_deadline(&self) -> Option<Duration> {
+        let deadline = self.config.deadline?;
+
+        let elapsed = self.elapsed_in_call();
+
+        (elapsed >= deadline.budget).then_some(elapsed)
+    }
+
+    // How long the call has run, zero without a deadline to start from.
+    pub(crate) fn elapsed_in_call(&self) -> Duration {
+        self.config.deadline.map_or(Duration::ZERO, |deadline| {
+            self.config
+                .clock
+                .now()
+                .duration_since(deadline.started)
+                .unwrap_or_default()
+        })
+    }
+
+    // Waits cut short where the deadline would pass first.
+    pub(crate) fn sleep_within_deadline(&self, wait: Duration) {
+        let wait = self
+            .remaining_budget()
+            .map_or(wait, |remaining| wait.min(remaining));
+
+        self.config.clock.sleep(wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, Clock, DownloadError, DownloadOptions,
+        DownloaderBuilder, FetchError, FileDownloader, Response,
+    };
+
+    const URL: &str = "https://example.com/slow.png";
+
+    // Takes `step` of the fake clock per response, or the whole timeout it is
+    // given when that is shorter, timing out.
+    struct SlowFetcher {
+        inner: MockFetcher,
+        clock: FakeClock,
+        step: Duration,
+        timeouts: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl FileDownloader for SlowFetcher {
+        fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+            self.clock.advance(self.step);
+
+            self.inner.fetch(url, headers)
+        }
+
+        fn fetch_within(
+            &self,
+            url: &str,
+            headers: &[(String, String)],
+            timeout: Duration,
+        ) -> Result<Response, FetchError> {
+            self.timeouts.lock().unwrap().push(timeout);
+
+            if timeout < self.step {
+                self.clock.advance(timeout);
+
+                return Err(FetchError::timeout(format!("{url}: timed out")));
+            }
+
+            self.fetch(url, headers)
+        }
+    }
+
+    fn slow(clock: &FakeClock, step: u64, responses: Vec<Response>) -> SlowFetcher {
+        SlowFetcher {
+            inner: MockFetcher::new(responses),
+            clock: clock.clone(),
+            step: Duration::from_secs(step),
+            timeouts: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn test_deadline_bounds_every_attempt() {
+        let clock = FakeClock::new();
+
+        let start = clock.now();
+
+        let fetcher = slow(
+            &clock,
+            4,
+            vec![
+                Response::invalid_body(),
+                Response::invalid_body(),
+                Response::ok(b"image".to_vec(), Some("image/png".to_string())),
+            ],
+        );
+
+        let timeouts = Arc::clone(&fetcher.timeouts);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("deadline_attempts"), fetcher)
+                .clock(clock.clone())
+                .build();
+
+        // Act
+
+        let error = downloader
+            .download_with(
+                URL,
+                &DownloadOptions::new().deadline(Duration::from_secs(10)),
+            )
+            .unwrap_err();
+
+        // Assert
+
+        let secs = Duration::from_secs;
+
+        assert_eq!(clock.now().duration_since(start).unwrap(), secs(10));
+        assert_eq!(*timeouts.lock().unwrap(), [secs(10), secs(6), secs(2)]);
+        assert!(matches!(
+            error,
+            DownloadError::DeadlineExceeded { elapsed, .. } if elapsed == secs(10)
+        ));
+        assert_eq!(error.attempts().len(), 3);
+        assert_eq!(error.code(), "deadline_exceeded");
+        assert!(!downloader.is_cached(URL));
+    }
+
+    #[test]
+    fn test_rate_limit_waits_are_cut_to_the_deadline() {
+        let clock = FakeClock::new();
+
+        let start = clock.now();
+
+        let fetcher = slow(
+            &clock,
+            1,
+            vec![
+                Response::new(429).with_header("Retry-After", "60"),
+                Response::ok(b"image".to_vec(), Some("image/png".to_string())),
+            ],
+        );
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("deadline_rate_limit"), fetcher)
+                .clock(clock.clone())
+                .wait_on_rate_limit(Duration::from_secs(120), 1)
+                .build();
+
+        // Act
+
+        let error = downloader
+            .download_with(
+                URL,
+                &DownloadOptions::new().deadline(Duration::from_secs(5)),
+            )
+            .unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            clock.now().duration_since(start).unwrap(),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            error.last_error(),
+            &DownloadError::RateLimited {
+                retry_after: Some(Duration::from_secs(60))
+            }
+        );
+        assert_eq!(downloader.fetcher().inner.calls(), 1);
+    }
+
+    #[test]
+    fn test_retry_queues_are_cut_to_the_deadline() {
+        let clock = FakeClock::new();
+
+        let fetcher = slow(
+            &clock,
+            1,
+            vec![
+                Response::invalid_body(),
+                Response::ok(b"image".to_vec(), Some("image/png".to_string())),
+                Response::invalid_body(),
+            ],
+        );
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("deadline_retry_queue"), fetcher)
+                .clock(clock.clone())
+                .throttle_retries(Duration::from_secs(60), 1)
+                .build();
+
+        downloader
+            .download("https://example.com/first.png")
+            .unwrap();
+
+        let start = clock.now();
+
+        // Act
+
+        let error = downloader
+            .download_with(
+                URL,
+                &DownloadOptions::new().deadline(Duration::from_secs(5)),
+            )
+            .unwrap_err();
+
+        // Assert
+
+        let secs = Duration::from_secs;
+
+        assert_eq!(clock.now().duration_since(start).unwrap(), secs(5));
+        assert!(matches!(
+            error,
+            DownloadError::DeadlineExceeded { elapsed, .. } if elapsed == secs(5)
+        ));
+        assert_eq!(error.attempts().len(), 1);
+        assert_eq!(downloader.fetcher().inner.calls(), 3);
+    }
+}
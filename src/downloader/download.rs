@@ -0,0 +1,327 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use super::Download;
+
+// Entries outside the filesystem are read whole, as seeking needs them in
+// memory.
+enum EntryReader {
+    File(BufReader<File>),
+    Stored(Cursor<Vec<u8>>),
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(reader) => reader.read(buf),
+            Self::Stored(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for EntryReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(reader) => reader.seek(position),
+            Self::Stored(reader) => reader.seek(position),
+        }
+    }
+}
+
+impl Download {
+    pub fn reader(&self) -> io::Result<impl Read + Seek> {
+        match &self.backing.0 {
+            Some(_) => self
+                .bytes()
+                .map(|bytes| EntryReader::Stored(Cursor::new(bytes))),
+            None => self
+                .open()
+                .map(|file| EntryReader::File(BufReader::new(file))),
+        }
+    }
+
+    pub fn bytes(&self) -> io::Result<Vec<u8>> {
+        self.resident.bytes(|| {
+            let mut bytes = Vec::with_capacity(self.metadata.size.unwrap_or(0) as usize);
+
+            self.stream()?.read_to_end(&mut bytes)?;
+
+            Ok(bytes)
+        })
+    }
+
+    pub fn len(&self) -> io::Result<u64> {
+        match &self.backing.0 {
+            Some(_) => io::copy(&mut self.stream()?, &mut io::sink()),
+            None => self.open()?.metadata().map(|metadata| metadata.len()),
+        }
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    // The announced `Content-Type` without parameters or, when there was
+    // none, the type sniffed from the body.
+    pub fn mime(&self) -> Option<&str> {
+        self.metadata.mime.as_deref()
+    }
+
+    // For callers that only care about the file.
+    pub fn into_path(self) -> PathBuf {
+        self.file
+    }
+
+    // Reads the entry from whichever storage holds it.
+    pub(super) fn stream(&self) -> io::Result<Box<dyn Read + Send>> {
+        match &self.backing.0 {
+            Some((storage, name)) => storage.open(name).map_err(|error| self.gone(error)),
+            None => Ok(Box::new(BufReader::new(self.open()?))),
+        }
+    }
+
+    pub(super) fn open(&self) -> io::Result<File> {
+        if self.backing.0.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} is not stored as a file", self.source),
+            ));
+        }
+
+        File::open(&self.file).map_err(|error| self.gone(error))
+    }
+
+    // The cache may evict or replace the file after the `Download` was handed
+    // out, so report which resource disappeared rather than a bare path.
+    fn gone(&self, error: io::Error) -> io::Error {
+        match error.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "cached file {} for {} is no longer available",
+                    self.file.display(),
+                    self.source
+                ),
+            ),
+            _ => error,
+        }
+    }
+}
+
+impl AsRef<Path> for Download {
+    fn as_ref(&self) -> &Path {
+        &self.file
+    }
+}
+
+impl From<Download> for PathBuf {
+    fn from(download: Download) -> Self {
+        download.into_path()
+    }
+}
+
+// Opens the file read-only, with the same error as `reader` once it is gone.
+impl TryFrom<&Download> for File {
+    type Error = io::Error;
+
+    fn try_from(download: &Download) -> io::Result<Self> {
+        download.open()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::{ErrorKind, Read, Seek, SeekFrom},
+        path::{Path, PathBuf},
+    };
+
+    use crate::downloader::{
+        fetcher::MockFetcher, fixtures, testing, CachePolicy, Downloader, DownloaderBuilder,
+        MemoryStorage, Response,
+    };
+
+    fn downloader(name: &str) -> Downloader<MockFetcher> {
+        let response = Response::ok(b"0123456789".to_vec(), Some("image/png".to_string()));
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), MockFetcher::new(vec![response]))
+            .build()
+    }
+
+    #[test]
+    fn test_read_cached_download() {
+        let downloader = downloader("download_reader");
+
+        let download = downloader
+            .download("https://example.com/digits.png")
+            .unwrap();
+
+        // Act
+
+        let mut reader = download.reader().unwrap();
+
+        reader.seek(SeekFrom::Start(6)).unwrap();
+
+        let mut tail = String::new();
+
+        reader.read_to_string(&mut tail).unwrap();
+
+        // Assert
+
+        assert_eq!(tail, "6789");
+        assert_eq!(download.bytes().unwrap(), b"0123456789");
+        assert_eq!(download.len().unwrap(), 10);
+    }
+
+    #[test]
+    fn test_stored_downloads_are_read_through_their_storage() {
+        let url = "https://example.com/digits.png";
+
+        let response = Response::ok(b"0123456789".to_vec(), Some("image/png".to_string()));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("download_stored"),
+            MockFetcher::new(vec![response]),
+        )
+        .storage(MemoryStorage::new())
+        .build();
+
+        let download = downloader.download(url).unwrap();
+
+        // Act
+
+        let mut reader = download.reader().unwrap();
+
+        reader.seek(SeekFrom::Start(6)).unwrap();
+
+        let mut tail = String::new();
+
+        reader.read_to_string(&mut tail).unwrap();
+
+        // Assert
+
+        assert!(download.file.is_relative());
+        assert_eq!(tail, "6789");
+        assert_eq!(download.bytes().unwrap(), b"0123456789");
+        assert_eq!(download.len().unwrap(), 10);
+        assert_eq!(
+            File::try_from(&download).unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+
+        downloader.clear_cache();
+
+        let error = download.bytes().unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().contains(url), "{error}");
+    }
+
+    #[test]
+    fn test_evicted_download_reports_source() {
+        let url = "https://example.com/evicted.png";
+
+        let downloader = downloader("download_evicted");
+
+        let download = downloader.download(url).unwrap();
+
+        downloader.clear_cache();
+
+        // Act
+
+        let errors = [
+            download.reader().err().unwrap(),
+            download.bytes().unwrap_err(),
+            download.len().unwrap_err(),
+            File::try_from(&download).unwrap_err(),
+        ];
+
+        // Assert
+
+        for error in errors {
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+            assert!(error.to_string().contains(url), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_downloads_convert_to_their_file() {
+        let downloader = downloader("download_into_path");
+
+        let download = downloader
+            .download("https://example.com/digits.png")
+            .unwrap();
+
+        let file = download.file.clone();
+
+        fn read(path: impl AsRef<Path>) -> Vec<u8> {
+            fs::read(path).unwrap()
+        }
+
+        // Act
+
+        let mut opened = File::try_from(&download).unwrap();
+
+        let mut contents = String::new();
+
+        opened.read_to_string(&mut contents).unwrap();
+
+        let borrowed = read(&download);
+
+        let path = PathBuf::from(download.clone());
+
+        // Assert
+
+        assert_eq!(contents, "0123456789");
+        assert_eq!(borrowed, b"0123456789");
+        assert_eq!(path, file);
+        assert_eq!(download.into_path(), file);
+    }
+
+    #[test]
+    fn test_mime_is_announced_or_sniffed() {
+        let responses = vec![
+            Response::ok(
+                b"{}".to_vec(),
+                Some("Application/JSON; charset=utf-8".to_string()),
+            ),
+            Response::ok(fixtures::PNG.to_vec(), None),
+            Response::ok(b"plain".to_vec(), None),
+        ];
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("download_mime"),
+            MockFetcher::new(responses),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        let urls = ["a.json", "b", "c"].map(|name| format!("https://example.com/{name}"));
+
+        // Act
+
+        let mimes: Vec<_> = urls
+            .iter()
+            .map(|url| downloader.download(url).unwrap().mime().map(str::to_string))
+            .collect();
+
+        let hit = downloader.download(&urls[1]).unwrap();
+
+        // Assert
+
+        assert_eq!(
+            mimes,
+            [
+                Some("application/json".to_string()),
+                Some("image/png".to_string()),
+                None
+            ]
+        );
+        assert_eq!(hit.mime(), Some("image/png"));
+        assert_eq!(downloader.fetcher().calls(), 3);
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::{
+    CancellationToken, Download, DownloadError, Downloader, FileDownloader, IntoDownloadUrl,
+    Storage,
+};
+
+type Item = (String, Result<Download, DownloadError>);
+
+// The results of `download_iter` in the order they complete, each URL as
+// given next to its result. Workers wait while results go unread, so no more
+// than one per worker is held at a time. Dropping the iterator waits for the
+// remaining URLs, or only for those in flight under `cancel_on_drop`.
+pub struct DownloadIter {
+    results: Option<Receiver<Item>>,
+    token: CancellationToken,
+    cancel_on_drop: bool,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl DownloadIter {
+    pub fn cancel_on_drop(mut self, cancel: bool) -> Self {
+        self.cancel_on_drop = cancel;
+        self
+    }
+}
+
+impl Iterator for DownloadIter {
+    type Item = Item;
+
+    fn next(&mut self) -> Option<Item> {
+        self.results.as_ref()?.recv().ok()
+    }
+}
+
+impl Drop for DownloadIter {
+    fn drop(&mut self) {
+        if self.cancel_on_drop {
+            self.token.cancel();
+        }
+
+        // Workers blocked on a full channel finish once it is read.
+        if let Some(results) = self.results.take() {
+            for _ in results {}
+        }
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Downloads the URLs on `max_concurrency` workers, handing out each result
+    // as soon as it is ready instead of once the whole batch is.
+    pub fn download_iter<I>(&self, urls: I) -> DownloadIter
+    where
+        I: IntoIterator,
+        I::Item: IntoDownloadUrl,
+    {
+        let urls: Arc<Vec<String>> = Arc::new(
+            urls.into_iter()
+                .map(|url| url.as_str().to_string())
+                .collect(),
+        );
+
+        // Cancelling on drop must not cancel the downloader's own token.
+        let token = self.config.cancellation_token.child();
+
+        let next = Arc::new(AtomicUsize::new(0));
+
+        let count = self.config.max_concurrency.clamp(1, urls.len().max(1));
+
+        let (sender, results) = mpsc::sync_channel(count);
+
+        let workers = (0..count)
+            .map(|_| {
+                let downloader = self.detached();
+
+                let (urls, next, token, sender) = (
+                    Arc::clone(&urls),
+                    Arc::clone(&next),
+                    token.clone(),
+                    sender.clone(),
+                );
+
+                thread::spawn(move || {
+                    while !token.is_cancelled() {
+                        let Some(url) = urls.get(next.fetch_add(1, Ordering::SeqCst)) else {
+                            break;
+                        };
+
+                        let result = downloader.download(url.as_str());
+
+                        if sender.send((url.clone(), result)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        DownloadIter {
+            results: Some(results),
+            token,
+            cancel_on_drop: false,
+            workers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, Downloader, DownloaderBuilder, Response,
+        UrlProblem,
+    };
+
+    fn urls() -> Vec<String> {
+        (0..5)
+            .map(|index| format!("https://example.com/{index}.png"))
+            .collect()
+    }
+
+    fn downloader(name: &str, workers: usize) -> Downloader<MockFetcher> {
+        let responses = (0..5)
+            .map(|_| Response::ok(b"image".to_vec(), Some("image/png".to_string())))
+            .collect();
+
+        let fetcher = MockFetcher::new(responses).with_delay(Duration::from_millis(20));
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), fetcher)
+            .max_concurrency(workers)
+            .build()
+    }
+
+    #[test]
+    fn test_every_result_is_yielded() {
+        let downloader = downloader("download_iter_all", 3);
+
+        let mut urls = urls();
+
+        urls.push("not a url".to_string());
+
+        // Act
+
+        let mut results: Vec<_> = downloader.download_iter(&urls).collect();
+
+        // Assert
+
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(results.len(), 6);
+        assert_eq!(
+            results[5],
+            (
+                "not a url".to_string(),
+                Err(DownloadError::InvalidUrl(UrlProblem::RelativeUrl))
+            )
+        );
+        assert!(results[..5].iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(downloader.fetcher().calls(), 5);
+    }
+
+    #[test]
+    fn test_dropping_drains_the_remaining_urls() {
+        let downloader = downloader("download_iter_drained", 1);
+
+        let urls = urls();
+
+        // Act
+
+        let first = downloader.download_iter(&urls).next();
+
+        // Assert
+
+        assert_eq!(first.unwrap().0, urls[0]);
+        assert_eq!(downloader.fetcher().calls(), 5);
+        assert!(urls.iter().all(|url| downloader.is_cached(url)));
+    }
+
+    #[test]
+    fn test_dropping_can_cancel_the_remaining_urls() {
+        let downloader = downloader("download_iter_cancelled", 1);
+
+        let urls = urls();
+
+        // Act
+
+        let first = downloader.download_iter(&urls).cancel_on_drop(true).next();
+
+        // Assert
+
+        assert!(first.unwrap().1.is_ok());
+        assert!(downloader.fetcher().calls() <= 3);
+        assert!(!urls.iter().all(|url| downloader.is_cached(url)));
+        assert!(!downloader.cancellation_token().is_cancelled());
+    }
+}
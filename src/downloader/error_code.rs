@@ -0,0 +1,242 @@
+use super::{DownloadError, UrlProblem};
+
+impl DownloadError {
+    // A stable snake_case identifier of the kind of failure, for callers that
+    // report errors outside Rust. Codes never change once released.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "not_found",
+            Self::NotCached => "not_cached",
+            Self::NetworkError { .. } => "network_error",
+            Self::InvalidUrl(UrlProblem::UnsupportedScheme(_)) => "unsupported_scheme",
+            Self::InvalidUrl(_) => "invalid_url",
+            Self::InvalidBody => "invalid_body",
+            Self::TruncatedBody { .. } => "truncated_body",
+            Self::CircuitOpen { .. } => "circuit_open",
+            Self::Forbidden { .. } => "forbidden_host",
+            Self::HttpStatus(_) => "http_status",
+            Self::Dns(_) => "dns",
+            Self::Connect(_) => "connect",
+            Self::Tls(_) => "tls",
+            Self::Timeout => "timeout",
+            Self::AlreadyExists => "already_exists",
+            Self::CorruptImage => "corrupt_image",
+            Self::DecoderUnavailable(_) => "decoder_unavailable",
+            Self::AnimatedImage => "animated_image",
+            Self::ImageTooLarge { .. } => "image_too_large",
+            Self::InvalidArchive => "invalid_archive",
+            Self::UnsafeArchiveEntry(_) => "unsafe_archive_entry",
+            Self::ArchiveTooLarge => "archive_too_large",
+            Self::UnsupportedContent => "unsupported_content",
+            Self::UnknownExtension => "unknown_extension",
+            Self::MissingContentType => "missing_content_type",
+            Self::SniffFailed => "sniff_failed",
+            Self::InsufficientSpace { .. } => "insufficient_space",
+            Self::Io(_) => "io",
+            Self::Writer(_) => "writer",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::ChecksumMismatch { .. } => "checksum_mismatch",
+            Self::UserMetaTooLarge { .. } => "user_meta_too_large",
+            Self::BudgetExceeded { .. } => "budget_exceeded",
+            Self::UnsupportedCacheFormat { .. } => "unsupported_cache_format",
+            Self::RetriesExhausted { .. } => "retries_exhausted",
+            Self::DeadlineExceeded { .. } => "deadline_exceeded",
+        }
+    }
+
+    // Whether `download` fetches the URL again after this error, budget
+    // permitting. A `RetriesExhausted` already spent its budget, and rate
+    // limits are only waited out when `wait_on_rate_limit` allows it.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Self::InvalidBody | Self::TruncatedBody { .. } | Self::CorruptImage => true,
+            Self::NotFound
+            | Self::NotCached
+            | Self::NetworkError { .. }
+            | Self::InvalidUrl(_)
+            | Self::CircuitOpen { .. }
+            | Self::Forbidden { .. }
+            | Self::HttpStatus(_)
+            | Self::Dns(_)
+            | Self::Connect(_)
+            | Self::Tls(_)
+            | Self::Timeout
+            | Self::AlreadyExists
+            | Self::DecoderUnavailable(_)
+            | Self::AnimatedImage
+            | Self::ImageTooLarge { .. }
+            | Self::InvalidArchive
+            | Self::UnsafeArchiveEntry(_)
+            | Self::ArchiveTooLarge
+            | Self::UnsupportedContent
+            | Self::UnknownExtension
+            | Self::MissingContentType
+            | Self::SniffFailed
+            | Self::InsufficientSpace { .. }
+            | Self::Io(_)
+            | Self::Writer(_)
+            | Self::RateLimited { .. }
+            | Self::ChecksumMismatch { .. }
+            | Self::UserMetaTooLarge { .. }
+            | Self::BudgetExceeded { .. }
+            | Self::UnsupportedCacheFormat { .. }
+            | Self::RetriesExhausted { .. }
+            | Self::DeadlineExceeded { .. } => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::downloader::{DownloadError, UrlProblem};
+
+    #[test]
+    fn test_codes_and_retriability_are_pinned() {
+        let text = || "reason".to_string();
+
+        let cases = [
+            (DownloadError::NotFound, "not_found", false),
+            (DownloadError::NotCached, "not_cached", false),
+            (
+                DownloadError::NetworkError { reason: text() },
+                "network_error",
+                false,
+            ),
+            (
+                DownloadError::InvalidUrl(UrlProblem::RelativeUrl),
+                "invalid_url",
+                false,
+            ),
+            (DownloadError::InvalidBody, "invalid_body", true),
+            (
+                DownloadError::TruncatedBody {
+                    received: 4,
+                    kind: io::ErrorKind::UnexpectedEof,
+                },
+                "truncated_body",
+                true,
+            ),
+            (
+                DownloadError::CircuitOpen {
+                    host: text(),
+                    retry_at: SystemTime::UNIX_EPOCH,
+                },
+                "circuit_open",
+                false,
+            ),
+            (
+                DownloadError::Forbidden { host: text() },
+                "forbidden_host",
+                false,
+            ),
+            (
+                DownloadError::InvalidUrl(UrlProblem::UnsupportedScheme(text())),
+                "unsupported_scheme",
+                false,
+            ),
+            (DownloadError::HttpStatus(503), "http_status", false),
+            (DownloadError::Dns(text()), "dns", false),
+            (DownloadError::Connect(text()), "connect", false),
+            (DownloadError::Tls(text()), "tls", false),
+            (DownloadError::Timeout, "timeout", false),
+            (DownloadError::AlreadyExists, "already_exists", false),
+            (DownloadError::CorruptImage, "corrupt_image", true),
+            (
+                DownloadError::DecoderUnavailable(text()),
+                "decoder_unavailable",
+                false,
+            ),
+            (DownloadError::AnimatedImage, "animated_image", false),
+            (
+                DownloadError::ImageTooLarge {
+                    width: 1,
+                    height: 1,
+                    limit: 0,
+                },
+                "image_too_large",
+                false,
+            ),
+            (DownloadError::InvalidArchive, "invalid_archive", false),
+            (
+                DownloadError::UnsafeArchiveEntry(text()),
+                "unsafe_archive_entry",
+                false,
+            ),
+            (DownloadError::ArchiveTooLarge, "archive_too_large", false),
+            (
+                DownloadError::UnsupportedContent,
+                "unsupported_content",
+                false,
+            ),
+            (DownloadError::UnknownExtension, "unknown_extension", false),
+            (
+                DownloadError::MissingContentType,
+                "missing_content_type",
+                false,
+            ),
+            (DownloadError::SniffFailed, "sniff_failed", false),
+            (
+                DownloadError::InsufficientSpace {
+                    needed: 1,
+                    available: 0,
+                },
+                "insufficient_space",
+                false,
+            ),
+            (DownloadError::Io(text()), "io", false),
+            (DownloadError::Writer(text()), "writer", false),
+            (
+                DownloadError::RateLimited {
+                    retry_after: Some(Duration::from_secs(120)),
+                },
+                "rate_limited",
+                false,
+            ),
+            (
+                DownloadError::ChecksumMismatch {
+                    expected: text(),
+                    actual: text(),
+                },
+                "checksum_mismatch",
+                false,
+            ),
+            (
+                DownloadError::UserMetaTooLarge { size: 2, limit: 1 },
+                "user_meta_too_large",
+                false,
+            ),
+            (
+                DownloadError::BudgetExceeded { limit: 1 },
+                "budget_exceeded",
+                false,
+            ),
+            (
+                DownloadError::UnsupportedCacheFormat {
+                    found: 4,
+                    supported: 3,
+                },
+                "unsupported_cache_format",
+                false,
+            ),
+            (
+                DownloadError::RetriesExhausted {
+                    attempts: Vec::new(),
+                },
+                "retries_exhausted",
+                false,
+            ),
+        ];
+
+        for (error, code, retriable) in cases {
+            // Assert
+
+            assert_eq!(error.code(), code);
+            assert_eq!(error.is_retriable(), retriable, "{code}");
+        }
+    }
+}
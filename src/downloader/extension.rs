@@ -0,0 +1,139 @@
+// Extensions end up in file names next to the cache key, so anything taken
+// from a URL or a header is reduced to a short run of `[a-z0-9_-]`.
+const MAX_LEN: usize = 16;
+
+// Compound extensions kept as one, under the short name tools recognise.
+const COMPOUND: &[(&str, &str)] = &[
+    ("tar.gz", "tgz"),
+    ("tar.bz2", "tbz2"),
+    ("tar.xz", "txz"),
+    ("tar.zst", "tzst"),
+];
+
+// `raw` is whatever follows the first dot of a file name, or a MIME subtype,
+// possibly still carrying a query, a fragment or parameters: `jpg?width=800`
+// gives `jpg`, `tar.gz` gives `tgz` and `min.js` gives `js`.
+pub(crate) fn sanitize_extension(raw: &str) -> Option<String> {
+    let lowercase = raw.to_ascii_lowercase();
+
+    let end = ["?", "#", ";", "%3f", "%23"]
+        .iter()
+        .filter_map(|remnant| lowercase.find(remnant))
+        .min()
+        .unwrap_or(lowercase.len());
+
+    let extension = lowercase[..end].trim().trim_matches('.').to_string();
+
+    let extension = COMPOUND
+        .iter()
+        .find(|(compound, _)| extension.ends_with(compound))
+        .map(|(_, short)| short.to_string())
+        .unwrap_or_else(|| match extension.rsplit_once('.') {
+            Some((_, last)) => last.to_string(),
+            None => extension,
+        });
+
+    is_safe(&extension).then_some(extension)
+}
+
+fn is_safe(extension: &str) -> bool {
+    (1..=MAX_LEN).contains(&extension.len())
+        && extension
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize_extension, MAX_LEN};
+
+    #[test]
+    fn test_sanitize_extension() {
+        let cases = [
+            ("jpg", Some("jpg")),
+            ("JPG", Some("jpg")),
+            ("jpg?width=800", Some("jpg")),
+            ("png#top", Some("png")),
+            ("jpg%3Fwidth=800", Some("jpg")),
+            ("tar.gz", Some("tgz")),
+            ("backup.tar.gz?v=2", Some("tgz")),
+            ("min.js", Some("js")),
+            ("x-icon", Some("x-icon")),
+            ("png; charset=binary", Some("png")),
+            ("", None),
+            ("?width=800", None),
+            ("..", None),
+            ("../../etc/passwd", None),
+            ("exe\0", None),
+            ("a very long extension name", None),
+        ];
+
+        for (raw, expected) in cases {
+            // Act
+
+            let extension = sanitize_extension(raw);
+
+            // Assert
+
+            assert_eq!(extension.as_deref(), expected, "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn test_adversarial_extensions_are_safe_path_components() {
+        let pieces = [
+            "",
+            ".",
+            "..",
+            "/",
+            "\\",
+            "\0",
+            "?",
+            "#",
+            "%2F",
+            "%3F",
+            "%00",
+            " ",
+            "\u{202e}",
+            "é",
+            "tar",
+            "gz",
+            "JPG",
+            "a",
+            ":",
+            "*",
+            "\n",
+            "-",
+            "_",
+            "x".repeat(40).leak(),
+        ];
+
+        // Act
+
+        for first in pieces {
+            for second in pieces {
+                for third in pieces {
+                    let raw = format!("{first}{second}{third}");
+
+                    let Some(extension) = sanitize_extension(&raw) else {
+                        continue;
+                    };
+
+                    // Assert
+
+                    assert!(
+                        !extension.is_empty() && extension.len() <= MAX_LEN,
+                        "{raw:?}"
+                    );
+                    assert!(
+                        extension.bytes().all(|byte| byte.is_ascii_lowercase()
+                            || byte.is_ascii_digit()
+                            || byte == b'-'
+                            || byte == b'_'),
+                        "{raw:?} gave {extension:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::{error::Error, fmt, io};
+
+pub type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+// Transport-level failures: no HTTP response was obtained at all.
+#[derive(Debug)]
+pub enum FetchError {
+    Dns(BoxError),
+    Connect(BoxError),
+    Tls(BoxError),
+    Io(io::Error),
+    Timeout(BoxError),
+    Other(String),
+}
+
+impl FetchError {
+    pub fn dns(error: impl Into<BoxError>) -> Self {
+        Self::Dns(error.into())
+    }
+
+    pub fn connect(error: impl Into<BoxError>) -> Self {
+        Self::Connect(error.into())
+    }
+
+    pub fn tls(error: impl Into<BoxError>) -> Self {
+        Self::Tls(error.into())
+    }
+
+    pub fn timeout(error: impl Into<BoxError>) -> Self {
+        Self::Timeout(error.into())
+    }
+
+    pub fn io(kind: io::ErrorKind) -> Self {
+        Self::Io(io::Error::from(kind))
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dns(error) => write!(f, "dns lookup failed: {error}"),
+            Self::Connect(error) => write!(f, "connection failed: {error}"),
+            Self::Tls(error) => write!(f, "tls handshake failed: {error}"),
+            Self::Io(error) => write!(f, "i/o error: {error}"),
+            Self::Timeout(error) => write!(f, "timed out: {error}"),
+            Self::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for FetchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Dns(error) | Self::Connect(error) | Self::Tls(error) | Self::Timeout(error) => {
+                Some(error.as_ref())
+            }
+            Self::Io(error) => Some(error),
+            Self::Other(_) => None,
+        }
+    }
+}
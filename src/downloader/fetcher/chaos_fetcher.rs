@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    io::{self, Cursor, Read},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use super::{Body, FetchError, FileDownloader, Response};
+
+// How a `ChaosFetcher` misbehaves for the URLs a rule matches.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Chaos {
+    truncate_at: Option<usize>,
+    latency: Duration,
+    fail_first: u32,
+    drop_content_type: bool,
+}
+
+impl Chaos {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The connection drops after `bytes` of the body, which reads as an
+    // error rather than a short body.
+    pub fn truncate_at(mut self, bytes: usize) -> Self {
+        self.truncate_at = Some(bytes);
+        self
+    }
+
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    // The first `calls` to each URL fail to connect.
+    pub fn fail_first(mut self, calls: u32) -> Self {
+        self.fail_first = calls;
+        self
+    }
+
+    pub fn drop_content_type(mut self) -> Self {
+        self.drop_content_type = true;
+        self
+    }
+}
+
+// Wraps a fetcher to simulate a bad network for URLs matching a pattern, where
+// `*` stands for any run of characters. The first matching rule applies.
+pub struct ChaosFetcher<T> {
+    inner: T,
+    rules: Vec<(String, Chaos)>,
+    calls: Mutex<HashMap<String, u32>>,
+}
+
+impl<T: FileDownloader> ChaosFetcher<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            rules: Vec::new(),
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn rule(mut self, pattern: &str, chaos: Chaos) -> Self {
+        self.rules.push((pattern.to_string(), chaos));
+        self
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: FileDownloader> FileDownloader for ChaosFetcher<T> {
+    fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+        let Some((_, chaos)) = self.rules.iter().find(|(pattern, _)| matches(pattern, url)) else {
+            return self.inner.fetch(url, headers);
+        };
+
+        if !chaos.latency.is_zero() {
+            thread::sleep(chaos.latency);
+        }
+
+        let call = {
+            let mut calls = self.calls.lock().unwrap();
+            let call = calls.entry(url.to_string()).or_default();
+            *call += 1;
+            *call
+        };
+
+        if call <= chaos.fail_first {
+            return Err(FetchError::connect(format!(
+                "chaos: call {call} to {url} refused"
+            )));
+        }
+
+        let mut response = self.inner.fetch(url, headers)?;
+
+        if chaos.drop_content_type {
+            response
+                .headers
+                .retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Type"));
+        }
+
+        if let Some(bytes) = chaos.truncate_at {
+            response.body = truncate(std::mem::take(&mut response.body), bytes);
+        }
+
+        Ok(response)
+    }
+
+    fn head(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+        self.inner.head(url, headers)
+    }
+
+    fn send_body(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: Body,
+    ) -> Result<Response, FetchError> {
+        self.inner.send_body(method, url, headers, body)
+    }
+
+    fn schemes(&self) -> &[&str] {
+        self.inner.schemes()
+    }
+}
+
+fn truncate(body: Body, bytes: usize) -> Body {
+    let reader: Box<dyn Read + Send> = match body {
+        Body::Bytes(body) if body.len() <= bytes => return Body::Bytes(body),
+        Body::Bytes(body) => Box::new(Cursor::new(body)),
+        Body::Reader(reader) => reader,
+    };
+
+    Body::Reader(Box::new(DroppedReader {
+        reader,
+        left: bytes,
+    }))
+}
+
+struct DroppedReader {
+    reader: Box<dyn Read + Send>,
+    left: usize,
+}
+
+impl Read for DroppedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "chaos: connection dropped",
+            ));
+        }
+
+        let limit = buf.len().min(self.left);
+
+        let read = self.reader.read(&mut buf[..limit])?;
+
+        self.left -= read;
+
+        Ok(read)
+    }
+}
+
+fn matches(pattern: &str, url: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    let first = parts.next().unwrap_or_default();
+
+    let Some(mut rest) = url.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        time::{Duration, Instant},
+    };
+
+    use super::{matches, Chaos, ChaosFetcher};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, Downloader, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://cdn.example.com/photos/cat.png";
+
+    fn downloader(name: &str, chaos: Chaos) -> Downloader<ChaosFetcher<MockFetcher>> {
+        let responses = (0..2)
+            .map(|_| Response::ok(b"0123456789".to_vec(), Some("image/png".to_string())))
+            .collect();
+
+        let fetcher = ChaosFetcher::new(MockFetcher::new(responses))
+            .rule("https://other.example/*", Chaos::new().fail_first(9))
+            .rule("https://cdn.example.com/*.png", chaos);
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), fetcher)
+            .retry_invalid_bodies(0)
+            .build()
+    }
+
+    #[test]
+    fn test_patterns() {
+        // Assert
+
+        assert!(matches("*", URL));
+        assert!(matches("https://cdn.example.com/*.png", URL));
+        assert!(matches("*/photos/*", URL));
+        assert!(matches(URL, URL));
+        assert!(!matches("*.jpg", URL));
+        assert!(!matches("https://example.com/*", URL));
+    }
+
+    #[test]
+    fn test_truncated_bodies_are_invalid() {
+        let downloader = downloader("chaos_truncate", Chaos::new().truncate_at(4));
+
+        // Act
+
+        let error = downloader.download(URL).unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            error,
+            DownloadError::TruncatedBody {
+                received: 4,
+                kind: io::ErrorKind::ConnectionReset,
+            }
+        );
+    }
+
+    #[test]
+    fn test_first_calls_fail_to_connect() {
+        let downloader = downloader("chaos_fail_first", Chaos::new().fail_first(1));
+
+        // Act
+
+        let first = downloader.download(URL);
+
+        let second = downloader.download(URL);
+
+        // Assert
+
+        assert!(matches!(first, Err(DownloadError::Connect(_))), "{first:?}");
+        assert!(second.is_ok());
+        assert_eq!(downloader.fetcher().inner().calls(), 1);
+    }
+
+    #[test]
+    fn test_latency_delays_the_response() {
+        let downloader = downloader(
+            "chaos_latency",
+            Chaos::new().latency(Duration::from_millis(50)),
+        );
+
+        let started = Instant::now();
+
+        // Act
+
+        let result = downloader.download(URL);
+
+        // Assert
+
+        assert!(result.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_dropped_content_type_falls_back_to_sniffing() {
+        let downloader = downloader("chaos_content_type", Chaos::new().drop_content_type());
+
+        // Act
+
+        let download = downloader.download(URL).unwrap();
+
+        let unmatched = downloader
+            .download("https://cdn.example.com/photos/cat")
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(download.mime(), None);
+        assert_eq!(unmatched.mime(), Some("image/png"));
+    }
+}
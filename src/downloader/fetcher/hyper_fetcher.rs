@@ -0,0 +1,410 @@
+use std::{
+    error::Error,
+    future::Future,
+    io::{self, Read},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use http_body_util::{BodyExt, Empty};
+use hyper::{
+    body::{Bytes, Incoming},
+    Method, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use tokio::{
+    runtime::{self, Handle, Runtime},
+    sync::Semaphore,
+    time,
+};
+use url::Url;
+
+use super::{ureq_fetcher::MAX_REDIRECTS, Body, FetchError, FileDownloader, Follow, Response};
+
+// Chunks buffered between the connection and a body that is not read yet.
+const BODY_CHUNKS: usize = 16;
+
+// Unless `timeouts` sets others.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Fetches over HTTP/2 when the server offers it through ALPN, HTTP/1.1
+// otherwise. Connections are pooled and reused across calls, including the
+// streams of one multiplexed HTTP/2 connection.
+pub struct HyperFetcher {
+    runtime: OwnRuntime,
+    // `None` trusts the Mozilla root certificates.
+    tls: Option<rustls::ClientConfig>,
+    client: Client<HttpsConnector<HttpConnector>, Empty<Bytes>>,
+    read_timeout: Duration,
+}
+
+impl FileDownloader for HyperFetcher {
+    fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+        self.send(Method::GET, url, headers, None, &|_| Ok(headers.to_vec()))
+    }
+
+    fn fetch_within(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        timeout: Duration,
+    ) -> Result<Response, FetchError> {
+        let deadline = Some(Instant::now() + timeout);
+
+        self.send(Method::GET, url, headers, deadline, &|_| {
+            Ok(headers.to_vec())
+        })
+    }
+
+    fn fetch_following(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        timeout: Option<Duration>,
+        follow: &Follow<'_>,
+    ) -> Result<Response, FetchError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        self.send(Method::GET, url, headers, deadline, follow)
+    }
+
+    fn head(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+        self.send(Method::HEAD, url, headers, None, &|_| Ok(headers.to_vec()))
+    }
+}
+
+impl HyperFetcher {
+    // Redirects are followed here, the same way `UReqFetcher` does. Every
+    // hop, body included, ends by `deadline`.
+    fn send(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(String, String)],
+        deadline: Option<Instant>,
+        follow: &Follow<'_>,
+    ) -> Result<Response, FetchError> {
+        let mut url = url.to_string();
+
+        let mut redirects = Vec::new();
+
+        let mut headers = headers.to_vec();
+
+        loop {
+            let request = headers
+                .iter()
+                .filter(|(key, _)| {
+                    redirects.is_empty() || !key.eq_ignore_ascii_case("Authorization")
+                })
+                .fold(
+                    Request::builder().method(method.clone()).uri(url.as_str()),
+                    |request, (key, value)| request.header(key, value),
+                )
+                .body(Empty::new())
+                .map_err(|error| FetchError::Other(format!("{url}: {error}")))?;
+
+            let response = self
+                .run(
+                    self.client.request(request),
+                    wait(self.read_timeout, deadline),
+                )
+                .ok_or_else(|| FetchError::timeout(format!("{url}: timed out")))?
+                .map_err(|error| into_fetch_error(&url, error))?;
+
+            let status = response.status().as_u16();
+
+            let location = response
+                .headers()
+                .get("Location")
+                .and_then(|location| location.to_str().ok())
+                .filter(|_| matches!(status, 301 | 302 | 303 | 307 | 308))
+                .and_then(|location| Url::parse(&url).ok()?.join(location).ok());
+
+            match location {
+                Some(_) if redirects.len() == MAX_REDIRECTS => {
+                    return Err(FetchError::Other(format!(
+                        "{url}: more than {MAX_REDIRECTS} redirects"
+                    )))
+                }
+                Some(location) => {
+                    headers = follow(&location)?;
+
+                    url = location.to_string();
+
+                    redirects.push((status, url.clone()));
+                }
+                None => {
+                    let mut response = self.stream_response(response, deadline);
+
+                    response.redirects = redirects;
+
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    // Trusts the Mozilla root certificates.
+    pub fn new() -> Self {
+        Self::with_tls(None)
+    }
+
+    // For custom roots or client certificates. ALPN is set up here, so
+    // `config` must not name any protocols itself.
+    pub fn with_tls_config(config: rustls::ClientConfig) -> Self {
+        Self::with_tls(Some(config))
+    }
+
+    // How long connecting may take, and how long a response or the next
+    // chunk of its body may keep the fetcher waiting.
+    pub fn timeouts(mut self, connect: Duration, read: Duration) -> Self {
+        self.client = client(self.tls.clone(), connect);
+        self.read_timeout = read;
+        self
+    }
+
+    fn with_tls(tls: Option<rustls::ClientConfig>) -> Self {
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("hyper-fetcher")
+            .enable_all()
+            .build()
+            .expect("the fetcher runtime starts");
+
+        Self {
+            runtime: OwnRuntime(Some(runtime)),
+            client: client(tls.clone(), CONNECT_TIMEOUT),
+            tls,
+            read_timeout: READ_TIMEOUT,
+        }
+    }
+
+    fn runtime(&self) -> &Runtime {
+        self.runtime
+            .0
+            .as_ref()
+            .expect("the runtime lives as long as the fetcher")
+    }
+
+    // Runs `future` on the fetcher's runtime for `within` at most, `None`
+    // once that passed. Blocking on a runtime from inside another panics, so
+    // callers already in one wait for the fetcher's runtime thread instead.
+    fn run<F>(&self, future: F, within: Duration) -> Option<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        // Timers are created once polled, on the fetcher's runtime.
+        let future = async move { time::timeout(within, future).await.ok() };
+
+        if Handle::try_current().is_err() {
+            return self.runtime().block_on(future);
+        }
+
+        let (sender, output) = mpsc::channel();
+
+        self.runtime().spawn(async move {
+            let _ = sender.send(future.await);
+        });
+
+        output.recv().ok().flatten()
+    }
+
+    // The body keeps streaming on the runtime and is handed over in chunks,
+    // so it can be read from any thread, inside a runtime or not. An empty
+    // chunk marks the end, anything else closing the channel is a cut body.
+    fn stream_response(
+        &self,
+        response: hyper::Response<Incoming>,
+        deadline: Option<Instant>,
+    ) -> Response {
+        let (parts, mut incoming) = response.into_parts();
+
+        let headers = parts
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+
+        let (chunks, received) = mpsc::channel();
+
+        // Permits for the chunks that may be buffered, handed back as they
+        // are read.
+        let room = Arc::new(Semaphore::new(BODY_CHUNKS));
+
+        let read_timeout = self.read_timeout;
+
+        self.runtime().spawn({
+            let room = Arc::clone(&room);
+
+            async move {
+                // Closed once the reader is dropped.
+                while let Ok(permit) = room.acquire().await {
+                    permit.forget();
+
+                    let frame = time::timeout(wait(read_timeout, deadline), incoming.frame());
+
+                    let chunk = match frame.await {
+                        Err(_) => Err(io::ErrorKind::TimedOut.into()),
+                        Ok(None) => Ok(Bytes::new()),
+                        Ok(Some(Ok(frame))) => match frame.into_data() {
+                            Ok(data) if !data.is_empty() => Ok(data),
+                            _ => {
+                                room.add_permits(1);
+                                continue;
+                            }
+                        },
+                        Ok(Some(Err(error))) => Err(io::Error::other(error)),
+                    };
+
+                    let last = !matches!(&chunk, Ok(data) if !data.is_empty());
+
+                    if chunks.send(chunk).is_err() || last {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Response {
+            status: parts.status.as_u16(),
+            headers,
+            body: Body::Reader(Box::new(ChunkReader {
+                received,
+                room,
+                chunk: Bytes::new(),
+                finished: false,
+            })),
+            redirects: Vec::new(),
+        }
+    }
+}
+
+impl Default for HyperFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn client(
+    tls: Option<rustls::ClientConfig>,
+    connect_timeout: Duration,
+) -> Client<HttpsConnector<HttpConnector>, Empty<Bytes>> {
+    let mut http = HttpConnector::new();
+
+    http.enforce_http(false);
+    http.set_connect_timeout(Some(connect_timeout));
+
+    let connector = match tls {
+        Some(config) => HttpsConnectorBuilder::new().with_tls_config(config),
+        None => HttpsConnectorBuilder::new()
+            .with_provider_and_webpki_roots(Arc::new(rustls::crypto::ring::default_provider()))
+            .expect("ring supports the default protocol versions"),
+    };
+
+    let connector = connector
+        .https_or_http()
+        .enable_all_versions()
+        .wrap_connector(http);
+
+    Client::builder(TokioExecutor::new()).build(connector)
+}
+
+// The read timeout, cut short where the deadline would pass first.
+fn wait(read_timeout: Duration, deadline: Option<Instant>) -> Duration {
+    deadline.map_or(read_timeout, |deadline| {
+        read_timeout.min(deadline.saturating_duration_since(Instant::now()))
+    })
+}
+
+// Dropping a runtime from inside another one panics, shutting it down in the
+// background does not.
+struct OwnRuntime(Option<Runtime>);
+
+impl Drop for OwnRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+struct ChunkReader {
+    received: mpsc::Receiver<io::Result<Bytes>>,
+    room: Arc<Semaphore>,
+    chunk: Bytes,
+    finished: bool,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.chunk.is_empty() && !self.finished {
+            match self.received.recv() {
+                Ok(chunk) => self.chunk = chunk?,
+                Err(_) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            }
+
+            self.room.add_permits(1);
+
+            self.finished = self.chunk.is_empty();
+        }
+
+        let read = buf.len().min(self.chunk.len());
+
+        buf[..read].copy_from_slice(&self.chunk.split_to(read));
+
+        Ok(read)
+    }
+}
+
+impl Drop for ChunkReader {
+    fn drop(&mut self) {
+        self.room.close();
+    }
+}
+
+// hyper only says whether connecting failed, the stage is found in the chain
+// of sources: the resolver, rustls or the socket.
+fn into_fetch_error(url: &str, error: hyper_util::client::legacy::Error) -> FetchError {
+    let mut message = format!("{url}: {error}");
+
+    let mut source = error.source();
+
+    let mut dns = false;
+
+    let mut tls = false;
+
+    let mut timed_out = false;
+
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+
+        dns |= cause.to_string() == "dns error";
+
+        tls |= cause.is::<rustls::Error>();
+
+        timed_out |= cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|error| error.kind() == io::ErrorKind::TimedOut);
+
+        source = cause.source();
+    }
+
+    match () {
+        _ if timed_out => FetchError::timeout(message),
+        _ if dns => FetchError::dns(message),
+        _ if tls => FetchError::tls(message),
+        _ if error.is_connect() => FetchError::connect(message),
+        _ => FetchError::Other(message),
+    }
+}
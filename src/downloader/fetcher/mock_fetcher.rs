@@ -1,17 +1,30 @@
-use std::cell::RefCell;
+use std::{sync::Mutex, thread, time::Duration};
 
-use super::{FileDownloader, Response};
+use super::{FetchError, FileDownloader, Response};
+
+type Call = (String, Vec<(String, String)>);
 
 pub struct MockFetcher {
-    responses: RefCell<Vec<Response>>,
+    responses: Mutex<Vec<Result<Response, FetchError>>>,
+    calls: Mutex<Vec<Call>>,
+    delay: Duration,
 }
 
 impl FileDownloader for MockFetcher {
-    fn fetch(&self, _url: &str) -> Response {
-        let mut responses = self.responses.borrow_mut();
+    fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push((url.to_string(), headers.to_vec()));
+
+        if !self.delay.is_zero() {
+            thread::sleep(self.delay);
+        }
+
+        let mut responses = self.responses.lock().unwrap();
 
         if responses.is_empty() {
-            Response::network_error()
+            Err(FetchError::Other("no scripted response left".to_string()))
         } else {
             responses.remove(0)
         }
@@ -20,8 +33,31 @@ impl FileDownloader for MockFetcher {
 
 impl MockFetcher {
     pub fn new(responses: Vec<Response>) -> Self {
+        Self::scripted(responses.into_iter().map(Ok).collect())
+    }
+
+    pub fn scripted(responses: Vec<Result<Response, FetchError>>) -> Self {
         Self {
-            responses: RefCell::new(responses),
+            responses: Mutex::new(responses),
+            calls: Mutex::new(Vec::new()),
+            delay: Duration::ZERO,
         }
     }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    pub fn requested_url(&self, call: usize) -> String {
+        self.calls.lock().unwrap()[call].0.clone()
+    }
+
+    pub fn request_headers(&self, call: usize) -> Vec<(String, String)> {
+        self.calls.lock().unwrap()[call].1.clone()
+    }
 }
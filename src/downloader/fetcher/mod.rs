@@ -1,11 +1,21 @@
+#[cfg(any(test, feature = "test-util"))]
+mod chaos_fetcher;
+#[cfg(feature = "http2")]
+mod hyper_fetcher;
+mod tls_roots;
 mod ureq_fetcher;
 
-use super::{FileDownloader, Response};
+use super::{Body, FetchError, FileDownloader, Follow, Response};
 
+#[cfg(feature = "test-util")]
+pub use chaos_fetcher::{Chaos, ChaosFetcher};
+#[cfg(feature = "http2")]
+pub use hyper_fetcher::HyperFetcher;
+pub use tls_roots::TlsRoots;
 pub use ureq_fetcher::UReqFetcher;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 mod mock_fetcher;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 pub use mock_fetcher::MockFetcher;
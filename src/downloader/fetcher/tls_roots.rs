@@ -0,0 +1,84 @@
+use std::{io, sync::Arc};
+
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer},
+    ClientConfig, RootCertStore,
+};
+
+// The certificates `UReqFetcher` trusts servers by.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum TlsRoots {
+    // The Mozilla roots compiled into ureq, for hosts without a CA bundle.
+    #[default]
+    Bundled,
+    // The operating system's store, with the roots a corporate setup adds.
+    #[cfg(feature = "native-roots")]
+    Native,
+    Custom(Vec<CertificateDer<'static>>),
+}
+
+impl TlsRoots {
+    // Every certificate in a PEM bundle, such as a private CA's.
+    pub fn from_pem(pem: &[u8]) -> io::Result<Self> {
+        let certificates = CertificateDer::pem_slice_iter(pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        Ok(Self::Custom(certificates))
+    }
+
+    // Names the store in error messages.
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Self::Bundled => "bundled roots",
+            #[cfg(feature = "native-roots")]
+            Self::Native => "native roots",
+            Self::Custom(_) => "custom roots",
+        }
+    }
+
+    // `None` leaves ureq to its own configuration. Failing stores are
+    // described, to be reported by every fetch.
+    pub(super) fn client_config(&self) -> Result<Option<Arc<ClientConfig>>, String> {
+        let mut roots = RootCertStore::empty();
+
+        match self {
+            Self::Bundled => return Ok(None),
+            #[cfg(feature = "native-roots")]
+            Self::Native => {
+                let loaded = rustls_native_certs::load_native_certs();
+
+                let (added, _) = roots.add_parsable_certificates(loaded.certs);
+
+                if added == 0 {
+                    return Err(match loaded.errors.first() {
+                        Some(error) => {
+                            format!("no usable certificate in the system store: {error}")
+                        }
+                        None => "no certificate in the system store".to_string(),
+                    });
+                }
+            }
+            Self::Custom(certificates) => {
+                if certificates.is_empty() {
+                    return Err("no certificate given".to_string());
+                }
+
+                for certificate in certificates {
+                    roots
+                        .add(certificate.clone())
+                        .map_err(|error| format!("unusable certificate: {error}"))?;
+                }
+            }
+        }
+
+        let config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|error| error.to_string())?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+        Ok(Some(Arc::new(config)))
+    }
+}
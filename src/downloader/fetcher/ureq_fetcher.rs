@@ -1,49 +1,227 @@
-use ureq::Error::Status;
+use std::{
+    error::Error,
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use super::{FileDownloader, Response};
+use ureq::{
+    Error::{Status, Transport},
+    ErrorKind,
+};
 
-use std::io::Read;
+use url::Url;
 
-pub struct UReqFetcher;
+use super::{Body, FetchError, FileDownloader, Follow, Response, TlsRoots};
 
-impl FileDownloader for UReqFetcher {
-    fn fetch(&self, url: &str) -> Response {
-        let request = ureq::request("GET", url);
+// What ureq follows by default.
+pub(super) const MAX_REDIRECTS: usize = 5;
+
+pub struct UReqFetcher {
+    roots: &'static str,
+    tls: Result<Option<Arc<rustls::ClientConfig>>, String>,
+}
 
-        // TODO: Add headers
+impl FileDownloader for UReqFetcher {
+    fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+        self.send("GET", url, headers, None, &|_| Ok(headers.to_vec()))
+    }
 
-        // let request = headers
-        //     .iter()
-        //     .fold(request, |request, (key, value)| request.set(key, value));
+    fn fetch_within(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        timeout: Duration,
+    ) -> Result<Response, FetchError> {
+        self.send("GET", url, headers, Some(Instant::now() + timeout), &|_| {
+            Ok(headers.to_vec())
+        })
+    }
 
-        let response = request.call();
+    fn fetch_following(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        timeout: Option<Duration>,
+        follow: &Follow<'_>,
+    ) -> Result<Response, FetchError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
 
-        match response {
-            Ok(response) => {
-                let mime = response.header("Content-Type").map(str::to_string);
+        self.send("GET", url, headers, deadline, follow)
+    }
 
-                let body = response
-                    .into_reader()
-                    .bytes()
-                    .collect::<Result<Vec<u8>, _>>();
+    fn head(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+        self.send("HEAD", url, headers, None, &|_| Ok(headers.to_vec()))
+    }
 
-                let Ok(body) = body else {
-                    return Response::invalid_body();
-                };
+    fn send_body(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: Body,
+    ) -> Result<Response, FetchError> {
+        let agent = self.agent()?;
 
-                Response::ok(body, mime)
-            }
+        let request = headers
+            .iter()
+            .fold(agent.request(method, url), |request, (key, value)| {
+                request.set(key, value)
+            });
 
-            Err(Status(404, _)) => Response::not_found(),
+        let response = match body {
+            Body::Bytes(bytes) => request.send_bytes(&bytes),
+            Body::Reader(reader) => request.send(reader),
+        };
 
-            Err(_) => Response::network_error(),
+        match response {
+            Ok(response) | Err(Status(_, response)) => Ok(Self::into_response(response)),
+            Err(Transport(transport)) => Err(self.fetch_error(transport)),
         }
     }
 }
 
 impl UReqFetcher {
     pub fn new() -> Self {
-        UReqFetcher
+        Self::with_tls_roots(TlsRoots::default())
+    }
+
+    // A store that cannot be used fails every https fetch with a TLS error
+    // telling why.
+    pub fn with_tls_roots(roots: TlsRoots) -> Self {
+        UReqFetcher {
+            roots: roots.name(),
+            tls: roots.client_config(),
+        }
+    }
+
+    fn agent(&self) -> Result<ureq::Agent, FetchError> {
+        let agent = ureq::AgentBuilder::new().redirects(0);
+
+        match &self.tls {
+            Ok(Some(config)) => Ok(agent.tls_config(Arc::clone(config)).build()),
+            Ok(None) => Ok(agent.build()),
+            Err(reason) => Err(FetchError::tls(format!("{}: {reason}", self.roots))),
+        }
+    }
+
+    // Redirects are followed here rather than by ureq, which does not tell
+    // which hops it took. Every hop, body included, ends by `deadline`, and
+    // none is followed without `follow` agreeing and giving its headers.
+    fn send(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        deadline: Option<Instant>,
+        follow: &Follow<'_>,
+    ) -> Result<Response, FetchError> {
+        let agent = self.agent()?;
+
+        let mut url = url.to_string();
+
+        let mut redirects = Vec::new();
+
+        let mut headers = headers.to_vec();
+
+        loop {
+            let request = match deadline {
+                Some(deadline) => agent
+                    .request(method, &url)
+                    .timeout(deadline.saturating_duration_since(Instant::now())),
+                None => agent.request(method, &url),
+            };
+
+            // Like ureq, credentials are not forwarded to where a redirect
+            // points.
+            let request = headers
+                .iter()
+                .filter(|(key, _)| {
+                    redirects.is_empty() || !key.eq_ignore_ascii_case("Authorization")
+                })
+                .fold(request, |request, (key, value)| request.set(key, value));
+
+            // ureq reports 4xx/5xx as errors, but they still carry a full
+            // response that the downloader classifies.
+            let response = match request.call() {
+                Ok(response) | Err(Status(_, response)) => response,
+
+                Err(Transport(transport)) => return Err(self.fetch_error(transport)),
+            };
+
+            let status = response.status();
+
+            let location = response
+                .header("Location")
+                .filter(|_| matches!(status, 301 | 302 | 303 | 307 | 308))
+                .and_then(|location| Url::parse(&url).ok()?.join(location).ok());
+
+            match location {
+                Some(_) if redirects.len() == MAX_REDIRECTS => {
+                    return Err(FetchError::Other(format!(
+                        "{url}: more than {MAX_REDIRECTS} redirects"
+                    )))
+                }
+                Some(location) => {
+                    headers = follow(&location)?;
+
+                    url = location.to_string();
+
+                    redirects.push((status, url.clone()));
+                }
+                None => {
+                    let mut response = Self::into_response(response);
+
+                    response.redirects = redirects;
+
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    fn fetch_error(&self, transport: ureq::Transport) -> FetchError {
+        let timed_out = transport
+            .source()
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .is_some_and(|error| error.kind() == io::ErrorKind::TimedOut);
+
+        // ureq reports failed TLS handshakes as connection failures, the
+        // message is the only thing telling them apart.
+        let tls = transport
+            .message()
+            .is_some_and(|message| message.starts_with("tls"));
+
+        match transport.kind() {
+            _ if timed_out => FetchError::timeout(transport),
+            ErrorKind::Dns => FetchError::dns(transport),
+            ErrorKind::ConnectionFailed if tls => {
+                FetchError::tls(format!("{}: {transport}", self.roots))
+            }
+            ErrorKind::ConnectionFailed | ErrorKind::ProxyConnect => FetchError::connect(transport),
+            ErrorKind::Io => FetchError::Io(io::Error::other(transport)),
+            _ => FetchError::Other(transport.to_string()),
+        }
+    }
+
+    fn into_response(response: ureq::Response) -> Response {
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+
+        let status = response.status();
+
+        Response {
+            status,
+            headers,
+            body: Body::Reader(Box::new(response.into_reader())),
+            redirects: Vec::new(),
+        }
     }
 }
 
@@ -52,3 +230,353 @@ impl Default for UReqFetcher {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use rcgen::CertifiedKey;
+    use rustls::{pki_types::PrivateKeyDer, ServerConfig, ServerConnection, StreamOwned};
+
+    use super::{FetchError, FileDownloader, TlsRoots, UReqFetcher};
+    use crate::downloader::{
+        fixtures, testing, CachePolicy, DownloadError, DownloaderBuilder, Storage,
+    };
+
+    // Sends `/a` and `/b` through `/hop` to `/logo.png`, recording every path
+    // it is asked for.
+    fn redirecting_server() -> (Arc<Mutex<Vec<String>>>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        let paths = Arc::new(Mutex::new(Vec::new()));
+
+        let (recorded, target) = (Arc::clone(&paths), format!("{base}/logo.png"));
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                respond(stream, &recorded, &target);
+            }
+        });
+
+        (paths, base)
+    }
+
+    fn respond(mut stream: TcpStream, paths: &Mutex<Vec<String>>, target: &str) {
+        let mut line = String::new();
+
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        reader.read_line(&mut line).unwrap();
+
+        let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+
+        let (head, body) = match path.as_str() {
+            "/a" | "/b" => ("302 Found\r\nLocation: /hop".to_string(), &[][..]),
+            "/hop" => (
+                format!("301 Moved Permanently\r\nLocation: {target}"),
+                &[][..],
+            ),
+            _ => (
+                "200 OK\r\nContent-Type: image/png".to_string(),
+                fixtures::PNG,
+            ),
+        };
+
+        paths.lock().unwrap().push(path);
+
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {head}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        let _ = stream.write_all(body);
+    }
+
+    // Serves the PNG fixture over https with a self-signed certificate for
+    // `localhost`, returned as PEM.
+    fn tls_server() -> (String, String) {
+        let CertifiedKey { cert, signing_key } =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+
+        let config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![cert.der().clone()],
+                    PrivateKeyDer::try_from(signing_key.serialize_der()).unwrap(),
+                )
+                .unwrap();
+
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let base = format!(
+            "https://localhost:{}",
+            listener.local_addr().unwrap().port()
+        );
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let connection = ServerConnection::new(Arc::clone(&config)).unwrap();
+
+                let mut stream = StreamOwned::new(connection, stream);
+
+                let mut reader = BufReader::new(&mut stream);
+
+                let mut line = String::new();
+
+                // Clients refusing the certificate end the handshake here.
+                while matches!(reader.read_line(&mut line), Ok(read) if read > 2) {
+                    line.clear();
+                }
+
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    fixtures::PNG.len()
+                );
+                let _ = stream.write_all(fixtures::PNG);
+
+                stream.conn.send_close_notify();
+
+                let _ = stream.flush();
+            }
+        });
+
+        (base, cert.pem())
+    }
+
+    #[test]
+    fn test_custom_roots_trust_a_private_certificate() {
+        let (base, pem) = tls_server();
+
+        let fetcher = UReqFetcher::with_tls_roots(TlsRoots::from_pem(pem.as_bytes()).unwrap());
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("tls_custom_roots"), fetcher)
+                .build();
+
+        // Act
+
+        let download = downloader.download(&format!("{base}/logo.png")).unwrap();
+
+        // Assert
+
+        assert_eq!(download.bytes().unwrap(), fixtures::PNG);
+    }
+
+    #[test]
+    fn test_tls_errors_name_the_store() {
+        let (base, _) = tls_server();
+
+        let url = format!("{base}/logo.png");
+
+        let fetchers = [
+            (UReqFetcher::new(), "bundled roots: "),
+            (
+                UReqFetcher::with_tls_roots(TlsRoots::Custom(Vec::new())),
+                "custom roots: no certificate given",
+            ),
+        ];
+
+        for (fetcher, expected) in fetchers {
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("tls_errors"), fetcher).build();
+
+            // Act
+
+            let error = downloader.download(&url).unwrap_err();
+
+            // Assert
+
+            let message = error.to_string();
+
+            assert!(matches!(error, DownloadError::Tls(_)), "{error:?}");
+            assert!(
+                message.starts_with(&format!("tls handshake failed: {expected}")),
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_connection_refused_is_a_connect_error() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let url = format!("http://127.0.0.1:{port}/image.png");
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("connection_refused"),
+            UReqFetcher::new(),
+        )
+        .build();
+
+        // Act
+
+        let error = UReqFetcher::new().fetch(&url, &[]).unwrap_err();
+
+        let message = downloader.download(&url).unwrap_err().to_string();
+
+        // Assert
+
+        assert!(matches!(error, FetchError::Connect(_)), "{error:?}");
+        assert!(message.starts_with("connection failed: "), "{message}");
+        assert!(message.contains(&url), "{message}");
+        assert!(message.contains("refused"), "{message}");
+    }
+
+    #[test]
+    fn test_fetches_within_a_deadline_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let url = format!("http://{}/stalled.png", listener.local_addr().unwrap());
+
+        // Accepts, then never answers.
+        thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+
+        let started = Instant::now();
+
+        // Act
+
+        let error = UReqFetcher::new()
+            .fetch_within(&url, &[], Duration::from_millis(200))
+            .unwrap_err();
+
+        // Assert
+
+        assert!(matches!(error, FetchError::Timeout(_)), "{error:?}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_unresolvable_host_is_a_dns_error() {
+        let url = "http://no-such-host.invalid/image.png";
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("dns_failure"), UReqFetcher::new())
+                .build();
+
+        // Act
+
+        let error = downloader.download(url).unwrap_err();
+
+        // Assert
+
+        let message = error.to_string();
+
+        assert!(matches!(error, DownloadError::Dns(_)), "{error:?}");
+        assert!(message.starts_with("dns lookup failed: "), "{message}");
+        assert!(message.contains(url), "{message}");
+    }
+
+    #[test]
+    fn test_redirect_chain_is_recorded() {
+        let (_, base) = redirecting_server();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("redirect_chain"),
+            UReqFetcher::new(),
+        )
+        .build();
+
+        // Act
+
+        let download = downloader.download(&format!("{base}/a")).unwrap();
+
+        // Assert
+
+        assert_eq!(
+            download.redirects,
+            [
+                (302, format!("{base}/hop")),
+                (301, format!("{base}/logo.png"))
+            ]
+        );
+        assert_eq!(download.bytes().unwrap(), fixtures::PNG);
+        assert_eq!(download.source, format!("{base}/a"));
+    }
+
+    #[test]
+    fn test_short_links_share_the_final_entry() {
+        let (paths, base) = redirecting_server();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("redirect_final_key"),
+            UReqFetcher::new(),
+        )
+        .key_on_final_url(true)
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        let first = downloader.download(&format!("{base}/a")).unwrap();
+
+        // Act
+
+        let second = downloader.download(&format!("{base}/b")).unwrap();
+
+        let repeated = downloader.download(&format!("{base}/a")).unwrap();
+
+        downloader.flush_maintenance();
+
+        // Assert
+
+        assert_eq!(second.file, first.file);
+        assert_eq!(repeated.file, first.file);
+        assert_eq!(repeated.redirects, first.redirects);
+        assert_eq!(
+            paths.lock().unwrap().as_slice(),
+            ["/a", "/hop", "/logo.png", "/b", "/hop", "/logo.png"]
+        );
+        assert_eq!(downloader.storage().list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_redirects_to_forbidden_hosts_are_not_followed() {
+        let (paths, base) = redirecting_server();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("redirect_forbidden"),
+            UReqFetcher::new(),
+        )
+        .deny_hosts(&["127.0.0.1"])
+        .build();
+
+        // The server redirects to itself by IP, the first hops go by name.
+        let url = format!("{base}/a").replace("127.0.0.1", "localhost");
+
+        // Act
+
+        let error = downloader.download(&url).unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            error,
+            DownloadError::Forbidden {
+                host: "127.0.0.1".to_string()
+            }
+        );
+        assert_eq!(paths.lock().unwrap().as_slice(), ["/a", "/hop"]);
+    }
+}
@@ -0,0 +1,316 @@
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use super::{
+    headers, sidecar, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Storage,
+};
+
+// Most filesystems cap a name at 255 bytes.
+const MAX_LEN: usize = 255;
+
+// Names Windows refuses whatever their extension.
+const RESERVED: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // A name to offer when saving the URL, without asking the network: the
+    // `Content-Disposition` name the cached entry was served with, else the
+    // last segment of the URL path, else the name `download` stores it under.
+    pub fn suggest_filename(&self, url: impl IntoDownloadUrl) -> Result<String, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        if let Some(name) = self.cached_filename(&url) {
+            return Ok(name);
+        }
+
+        self.fallback_filename(&url)
+    }
+
+    // Like `suggest_filename`, but a `Content-Disposition` the server sends
+    // now comes first. Asks like `validate_urls`, without downloading.
+    pub fn probe_filename(&self, url: impl IntoDownloadUrl) -> Result<String, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        let response = self.head_or_first_byte(&url)?;
+
+        let announced = (200..=299)
+            .contains(&response.status)
+            .then(|| response.header("Content-Disposition"))
+            .flatten()
+            .and_then(disposition_filename);
+
+        match announced.or_else(|| self.cached_filename(&url)) {
+            Some(name) => Ok(name),
+            None => self.fallback_filename(&url),
+        }
+    }
+
+    fn cached_filename(&self, url: &Url) -> Option<String> {
+        let entry = self.cached_entry(url)?;
+
+        if let Some(name) = entry.meta.and_then(|meta| meta.filename) {
+            return Some(name);
+        }
+
+        let sidecar = sidecar::read(&entry.file).ok()?;
+
+        headers::find(&sidecar.headers, "Content-Disposition").and_then(disposition_filename)
+    }
+
+    fn fallback_filename(&self, url: &Url) -> Result<String, DownloadError> {
+        let segment = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy())
+            .and_then(|segment| sanitize_filename(&segment));
+
+        if let Some(name) = segment {
+            return Ok(name);
+        }
+
+        let path = self.target_path_for(url)?;
+
+        Ok(path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default())
+    }
+}
+
+// `filename*` (RFC 6266, encoded as in RFC 8187) is preferred to `filename`,
+// which older clients read instead.
+pub(crate) fn disposition_filename(value: &str) -> Option<String> {
+    let mut plain = None;
+
+    let mut extended = None;
+
+    for param in value.split(';').skip(1) {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "filename" => plain = Some(unquote(value.trim())),
+            "filename*" => {
+                extended = value
+                    .trim()
+                    .split_once("''")
+                    .filter(|(charset, _)| charset.eq_ignore_ascii_case("utf-8"))
+                    .and_then(|(_, name)| percent_decode_str(name).decode_utf8().ok())
+                    .map(|name| name.into_owned())
+            }
+            _ => {}
+        }
+    }
+
+    extended
+        .and_then(|name| sanitize_filename(&name))
+        .or_else(|| plain.and_then(|name| sanitize_filename(&name)))
+}
+
+fn unquote(value: &str) -> String {
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+// Keeps only the last path component, replaces characters some filesystem
+// refuses with `_`, drops leading and trailing dots and spaces, and cuts the
+// name to `MAX_LEN` bytes. `None` when nothing is left.
+pub(crate) fn sanitize_filename(raw: &str) -> Option<String> {
+    let base = raw.rsplit(['/', '\\']).next().unwrap_or(raw);
+
+    let replaced: String = base
+        .chars()
+        .map(|char| match char {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            char if char.is_control() => '_',
+            char => char,
+        })
+        .collect();
+
+    let mut name = replaced.trim_matches(['.', ' ']).to_string();
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let stem = name.split('.').next().unwrap_or_default();
+
+    if RESERVED.contains(&stem.to_ascii_lowercase().as_str()) {
+        name.insert(0, '_');
+    }
+
+    if name.len() > MAX_LEN {
+        let mut end = MAX_LEN;
+
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        name.truncate(end);
+    }
+
+    Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disposition_filename, sanitize_filename};
+    use crate::downloader::{
+        cache_key::CacheKey, fetcher::MockFetcher, testing, CachePolicy, DownloaderBuilder,
+        Response,
+    };
+
+    #[test]
+    fn test_sanitize_filename() {
+        let long = "a".repeat(300);
+
+        let cases = [
+            ("report.pdf", Some("report.pdf")),
+            ("../../etc/passwd", Some("passwd")),
+            ("C:\\Users\\me\\notes.txt", Some("notes.txt")),
+            ("what?.png", Some("what_.png")),
+            ("tab\there.txt", Some("tab_here.txt")),
+            (" .hidden. ", Some("hidden")),
+            ("CON.txt", Some("_CON.txt")),
+            ("..", None),
+            ("", None),
+            (long.as_str(), Some(&long[..255])),
+        ];
+
+        for (raw, expected) in cases {
+            // Act
+
+            let name = sanitize_filename(raw);
+
+            // Assert
+
+            assert_eq!(name.as_deref(), expected, "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn test_disposition_filename() {
+        let cases = [
+            (
+                "attachment; filename=\"Q3 report.pdf\"",
+                Some("Q3 report.pdf"),
+            ),
+            ("attachment; filename=plain.txt", Some("plain.txt")),
+            (
+                "attachment; filename=\"fallback.txt\"; filename*=UTF-8''r%C3%A9sum%C3%A9.txt",
+                Some("résumé.txt"),
+            ),
+            (
+                "attachment; filename*=ISO-8859-1''x.txt; filename=\"ok.txt\"",
+                Some("ok.txt"),
+            ),
+            ("inline; filename=\"../../.bashrc\"", Some("bashrc")),
+            ("attachment", None),
+        ];
+
+        for (value, expected) in cases {
+            // Act
+
+            let name = disposition_filename(value);
+
+            // Assert
+
+            assert_eq!(name.as_deref(), expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_filenames_are_suggested_without_fetching() {
+        let report = Response::ok(b"%PDF".to_vec(), Some("application/pdf".to_string()))
+            .with_header(
+                "Content-Disposition",
+                "attachment; filename=\"Q3 report.pdf\"",
+            );
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("suggest_filename"),
+            MockFetcher::new(vec![report]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        downloader
+            .download("https://example.com/export?id=3")
+            .unwrap();
+
+        let root = "https://example.com/";
+
+        // Act
+
+        let cached = downloader
+            .suggest_filename("https://example.com/export?id=3")
+            .unwrap();
+
+        let named = downloader
+            .suggest_filename("https://example.com/files/caf%C3%A9%20menu.png?size=2")
+            .unwrap();
+
+        let unnamed = downloader.suggest_filename(root).unwrap();
+
+        // Assert
+
+        assert_eq!(cached, "Q3 report.pdf");
+        assert_eq!(named, "café menu.png");
+        assert_eq!(
+            unnamed,
+            format!("{}.dat", CacheKey::from_url(root).as_str())
+        );
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_probed_filenames_prefer_live_headers() {
+        let head = |disposition: Option<&str>| {
+            let response = Response::new(200);
+
+            match disposition {
+                Some(value) => response.with_header("Content-Disposition", value),
+                None => response,
+            }
+        };
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("probe_filename"),
+            MockFetcher::new(vec![
+                head(Some("attachment; filename*=UTF-8''%E2%82%AC.csv")),
+                head(None),
+            ]),
+        )
+        .build();
+
+        let url = "https://example.com/export";
+
+        // Act
+
+        let announced = downloader.probe_filename(url).unwrap();
+
+        let fallback = downloader.probe_filename(url).unwrap();
+
+        // Assert
+
+        assert_eq!(announced, "€.csv");
+        assert_eq!(fallback, "export");
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+}
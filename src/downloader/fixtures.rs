@@ -0,0 +1,216 @@
+// Tiny bodies for tests, so they need not hand-craft images. Built with the
+// `test-util` feature for use outside the crate.
+
+#[cfg(feature = "image")]
+use std::io::Cursor;
+
+#[cfg(feature = "image")]
+use image::{ImageFormat, Rgb, RgbImage};
+
+use super::Response;
+
+// 1x1 RGBA, one red pixel.
+pub const PNG: &[u8] = b"\
+    \x89\x50\x4e\x47\x0d\x0a\x1a\x0a\x00\x00\x00\x0d\x49\x48\x44\x52\
+    \x00\x00\x00\x01\x00\x00\x00\x01\x08\x06\x00\x00\x00\x1f\x15\xc4\
+    \x89\x00\x00\x00\x10\x49\x44\x41\x54\x78\x01\x01\x05\x00\xfa\xff\
+    \x00\xff\x00\x00\xff\x05\x00\x01\xff\xfa\x5c\x88\xd1\x00\x00\x00\
+    \x00\x49\x45\x4e\x44\xae\x42\x60\x82";
+
+// 1x1 greyscale.
+pub const JPEG: &[u8] = b"\
+    \xff\xd8\xff\xe0\x00\x10\x4a\x46\x49\x46\x00\x01\x02\x00\x00\x01\
+    \x00\x01\x00\x00\xff\xc0\x00\x0b\x08\x00\x01\x00\x01\x01\x01\x11\
+    \x00\xff\xdb\x00\x43\x00\x08\x06\x06\x07\x06\x05\x08\x07\x07\x07\
+    \x09\x09\x08\x0a\x0c\x14\x0d\x0c\x0b\x0b\x0c\x19\x12\x13\x0f\x14\
+    \x1d\x1a\x1f\x1e\x1d\x1a\x1c\x1c\x20\x24\x2e\x27\x20\x22\x2c\x23\
+    \x1c\x1c\x28\x37\x29\x2c\x30\x31\x34\x34\x34\x1f\x27\x39\x3d\x38\
+    \x32\x3c\x2e\x33\x34\x32\xff\xc4\x00\x1f\x00\x00\x01\x05\x01\x01\
+    \x01\x01\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x01\x02\x03\x04\
+    \x05\x06\x07\x08\x09\x0a\x0b\xff\xc4\x00\xb5\x10\x00\x02\x01\x03\
+    \x03\x02\x04\x03\x05\x05\x04\x04\x00\x00\x01\x7d\x01\x02\x03\x00\
+    \x04\x11\x05\x12\x21\x31\x41\x06\x13\x51\x61\x07\x22\x71\x14\x32\
+    \x81\x91\xa1\x08\x23\x42\xb1\xc1\x15\x52\xd1\xf0\x24\x33\x62\x72\
+    \x82\x09\x0a\x16\x17\x18\x19\x1a\x25\x26\x27\x28\x29\x2a\x34\x35\
+    \x36\x37\x38\x39\x3a\x43\x44\x45\x46\x47\x48\x49\x4a\x53\x54\x55\
+    \x56\x57\x58\x59\x5a\x63\x64\x65\x66\x67\x68\x69\x6a\x73\x74\x75\
+    \x76\x77\x78\x79\x7a\x83\x84\x85\x86\x87\x88\x89\x8a\x92\x93\x94\
+    \x95\x96\x97\x98\x99\x9a\xa2\xa3\xa4\xa5\xa6\xa7\xa8\xa9\xaa\xb2\
+    \xb3\xb4\xb5\xb6\xb7\xb8\xb9\xba\xc2\xc3\xc4\xc5\xc6\xc7\xc8\xc9\
+    \xca\xd2\xd3\xd4\xd5\xd6\xd7\xd8\xd9\xda\xe1\xe2\xe3\xe4\xe5\xe6\
+    \xe7\xe8\xe9\xea\xf1\xf2\xf3\xf4\xf5\xf6\xf7\xf8\xf9\xfa\xff\xda\
+    \x00\x08\x01\x01\x00\x00\x3f\x00\x2b\xff\xd9";
+
+// 1x1, single frame.
+pub const GIF: &[u8] = b"\
+    \x47\x49\x46\x38\x39\x61\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\
+    \x00\x00\x00\x21\xf9\x04\x08\x00\x00\x00\x00\x2c\x00\x00\x00\x00\
+    \x01\x00\x01\x00\x80\xff\x00\x00\x00\x00\x00\x02\x02\x44\x01\x00\
+    \x3b";
+
+// 1x1 lossless WebP.
+pub const WEBP: &[u8] = b"\
+    \x52\x49\x46\x46\x1a\x00\x00\x00\x57\x45\x42\x50\x56\x50\x38\x4c\
+    \x0e\x00\x00\x00\x2f\x00\x00\x00\x10\xcd\x55\x20\x22\x02\xd1\xff\
+    \x88\x04";
+
+// Cut inside the IDAT chunk: the header parses, the pixels do not.
+pub const TRUNCATED_PNG: &[u8] = PNG.split_at(48).0;
+
+pub const SVG: &[u8] = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1\" height=\"1\"><rect width=\"1\" height=\"1\"/></svg>";
+
+pub const HTML: &[u8] = b"<!DOCTYPE html><html><head><title>Not Found</title></head></html>";
+
+// Images of any size, encoded on demand.
+#[cfg(feature = "image")]
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    encode(width, height, ImageFormat::Png)
+}
+
+#[cfg(feature = "image")]
+pub fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 7]))
+        .write_to(&mut Cursor::new(&mut bytes), format)
+        .unwrap();
+
+    bytes
+}
+
+// The `ftyp` box AVIF files open with, then bytes no decoder would accept.
+#[cfg(feature = "image")]
+pub fn avif_header() -> Vec<u8> {
+    let mut bytes = b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf".to_vec();
+
+    bytes.extend_from_slice(b"\0\0\0\x10meta-not-a-box");
+
+    bytes
+}
+
+// A valid 1x1 PNG whose IHDR announces `width`x`height`.
+#[cfg(feature = "image")]
+pub fn bomb(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = png(1, 1);
+
+    bytes[16..20].copy_from_slice(&width.to_be_bytes());
+    bytes[20..24].copy_from_slice(&height.to_be_bytes());
+
+    let crc = crc32(&bytes[12..29]);
+    bytes[29..33].copy_from_slice(&crc.to_be_bytes());
+
+    bytes
+}
+
+#[cfg(feature = "image")]
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+impl Response {
+    pub fn ok_png() -> Self {
+        Self::ok(PNG.to_vec(), Some("image/png".to_string()))
+    }
+
+    pub fn ok_jpeg() -> Self {
+        Self::ok(JPEG.to_vec(), Some("image/jpeg".to_string()))
+    }
+
+    pub fn ok_gif() -> Self {
+        Self::ok(GIF.to_vec(), Some("image/gif".to_string()))
+    }
+
+    pub fn ok_webp() -> Self {
+        Self::ok(WEBP.to_vec(), Some("image/webp".to_string()))
+    }
+
+    pub fn ok_svg() -> Self {
+        Self::ok(SVG.to_vec(), Some("image/svg+xml".to_string()))
+    }
+
+    pub fn ok_html() -> Self {
+        Self::ok(HTML.to_vec(), Some("text/html".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GIF, HTML, JPEG, PNG, SVG, TRUNCATED_PNG, WEBP};
+    use crate::downloader::{fetcher::MockFetcher, sniff, testing, DownloaderBuilder, Response};
+
+    #[test]
+    fn test_fixtures_are_sniffed_as_labeled() {
+        let cases: [(&[u8], Option<&str>); 7] = [
+            (PNG, Some("png")),
+            (JPEG, Some("jpg")),
+            (GIF, Some("gif")),
+            (WEBP, Some("webp")),
+            (TRUNCATED_PNG, Some("png")),
+            (SVG, Some("svg")),
+            (HTML, Some("html")),
+        ];
+
+        for (body, expected) in cases {
+            // Act
+
+            let extension = sniff::extension_from_magic(body);
+
+            // Assert
+
+            assert_eq!(extension, expected);
+        }
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_fixtures_decode_as_labeled() {
+        for body in [PNG, JPEG, GIF, WEBP] {
+            // Act
+
+            let image = image::load_from_memory(body).unwrap();
+
+            // Assert
+
+            assert_eq!((image.width(), image.height()), (1, 1));
+        }
+
+        assert!(image::load_from_memory(TRUNCATED_PNG).is_err());
+        assert!(image::load_from_memory(HTML).is_err());
+    }
+
+    #[test]
+    fn test_fixture_responses() {
+        let fetcher = MockFetcher::new(vec![Response::ok_png(), Response::ok_html()]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("fixture_responses"), fetcher)
+                .build();
+
+        // Act
+
+        let png = downloader.download("https://example.com/pixel").unwrap();
+
+        let html = downloader.download("https://example.com/page").unwrap();
+
+        // Assert
+
+        assert_eq!(png.metadata.extension.as_deref(), Some("png"));
+        assert_eq!(png.bytes().unwrap(), PNG);
+        assert_eq!(html.metadata.extension.as_deref(), Some("html"));
+    }
+}
@@ -0,0 +1,376 @@
+use std::{fs, io, path::Path};
+
+use super::{manifest::MANIFEST_FILE, partial, DownloadError, Downloader, FileDownloader, Storage};
+
+pub(crate) const FORMAT_FILE: &str = "CACHE_FORMAT";
+
+// The layout this crate reads and writes:
+// 1. data files and sidecars, found by name only
+// 2. a manifest indexing every entry
+// 3. manifest entries record their host, for per-host limits
+pub(crate) const CURRENT_FORMAT: u32 = 3;
+
+type Migration<T, S> = fn(&Downloader<T, S>) -> io::Result<()>;
+
+// Step `n` upgrades a directory of version `n + 1` to the next. Steps may
+// run again on a directory they already upgraded, after a crash between the
+// step and recording the new version.
+fn migrations<T: FileDownloader, S: Storage>() -> [Migration<T, S>; CURRENT_FORMAT as usize - 1] {
+    [
+        |downloader| downloader.adopt_files().map(drop),
+        |downloader| {
+            downloader.manifest.backfill_hosts();
+
+            Ok(())
+        },
+    ]
+}
+
+// The version recorded in `dir`, if any.
+pub(crate) fn read(dir: &Path) -> Result<Option<u32>, DownloadError> {
+    match fs::read_to_string(dir.join(FORMAT_FILE)) {
+        Ok(content) => content.trim().parse().map(Some).map_err(|_| {
+            DownloadError::Io(format!("unreadable {FORMAT_FILE}: {:?}", content.trim()))
+        }),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(io_error(error)),
+    }
+}
+
+// Refuses directories written by a newer version before anything touches
+// them.
+pub(crate) fn check(dir: &Path) -> Result<(), DownloadError> {
+    match read(dir)? {
+        Some(found) if found > CURRENT_FORMAT => Err(DownloadError::UnsupportedCacheFormat {
+            found,
+            supported: CURRENT_FORMAT,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Directories from before the version file are told apart by what they
+// hold; an empty one is new.
+fn detect(dir: &Path) -> io::Result<u32> {
+    if dir.join(MANIFEST_FILE).exists() {
+        return Ok(2);
+    }
+
+    match fs::read_dir(dir)?.next() {
+        Some(_) => Ok(1),
+        None => Ok(CURRENT_FORMAT),
+    }
+}
+
+fn write(dir: &Path, version: u32) -> io::Result<()> {
+    let path = dir.join(FORMAT_FILE);
+
+    let partial = partial::staging_path(&path);
+
+    partial::recreating_parent(&partial, || fs::write(&partial, format!("{version}\n")))?;
+
+    fs::rename(&partial, path)
+}
+
+fn io_error(error: io::Error) -> DownloadError {
+    DownloadError::Io(error.to_string())
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Records the version of a directory that has none yet, so a directory
+    // keeps telling what it holds once this crate starts writing to it.
+    // Older directories are used as they are unless `migrate` is set.
+    pub(crate) fn open_format(&self, migrate: bool) -> Result<(), DownloadError> {
+        let version = match read(&self.path)? {
+            Some(version) => version,
+            None => {
+                let version = detect(&self.path).map_err(io_error)?;
+
+                write(&self.path, version).map_err(io_error)?;
+
+                version
+            }
+        };
+
+        if migrate && version < CURRENT_FORMAT {
+            self.migrate()?;
+        }
+
+        Ok(())
+    }
+
+    // Upgrades the cache directory to the current format one step at a
+    // time, recording each version reached.
+    pub fn migrate(&self) -> Result<(), DownloadError> {
+        if self.config.read_only {
+            return Err(DownloadError::Io(
+                "a read-only cache cannot be migrated".to_string(),
+            ));
+        }
+
+        check(&self.path)?;
+
+        let mut version = match read(&self.path)? {
+            Some(version) => version,
+            None => detect(&self.path).map_err(io_error)?,
+        };
+
+        let migrations = migrations::<T, S>();
+
+        while version < CURRENT_FORMAT {
+            migrations[version as usize - 1](self).map_err(io_error)?;
+
+            // The step's manifest changes are on disk before its version is.
+            self.maintenance.flush();
+
+            version += 1;
+
+            write(&self.path, version).map_err(io_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{FORMAT_FILE, MANIFEST_FILE};
+    use crate::downloader::{
+        cache_key::CacheKey,
+        fetcher::MockFetcher,
+        fixtures,
+        sidecar::{self, Sidecar},
+        tee, testing, CachePolicy, DownloadError, Downloader, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn version(dir: &Path) -> String {
+        fs::read_to_string(dir.join(FORMAT_FILE)).unwrap()
+    }
+
+    // A data file with its sidecar and no manifest, as written before
+    // manifests.
+    fn version_1(name: &str) -> std::path::PathBuf {
+        let dir = testing::cache_dir(name);
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let logo = dir.join(format!("{}.png", CacheKey::from_url(URL).as_str()));
+
+        fs::write(&logo, fixtures::PNG).unwrap();
+
+        let sidecar = Sidecar {
+            source: URL.to_string(),
+            fetched_at: 1_700_000_000,
+            status: 200,
+            headers: Vec::new(),
+            sha256: Some(tee::BodySummary::of(fixtures::PNG).sha256),
+            mime: Some("image/png".to_string()),
+        };
+
+        sidecar::write(&logo, &sidecar).unwrap();
+
+        fs::write(dir.join(FORMAT_FILE), "1\n").unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn test_new_directories_are_stamped_with_the_current_format() {
+        let dir = testing::cache_dir("format_new");
+
+        // Act
+
+        Downloader::builder(&dir).try_build().unwrap();
+
+        // Assert
+
+        assert_eq!(version(&dir), "3\n");
+    }
+
+    #[test]
+    fn test_directories_from_before_the_version_file_are_detected() {
+        let dir = version_1("format_detected");
+
+        fs::remove_file(dir.join(FORMAT_FILE)).unwrap();
+
+        // Act
+
+        Downloader::builder(&dir).build();
+
+        // Assert
+
+        assert_eq!(version(&dir), "1\n", "recorded, not migrated");
+    }
+
+    #[test]
+    fn test_version_1_files_are_indexed_in_a_manifest() {
+        let dir = version_1("format_v1");
+
+        let downloader = Downloader::builder(&dir).build();
+
+        // Act
+
+        downloader.migrate().unwrap();
+
+        // Assert
+
+        assert_eq!(version(&dir), "3\n");
+        assert!(fs::read_to_string(dir.join(MANIFEST_FILE))
+            .unwrap()
+            .contains(URL));
+        assert_eq!(
+            downloader.cached(URL).unwrap().metadata.mime.as_deref(),
+            Some("image/png")
+        );
+    }
+
+    #[test]
+    fn test_version_2_entries_record_their_host() {
+        let dir = testing::cache_dir("format_v2");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let key = CacheKey::from_url(URL);
+
+        fs::write(dir.join(format!("{}.png", key.as_str())), fixtures::PNG).unwrap();
+
+        fs::write(
+            dir.join(MANIFEST_FILE),
+            format!(
+                r#"{{"{}": {{"url": "{URL}", "file": "{}.png", "fetched_at": 1700000000}}}}"#,
+                key.as_str(),
+                key.as_str()
+            ),
+        )
+        .unwrap();
+
+        fs::write(dir.join(FORMAT_FILE), "2\n").unwrap();
+
+        // Act
+
+        let downloader = Downloader::builder(&dir)
+            .migrate_cache_format(true)
+            .cache_policy(CachePolicy::CacheFirst)
+            .build();
+
+        // Assert
+
+        let entry = downloader.manifest.get(key.as_str()).unwrap();
+
+        assert_eq!(version(&dir), "3\n");
+        assert_eq!(entry.host.as_deref(), Some("example.com"));
+        assert_eq!(entry.used_at, Some(1_700_000_000));
+        assert!(fs::read_to_string(dir.join(MANIFEST_FILE))
+            .unwrap()
+            .contains("\"host\": \"example.com\""));
+    }
+
+    #[test]
+    fn test_newer_formats_are_refused() {
+        let dir = testing::cache_dir("format_newer");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join(FORMAT_FILE), "9\n").unwrap();
+
+        // Act
+
+        let error = Downloader::builder(&dir).try_build().err().unwrap();
+
+        // Assert
+
+        assert_eq!(
+            error,
+            DownloadError::UnsupportedCacheFormat {
+                found: 9,
+                supported: 3
+            }
+        );
+        assert_eq!(version(&dir), "9\n");
+    }
+
+    #[test]
+    fn test_newer_formats_build_a_downloader_that_fails_every_download() {
+        let dir = testing::cache_dir("format_newer_build");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join(FORMAT_FILE), "9\n").unwrap();
+
+        let fetcher = MockFetcher::new(vec![Response::ok(
+            fixtures::PNG.to_vec(),
+            Some("image/png".to_string()),
+        )]);
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher).build();
+
+        // Act
+
+        let error = downloader.download(URL).unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            error,
+            DownloadError::UnsupportedCacheFormat {
+                found: 9,
+                supported: 3
+            }
+        );
+        assert_eq!(downloader.fetcher().calls(), 0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_directories_that_cannot_be_created_fail_every_download() {
+        let parent = testing::cache_dir("format_uncreatable");
+
+        fs::create_dir_all(&parent).unwrap();
+
+        fs::write(parent.join("file"), "").unwrap();
+
+        let downloader = Downloader::builder(parent.join("file").join("cache")).build();
+
+        // Act
+
+        let error = downloader.download(URL).unwrap_err();
+
+        // Assert
+
+        assert_eq!(error.code(), "io");
+    }
+
+    #[test]
+    fn test_overlays_that_cannot_be_created_fail_every_download() {
+        let parent = testing::cache_dir("format_uncreatable_overlay");
+
+        fs::create_dir_all(&parent).unwrap();
+
+        fs::write(parent.join("file"), "").unwrap();
+
+        let builder = || {
+            Downloader::builder(&parent)
+                .read_only(true)
+                .overlay(parent.join("file").join("overlay"))
+        };
+
+        // Act
+
+        let built = builder().try_build();
+
+        let error = builder().build().download(URL).unwrap_err();
+
+        // Assert
+
+        assert!(matches!(built, Err(DownloadError::Io(_))));
+        assert_eq!(error.code(), "io");
+    }
+}
@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+
+// `defaults` without the names `overrides` gives, followed by `overrides`.
+// Requests add no headers of their own more often than not, so only a real
+// merge allocates.
+pub(crate) fn merge<'a>(
+    defaults: &'a [(String, String)],
+    overrides: &'a [(String, String)],
+) -> Cow<'a, [(String, String)]> {
+    if overrides.is_empty() {
+        return Cow::Borrowed(defaults);
+    }
+
+    if defaults.is_empty() {
+        return Cow::Borrowed(overrides);
+    }
+
+    defaults
+        .iter()
+        .filter(|(name, _)| find(overrides, name).is_none())
+        .chain(overrides)
+        .cloned()
+        .collect()
+}
+
+pub(crate) fn find<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::merge;
+
+    fn pairs(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_overrides_win_and_only_real_merges_allocate() {
+        let defaults = pairs(&[("User-Agent", "gallery/1.0"), ("Accept", "*/*")]);
+
+        let overrides = pairs(&[("accept", "image/png"), ("Range", "bytes=0-0")]);
+
+        // Act
+
+        let unchanged = merge(&defaults, &[]);
+
+        let only_overrides = merge(&[], &overrides);
+
+        let merged = merge(&defaults, &overrides);
+
+        // Assert
+
+        assert!(matches!(unchanged, Cow::Borrowed(headers) if headers == defaults));
+        assert!(matches!(only_overrides, Cow::Borrowed(headers) if headers == overrides));
+        assert_eq!(
+            merged.into_owned(),
+            pairs(&[
+                ("User-Agent", "gallery/1.0"),
+                ("accept", "image/png"),
+                ("Range", "bytes=0-0")
+            ])
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use super::{manifest, Downloader, FileDownloader, Storage};
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Hits count as uses only while a per-host limit needs them.
+    pub(crate) fn mark_used(&self, url: &str) {
+        if self.config.max_entries_per_host.is_none() {
+            return;
+        }
+
+        let now = manifest::unix_secs(self.config.clock.now());
+
+        self.manifest.touch(&self.entry_key(url), now);
+    }
+
+    // Runs once `key` was written for `host`. A file another entry still
+    // names, as an alias does, is left in place.
+    pub(crate) fn limit_host_entries(&self, key: &str, host: &str) {
+        let Some(max) = self.config.max_entries_per_host else {
+            return;
+        };
+
+        let entries = self.manifest.entries_for_host(host);
+
+        let excess = entries.len().saturating_sub(max);
+
+        for (evicted, _) in entries
+            .into_iter()
+            .filter(|(other, _)| other != key)
+            .take(excess)
+        {
+            let Some(entry) = self.manifest.remove(&evicted) else {
+                continue;
+            };
+
+            if !self.manifest.names_file(&entry.file) {
+                self.remove_entry(&self.locate(&entry.file));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, CachePolicy, DownloaderBuilder, Response,
+    };
+
+    #[test]
+    fn test_hosts_past_their_limit_lose_their_least_recently_used_entry() {
+        let png =
+            |body: &str| Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()));
+
+        let clock = FakeClock::new();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("host_limit"),
+            MockFetcher::new(vec![png("a"), png("b"), png("other"), png("c"), png("d")]),
+        )
+        .clock(clock.clone())
+        .cache_policy(CachePolicy::CacheFirst)
+        .with_max_entries_per_host(3)
+        .build();
+
+        let download = |url: &str| {
+            clock.advance(Duration::from_secs(1));
+
+            downloader.download(url).unwrap()
+        };
+
+        let a = download("https://noisy.example.com/a.png");
+        let b = download("https://noisy.example.com/b.png");
+        let other = download("https://quiet.example.com/other.png");
+        let c = download("https://noisy.example.com/c.png");
+
+        // Act
+
+        download("https://noisy.example.com/a.png");
+
+        let d = download("https://noisy.example.com/d.png");
+
+        // Assert
+
+        assert!(a.file.exists(), "served since, so used more recently");
+        assert!(!b.file.exists());
+        assert!(c.file.exists());
+        assert!(d.file.exists());
+        assert!(other.file.exists());
+        assert_eq!(
+            downloader
+                .manifest
+                .entries_for_host("noisy.example.com")
+                .len(),
+            3
+        );
+        assert_eq!(downloader.fetcher().calls(), 5);
+    }
+}
@@ -0,0 +1,160 @@
+use url::Host;
+
+// Hosts the downloader may contact. Patterns are exact hosts or `*.domain`,
+// which matches the domain itself and every subdomain. Deny wins over allow,
+// and an empty allow list allows every host not denied.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct HostPolicy {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl HostPolicy {
+    pub fn allow(&mut self, patterns: &[&str]) {
+        self.allow
+            .extend(patterns.iter().map(|pattern| normalize(pattern)));
+    }
+
+    pub fn deny(&mut self, patterns: &[&str]) {
+        self.deny
+            .extend(patterns.iter().map(|pattern| normalize(pattern)));
+    }
+
+    // `host` is expected as `Url` gives it: lowercase, IDNs in punycode.
+    pub fn permits(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.');
+
+        if self.deny.iter().any(|pattern| matches(pattern, host)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|pattern| matches(pattern, host))
+    }
+}
+
+// Patterns go through the same IDNA mapping as URLs, so `*.bücher.example`
+// matches `xn--bcher-kva.example`.
+fn normalize(pattern: &str) -> String {
+    let (wildcard, domain) = match pattern.strip_prefix("*.") {
+        Some(domain) => ("*.", domain),
+        None => ("", pattern),
+    };
+
+    let domain = domain.trim_end_matches('.');
+
+    let domain = match Host::parse(domain) {
+        Ok(Host::Domain(domain)) => domain,
+        _ => domain.to_lowercase(),
+    };
+
+    format!("{wildcard}{domain}")
+}
+
+fn matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => {
+            host == domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|subdomain| subdomain.ends_with('.'))
+        }
+        None => host == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::HostPolicy;
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, DownloaderBuilder, Response,
+    };
+
+    fn host(url: &str) -> String {
+        Url::parse(url).unwrap().host_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_host_patterns() {
+        let mut policy = HostPolicy::default();
+
+        policy.allow(&["*.mycdn.com", "images.partner.org", "*.bücher.example"]);
+
+        policy.deny(&["private.mycdn.com"]);
+
+        // Act
+
+        let permitted = |url: &str| policy.permits(&host(url));
+
+        // Assert
+
+        assert!(permitted("https://mycdn.com/a.png"), "apex");
+        assert!(permitted("https://eu.static.mycdn.com/a.png"), "subdomain");
+        assert!(permitted("https://images.partner.org/a.png"));
+        assert!(permitted("https://cdn.bücher.example/a.png"), "IDN");
+        assert!(!permitted("https://private.mycdn.com/a.png"), "deny wins");
+        assert!(!permitted("https://evilmycdn.com/a.png"));
+        assert!(!permitted("https://partner.org/a.png"));
+        assert!(!permitted("https://other.example/a.png"));
+    }
+
+    #[test]
+    fn test_forbidden_hosts_are_never_fetched() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("host_policy"),
+            MockFetcher::new(vec![]),
+        )
+        .allow_hosts(&["*.mycdn.com"])
+        .build();
+
+        // Act
+
+        let error = downloader
+            .download("https://tracker.example/pixel.gif")
+            .unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            error,
+            DownloadError::Forbidden {
+                host: "tracker.example".to_string()
+            }
+        );
+        assert_eq!(downloader.fetcher().calls(), 0);
+    }
+
+    #[test]
+    fn test_redirects_to_forbidden_hosts_are_refused() {
+        let mut redirected = Response::ok(b"pixel".to_vec(), Some("image/gif".to_string()));
+
+        redirected.redirects = vec![
+            (302, "https://eu.mycdn.com/pixel.gif".to_string()),
+            (302, "https://tracker.example/pixel.gif".to_string()),
+        ];
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("host_policy_redirects"),
+            MockFetcher::new(vec![redirected]),
+        )
+        .allow_hosts(&["*.mycdn.com"])
+        .build();
+
+        // Act
+
+        let error = downloader
+            .download("https://img.mycdn.com/pixel.gif")
+            .unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            error,
+            DownloadError::Forbidden {
+                host: "tracker.example".to_string()
+            }
+        );
+        assert!(!downloader.is_cached("https://img.mycdn.com/pixel.gif"));
+    }
+}
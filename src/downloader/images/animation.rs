@@ -0,0 +1,242 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    error::{DecodingError, ImageFormatHint},
+    AnimationDecoder, DynamicImage, Frame, Frames, ImageError, ImageFormat, ImageResult,
+};
+
+use super::{open, ImageOptions};
+use crate::downloader::DownloadError;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AnimatedPolicy {
+    #[default]
+    Keep,
+    // Fail with `DownloadError::AnimatedImage`.
+    Reject,
+    // Keep only frame zero, re-encoded in the original format unless a
+    // `first_frame_format` is configured.
+    FirstFrame,
+}
+
+pub(crate) struct Inspection {
+    pub animated: bool,
+    // Set when the body was replaced by its first frame.
+    pub first_frame: Option<(Vec<u8>, ImageFormat)>,
+}
+
+impl ImageOptions {
+    // Only runs when an animated policy is configured. Bodies that are not
+    // GIF, PNG or WebP are never animated.
+    pub fn inspect_animation(&self, file: &Path) -> Result<Option<Inspection>, DownloadError> {
+        let Some(policy) = self.animated else {
+            return Ok(None);
+        };
+
+        let Some(format) = open(file).ok().and_then(|reader| reader.format()) else {
+            return Ok(Some(Inspection {
+                animated: false,
+                first_frame: None,
+            }));
+        };
+
+        let animated = is_animated(file, format).map_err(|_| DownloadError::CorruptImage)?;
+
+        let first_frame = match (animated, policy) {
+            (false, _) | (true, AnimatedPolicy::Keep) => None,
+            (true, AnimatedPolicy::Reject) => return Err(DownloadError::AnimatedImage),
+            (true, AnimatedPolicy::FirstFrame) => {
+                let target = self.first_frame_format.unwrap_or(format);
+
+                Some(first_frame(file, format, target).map_err(|_| DownloadError::CorruptImage)?)
+            }
+        };
+
+        Ok(Some(Inspection {
+            animated,
+            first_frame,
+        }))
+    }
+}
+
+fn reader(file: &Path) -> ImageResult<BufReader<File>> {
+    Ok(BufReader::new(File::open(file)?))
+}
+
+fn is_animated(file: &Path, format: ImageFormat) -> ImageResult<bool> {
+    match format {
+        ImageFormat::Gif => Ok(GifDecoder::new(reader(file)?)?
+            .into_frames()
+            .take(2)
+            .count()
+            > 1),
+        ImageFormat::Png => PngDecoder::new(reader(file)?)?.is_apng(),
+        ImageFormat::WebP => Ok(WebPDecoder::new(reader(file)?)?.has_animation()),
+        _ => Ok(false),
+    }
+}
+
+fn first_frame(
+    file: &Path,
+    format: ImageFormat,
+    target: ImageFormat,
+) -> ImageResult<(Vec<u8>, ImageFormat)> {
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader(file)?)?.into_frames(),
+        ImageFormat::Png => PngDecoder::new(reader(file)?)?.apng()?.into_frames(),
+        _ => WebPDecoder::new(reader(file)?)?.into_frames(),
+    };
+
+    let image = DynamicImage::ImageRgba8(frame_zero(frames, format)?.into_buffer());
+
+    let mut bytes = Cursor::new(Vec::new());
+
+    image.write_to(&mut bytes, target)?;
+
+    Ok((bytes.into_inner(), target))
+}
+
+// Animations may declare frames they do not hold.
+fn frame_zero(mut frames: Frames, format: ImageFormat) -> ImageResult<Frame> {
+    frames.next().unwrap_or_else(|| {
+        Err(ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Exact(format),
+            "the animation holds no frames",
+        )))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{
+        codecs::gif::GifEncoder, Delay, Frame, Frames, ImageError, ImageFormat, Rgba, RgbaImage,
+    };
+
+    use super::{frame_zero, AnimatedPolicy};
+    use crate::downloader::{
+        fetcher::MockFetcher, images::image_error, testing, DownloadError, Downloader,
+        DownloaderBuilder, Response,
+    };
+
+    fn gif(frames: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+
+            let frames = (0..frames).map(|index| {
+                let image = RgbaImage::from_pixel(4, 4, Rgba([index as u8 * 100, 0, 0, 255]));
+
+                Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            });
+
+            encoder.encode_frames(frames).unwrap();
+        }
+
+        bytes
+    }
+
+    fn downloader(name: &str, policy: AnimatedPolicy, body: Vec<u8>) -> Downloader<MockFetcher> {
+        let response = Response::ok(body, Some("image/gif".to_string()));
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), MockFetcher::new(vec![response]))
+            .animated_policy(policy)
+            .build()
+    }
+
+    const URL: &str = "https://example.com/spinner.gif";
+
+    #[test]
+    fn test_animation_is_detected() {
+        let cases = [(gif(1), false), (gif(3), true)];
+
+        for (index, (body, animated)) in cases.into_iter().enumerate() {
+            let downloader = downloader(&format!("animated_{index}"), AnimatedPolicy::Keep, body);
+
+            // Act
+
+            let download = downloader.download(URL).unwrap();
+
+            // Assert
+
+            assert_eq!(download.metadata.animated, Some(animated), "case {index}");
+        }
+    }
+
+    #[test]
+    fn test_animated_images_can_be_rejected() {
+        let animated = downloader("animated_reject", AnimatedPolicy::Reject, gif(2));
+
+        let still = downloader("animated_reject_still", AnimatedPolicy::Reject, gif(1));
+
+        // Act
+
+        let rejected = animated.download(URL);
+
+        let accepted = still.download(URL);
+
+        // Assert
+
+        assert_eq!(rejected, Err(DownloadError::AnimatedImage));
+        assert!(accepted.is_ok());
+    }
+
+    #[test]
+    fn test_first_frame_is_extracted() {
+        let animated = downloader("animated_first_frame", AnimatedPolicy::FirstFrame, gif(3));
+
+        let fetcher = MockFetcher::new(vec![Response::ok(gif(3), Some("image/gif".to_string()))]);
+
+        let converted = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("animated_first_frame_png"),
+            fetcher,
+        )
+        .animated_policy(AnimatedPolicy::FirstFrame)
+        .first_frame_format(ImageFormat::Png)
+        .build();
+
+        // Act
+
+        let gif = animated.download(URL).unwrap();
+
+        let png = converted.download(URL).unwrap();
+
+        // Assert
+
+        let bytes = gif.bytes().unwrap();
+
+        let frames = image::codecs::gif::GifDecoder::new(Cursor::new(&bytes)).unwrap();
+
+        assert_eq!(gif.metadata.animated, Some(true));
+        assert_eq!(image::AnimationDecoder::into_frames(frames).count(), 1);
+        assert_eq!(gif.metadata.size, Some(bytes.len() as u64));
+
+        assert_eq!(png.metadata.extension.as_deref(), Some("png"));
+        assert_eq!(png.file.extension().unwrap(), "png");
+        assert_eq!(png.as_image().unwrap().to_rgba8().get_pixel(0, 0)[0], 0);
+    }
+
+    #[test]
+    fn test_animations_without_frames_are_errors() {
+        let frames = Frames::new(Box::new(std::iter::empty()));
+
+        // Act
+
+        let frame = frame_zero(frames, ImageFormat::WebP);
+
+        // Assert
+
+        assert!(matches!(frame, Err(ImageError::Decoding(_))));
+        assert_eq!(
+            frame.map_err(image_error).err(),
+            Some(DownloadError::CorruptImage)
+        );
+    }
+}
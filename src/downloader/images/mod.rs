@@ -0,0 +1,176 @@
+mod animation;
+mod negotiate;
+mod thumbnail;
+mod verify;
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Cursor},
+    path::Path,
+};
+
+use image::{
+    error::{ImageFormatHint, UnsupportedErrorKind},
+    DynamicImage, ImageError, ImageFormat, ImageReader, Limits,
+};
+
+use super::{Download, DownloadError};
+
+pub use animation::AnimatedPolicy;
+pub(crate) use negotiate::{FALLBACK_ACCEPT, MODERN_ACCEPT};
+pub use thumbnail::ThumbSpec;
+pub(crate) use thumbnail::{find_thumbnail, remove_thumbnails};
+pub use verify::VerifyLevel;
+
+// 64 megapixels, roughly 8000x8000. A small body announcing more than this is
+// treated as a decompression bomb unless configured otherwise.
+pub(crate) const DEFAULT_MAX_PIXELS: u64 = 64_000_000;
+
+// Bytes per pixel for the widest colour type `image` decodes into (Rgba32F).
+const MAX_BYTES_PER_PIXEL: u64 = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ImageOptions {
+    pub verify: VerifyLevel,
+    pub max_pixels: u64,
+    pub retries: u32,
+    pub thumbnails: Option<ThumbSpec>,
+    pub animated: Option<AnimatedPolicy>,
+    pub first_frame_format: Option<ImageFormat>,
+    pub negotiate: bool,
+    pub allow_svg: bool,
+    pub accept_undecodable: bool,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            verify: VerifyLevel::None,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            retries: 0,
+            thumbnails: None,
+            animated: None,
+            first_frame_format: None,
+            negotiate: false,
+            allow_svg: false,
+            accept_undecodable: false,
+        }
+    }
+}
+
+impl Download {
+    pub fn as_image(&self) -> Result<DynamicImage, DownloadError> {
+        self.as_image_with_limit(DEFAULT_MAX_PIXELS)
+    }
+
+    pub fn as_image_with_limit(&self, max_pixels: u64) -> Result<DynamicImage, DownloadError> {
+        decode(&self.file, max_pixels)
+    }
+}
+
+// Every decode goes through here: the dimensions are read from the header
+// first so nothing is allocated for an image over the pixel limit.
+pub(crate) fn decode(file: &Path, max_pixels: u64) -> Result<DynamicImage, DownloadError> {
+    let (width, height) = open(file)
+        .map_err(|error| DownloadError::Io(error.to_string()))?
+        .into_dimensions()
+        .map_err(image_error)?;
+
+    if u64::from(width) * u64::from(height) > max_pixels {
+        return Err(DownloadError::ImageTooLarge {
+            width,
+            height,
+            limit: max_pixels,
+        });
+    }
+
+    let mut reader = open(file).map_err(|error| DownloadError::Io(error.to_string()))?;
+
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(max_pixels.saturating_mul(MAX_BYTES_PER_PIXEL));
+    reader.limits(limits);
+
+    reader.decode().map_err(image_error)
+}
+
+// `image` recognizes formats whose decoder is left out of the build, such as
+// AVIF without `avif-native`. Their bodies are not at fault.
+pub(crate) fn image_error(error: ImageError) -> DownloadError {
+    match error {
+        ImageError::Unsupported(error) => match error.kind() {
+            UnsupportedErrorKind::Format(ImageFormatHint::Exact(format)) => {
+                DownloadError::DecoderUnavailable(format.to_mime_type().to_string())
+            }
+            _ => DownloadError::CorruptImage,
+        },
+        _ => DownloadError::CorruptImage,
+    }
+}
+
+fn open(file: &Path) -> io::Result<ImageReader<BufReader<File>>> {
+    ImageReader::open(file)?.with_guessed_format()
+}
+
+pub(crate) fn extension_from_image(body: &[u8]) -> Option<&'static str> {
+    let reader = ImageReader::new(Cursor::new(body))
+        .with_guessed_format()
+        .ok()?;
+
+    let format = reader.format()?;
+
+    format.extensions_str().first().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::downloader::fixtures;
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, DownloaderBuilder, Response,
+    };
+
+    #[test]
+    fn test_as_image_bounds_pixel_count() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("as_image_limit"),
+            MockFetcher::new(vec![
+                Response::ok(fixtures::bomb(50_000, 50_000), None),
+                Response::ok(fixtures::png(4, 4), None),
+            ]),
+        )
+        .build();
+
+        let bomb = downloader.download("https://example.com/bomb.png").unwrap();
+
+        let small = downloader
+            .download("https://example.com/small.png")
+            .unwrap();
+
+        // Act
+
+        let bombed = bomb.as_image();
+
+        let limited = small.as_image_with_limit(15);
+
+        let decoded = small.as_image().unwrap();
+
+        // Assert
+
+        assert_eq!(
+            bombed.unwrap_err(),
+            DownloadError::ImageTooLarge {
+                width: 50_000,
+                height: 50_000,
+                limit: 64_000_000,
+            }
+        );
+        assert_eq!(
+            limited.unwrap_err(),
+            DownloadError::ImageTooLarge {
+                width: 4,
+                height: 4,
+                limit: 15,
+            }
+        );
+        assert_eq!((decoded.width(), decoded.height()), (4, 4));
+    }
+}
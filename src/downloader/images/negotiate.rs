@@ -0,0 +1,123 @@
+// What browsers send, preferring the smaller modern formats.
+pub(crate) const MODERN_ACCEPT: &str = "image/avif,image/webp,image/*;q=0.8";
+
+// Formats every decoder handles, for servers whose first answer did not decode.
+pub(crate) const FALLBACK_ACCEPT: &str = "image/png,image/jpeg";
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use image::ImageFormat;
+
+    use super::{FALLBACK_ACCEPT, MODERN_ACCEPT};
+    use crate::downloader::{
+        fixtures, headers, testing, DownloaderBuilder, FetchError, FileDownloader, Response,
+        VerifyLevel,
+    };
+
+    // Answers modern requests with `modern` and anything else with a PNG.
+    struct NegotiatingFetcher {
+        modern: (Vec<u8>, &'static str),
+        accepts: Mutex<Vec<Option<String>>>,
+    }
+
+    impl NegotiatingFetcher {
+        fn new(body: Vec<u8>, mime: &'static str) -> Self {
+            Self {
+                modern: (body, mime),
+                accepts: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn accepts(&self) -> Vec<Option<String>> {
+            self.accepts.lock().unwrap().clone()
+        }
+    }
+
+    impl FileDownloader for NegotiatingFetcher {
+        fn fetch(&self, _url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+            let accept = headers::find(headers, "Accept").map(str::to_string);
+
+            self.accepts.lock().unwrap().push(accept.clone());
+
+            let (body, mime) = match accept.as_deref() {
+                Some(MODERN_ACCEPT) => self.modern.clone(),
+                _ => (fixtures::png(2, 2), "image/png"),
+            };
+
+            Ok(Response::ok(body, Some(mime.to_string())))
+        }
+    }
+
+    #[test]
+    fn test_negotiated_format_is_kept() {
+        let fetcher =
+            NegotiatingFetcher::new(fixtures::encode(2, 2, ImageFormat::WebP), "image/webp");
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("negotiate_webp"), fetcher)
+                .negotiate_image_formats(true)
+                .verify_images(VerifyLevel::FullDecode)
+                .build();
+
+        // Act
+
+        let download = downloader.download("https://example.com/photo").unwrap();
+
+        // Assert
+
+        assert_eq!(download.metadata.extension.as_deref(), Some("webp"));
+        assert_eq!(download.metadata.mime.as_deref(), Some("image/webp"));
+        assert_eq!(
+            downloader.fetcher().accepts(),
+            vec![Some(MODERN_ACCEPT.to_string())]
+        );
+    }
+
+    #[test]
+    fn test_undecodable_format_falls_back_once() {
+        // An AVIF header; the decoder is not built in.
+        let avif = b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf".to_vec();
+
+        let fetcher = NegotiatingFetcher::new(avif, "image/avif");
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("negotiate_avif"), fetcher)
+                .negotiate_image_formats(true)
+                .verify_images(VerifyLevel::Header)
+                .build();
+
+        // Act
+
+        let download = downloader.download("https://example.com/photo").unwrap();
+
+        // Assert
+
+        assert_eq!(download.metadata.extension.as_deref(), Some("png"));
+        assert_eq!(download.metadata.mime.as_deref(), Some("image/png"));
+        assert_eq!(
+            downloader.fetcher().accepts(),
+            vec![
+                Some(MODERN_ACCEPT.to_string()),
+                Some(FALLBACK_ACCEPT.to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_accept_header_unless_enabled() {
+        let fetcher = NegotiatingFetcher::new(Vec::new(), "image/avif");
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("negotiate_off"), fetcher).build();
+
+        // Act
+
+        downloader.download("https://example.com/photo").unwrap();
+
+        // Assert
+
+        assert_eq!(downloader.fetcher().accepts(), vec![None]);
+    }
+}
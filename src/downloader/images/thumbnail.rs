@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    io::{BufWriter, Cursor, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat};
+
+use super::{decode, ImageOptions};
+use crate::downloader::{
+    partial::{self, PartialFile},
+    PARTIAL_SUFFIX,
+};
+
+const THUMB_SUFFIX: &str = "_thumb";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbSpec {
+    pub max_dim: u32,
+    pub format: ImageFormat,
+    // Only used by lossy formats.
+    pub quality: u8,
+}
+
+impl Default for ThumbSpec {
+    fn default() -> Self {
+        Self {
+            max_dim: 256,
+            format: ImageFormat::Jpeg,
+            quality: 80,
+        }
+    }
+}
+
+impl ImageOptions {
+    // Reuses the image decoded during verification when there is one. Bodies
+    // that are not images or do not decode simply get no thumbnail.
+    pub fn thumbnail(
+        &self,
+        file: &Path,
+        decoded: Option<DynamicImage>,
+        modified: SystemTime,
+    ) -> Option<PathBuf> {
+        let spec = self.thumbnails?;
+
+        remove_thumbnails(file);
+
+        let image = match decoded {
+            Some(image) => image,
+            None => decode(file, self.max_pixels).ok()?,
+        };
+
+        let thumbnail = image.thumbnail(spec.max_dim, spec.max_dim);
+
+        let extension = spec.format.extensions_str().first()?;
+
+        let target = thumbnail_stem(file)?.with_extension(extension);
+
+        let mut partial = PartialFile::create(partial::staging_path(&target)).ok()?;
+
+        let mut writer = BufWriter::new(&mut partial);
+
+        encode(&thumbnail, spec, &mut writer)?;
+
+        writer.flush().ok()?;
+
+        drop(writer);
+
+        partial.commit(&target, modified).ok()?;
+
+        Some(target)
+    }
+}
+
+fn encode(image: &DynamicImage, spec: ThumbSpec, writer: &mut impl Write) -> Option<()> {
+    match spec.format {
+        // JPEG has no alpha channel.
+        ImageFormat::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(writer, spec.quality))
+            .ok(),
+        format => {
+            let mut bytes = Cursor::new(Vec::new());
+            image.write_to(&mut bytes, format).ok()?;
+            writer.write_all(bytes.get_ref()).ok()
+        }
+    }
+}
+
+fn thumbnail_stem(file: &Path) -> Option<PathBuf> {
+    let stem = file.file_stem()?.to_string_lossy();
+
+    Some(file.with_file_name(format!("{stem}{THUMB_SUFFIX}")))
+}
+
+pub(crate) fn find_thumbnail(file: &Path) -> Option<PathBuf> {
+    thumbnails_of(file).into_iter().next()
+}
+
+pub(crate) fn remove_thumbnails(file: &Path) {
+    for thumbnail in thumbnails_of(file) {
+        let _ = fs::remove_file(thumbnail);
+    }
+}
+
+fn thumbnails_of(file: &Path) -> Vec<PathBuf> {
+    let (Some(stem), Some(dir)) = (thumbnail_stem(file), file.parent()) else {
+        return Vec::new();
+    };
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_stem() == stem.file_name()
+                && !path.to_string_lossy().ends_with(PARTIAL_SUFFIX)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use image::ImageFormat;
+
+    use super::ThumbSpec;
+    use crate::downloader::{fetcher::MockFetcher, fixtures, testing, DownloaderBuilder, Response};
+
+    const URL: &str = "https://example.com/photo";
+
+    fn responses(bodies: Vec<Vec<u8>>) -> MockFetcher {
+        MockFetcher::new(
+            bodies
+                .into_iter()
+                .map(|body| Response::ok(body, None))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_thumbnail_written_next_to_original() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("thumbnail"),
+            responses(vec![fixtures::png(600, 300)]),
+        )
+        .with_thumbnails(ThumbSpec::default())
+        .build();
+
+        // Act
+
+        let download = downloader.download(URL).unwrap();
+
+        // Assert
+
+        let url = URL.parse().unwrap();
+
+        let thumbnail = download.thumbnail.clone().unwrap();
+
+        let stem = download.file.file_stem().unwrap().to_string_lossy();
+
+        let image = image::open(&thumbnail).unwrap();
+
+        assert_eq!(
+            thumbnail,
+            download.file.with_file_name(format!("{stem}_thumb.jpg"))
+        );
+        assert_eq!((image.width(), image.height()), (256, 128));
+        assert_eq!(
+            image::guess_format(&fs::read(&thumbnail).unwrap()).unwrap(),
+            ImageFormat::Jpeg
+        );
+        assert_eq!(
+            downloader.cached_entry(&url).unwrap().thumbnail(),
+            Some(thumbnail)
+        );
+    }
+
+    #[test]
+    fn test_non_images_skip_thumbnails_and_replace_stale_ones() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("thumbnail_skip"),
+            responses(vec![
+                fixtures::png(32, 32),
+                b"not an image".to_vec(),
+                fixtures::png(32, 32)[..40].to_vec(),
+            ]),
+        )
+        .with_thumbnails(ThumbSpec {
+            format: ImageFormat::Png,
+            ..Default::default()
+        })
+        .build();
+
+        let image = downloader.download(URL).unwrap();
+
+        let old_thumbnail = image.thumbnail.clone().unwrap();
+
+        // Act
+
+        let text = downloader.download(URL).unwrap();
+
+        let truncated = downloader.download(URL).unwrap();
+
+        // Assert
+
+        assert!(!old_thumbnail.exists());
+        assert!(!text.file.exists());
+        assert_eq!(text.thumbnail, None);
+        assert_eq!(truncated.file, image.file);
+        assert_eq!(truncated.thumbnail, None);
+    }
+}
@@ -0,0 +1,232 @@
+use std::path::Path;
+
+use image::DynamicImage;
+
+use super::{decode, image_error, open, ImageOptions};
+use crate::downloader::DownloadError;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyLevel {
+    #[default]
+    None,
+    // Accept an image when its header and dimensions parse.
+    Header,
+    // Decode every pixel, so truncated or corrupt bodies are rejected.
+    FullDecode,
+}
+
+impl ImageOptions {
+    // Bodies that are not images always pass. A full decode hands the image
+    // back so later processing does not decode it again.
+    pub fn verify(&self, file: &Path) -> Result<Option<DynamicImage>, DownloadError> {
+        if self.verify == VerifyLevel::None {
+            return Ok(None);
+        }
+
+        let reader = open(file).map_err(|_| DownloadError::CorruptImage)?;
+
+        if reader.format().is_none() {
+            return Ok(None);
+        }
+
+        let verified = match self.verify {
+            VerifyLevel::None => Ok(None),
+            VerifyLevel::Header => reader.into_dimensions().map(|_| None).map_err(image_error),
+            VerifyLevel::FullDecode => decode(file, self.max_pixels).map(Some),
+        };
+
+        match verified {
+            Err(DownloadError::DecoderUnavailable(_)) if self.accept_undecodable => Ok(None),
+            verified => verified,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VerifyLevel;
+    use crate::downloader::{
+        fetcher::MockFetcher, fixtures, testing, DownloadError, Downloader, DownloaderBuilder,
+        Response,
+    };
+
+    fn downloader(name: &str, bodies: Vec<Vec<u8>>) -> Downloader<MockFetcher> {
+        let responses = bodies
+            .into_iter()
+            .map(|body| Response::ok(body, Some("image/png".to_string())))
+            .collect();
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), MockFetcher::new(responses))
+            .verify_images(VerifyLevel::FullDecode)
+            .max_image_pixels(1_000_000)
+            .build()
+    }
+
+    #[test]
+    fn test_full_decode_verification() {
+        let valid = fixtures::png(32, 32);
+
+        let truncated = valid[..valid.len() * 4 / 5].to_vec();
+
+        let cases = [
+            (valid.clone(), Ok(())),
+            (truncated, Err(DownloadError::CorruptImage)),
+            (
+                fixtures::bomb(50_000, 50_000),
+                Err(DownloadError::ImageTooLarge {
+                    width: 50_000,
+                    height: 50_000,
+                    limit: 1_000_000,
+                }),
+            ),
+            (b"plain text body".to_vec(), Ok(())),
+        ];
+
+        for (index, (body, expected)) in cases.into_iter().enumerate() {
+            let downloader = downloader(&format!("verify_{index}"), vec![body]);
+
+            // Act
+
+            let result = downloader
+                .download("https://example.com/image.png")
+                .map(|_| ());
+
+            // Assert
+
+            assert_eq!(result, expected, "case {index}");
+        }
+    }
+
+    #[test]
+    fn test_header_level_accepts_truncated_image() {
+        let valid = fixtures::png(32, 32);
+
+        let truncated = valid[..valid.len() * 4 / 5].to_vec();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("verify_header"),
+            MockFetcher::new(vec![Response::ok(truncated, None)]),
+        )
+        .verify_images(VerifyLevel::Header)
+        .build();
+
+        // Act
+
+        let result = downloader.download("https://example.com/image.png");
+
+        // Assert
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_corrupt_image_is_retried_and_keeps_previous_entry() {
+        let url = "https://example.com/image.png";
+
+        let valid = fixtures::png(8, 8);
+
+        let truncated = valid[..valid.len() / 2].to_vec();
+
+        let fetcher = MockFetcher::new(vec![
+            Response::ok(valid.clone(), Some("image/png".to_string())),
+            Response::ok(truncated.clone(), Some("image/png".to_string())),
+            Response::ok(truncated, Some("image/png".to_string())),
+        ]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("verify_retry"), fetcher)
+                .verify_images(VerifyLevel::FullDecode)
+                .retry_corrupt_images(1)
+                .build();
+
+        let first = downloader.download(url).unwrap();
+
+        // Act
+
+        let second = downloader.download(url).unwrap_err();
+
+        // Assert
+
+        assert_eq!(second.last_error(), &DownloadError::CorruptImage);
+        assert_eq!(second.attempts().len(), 2);
+        assert_eq!(downloader.fetcher().calls(), 3);
+        assert_eq!(first.bytes().unwrap(), valid);
+    }
+
+    // `image` is built without `avif-native`, so AVIF is recognized but not
+    // decoded.
+    #[test]
+    fn test_undecodable_formats_are_told_apart_from_corrupt_ones() {
+        let build = |name: &str, level: VerifyLevel, accept: bool| {
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir(name),
+                MockFetcher::new(vec![Response::ok(fixtures::avif_header(), None)]),
+            )
+            .verify_images(level)
+            .accept_undecodable_images(accept)
+            .build()
+        };
+
+        let unavailable = || Err(DownloadError::DecoderUnavailable("image/avif".to_string()));
+
+        let url = "https://example.com/photo";
+
+        // Act
+
+        let unverified = build("verify_avif_none", VerifyLevel::None, false)
+            .download(url)
+            .unwrap();
+
+        let header = build("verify_avif_header", VerifyLevel::Header, false).download(url);
+
+        let decoded = build("verify_avif_decode", VerifyLevel::FullDecode, false).download(url);
+
+        let accepted = build("verify_avif_accepted", VerifyLevel::FullDecode, true)
+            .download(url)
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(unverified.metadata.extension.as_deref(), Some("avif"));
+        assert_eq!(unverified.as_image().map(|_| ()), unavailable());
+        assert_eq!(header.map(|_| ()), unavailable());
+        assert_eq!(decoded.map(|_| ()), unavailable());
+        assert_eq!(accepted.metadata.extension.as_deref(), Some("avif"));
+    }
+
+    #[test]
+    fn test_svg_needs_to_be_allowed_when_verifying() {
+        let build = |name: &str, allow_svg: bool| {
+            let fetcher = MockFetcher::new(vec![
+                Response::ok_svg(),
+                Response::ok(crate::downloader::fixtures::SVG.to_vec(), None),
+            ]);
+
+            DownloaderBuilder::with_fetcher(testing::cache_dir(name), fetcher)
+                .verify_images(VerifyLevel::FullDecode)
+                .allow_svg(allow_svg)
+                .build()
+        };
+
+        let strict = build("verify_svg_strict", false);
+
+        let lenient = build("verify_svg_allowed", true);
+
+        // Act
+
+        let rejected = strict.download("https://example.com/logo");
+
+        let allowed = [
+            lenient.download("https://example.com/logo").unwrap(),
+            lenient.download("https://example.com/icon").unwrap(),
+        ];
+
+        // Assert
+
+        assert_eq!(rejected.unwrap_err(), DownloadError::UnsupportedContent);
+
+        for download in allowed {
+            assert_eq!(download.metadata.extension.as_deref(), Some("svg"));
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::{borrow::Cow, fmt};
+
+use url::{ParseError, Url};
+
+// Anything the downloader accepts as a URL. `as_str` is what observers see,
+// `to_download_url` yields the normalized form used for fetching and hashing.
+pub trait IntoDownloadUrl {
+    fn as_str(&self) -> &str;
+
+    fn to_download_url(&self) -> Result<Cow<'_, Url>, ParseError>;
+}
+
+impl IntoDownloadUrl for str {
+    fn as_str(&self) -> &str {
+        self
+    }
+
+    fn to_download_url(&self) -> Result<Cow<'_, Url>, ParseError> {
+        parse(self).map(Cow::Owned)
+    }
+}
+
+impl IntoDownloadUrl for String {
+    fn as_str(&self) -> &str {
+        self
+    }
+
+    fn to_download_url(&self) -> Result<Cow<'_, Url>, ParseError> {
+        parse(self).map(Cow::Owned)
+    }
+}
+
+impl IntoDownloadUrl for Url {
+    fn as_str(&self) -> &str {
+        self.as_str()
+    }
+
+    fn to_download_url(&self) -> Result<Cow<'_, Url>, ParseError> {
+        Ok(normalize(self))
+    }
+}
+
+impl<U: IntoDownloadUrl + ?Sized> IntoDownloadUrl for &U {
+    fn as_str(&self) -> &str {
+        (**self).as_str()
+    }
+
+    fn to_download_url(&self) -> Result<Cow<'_, Url>, ParseError> {
+        (**self).to_download_url()
+    }
+}
+
+// Why a string is not a URL the downloader fetches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlProblem {
+    // No scheme, such as `logo.png` or `//example.com/logo.png`.
+    RelativeUrl,
+    // One the fetcher cannot handle, by default neither `http` nor `https`.
+    UnsupportedScheme(String),
+    EmptyHost,
+    // Anything else `Url::parse` refuses, as it describes it.
+    ParseError(String),
+}
+
+impl fmt::Display for UrlProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RelativeUrl => f.write_str("relative url"),
+            Self::UnsupportedScheme(scheme) => write!(f, "unsupported scheme {scheme}"),
+            Self::EmptyHost => f.write_str("empty host"),
+            Self::ParseError(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl From<ParseError> for UrlProblem {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::RelativeUrlWithoutBase | ParseError::RelativeUrlWithCannotBeABaseBase => {
+                Self::RelativeUrl
+            }
+            ParseError::EmptyHost => Self::EmptyHost,
+            error => Self::ParseError(error.to_string()),
+        }
+    }
+}
+
+// What `FileDownloader::schemes` accepts unless a fetcher says otherwise.
+pub(crate) const DEFAULT_SCHEMES: &[&str] = &["http", "https"];
+
+// Checks a URL as `download` would with the default fetcher, without a
+// downloader, such as for input typed in a form. The URL is normalized.
+pub fn validate_url(input: &str) -> Result<Url, UrlProblem> {
+    let url = parse(input)?;
+
+    check_scheme(&url, DEFAULT_SCHEMES)?;
+
+    Ok(url)
+}
+
+pub(crate) fn check_scheme(url: &Url, schemes: &[&str]) -> Result<(), UrlProblem> {
+    match schemes.contains(&url.scheme()) {
+        true => Ok(()),
+        false => Err(UrlProblem::UnsupportedScheme(url.scheme().to_string())),
+    }
+}
+
+pub(crate) fn parse(input: &str) -> Result<Url, ParseError> {
+    let url = Url::parse(input)?;
+
+    Ok(normalize(&url).into_owned())
+}
+
+// `Url::parse` already converts hosts to punycode and percent-encodes
+// non-ASCII path and query characters. Escapes are also brought to upper case
+// so `%c3%bc` and `%C3%BC` share a cache key. Unicode normalization of the path
+// is left untouched: servers treat NFC and NFD bytes as different resources.
+fn normalize(url: &Url) -> Cow<'_, Url> {
+    let path = uppercase_escapes(url.path());
+
+    let query = url.query().and_then(uppercase_escapes);
+
+    if path.is_none() && query.is_none() {
+        return Cow::Borrowed(url);
+    }
+
+    let mut url = url.clone();
+
+    if let Some(path) = path {
+        url.set_path(&path);
+    }
+
+    if let Some(query) = query {
+        url.set_query(Some(&query));
+    }
+
+    Cow::Owned(url)
+}
+
+fn uppercase_escapes(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+
+    let needs_rewrite = bytes.windows(3).any(|window| {
+        window[0] == b'%'
+            && window[1..].iter().all(u8::is_ascii_hexdigit)
+            && window[1..].iter().any(u8::is_ascii_lowercase)
+    });
+
+    if !needs_rewrite {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(bytes.len());
+
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escape = bytes.get(index + 1..index + 3);
+
+        match escape {
+            Some(hex) if bytes[index] == b'%' && hex.iter().all(u8::is_ascii_hexdigit) => {
+                output.push(b'%');
+                output.extend(hex.iter().map(u8::to_ascii_uppercase));
+                index += 3;
+            }
+            _ => {
+                output.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8(output).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+
+    use super::{parse, validate_url, UrlProblem};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, CachePolicy, DownloaderBuilder, Response,
+    };
+
+    #[test]
+    fn test_iri_round_trips() {
+        let cases = [
+            (
+                "https://bücher.example/img.png",
+                "https://xn--bcher-kva.example/img.png",
+            ),
+            (
+                "https://example.com/😀/logo.png",
+                "https://example.com/%F0%9F%98%80/logo.png",
+            ),
+            (
+                "https://example.com/café.png?name=ü",
+                "https://example.com/caf%C3%A9.png?name=%C3%BC",
+            ),
+            (
+                "https://example.com/caf%c3%a9.png?name=%c3%bc",
+                "https://example.com/caf%C3%A9.png?name=%C3%BC",
+            ),
+            (
+                "https://EXAMPLE.com/a%2fb.png",
+                "https://example.com/a%2Fb.png",
+            ),
+        ];
+
+        for (input, expected) in cases {
+            // Act
+
+            let url = parse(input).unwrap();
+
+            // Assert
+
+            assert_eq!(url.as_str(), expected, "{input}");
+            assert_eq!(parse(url.as_str()).unwrap(), url, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_url_problems() {
+        let parse_error = |reason: &str| Err(UrlProblem::ParseError(reason.to_string()));
+
+        let cases = [
+            ("", Err(UrlProblem::RelativeUrl)),
+            ("logo.png", Err(UrlProblem::RelativeUrl)),
+            ("/images/logo.png", Err(UrlProblem::RelativeUrl)),
+            ("//example.com/logo.png", Err(UrlProblem::RelativeUrl)),
+            ("example.com/logo.png", Err(UrlProblem::RelativeUrl)),
+            (
+                "ftp://example.com/logo.png",
+                Err(UrlProblem::UnsupportedScheme("ftp".to_string())),
+            ),
+            (
+                "mailto:someone@example.com",
+                Err(UrlProblem::UnsupportedScheme("mailto".to_string())),
+            ),
+            ("https://", Err(UrlProblem::EmptyHost)),
+            ("https://:443/logo.png", Err(UrlProblem::EmptyHost)),
+            (
+                "https://exa mple.com/logo.png",
+                parse_error("invalid international domain name"),
+            ),
+            (
+                "https://example.com:99999/",
+                parse_error("invalid port number"),
+            ),
+            ("https://[::1/logo.png", parse_error("invalid IPv6 address")),
+            ("https://256.0.0.1/", parse_error("invalid IPv4 address")),
+            (
+                "HTTPS://Example.com/caf%c3%a9.png",
+                Ok("https://example.com/caf%C3%A9.png"),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            // Act
+
+            let result = validate_url(input);
+
+            // Assert
+
+            assert_eq!(
+                result.map(String::from),
+                expected.map(str::to_string),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unicode_normalization() {
+        let nfc_host = "https://b\u{fc}cher.example/img.png";
+        let nfd_host = "https://bu\u{308}cher.example/img.png";
+
+        let nfc_path = "https://example.com/caf\u{e9}.png";
+        let nfd_path = "https://example.com/cafe\u{301}.png";
+
+        // Act
+
+        let hosts = (parse(nfc_host).unwrap(), parse(nfd_host).unwrap());
+
+        let paths = (parse(nfc_path).unwrap(), parse(nfd_path).unwrap());
+
+        // Assert
+
+        assert_eq!(hosts.0, hosts.1);
+        assert_eq!(paths.0.as_str(), "https://example.com/caf%C3%A9.png");
+        assert_eq!(paths.1.as_str(), "https://example.com/cafe%CC%81.png");
+    }
+
+    #[test]
+    fn test_iri_spellings_share_cache_entry() {
+        let fetcher = MockFetcher::new(vec![Response::ok(
+            b"png".to_vec(),
+            Some("image/png".to_string()),
+        )]);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("iri"), fetcher)
+            .cache_policy(CachePolicy::CacheFirst)
+            .ttl(Duration::from_secs(60))
+            .build();
+
+        // Act
+
+        let first = downloader.download("https://bücher.example/ü.png").unwrap();
+
+        let second = downloader
+            .download("https://xn--bcher-kva.example/%c3%bc.png")
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(first.file, second.file);
+        assert_eq!(downloader.fetcher().calls(), 1);
+        assert_eq!(
+            downloader.fetcher().requested_url(0),
+            "https://xn--bcher-kva.example/%C3%BC.png"
+        );
+    }
+
+    #[test]
+    fn test_accepted_url_inputs_share_cache_entry() {
+        let fetcher = MockFetcher::new(vec![Response::ok(
+            b"png".to_vec(),
+            Some("image/png".to_string()),
+        )]);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("iri_inputs"), fetcher)
+            .cache_policy(CachePolicy::CacheFirst)
+            .ttl(Duration::from_secs(60))
+            .build();
+
+        let parsed = Url::parse("https://example.com/caf%c3%a9.png").unwrap();
+
+        let owned = String::from("https://example.com/café.png");
+
+        // Act
+
+        let from_url = downloader.download_url(&parsed).unwrap();
+
+        let from_str = downloader
+            .download("https://example.com/caf%C3%A9.png")
+            .unwrap();
+
+        let summary = downloader.prefetch(&[owned]);
+
+        let urls = downloader.prefetch(&[parsed]);
+
+        // Assert
+
+        assert_eq!(from_url.file, from_str.file);
+        assert_eq!(summary.cached, 1);
+        assert_eq!(urls.cached, 1);
+        assert_eq!(downloader.fetcher().calls(), 1);
+        assert_eq!(
+            downloader.fetcher().requested_url(0),
+            "https://example.com/caf%C3%A9.png"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use url::Url;
+
+use super::{cache_key::CacheKey, tee::hex, HashAlgo, KeyEncoding};
+
+// Bytes of the HMAC kept, as 32 hex digits.
+const SALTED_LEN: usize = 16;
+
+// Names entries in place of `hash_algo` and `key_encoding`. Keys that are
+// not 1 to 78 of `A-Z a-z 0-9 - _` are hashed again, so they always make a
+// valid file name.
+pub trait CacheKeyer: Send + Sync {
+    fn key(&self, url: &Url) -> String;
+
+    // The key of an entry stored per variant, `variant` being the
+    // significant request headers as `\nname: value` lines.
+    fn variant_key(&self, url: &Url, variant: &str) -> String {
+        format!("{}{variant}", self.key(url))
+    }
+}
+
+// Hashes the URL with `hash_algo` in `key_encoding`, without a keyer.
+pub(crate) struct HashKeyer {
+    pub algo: HashAlgo,
+    pub encoding: KeyEncoding,
+}
+
+impl HashKeyer {
+    // The variant is hashed with the URL, as entries always were. Plain keys
+    // are hashed without allocating, being computed for every lookup.
+    pub fn cache_key(&self, url: &str, variant: &str) -> CacheKey {
+        match variant.is_empty() {
+            true => CacheKey::new(url, self.algo, self.encoding),
+            false => CacheKey::new(&format!("{url}{variant}"), self.algo, self.encoding),
+        }
+    }
+}
+
+impl CacheKeyer for HashKeyer {
+    fn key(&self, url: &Url) -> String {
+        self.variant_key(url, "")
+    }
+
+    fn variant_key(&self, url: &Url, variant: &str) -> String {
+        self.cache_key(url.as_str(), variant).as_str().to_string()
+    }
+}
+
+// Keys entries by an HMAC-SHA256 of their URL under a secret, so names
+// cannot be matched to URLs, nor shared between caches, without it.
+pub struct SaltedKeyer {
+    mac: Hmac<Sha256>,
+}
+
+impl SaltedKeyer {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(secret.as_ref()).expect("HMAC accepts keys of any length"),
+        }
+    }
+}
+
+impl CacheKeyer for SaltedKeyer {
+    fn key(&self, url: &Url) -> String {
+        let mut mac = self.mac.clone();
+
+        mac.update(url.as_str().as_bytes());
+
+        hex(&mac.finalize().into_bytes()[..SALTED_LEN])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use url::Url;
+
+    use super::{CacheKeyer, HashKeyer, SaltedKeyer};
+    use crate::downloader::{
+        cache_key::CacheKey, fetcher::MockFetcher, testing, CachePolicy, Downloader,
+        DownloaderBuilder, HashAlgo, KeyEncoding, Response,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn tenant(dir: &Path, secret: &str) -> Downloader<MockFetcher> {
+        DownloaderBuilder::with_fetcher(
+            dir,
+            MockFetcher::new(vec![Response::ok(
+                secret.as_bytes().to_vec(),
+                Some("image/png".to_string()),
+            )]),
+        )
+        .cache_keyer(SaltedKeyer::new(secret))
+        .cache_policy(CachePolicy::CacheFirst)
+        .build()
+    }
+
+    #[test]
+    fn test_salted_keys_are_hmacs() {
+        // Act
+
+        let key = SaltedKeyer::new("key").key(&Url::parse(URL).unwrap());
+
+        // Assert
+
+        assert_eq!(key, "2e7021692fbc4c576b3996b61d544e69");
+    }
+
+    #[test]
+    fn test_default_keys_hash_the_url_and_variant() {
+        let keyer = HashKeyer {
+            algo: HashAlgo::default(),
+            encoding: KeyEncoding::default(),
+        };
+
+        let url = Url::parse(URL).unwrap();
+
+        // Act
+
+        let plain = keyer.key(&url);
+
+        let variant = keyer.variant_key(&url, "\naccept: image/avif");
+
+        // Assert
+
+        assert_eq!(plain, CacheKey::from_url(URL).as_str());
+        assert_eq!(
+            variant,
+            CacheKey::from_url(&format!("{URL}\naccept: image/avif")).as_str()
+        );
+    }
+
+    #[test]
+    fn test_tenants_do_not_share_entries() {
+        let dir = testing::cache_dir("keyer_tenants");
+
+        let first = tenant(&dir, "first secret");
+
+        let second = tenant(&dir, "second secret");
+
+        let planned = second.target_path_for(URL).unwrap();
+
+        // Act
+
+        let from_first = first.download(URL).unwrap();
+
+        let from_second = second.download(URL).unwrap();
+
+        // Assert
+
+        assert_ne!(from_first.file, from_second.file);
+        assert_eq!(planned, from_second.file);
+        assert_eq!(from_second.bytes().unwrap(), b"second secret");
+        assert_eq!(first.fetcher().calls(), 1);
+        assert_eq!(second.fetcher().calls(), 1);
+        assert_ne!(
+            from_first.file.file_stem().unwrap(),
+            CacheKey::from_url(URL).as_str()
+        );
+    }
+}
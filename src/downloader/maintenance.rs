@@ -0,0 +1,164 @@
+use std::{
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Lets a job put itself off for a while, unless someone is waiting for the
+// queue to finish.
+#[derive(Clone, Default)]
+pub(crate) struct Pause(Arc<(Mutex<usize>, Condvar)>);
+
+impl Pause {
+    pub fn wait(&self, wait: Duration) {
+        let (waiting, woken) = &*self.0;
+
+        let _ = woken.wait_timeout_while(waiting.lock().unwrap(), wait, |waiting| *waiting == 0);
+    }
+
+    fn hurry(&self, waiting: impl FnOnce(&mut usize)) {
+        let (count, woken) = &*self.0;
+
+        waiting(&mut count.lock().unwrap());
+
+        woken.notify_all();
+    }
+}
+
+// Background worker owned by a `Downloader` for cache upkeep that callers
+// should not wait on, such as persisting the manifest. It is spawned with the
+// first job, runs jobs in order, and finishes the queue before being dropped.
+#[derive(Default)]
+pub(crate) struct Maintenance {
+    worker: Mutex<Option<(Sender<Job>, JoinHandle<()>)>>,
+    pause: Pause,
+}
+
+impl Maintenance {
+    pub fn pause(&self) -> Pause {
+        self.pause.clone()
+    }
+
+    pub fn enqueue(&self, job: impl FnOnce() + Send + 'static) {
+        let mut worker = self.worker.lock().unwrap();
+
+        let (sender, _) = worker.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<Job>();
+
+            let handle = thread::spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            });
+
+            (sender, handle)
+        });
+
+        // The receiver only goes away with the worker, which a panicking job
+        // takes down; the job then runs here instead of being lost.
+        if let Err(mpsc::SendError(job)) = sender.send(Box::new(job)) {
+            job();
+        }
+    }
+
+    // Blocks until every job enqueued so far has run.
+    pub fn flush(&self) {
+        if self.worker.lock().unwrap().is_none() {
+            return;
+        }
+
+        let (done, finished) = mpsc::channel();
+
+        self.pause.hurry(|waiting| *waiting += 1);
+
+        self.enqueue(move || {
+            let _ = done.send(());
+        });
+
+        let _ = finished.recv();
+
+        self.pause.hurry(|waiting| *waiting -= 1);
+    }
+}
+
+impl Drop for Maintenance {
+    fn drop(&mut self) {
+        if let Some((sender, handle)) = self.worker.get_mut().unwrap().take() {
+            // Nothing is put off past the last job.
+            self.pause.hurry(|waiting| *waiting += 1);
+
+            drop(sender);
+
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use super::Maintenance;
+
+    #[test]
+    fn test_jobs_run_in_order_and_finish_on_drop() {
+        let maintenance = Maintenance::default();
+
+        let ran = Arc::new(Mutex::new(Vec::new()));
+
+        for index in 0..3 {
+            let ran = Arc::clone(&ran);
+
+            maintenance.enqueue(move || {
+                thread::sleep(Duration::from_millis(10));
+
+                ran.lock().unwrap().push(index);
+            });
+        }
+
+        // Act
+
+        maintenance.flush();
+
+        let flushed = ran.lock().unwrap().clone();
+
+        let ran_later = Arc::clone(&ran);
+
+        maintenance.enqueue(move || ran_later.lock().unwrap().push(3));
+
+        drop(maintenance);
+
+        // Assert
+
+        assert_eq!(flushed, [0, 1, 2]);
+        assert_eq!(*ran.lock().unwrap(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_pauses_are_cut_short_by_flushes() {
+        let maintenance = Maintenance::default();
+
+        let pause = maintenance.pause();
+
+        maintenance.enqueue(move || pause.wait(Duration::from_secs(60)));
+
+        let started = std::time::Instant::now();
+
+        // Act
+
+        maintenance.flush();
+
+        // Assert
+
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{maintenance::Maintenance, partial};
+
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+// Each save rewrites the whole manifest, so a long run of downloads costs a
+// write per interval rather than one per entry.
+const SAVE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ManifestEntry {
+    pub url: String,
+    pub file: String,
+    pub fetched_at: u64,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub no_cache: bool,
+    #[serde(default)]
+    pub no_store: bool,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub mime: Option<String>,
+    #[serde(default)]
+    pub redirects: Vec<(u16, String)>,
+    // The host of `url`, which per-host limits count entries by.
+    #[serde(default)]
+    pub host: Option<String>,
+    // When the entry was last written or served, for per-host limits.
+    #[serde(default)]
+    pub used_at: Option<u64>,
+    // The name the response's `Content-Disposition` offered, sanitized.
+    #[serde(default)]
+    pub filename: Option<String>,
+    // The names the file had before `rescan_unknown` renamed it.
+    #[serde(default)]
+    pub renamed_from: Vec<String>,
+    // Under the plain URL key: the request headers its responses vary on.
+    #[serde(default)]
+    pub vary: Vec<String>,
+    // The significant request headers this variant was fetched with.
+    #[serde(default)]
+    pub variant: Vec<(String, String)>,
+    // Set while the URL's last failure is remembered instead of refetched.
+    #[serde(default)]
+    pub negative: Option<NegativeEntry>,
+    // Caller supplied, kept across refetches of the URL.
+    #[serde(default)]
+    pub user_meta: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct NegativeEntry {
+    pub status: u16,
+    pub expires_at: u64,
+}
+
+impl ManifestEntry {
+    pub fn fetched_at(&self) -> SystemTime {
+        from_unix_secs(self.fetched_at)
+    }
+
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at.map(from_unix_secs)
+    }
+
+    pub fn host(&self) -> Option<String> {
+        self.host.clone().or_else(|| host_of(&self.url))
+    }
+}
+
+// Per-directory index of cache entries keyed by the hashed file name. The
+// data files stay the source of truth: a missing or corrupt manifest only
+// loses freshness metadata. Changes are written out by the maintenance
+// worker, so several in a row cost a single write.
+pub(crate) struct Manifest {
+    path: PathBuf,
+    entries: Arc<Mutex<HashMap<String, ManifestEntry>>>,
+    // The keys of the entries holding each body, by SHA-256. Rebuilt on load,
+    // as the entries record their digest.
+    digests: Mutex<HashMap<String, BTreeSet<String>>>,
+    dirty: Arc<AtomicBool>,
+    saved_at: Arc<Mutex<Option<Instant>>>,
+    maintenance: Arc<Maintenance>,
+}
+
+impl Manifest {
+    pub fn load(dir: &Path, maintenance: Arc<Maintenance>) -> Self {
+        let path = dir.join(MANIFEST_FILE);
+
+        let entries: HashMap<String, ManifestEntry> = fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+
+        let mut digests: HashMap<_, BTreeSet<_>> = HashMap::new();
+
+        for (key, entry) in &entries {
+            if let Some(sha256) = &entry.sha256 {
+                digests
+                    .entry(sha256.clone())
+                    .or_default()
+                    .insert(key.clone());
+            }
+        }
+
+        Self {
+            path,
+            entries: Arc::new(Mutex::new(entries)),
+            digests: Mutex::new(digests),
+            dirty: Arc::new(AtomicBool::new(false)),
+            saved_at: Arc::default(),
+            maintenance,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<ManifestEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: &str, entry: ManifestEntry) {
+        let digest = entry.sha256.clone();
+
+        let previous = self.entries.lock().unwrap().insert(key.to_string(), entry);
+
+        let previous = previous.and_then(|entry| entry.sha256);
+
+        if previous != digest {
+            let mut digests = self.digests.lock().unwrap();
+
+            if let Some(previous) = previous {
+                forget_digest(&mut digests, &previous, key);
+            }
+
+            if let Some(digest) = digest {
+                digests.entry(digest).or_default().insert(key.to_string());
+            }
+        }
+
+        self.schedule_save();
+    }
+
+    // Changes the entry under `key` in place, `None` without one. Nothing is
+    // saved when `change` fails, which must then leave the entry as it was.
+    pub fn update<R, E>(
+        &self,
+        key: &str,
+        change: impl FnOnce(&mut ManifestEntry) -> Result<R, E>,
+    ) -> Option<Result<R, E>> {
+        let result = change(self.entries.lock().unwrap().get_mut(key)?);
+
+        if result.is_ok() {
+            self.schedule_save();
+        }
+
+        Some(result)
+    }
+
+    pub fn entries(&self) -> Vec<(String, ManifestEntry)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect()
+    }
+
+    // The keys and entries holding a file from `host`, least recently used
+    // first. Entries recorded before hosts were have theirs read off the URL.
+    pub fn entries_for_host(&self, host: &str) -> Vec<(String, ManifestEntry)> {
+        let mut entries: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| !entry.file.is_empty() && entry.host().as_deref() == Some(host))
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+
+        entries
+            .sort_by_key(|(key, entry)| (entry.used_at.unwrap_or(entry.fetched_at), key.clone()));
+
+        entries
+    }
+
+    // Entries recorded before hosts were get theirs, and count as used when
+    // they were fetched.
+    pub fn backfill_hosts(&self) {
+        let mut changed = false;
+
+        for entry in self.entries.lock().unwrap().values_mut() {
+            if entry.host.is_none() {
+                entry.host = host_of(&entry.url);
+                changed |= entry.host.is_some();
+            }
+
+            if entry.used_at.is_none() {
+                entry.used_at = Some(entry.fetched_at);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.schedule_save();
+        }
+    }
+
+    pub fn touch(&self, key: &str, at: u64) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(key) {
+            entry.used_at = Some(at);
+        } else {
+            return;
+        }
+
+        self.schedule_save();
+    }
+
+    pub fn remove(&self, key: &str) -> Option<ManifestEntry> {
+        let entry = self.entries.lock().unwrap().remove(key)?;
+
+        if let Some(sha256) = &entry.sha256 {
+            self.remove_digest(sha256, key);
+        }
+
+        self.schedule_save();
+
+        Some(entry)
+    }
+
+    // Whether any entry names `file`, as aliases share their target's.
+    pub fn names_file(&self, file: &str) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .any(|entry| entry.file == file)
+    }
+
+    // The keys of the entries whose body has `sha256`, in no useful order.
+    pub fn keys_with_digest(&self, sha256: &str) -> Vec<String> {
+        self.digests
+            .lock()
+            .unwrap()
+            .get(sha256)
+            .map(|keys| keys.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // Drops `key` from the digest index, for entries whose file is gone.
+    pub fn remove_digest(&self, sha256: &str, key: &str) {
+        forget_digest(&mut self.digests.lock().unwrap(), sha256, key);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        self.digests.lock().unwrap().clear();
+    }
+
+    fn schedule_save(&self) {
+        if self.dirty.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let path = self.path.clone();
+
+        let entries = Arc::clone(&self.entries);
+
+        let dirty = Arc::clone(&self.dirty);
+
+        let saved_at = Arc::clone(&self.saved_at);
+
+        let pause = self.maintenance.pause();
+
+        self.maintenance.enqueue(move || {
+            // Changes made while waiting are written with this save.
+            if let Some(saved_at) = *saved_at.lock().unwrap() {
+                pause.wait(SAVE_INTERVAL.saturating_sub(saved_at.elapsed()));
+            }
+
+            // Changes made from here on schedule another save.
+            dirty.store(false, Ordering::Release);
+
+            let content = serde_json::to_vec_pretty(&*entries.lock().unwrap());
+
+            // The manifest only carries freshness hints, failing to persist it
+            // must not fail anything.
+            if let Ok(content) = content {
+                let _ = save(&path, &content);
+            }
+
+            *saved_at.lock().unwrap() = Some(Instant::now());
+        });
+    }
+}
+
+fn forget_digest(digests: &mut HashMap<String, BTreeSet<String>>, sha256: &str, key: &str) {
+    if let Some(keys) = digests.get_mut(sha256) {
+        keys.remove(key);
+
+        if keys.is_empty() {
+            digests.remove(sha256);
+        }
+    }
+}
+
+fn save(path: &Path, content: &[u8]) -> io::Result<()> {
+    let partial = path.with_extension("json.part");
+
+    partial::recreating_parent(&partial, || fs::write(&partial, content))?;
+
+    fs::rename(&partial, path)
+}
+
+pub(crate) fn host_of(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_string)
+}
+
+pub(crate) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub(crate) fn from_unix_secs(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
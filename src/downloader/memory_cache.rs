@@ -0,0 +1,364 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use super::{tee::BodySummary, Download, Downloader, FileDownloader, Storage};
+
+// What a slot costs beyond its bytes and key: the map entries, the `Arc`
+// header and the digest it is checked against.
+const SLOT_OVERHEAD: u64 = 160;
+
+// The bytes of a cached file, when the memory cache holds or is to hold
+// them. Where the bytes are read from does not make two downloads differ.
+#[derive(Clone, Default)]
+pub(crate) struct Resident(pub Option<Fill>);
+
+#[derive(Clone)]
+pub(crate) enum Fill {
+    Filled(Arc<[u8]>),
+    // The slot is filled by the first read of the file, so downloads that
+    // are never read cost no more than without a memory cache.
+    OnFirstRead {
+        memory: Arc<MemoryCache>,
+        key: String,
+        sha256: String,
+    },
+}
+
+impl Resident {
+    // The bytes from memory, or from `read` when the slot is not filled yet.
+    pub fn bytes(&self, read: impl FnOnce() -> io::Result<Vec<u8>>) -> io::Result<Vec<u8>> {
+        match &self.0 {
+            Some(Fill::Filled(bytes)) => Ok(bytes.to_vec()),
+            Some(Fill::OnFirstRead {
+                memory,
+                key,
+                sha256,
+            }) => {
+                if let Some(bytes) = memory.get(key, sha256) {
+                    return Ok(bytes.to_vec());
+                }
+
+                let bytes = read()?;
+
+                // The file may have been replaced since it was served.
+                if BodySummary::of(&bytes).sha256 == *sha256 {
+                    memory.insert(key, sha256, bytes.as_slice().into());
+                }
+
+                Ok(bytes)
+            }
+            None => read(),
+        }
+    }
+}
+
+impl PartialEq for Resident {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for Resident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(Fill::Filled(bytes)) => write!(f, "Resident({} bytes)", bytes.len()),
+            Some(Fill::OnFirstRead { .. }) => f.write_str("Resident(on first read)"),
+            None => f.write_str("Resident(None)"),
+        }
+    }
+}
+
+struct Slot {
+    bytes: Arc<[u8]>,
+    sha256: String,
+    used: u64,
+}
+
+impl Slot {
+    fn cost(&self, key: &str) -> u64 {
+        self.bytes.len() as u64 + key.len() as u64 + SLOT_OVERHEAD
+    }
+}
+
+#[derive(Default)]
+struct Slots {
+    slots: HashMap<String, Slot>,
+    // Least recently used first.
+    order: BTreeMap<u64, String>,
+    bytes: u64,
+    clock: u64,
+}
+
+impl Slots {
+    fn remove(&mut self, key: &str) {
+        if let Some(slot) = self.slots.remove(key) {
+            self.order.remove(&slot.used);
+            self.bytes -= slot.cost(key);
+        }
+    }
+}
+
+// Small files served again and again, held in front of the disk cache. Slots
+// are keyed by path and only answer for the digest they were filled with, so
+// a file replaced on disk is never served from memory. Evicting a slot leaves
+// the file alone.
+pub(crate) struct MemoryCache {
+    max_bytes: u64,
+    slots: Mutex<Slots>,
+}
+
+impl MemoryCache {
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            slots: Mutex::new(Slots::default()),
+        }
+    }
+
+    pub fn get(&self, key: &str, sha256: &str) -> Option<Arc<[u8]>> {
+        let mut slots = self.slots.lock().unwrap();
+
+        if slots.slots.get(key)?.sha256 != sha256 {
+            slots.remove(key);
+
+            return None;
+        }
+
+        slots.clock += 1;
+
+        let used = slots.clock;
+
+        let slot = slots.slots.get_mut(key)?;
+
+        let previous = std::mem::replace(&mut slot.used, used);
+
+        let bytes = Arc::clone(&slot.bytes);
+
+        slots.order.remove(&previous);
+        slots.order.insert(used, key.to_string());
+
+        Some(bytes)
+    }
+
+    pub fn insert(&self, key: &str, sha256: &str, bytes: Arc<[u8]>) {
+        let mut slots = self.slots.lock().unwrap();
+
+        slots.remove(key);
+
+        slots.clock += 1;
+
+        let slot = Slot {
+            bytes,
+            sha256: sha256.to_string(),
+            used: slots.clock,
+        };
+
+        let cost = slot.cost(key);
+
+        if cost > self.max_bytes {
+            return;
+        }
+
+        while slots.bytes + cost > self.max_bytes {
+            let Some((_, oldest)) = slots.order.pop_first() else {
+                break;
+            };
+
+            if let Some(evicted) = slots.slots.remove(&oldest) {
+                slots.bytes -= evicted.cost(&oldest);
+            }
+        }
+
+        slots.order.insert(slot.used, key.to_string());
+        slots.slots.insert(key.to_string(), slot);
+        slots.bytes += cost;
+    }
+
+    pub fn clear(&self) {
+        *self.slots.lock().unwrap() = Slots::default();
+    }
+
+    #[cfg(test)]
+    fn bytes(&self) -> u64 {
+        self.slots.lock().unwrap().bytes
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Hits are served from their slot; downloads just written, and hits not
+    // held yet, fill theirs when first read.
+    pub(crate) fn with_resident_bytes(&self, mut download: Download, written: bool) -> Download {
+        let Some(memory) = &self.memory_cache else {
+            return download;
+        };
+
+        let Some(sha256) = download.metadata.sha256.clone() else {
+            return download;
+        };
+
+        let key = download.file.to_string_lossy().into_owned();
+
+        let resident = match memory.get(&key, &sha256).filter(|_| !written) {
+            Some(bytes) => Some(Fill::Filled(bytes)),
+            None if download.metadata.size.unwrap_or(u64::MAX) < memory.max_bytes => {
+                Some(Fill::OnFirstRead {
+                    memory: Arc::clone(memory),
+                    key,
+                    sha256,
+                })
+            }
+            None => None,
+        };
+
+        download.resident = Resident(resident);
+
+        download
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc, time::Duration};
+
+    use super::{Fill, MemoryCache, SLOT_OVERHEAD};
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, CachePolicy, DownloaderBuilder, Response,
+    };
+
+    #[test]
+    fn test_least_recently_used_slots_are_evicted() {
+        let memory = MemoryCache::new(3 * (SLOT_OVERHEAD + 11));
+
+        let bytes = |byte: u8| -> Arc<[u8]> { vec![byte; 10].into() };
+
+        memory.insert("a", "1", bytes(1));
+        memory.insert("b", "2", bytes(2));
+        memory.insert("c", "3", bytes(3));
+
+        // Act
+
+        memory.get("a", "1");
+
+        memory.insert("d", "4", bytes(4));
+
+        // Assert
+
+        assert!(memory.get("b", "2").is_none(), "least recently used");
+        assert_eq!(memory.get("a", "1").as_deref(), Some(&[1; 10][..]));
+        assert!(memory.get("c", "3").is_some());
+        assert!(memory.get("d", "4").is_some());
+        assert!(memory.get("d", "other").is_none(), "digest changed");
+        assert!(memory.get("d", "4").is_none(), "dropped on mismatch");
+        assert_eq!(memory.bytes(), 2 * (SLOT_OVERHEAD + 11));
+
+        memory.insert("large", "5", vec![0; 1024].into());
+
+        assert!(memory.get("large", "5").is_none());
+    }
+
+    #[test]
+    fn test_memory_and_disk_agree_after_overwrites() {
+        let png =
+            |body: &str| Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()));
+
+        let clock = FakeClock::new();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("memory_cache_overwrites"),
+            MockFetcher::new(vec![png("first"), png("second"), png("third")]),
+        )
+        .clock(clock.clone())
+        .cache_policy(CachePolicy::CacheFirst)
+        .ttl(Duration::from_secs(60))
+        .with_memory_cache(64 * 1024)
+        .build();
+
+        let url = "https://example.com/icon.png";
+
+        // Act
+
+        let mut served = Vec::new();
+
+        for _ in 0..3 {
+            let written = downloader.download(url).unwrap().bytes().unwrap();
+
+            let hit = downloader.download(url).unwrap();
+
+            served.push((written, hit));
+
+            clock.advance(Duration::from_secs(61));
+        }
+
+        // Assert
+
+        for ((written, hit), expected) in served.iter().zip(["first", "second", "third"]) {
+            assert!(matches!(hit.resident.0, Some(Fill::Filled(_))));
+            assert_eq!(hit.bytes().unwrap(), expected.as_bytes());
+            assert_eq!(written, expected.as_bytes());
+        }
+
+        let last = &served[2].1;
+
+        assert_eq!(last.bytes().unwrap(), fs::read(&last.file).unwrap());
+        assert_eq!(downloader.fetcher().calls(), 3);
+    }
+
+    #[test]
+    fn test_files_are_read_from_disk_without_a_memory_cache() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("memory_cache_off"),
+            MockFetcher::new(vec![Response::ok(b"icon".to_vec(), None)]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        downloader.download("https://example.com/icon").unwrap();
+
+        // Act
+
+        let hit = downloader.download("https://example.com/icon").unwrap();
+
+        // Assert
+
+        assert!(hit.resident.0.is_none());
+        assert_eq!(hit.bytes().unwrap(), b"icon");
+    }
+
+    #[test]
+    fn test_slots_are_filled_by_the_first_read() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("memory_cache_first_read"),
+            MockFetcher::new(vec![Response::ok(b"icon".to_vec(), None)]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .with_memory_cache(64 * 1024)
+        .build();
+
+        let memory = downloader.memory_cache.clone().unwrap();
+
+        let written = downloader.download("https://example.com/icon").unwrap();
+
+        let unread = memory.bytes();
+
+        // Act
+
+        let bytes = written.bytes().unwrap();
+
+        // Assert
+
+        assert_eq!(unread, 0);
+        assert_eq!(bytes, b"icon");
+        assert!(memory.bytes() > 0);
+
+        let hit = downloader.download("https://example.com/icon").unwrap();
+
+        assert!(matches!(hit.resident.0, Some(Fill::Filled(_))));
+    }
+}
@@ -1,69 +1,461 @@
+mod adopt;
+#[cfg(feature = "archives")]
+mod archive;
+mod attempts;
+mod batch;
+mod budget;
+mod builder;
+mod cache;
+mod cache_control;
+mod cache_key;
+mod cache_policy;
+mod cancel;
+mod checksum;
+mod checksum_file;
+mod circuit_breaker;
+mod clock;
+mod connections;
+mod data_uri;
+mod deadline;
+mod download;
+mod download_iter;
+mod error_code;
+mod extension;
+mod fetch_error;
 mod fetcher;
+mod filename;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixtures;
+mod format;
+mod headers;
+mod host_limit;
+mod host_policy;
+#[cfg(feature = "image")]
+mod images;
+mod iri;
+mod keyer;
+mod maintenance;
+mod manifest;
+mod memory_cache;
+mod name_by;
+mod negative;
+mod observer;
+mod options;
+mod outcome;
+mod overwrite_policy;
+mod parallel;
+mod partial;
+mod partition;
+mod peek;
+mod persist;
+mod prefetch;
+mod probe;
+mod ranges;
+mod rate_limit;
+mod referer;
+mod refresher;
+mod report;
+mod rescan;
+mod response;
+mod retry_scheduler;
+#[cfg(feature = "s3")]
+mod s3;
+mod server_digest;
+mod shared;
+mod sidecar;
+mod sniff;
+mod space;
+mod storage;
+mod stream;
+mod strict;
+mod strip;
+mod tee;
+mod temp;
+mod upload;
+mod user_meta;
+mod watch;
+
+#[cfg(test)]
+mod testing;
 
-use image::ImageReader;
-use itertools::Itertools;
 use std::{
-    collections::hash_map::DefaultHasher,
-    env, fs,
-    hash::{Hash, Hasher},
-    io::Cursor,
+    collections::HashMap,
+    env, fmt, fs, io,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 
-use fetcher::UReqFetcher;
-
-#[derive(Debug)]
-pub enum Response {
-    Ok { body: Vec<u8>, mime: Option<String> },
-    InvalidBody,
-    NotFound,
-    NetworkError,
-}
-
-impl Response {
-    pub fn ok(body: Vec<u8>, mime: Option<String>) -> Self {
-        Self::Ok { body, mime }
+pub use adopt::AdoptReport;
+#[cfg(feature = "archives")]
+pub use archive::{ArchiveLimits, Extraction};
+pub use attempts::AttemptRecord;
+pub use batch::{BatchEvent, BatchOptions, BatchResult};
+pub use builder::DownloaderBuilder;
+pub use cache_key::{HashAlgo, KeyEncoding};
+pub use cache_policy::CachePolicy;
+pub use cancel::CancellationToken;
+pub use checksum_file::ChecksumReport;
+pub use circuit_breaker::{CircuitBreakerConfig, CircuitState};
+pub use clock::{Clock, SystemClock};
+pub use data_uri::DATA_URI_LIMIT;
+pub use download_iter::DownloadIter;
+pub use fetch_error::FetchError;
+#[cfg(feature = "http2")]
+pub use fetcher::HyperFetcher;
+#[cfg(feature = "test-util")]
+pub use fetcher::{Chaos, ChaosFetcher, MockFetcher};
+pub use fetcher::{TlsRoots, UReqFetcher};
+#[cfg(feature = "image")]
+pub use images::{AnimatedPolicy, ThumbSpec, VerifyLevel};
+pub use iri::{validate_url, IntoDownloadUrl, UrlProblem};
+pub use keyer::{CacheKeyer, SaltedKeyer};
+pub use name_by::NameBy;
+pub use observer::Observer;
+pub use options::DownloadOptions;
+pub use outcome::Outcome;
+pub use overwrite_policy::OverwritePolicy;
+pub use partition::Partitioner;
+pub use peek::Peek;
+pub use persist::{ExistingDestination, PersistMode};
+pub use prefetch::PrefetchSummary;
+pub use probe::Probe;
+pub use referer::RefererPolicy;
+pub use rescan::RescanReport;
+pub use response::{Body, Response};
+#[cfg(feature = "s3")]
+pub use s3::{S3Client, S3Config, S3Storage, UreqS3Client};
+pub use shared::{PendingDownload, SharedDownloader};
+pub use sidecar::Sidecar;
+pub use space::{FsSpace, SpaceProvider};
+pub use storage::{FsStorage, MemoryStorage, Storage, StoredFile};
+pub use stream::DownloadInfo;
+pub use strip::StripOutcome;
+pub use temp::TempDownload;
+pub use upload::PutOrPost;
+
+use budget::DailyBudget;
+use builder::Config;
+use cache::{BodySource, CachedEntry, Listing, StoreError};
+use cache_key::CacheKey;
+use circuit_breaker::{CircuitBreaker, CircuitProbe};
+use connections::ConnectionLimiter;
+use keyer::HashKeyer;
+use maintenance::Maintenance;
+use manifest::Manifest;
+use memory_cache::{MemoryCache, Resident};
+use partial::PartialFile;
+use refresher::Refresher;
+use retry_scheduler::{GaveUp, RetryScheduler};
+use storage::Backing;
+use stream::Tap;
+
+// Asked before a redirect is followed, answering with the headers to send
+// to where it points.
+pub type Follow<'a> = dyn Fn(&Url) -> Result<Vec<(String, String)>, FetchError> + 'a;
+
+pub trait FileDownloader: Send + Sync + 'static {
+    fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError>;
+
+    // Like `fetch` without the body. Fetchers that cannot send HEAD answer
+    // 405, which callers treat as a server rejecting it.
+    fn head(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+        Ok(Response::new(405))
     }
 
-    pub fn invalid_body() -> Self {
-        Self::InvalidBody
+    // Sends `body` with `method`, for uploads. Redirects are not followed,
+    // as the body cannot be replayed.
+    fn send_body(
+        &self,
+        method: &str,
+        url: &str,
+        _headers: &[(String, String)],
+        _body: Body,
+    ) -> Result<Response, FetchError> {
+        Err(FetchError::Other(format!(
+            "{url}: this fetcher cannot send a {method} body"
+        )))
     }
 
-    pub fn not_found() -> Self {
-        Self::NotFound
+    // `fetch` under a download's deadline: connecting, following redirects
+    // and reading the body should together take no longer than `timeout`.
+    // Fetchers without timeouts leave the deadline to be checked between
+    // attempts.
+    fn fetch_within(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        _timeout: Duration,
+    ) -> Result<Response, FetchError> {
+        self.fetch(url, headers)
     }
 
-    pub fn network_error() -> Self {
-        Self::NetworkError
+    // `fetch`, or `fetch_within` given a timeout, asking `follow` before each
+    // redirect it follows for the headers to send there, and stopping with
+    // its error. Fetchers that follow redirects themselves override it, the
+    // hops are otherwise only checked once they were taken, and sent the
+    // first request's headers.
+    fn fetch_following(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        timeout: Option<Duration>,
+        _follow: &Follow<'_>,
+    ) -> Result<Response, FetchError> {
+        match timeout {
+            Some(timeout) => self.fetch_within(url, headers, timeout),
+            None => self.fetch(url, headers),
+        }
     }
-}
 
-pub trait FileDownloader {
-    fn fetch(&self, url: &str) -> Response;
+    // Lowercase URL schemes this fetcher can handle. URLs with any other
+    // scheme are refused before the fetcher is called.
+    fn schemes(&self) -> &[&str] {
+        iri::DEFAULT_SCHEMES
+    }
 }
 
-pub struct Downloader<T: FileDownloader> {
-    fetcher: T,
+pub struct Downloader<T: FileDownloader, S: Storage = FsStorage> {
+    fetcher: Arc<T>,
+    storage: Arc<S>,
     path: PathBuf,
+    config: Arc<Config>,
+    manifest: Arc<Manifest>,
+    listing: Arc<Listing>,
+    maintenance: Arc<Maintenance>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    daily_budget: Option<Arc<DailyBudget>>,
+    memory_cache: Option<Arc<MemoryCache>>,
+    connections: Option<Arc<ConnectionLimiter>>,
+    retry_scheduler: Option<Arc<RetryScheduler>>,
+    refresher: Option<Arc<Refresher>>,
+    overlay: Option<Box<Downloader<T>>>,
+    // Why the cache directory could not be opened, which every download
+    // fails with.
+    unusable: Option<DownloadError>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DownloadError {
     NotFound,
-    NetworkError,
-    InvalidUrl,
+    // A read-only cache without an overlay does not hold the URL.
+    NotCached,
+    // Any other transport failure, described by the fetcher.
+    NetworkError {
+        reason: String,
+    },
+    InvalidUrl(UrlProblem),
     InvalidBody,
+    // The body failed after `received` bytes arrived.
+    TruncatedBody {
+        received: u64,
+        kind: io::ErrorKind,
+    },
+    CircuitOpen {
+        host: String,
+        retry_at: SystemTime,
+    },
+    Forbidden {
+        host: String,
+    },
+    HttpStatus(u16),
+    Dns(String),
+    Connect(String),
+    Tls(String),
+    Timeout,
+    AlreadyExists,
+    CorruptImage,
+    // The body is an image of this type, which this build cannot decode.
+    DecoderUnavailable(String),
+    AnimatedImage,
+    ImageTooLarge {
+        width: u32,
+        height: u32,
+        limit: u64,
+    },
+    InvalidArchive,
+    UnsafeArchiveEntry(String),
+    ArchiveTooLarge,
+    UnsupportedContent,
+    // Raised instead of the silent fallbacks when strict: an entry named
+    // `.dat`, a response without `Content-Type`, a body of no known type.
+    UnknownExtension,
+    MissingContentType,
+    SniffFailed,
+    // `needed` is a lower bound when the server did not announce the size.
+    InsufficientSpace {
+        needed: u64,
+        available: u64,
+    },
+    Io(String),
+    // The caller supplied writer failed, as opposed to the network.
+    Writer(String),
+    // 429, or 503 with a `Retry-After`, with how long the server asked to
+    // wait when it said.
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    // The body does not match the digest the server sent with it. Both are
+    // hex.
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    // The metadata to store with an entry serializes to more than `limit`
+    // bytes.
+    UserMetaTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    // The bytes a batch or the day may download were already downloaded.
+    BudgetExceeded {
+        limit: u64,
+    },
+    // The cache directory was written by a newer version of this crate.
+    UnsupportedCacheFormat {
+        found: u32,
+        supported: u32,
+    },
+    // Every attempt at a download that was retried failed, the last one
+    // last.
+    RetriesExhausted {
+        attempts: Vec<AttemptRecord>,
+    },
+    // The call's deadline passed `elapsed` into it, after these attempts.
+    DeadlineExceeded {
+        elapsed: Duration,
+        attempts: Vec<AttemptRecord>,
+    },
+}
+
+impl From<FetchError> for DownloadError {
+    fn from(error: FetchError) -> Self {
+        match error {
+            FetchError::Dns(error) => Self::Dns(error.to_string()),
+            FetchError::Connect(error) => Self::Connect(error.to_string()),
+            FetchError::Tls(error) => Self::Tls(error.to_string()),
+            FetchError::Timeout(_) => Self::Timeout,
+            FetchError::Io(_) | FetchError::Other(_) => Self::NetworkError {
+                reason: error.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("not found"),
+            Self::NotCached => f.write_str("not in the cache"),
+            Self::NetworkError { reason } => write!(f, "network error: {reason}"),
+            Self::InvalidUrl(problem) => write!(f, "invalid url: {problem}"),
+            Self::InvalidBody => f.write_str("invalid or incomplete body"),
+            Self::TruncatedBody { received, kind } => {
+                write!(f, "body cut off after {received} bytes: {kind}")
+            }
+            Self::CircuitOpen { host, .. } => write!(f, "circuit open for {host}"),
+            Self::Forbidden { host } => write!(f, "host {host} is not allowed"),
+            Self::HttpStatus(status) => write!(f, "http status {status}"),
+            Self::Dns(reason) => write!(f, "dns lookup failed: {reason}"),
+            Self::Connect(reason) => write!(f, "connection failed: {reason}"),
+            Self::Tls(reason) => write!(f, "tls handshake failed: {reason}"),
+            Self::Timeout => f.write_str("timed out"),
+            Self::AlreadyExists => f.write_str("file already exists"),
+            Self::CorruptImage => f.write_str("corrupt image"),
+            Self::DecoderUnavailable(format) => write!(f, "no decoder for {format}"),
+            Self::AnimatedImage => f.write_str("animated image"),
+            Self::ImageTooLarge {
+                width,
+                height,
+                limit,
+            } => write!(f, "image of {width}x{height} exceeds {limit} pixels"),
+            Self::InvalidArchive => f.write_str("invalid archive"),
+            Self::UnsafeArchiveEntry(entry) => write!(f, "unsafe archive entry {entry}"),
+            Self::ArchiveTooLarge => f.write_str("archive too large"),
+            Self::UnsupportedContent => f.write_str("unsupported content"),
+            Self::UnknownExtension => f.write_str("unknown file extension"),
+            Self::MissingContentType => f.write_str("missing content type"),
+            Self::SniffFailed => f.write_str("content type could not be sniffed"),
+            Self::InsufficientSpace { needed, available } => write!(
+                f,
+                "insufficient space: {needed} bytes needed, {available} available"
+            ),
+            Self::Io(reason) => write!(f, "i/o error: {reason}"),
+            Self::Writer(reason) => write!(f, "writer failed: {reason}"),
+            Self::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch: expected {expected}, got {actual}")
+            }
+            Self::UserMetaTooLarge { size, limit } => {
+                write!(f, "user metadata of {size} bytes exceeds {limit}")
+            }
+            Self::BudgetExceeded { limit } => write!(f, "byte budget of {limit} exceeded"),
+            Self::UnsupportedCacheFormat { found, supported } => write!(
+                f,
+                "cache format {found} is newer than the supported {supported}"
+            ),
+            Self::RateLimited { retry_after } => match retry_after {
+                Some(wait) => write!(f, "rate limited, retry after {}s", wait.as_secs()),
+                None => f.write_str("rate limited"),
+            },
+            Self::RetriesExhausted { attempts } => attempts::summarize(f, attempts),
+            Self::DeadlineExceeded { elapsed, attempts } => write!(
+                f,
+                "deadline exceeded after {:.1}s and {} attempts",
+                elapsed.as_secs_f64(),
+                attempts.len()
+            ),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+impl std::error::Error for DownloadError {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DownloadMetadata {
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub extension: Option<String>,
+    pub stripped: Option<StripOutcome>,
+    pub animated: Option<bool>,
+    pub mime: Option<String>,
+    // Set when the body's signature contradicts its `Content-Type`: the type
+    // announced, and the one sniffed from the body.
+    pub declared_mime: Option<String>,
+    pub detected_format: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Download {
     pub source: String,
     pub file: PathBuf,
+    pub metadata: DownloadMetadata,
+    pub thumbnail: Option<PathBuf>,
+    pub remote_url: Option<String>,
+    // The redirects the fetcher followed, empty when the URL answered itself.
+    pub redirects: Vec<(u16, String)>,
+    // What callers stored with the entry through `DownloadOptions::user_meta`.
+    pub user_meta: HashMap<String, String>,
+    pub(crate) resident: Resident,
+    pub(crate) backing: Backing,
 }
 
 impl Download {
     pub fn new(source: String, file: PathBuf) -> Self {
-        Self { source, file }
+        Self::with_metadata(source, file, DownloadMetadata::default())
+    }
+
+    pub fn with_metadata(source: String, file: PathBuf, metadata: DownloadMetadata) -> Self {
+        Self {
+            source,
+            file,
+            metadata,
+            thumbnail: None,
+            remote_url: None,
+            redirects: Vec::new(),
+            user_meta: HashMap::new(),
+            resident: Resident::default(),
+            backing: Backing::default(),
+        }
     }
 }
 
@@ -72,96 +464,787 @@ where
     T: FileDownloader,
 {
     pub fn with_fetcher(path: &str, fetcher: T) -> Self {
-        let path = Self::create_path_from_string(path)
-            .unwrap_or_else(|_| panic!("Error creating path: {}", path));
-
-        Downloader { path, fetcher }
+        DownloaderBuilder::with_fetcher(path, fetcher).build()
     }
+}
 
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
     pub fn download(&self, url: &str) -> Result<Download, DownloadError> {
-        let url = Url::parse(url).map_err(|_| DownloadError::InvalidUrl)?;
+        self.download_with(url, &DownloadOptions::default())
+    }
 
-        let url = url.as_str();
+    pub fn download_url(&self, url: &Url) -> Result<Download, DownloadError> {
+        self.download_any(url)
+    }
 
-        let response = self.fetcher.fetch(url);
+    // Like `download`, but tells whether the body came from the network.
+    pub fn download_checked(&self, url: &str) -> Result<Outcome, DownloadError> {
+        self.download_outcome(url)
+    }
 
-        match response {
-            Response::NetworkError => Err(DownloadError::NetworkError),
-            Response::NotFound => Err(DownloadError::NotFound),
-            Response::InvalidBody => Err(DownloadError::InvalidBody),
+    pub(crate) fn download_any(
+        &self,
+        url: impl IntoDownloadUrl,
+    ) -> Result<Download, DownloadError> {
+        self.download_outcome(url).map(Outcome::into_download)
+    }
+
+    fn download_outcome(&self, url: impl IntoDownloadUrl) -> Result<Outcome, DownloadError> {
+        let (outcome, result) = match url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))
+            .and_then(|parsed| self.serve(&parsed))
+        {
+            Ok(outcome) => {
+                let written = matches!(outcome, Outcome::Downloaded(_));
+
+                if !written {
+                    self.mark_used(url.as_str());
+                }
+
+                let (outcome, download) = outcome.into_parts();
+
+                let download = self.with_resident_bytes(download, written);
+
+                (outcome, Ok(self.with_remote_url(download)))
+            }
+            Err(error) => (Outcome::Downloaded as fn(Download) -> Outcome, Err(error)),
+        };
+
+        if let Some(observer) = &self.config.observer {
+            observer.on_download(url.as_str(), &result);
+        }
+
+        result.map(outcome)
+    }
 
-            Response::Ok { body, mime } => {
-                let extension = self.get_extension(mime, &body);
+    fn with_remote_url(&self, mut download: Download) -> Download {
+        download.remote_url = self.storage.remote_url(&self.storage_name(&download.file));
 
-                let file_name = self.get_hash(url);
+        download
+    }
+
+    fn serve(&self, url: &Url) -> Result<Outcome, DownloadError> {
+        if let Some(error) = &self.unusable {
+            return Err(error.clone());
+        }
+
+        // Fetches into a target leave the cache alone, read-only or not.
+        let cached = match self.config.target {
+            Some(_) => None,
+            None => self.cached_entry(url),
+        };
+
+        // Entries of a read-only cache cannot be refreshed, so any is served.
+        if self.config.read_only && self.config.target.is_none() {
+            return match (cached, &self.overlay) {
+                (Some(entry), _) => Ok(Outcome::CacheHit(entry.download(url))),
+                (None, Some(overlay)) => overlay.serve(url),
+                (None, None) => Err(DownloadError::NotCached),
+            };
+        }
+
+        match (self.config.cache_policy, &cached) {
+            (CachePolicy::CacheFirst, Some(entry)) if self.is_fresh(entry) => {
+                return Ok(Outcome::CacheHit(entry.download(url)));
+            }
 
-                let file_name_with_extension = format!("{}.{}", file_name, extension);
+            (CachePolicy::StaleWhileRevalidate, Some(entry)) if !self.must_revalidate(entry) => {
+                if !self.is_fresh(entry) {
+                    self.revalidate(url);
+                }
 
-                let file_path = self.path.join(file_name_with_extension);
+                return Ok(Outcome::CacheHit(entry.download(url)));
+            }
+
+            _ => {}
+        }
 
-                std::fs::write(&file_path, &body)
-                    .unwrap_or_else(|_| panic!("Error saving file: {:?}", file_path));
+        // Whatever a fetch brought back, the existing entry would be kept.
+        if let (OverwritePolicy::Skip, Some(entry)) = (self.config.overwrite_policy, &cached) {
+            return Ok(Outcome::CacheHit(entry.download(url)));
+        }
 
-                Ok(Download::new(String::from(url), file_path))
+        match (self.fetch_with_retries(url, cached.as_ref()), cached) {
+            (Err(error), Some(entry)) if self.falls_back_on(&error) => {
+                Ok(Outcome::Stale(entry.download(url)))
             }
+            (result, _) => result,
         }
     }
 
-    pub fn clear_cache(&self) {
-        fs::remove_dir_all(&self.path).unwrap_or_else(|_| {
-            panic!("Error removing cache directory: {:?}", self.path);
+    // Failures the server or the network may recover from; a missing URL
+    // only when the caller opted in.
+    fn falls_back_on(&self, error: &DownloadError) -> bool {
+        if self.config.cache_policy != CachePolicy::NetworkFirstFallbackStale {
+            return false;
+        }
+
+        match error.last_error() {
+            DownloadError::NotFound => self.config.stale_on_not_found,
+            DownloadError::HttpStatus(status) => (500..=599).contains(status),
+            DownloadError::NetworkError { .. }
+            | DownloadError::Dns(_)
+            | DownloadError::Connect(_)
+            | DownloadError::Tls(_)
+            | DownloadError::Timeout
+            | DownloadError::InvalidBody
+            | DownloadError::TruncatedBody { .. }
+            | DownloadError::RateLimited { .. }
+            | DownloadError::CircuitOpen { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn fetch_with_retries(
+        &self,
+        url: &Url,
+        cached: Option<&CachedEntry>,
+    ) -> Result<Outcome, DownloadError> {
+        if let Some(error) = self.remembered_failure(url) {
+            return Err(error);
+        }
+
+        self.check_budget()?;
+
+        #[cfg(feature = "image")]
+        let mut retries = self.config.image.retries;
+
+        #[cfg(not(feature = "image"))]
+        let mut retries = 0;
+
+        let mut body_retries = self.config.body_retries;
+
+        let mut rate_limit_waits = 0;
+
+        let mut attempts = Vec::new();
+
+        #[cfg_attr(not(feature = "image"), allow(unused_mut))]
+        let mut accept = self.accept();
+
+        // Truncated bodies and images are often transient, so they may be
+        // fetched again. A failed attempt leaves no partial file behind.
+        loop {
+            // Waits for a retry's turn count against the deadline, so it is
+            // checked before as well as after.
+            if let Some(elapsed) = self.past_deadline() {
+                return Err(DownloadError::DeadlineExceeded { elapsed, attempts });
+            }
+
+            let _retry = match attempts.len() {
+                0 => None,
+                failed => match self.schedule_retry(url, failed as u32 + 1) {
+                    Ok(slot) => slot,
+                    Err(GaveUp::Deadline) => {
+                        return Err(DownloadError::DeadlineExceeded {
+                            elapsed: self.elapsed_in_call(),
+                            attempts,
+                        })
+                    }
+                    // The retry is not made, so the attempts so far stand.
+                    Err(GaveUp::Cancelled) => {
+                        return match attempts.len() {
+                            1 => Err(attempts.remove(0).error),
+                            _ => Err(DownloadError::RetriesExhausted { attempts }),
+                        }
+                    }
+                },
+            };
+
+            let overwrite = self.config.overwrite_policy;
+
+            let at = self.config.clock.now();
+
+            let error = match self.fetch_and_store(url, cached, overwrite, accept) {
+                Ok(outcome) => {
+                    self.charge_budget(&outcome);
+
+                    return Ok(outcome);
+                }
+                Err(error) => error,
+            };
+
+            attempts.push(AttemptRecord {
+                url: url.to_string(),
+                attempt: attempts.len() as u32 + 1,
+                error: error.clone(),
+                at,
+                duration: self
+                    .config
+                    .clock
+                    .now()
+                    .duration_since(at)
+                    .unwrap_or_default(),
+            });
+
+            // An attempt the deadline cut short says nothing about the URL.
+            let elapsed = match error {
+                DownloadError::DeadlineExceeded { elapsed, .. } => Some(elapsed),
+                _ => self.past_deadline(),
+            };
+
+            if let Some(elapsed) = elapsed {
+                return Err(DownloadError::DeadlineExceeded { elapsed, attempts });
+            }
+
+            let budget = match error {
+                DownloadError::CorruptImage => &mut retries,
+                _ => &mut body_retries,
+            };
+
+            // A body already handed to the caller's writer cannot be taken
+            // back, so it is not fetched again.
+            let replayable = !self.config.tap.as_ref().is_some_and(Tap::streamed);
+
+            match error {
+                #[cfg(feature = "image")]
+                DownloadError::CorruptImage | DownloadError::DecoderUnavailable(_)
+                    if accept == Some(images::MODERN_ACCEPT) && replayable =>
+                {
+                    accept = Some(images::FALLBACK_ACCEPT)
+                }
+                DownloadError::RateLimited {
+                    retry_after: Some(wait),
+                } if self.may_wait(wait, &mut rate_limit_waits) => self.sleep_within_deadline(wait),
+                error if error.is_retriable() && *budget > 0 && replayable => *budget -= 1,
+                error => {
+                    self.remember_failure(url, &error);
+
+                    if let DownloadError::RateLimited {
+                        retry_after: Some(wait),
+                    } = error
+                    {
+                        self.hold_host(url, wait);
+                    }
+
+                    return match attempts.len() {
+                        1 => Err(error),
+                        _ => Err(DownloadError::RetriesExhausted { attempts }),
+                    };
+                }
+            }
+        }
+    }
+
+    fn fetch_and_store(
+        &self,
+        url: &Url,
+        cached: Option<&CachedEntry>,
+        overwrite: OverwritePolicy,
+        accept: Option<&str>,
+    ) -> Result<Outcome, DownloadError> {
+        let mut request_headers = self.validators(cached);
+
+        if let Some(accept) = accept {
+            request_headers.push(("Accept".to_string(), accept.to_string()));
+        }
+
+        let response = self.fetch(url, &request_headers)?;
+
+        match response.status {
+            200..=299 => {}
+
+            304 => {
+                let Some(cached) = cached else {
+                    return Err(DownloadError::InvalidBody);
+                };
+
+                let download = cached.download(url);
+
+                self.record_entry(
+                    url.as_str(),
+                    &download,
+                    &response.headers,
+                    cached.meta.as_ref(),
+                );
+
+                return Ok(Outcome::NotModified(download));
+            }
+
+            404 => return Err(DownloadError::NotFound),
+
+            status @ (429 | 503) => {
+                let retry_after = self.retry_after(&response);
+
+                // Only a 503 announcing when to come back is a maintenance
+                // window.
+                if status == 503 && retry_after.is_none() {
+                    return Err(DownloadError::HttpStatus(status));
+                }
+
+                return Err(DownloadError::RateLimited { retry_after });
+            }
+
+            status => return Err(DownloadError::HttpStatus(status)),
+        }
+
+        self.store_response(url, response, overwrite)
+    }
+
+    // Stores a successful response as the URL's entry.
+    fn store_response(
+        &self,
+        url: &Url,
+        response: Response,
+        overwrite: OverwritePolicy,
+    ) -> Result<Outcome, DownloadError> {
+        self.store_from(url, response, None, overwrite)
+    }
+
+    // `staged` holds the body in place of the response's, already on disk.
+    fn store_from(
+        &self,
+        url: &Url,
+        response: Response,
+        staged: Option<PartialFile>,
+        overwrite: OverwritePolicy,
+    ) -> Result<Outcome, DownloadError> {
+        let content_length = response.content_length();
+
+        if let Some(length) = content_length.filter(|_| staged.is_none()) {
+            self.check_space(length)?;
+        }
+
+        let Response {
+            status,
+            headers,
+            body,
+            redirects,
+        } = response;
+
+        let body = match staged {
+            Some(staged) => BodySource::Staged(staged),
+            None => BodySource::Streamed(body),
+        };
+
+        let modified = headers::find(&headers, "Last-Modified")
+            .filter(|_| self.config.preserve_mtime)
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+
+        let final_url = redirects
+            .last()
+            .filter(|_| self.config.key_on_final_url)
+            .map(|(_, location)| location.clone());
+
+        let keyed_url = final_url.as_deref().unwrap_or(url.as_str());
+
+        if self.config.learn_vary && self.config.target.is_none() {
+            self.learn_vary(keyed_url, &headers);
+        }
+
+        let file_name = self.partitioned(url, &self.entry_key(keyed_url));
+
+        let stored = match self.store_body(
+            &file_name,
+            body,
+            &headers,
+            content_length,
+            modified,
+            overwrite,
+        ) {
+            Ok(stored) => stored,
+            Err(StoreError::Read { received, kind }) => {
+                return Err(DownloadError::TruncatedBody { received, kind })
+            }
+            Err(StoreError::AlreadyExists) => return Err(DownloadError::AlreadyExists),
+            Err(StoreError::UnsupportedContent) => return Err(DownloadError::UnsupportedContent),
+            Err(StoreError::Full { written }) => {
+                return Err(self.insufficient_space(content_length.unwrap_or(written)))
+            }
+            Err(StoreError::Rejected(error)) => return Err(error),
+            Err(StoreError::Write(error)) => {
+                return Err(DownloadError::Io(format!("saving {file_name}: {error}")))
+            }
+        };
+
+        let mut download = Download::with_metadata(url.to_string(), stored.file, stored.metadata);
+
+        if self.config.target.is_none() {
+            download.backing = self.backing(&download.file);
+        }
+
+        let metadata = &download.metadata;
+
+        if let (Some(observer), Some(declared), Some(detected)) = (
+            &self.config.observer,
+            &metadata.declared_mime,
+            &metadata.detected_format,
+        ) {
+            observer.on_content_mismatch(url.as_str(), declared, detected);
+        }
+
+        download.thumbnail = stored.thumbnail;
+        download.redirects = redirects;
+        download.user_meta = self.stored_user_meta(url.as_str());
+
+        if let Some(dir) = &self.config.persist_dir {
+            download.file = download
+                .persist_with(dir, PersistMode::Move, ExistingDestination::Overwrite)
+                .map_err(|error| DownloadError::Io(format!("persisting {file_name}: {error}")))?;
+        }
+
+        let no_store = cache_control::CacheDirectives::from_headers(&headers).no_store;
+
+        if stored.written && stored.on_disk && self.config.write_sidecars && !no_store {
+            let sidecar = Sidecar {
+                source: download.source.clone(),
+                fetched_at: manifest::unix_secs(self.config.clock.now()),
+                status,
+                headers: headers.clone(),
+                sha256: download.metadata.sha256.clone(),
+                mime: download.metadata.mime.clone(),
+            };
+
+            // Like the manifest, sidecars are best effort.
+            let _ = sidecar::write(&download.file, &sidecar);
+        }
+
+        if self.config.persist_dir.is_none() && stored.is_entry {
+            // The requested URL is recorded as an alias of the final one.
+            if let Some(final_url) = &final_url {
+                self.record_entry(final_url, &download, &headers, None);
+            }
+
+            self.record_entry(url.as_str(), &download, &headers, None);
+        }
+
+        if stored.written {
+            Ok(Outcome::Downloaded(download))
+        } else if stored.unchanged {
+            Ok(Outcome::Unchanged(download))
+        } else {
+            Ok(Outcome::CacheHit(download))
+        }
+    }
+
+    fn fetch(&self, url: &Url, headers: &[(String, String)]) -> Result<Response, DownloadError> {
+        // Measured once a connection slot is free, just before the request.
+        self.send(url, headers, |fetcher, url, headers, follow| {
+            let timeout = self
+                .config
+                .deadline
+                .map(|_| self.remaining_budget().unwrap_or_default());
+
+            fetcher.fetch_following(url, headers, timeout, follow)
+        })
+    }
+
+    fn fetch_head(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+    ) -> Result<Response, DownloadError> {
+        self.send(url, headers, |fetcher, url, headers, _| {
+            fetcher.head(url, headers)
+        })
+    }
+
+    // Every host the request goes to, redirects included, has to be
+    // permitted by the host policy.
+    fn send(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+        request: impl FnOnce(&T, &str, &[(String, String)], &Follow<'_>) -> Result<Response, FetchError>,
+    ) -> Result<Response, DownloadError> {
+        if let Some(error) = &self.unusable {
+            return Err(error.clone());
+        }
+
+        iri::check_scheme(url, self.fetcher.schemes()).map_err(DownloadError::InvalidUrl)?;
+
+        let host = url.host_str().unwrap_or_default().to_string();
+
+        if !self.config.host_policy.permits(&host) {
+            return Err(DownloadError::Forbidden { host });
+        }
+
+        let probe = self.check_circuit(&host)?;
+
+        // `Referer` and `Origin` are worked out again for every hop.
+        let headers_for = |url: &Url| {
+            let referer = self.referer_headers(url);
+
+            let defaults = headers::merge(&referer, &self.config.headers);
+
+            headers::merge(&defaults, headers).into_owned()
+        };
+
+        let headers = headers_for(url);
+
+        // Retries go through here again, so every attempt waits for a slot.
+        let connection = match &self.connections {
+            Some(limiter) => match limiter.acquire(
+                &host,
+                self.remaining_budget(),
+                &self.config.cancellation_token,
+            ) {
+                Ok(connection) => Some(connection),
+                Err(GaveUp::Deadline) => {
+                    return Err(DownloadError::DeadlineExceeded {
+                        elapsed: self.elapsed_in_call(),
+                        attempts: Vec::new(),
+                    })
+                }
+                // Like a transfer cut short, which retries give up on once
+                // they see the cancellation.
+                Err(GaveUp::Cancelled) => {
+                    return Err(DownloadError::NetworkError {
+                        reason: format!("{url}: cancelled waiting for a connection"),
+                    })
+                }
+            },
+            None => None,
+        };
+
+        let refused = Mutex::new(None);
+
+        let follow = |hop: &Url| {
+            let host = hop.host_str().unwrap_or_default();
+
+            if self.config.host_policy.permits(host) {
+                return Ok(headers_for(hop));
+            }
+
+            *refused.lock().unwrap() = Some(host.to_string());
+
+            Err(FetchError::Other(format!(
+                "{hop}: redirected to a forbidden host"
+            )))
+        };
+
+        let response = request(&self.fetcher, url.as_str(), &headers, &follow);
+
+        if let Some(host) = refused.into_inner().unwrap() {
+            return Err(DownloadError::Forbidden { host });
+        }
+
+        // Fetchers that do not ask before following are checked after.
+        let forbidden = response.as_ref().ok().and_then(|response| {
+            response
+                .redirects
+                .iter()
+                .filter_map(|(_, hop)| Url::parse(hop).ok())
+                .find_map(|hop| {
+                    let host = hop.host_str().unwrap_or_default();
+
+                    (!self.config.host_policy.permits(host)).then(|| host.to_string())
+                })
         });
+
+        if let Some(host) = forbidden {
+            return Err(DownloadError::Forbidden { host });
+        }
+
+        self.record_circuit(&host, &response, probe);
+
+        let mut response = response?;
+
+        if let Some(connection) = connection {
+            response.body = connection.hold_until_read(std::mem::take(&mut response.body));
+        }
+
+        Ok(response)
     }
 
-    fn get_extension(&self, mime: Option<String>, body: &[u8]) -> String {
-        self.get_extension_from_mimetype(mime)
-            .or_else(|| self.get_extension_from_content(body))
-            .unwrap_or(String::from("dat"))
+    fn accept(&self) -> Option<&'static str> {
+        #[cfg(feature = "image")]
+        return self.config.image.negotiate.then_some(images::MODERN_ACCEPT);
+
+        #[cfg(not(feature = "image"))]
+        None
     }
 
-    fn get_extension_from_mimetype(&self, mime: Option<String>) -> Option<String> {
-        let mime = mime?;
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.config.cache_policy
+    }
 
-        let mime_parts = mime.split('/').collect_vec();
+    pub fn fetcher(&self) -> &T {
+        &self.fetcher
+    }
 
-        if mime_parts.len() != 2 {
-            return None;
+    pub fn supported_schemes(&self) -> &[&str] {
+        self.fetcher.schemes()
+    }
+
+    pub fn circuit_state(&self, host: &str) -> CircuitState {
+        self.circuit_breaker
+            .as_ref()
+            .map(|breaker| breaker.state(host))
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.config.cancellation_token
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    // Waits for the background upkeep queued so far, such as writing the
+    // manifest, for callers that inspect the cache directory or shut down.
+    pub fn flush_maintenance(&self) {
+        self.maintenance.flush();
+
+        if let Some(overlay) = &self.overlay {
+            overlay.flush_maintenance();
+        }
+    }
+
+    pub fn clear_cache(&self) {
+        if self.config.read_only {
+            if let Some(overlay) = &self.overlay {
+                overlay.clear_cache();
+            }
+
+            return;
         }
 
-        let extension = mime_parts[1];
+        self.manifest.clear();
 
-        if extension.is_empty() {
-            return None;
+        if let Some(memory) = &self.memory_cache {
+            memory.clear();
         }
 
-        Some(extension.to_string())
+        // A save still queued would bring the directory back.
+        self.maintenance.flush();
+
+        for name in self.storage.list().unwrap_or_default() {
+            let _ = self.storage.delete(&name);
+        }
+
+        fs::remove_dir_all(&self.path).unwrap_or_else(|_| {
+            panic!("Error removing cache directory: {:?}", self.path);
+        });
     }
 
-    fn get_extension_from_content(&self, body: &[u8]) -> Option<String> {
-        let Ok(reader) = ImageReader::new(Cursor::new(body)).with_guessed_format() else {
-            return None;
+    fn revalidate(&self, url: &Url) {
+        if let Some(refresher) = &self.refresher {
+            refresher.schedule(url);
+        }
+    }
+
+    // A handle over the same fetcher, cache directory and configuration that
+    // does not own any background work, used by the refresh worker.
+    fn detached(&self) -> Self {
+        Downloader {
+            fetcher: Arc::clone(&self.fetcher),
+            storage: Arc::clone(&self.storage),
+            path: self.path.clone(),
+            config: Arc::clone(&self.config),
+            manifest: Arc::clone(&self.manifest),
+            listing: Arc::clone(&self.listing),
+            maintenance: Arc::clone(&self.maintenance),
+            circuit_breaker: self.circuit_breaker.clone(),
+            daily_budget: self.daily_budget.clone(),
+            memory_cache: self.memory_cache.clone(),
+            connections: self.connections.clone(),
+            retry_scheduler: self.retry_scheduler.clone(),
+            refresher: None,
+            overlay: None,
+            unusable: self.unusable.clone(),
+        }
+    }
+
+    fn check_circuit<'a>(
+        &'a self,
+        host: &'a str,
+    ) -> Result<Option<CircuitProbe<'a>>, DownloadError> {
+        let Some(breaker) = &self.circuit_breaker else {
+            return Ok(None);
+        };
+
+        breaker
+            .check(host, self.config.clock.now())
+            .map_err(|retry_at| DownloadError::CircuitOpen {
+                host: host.to_string(),
+                retry_at,
+            })
+    }
+
+    fn record_circuit(
+        &self,
+        host: &str,
+        response: &Result<Response, FetchError>,
+        probe: Option<CircuitProbe<'_>>,
+    ) {
+        let Some(breaker) = &self.circuit_breaker else {
+            return;
         };
 
-        let format = reader.format()?;
+        match response {
+            Err(_) => breaker.record_failure(host, self.config.clock.now()),
+            Ok(_) => breaker.record_success(host),
+        }
+
+        if let Some(probe) = probe {
+            probe.settle();
+        }
+    }
 
-        let extensions = format.extensions_str();
+    fn get_extension(&self, mime: Option<&str>, body: &[u8]) -> String {
+        self.get_extension_from_mimetype(mime)
+            .and_then(extension::sanitize_extension)
+            .or_else(|| self.get_extension_from_content(body).map(str::to_string))
+            .unwrap_or_else(|| "dat".to_string())
+    }
 
-        if extensions.is_empty() {
+    fn get_extension_from_mimetype<'a>(&self, mime: Option<&'a str>) -> Option<&'a str> {
+        let (_, extension) = mime?.split(';').next()?.trim().split_once('/')?;
+
+        if extension.is_empty() || extension.contains('/') {
             return None;
         }
 
-        Some(extensions[0].to_string())
+        if extension.eq_ignore_ascii_case("svg+xml") {
+            return Some("svg");
+        }
+
+        Some(extension)
     }
 
-    fn get_hash(&self, url: &str) -> String {
-        let mut hasher = DefaultHasher::new();
-        url.hash(&mut hasher);
-        hasher.finish().to_string()
+    fn get_extension_from_content(&self, body: &[u8]) -> Option<&'static str> {
+        #[cfg(feature = "image")]
+        if let Some(extension) = images::extension_from_image(body) {
+            return Some(extension);
+        }
+
+        sniff::extension_from_magic(body)
     }
 
-    fn create_path_from_string(path_str: &str) -> std::io::Result<PathBuf> {
-        let path = Path::new(path_str);
+    // `variant` is empty for entries not stored per variant. Keys of what
+    // cannot be a URL, and so is never fetched, are only hashed.
+    fn get_hash(&self, url: &str, variant: &str) -> CacheKey {
+        let (algo, encoding) = (self.config.hash_algo, self.config.key_encoding);
+
+        let default = HashKeyer { algo, encoding };
 
+        let Some(keyer) = &self.config.keyer else {
+            return default.cache_key(url, variant);
+        };
+
+        let Ok(url) = Url::parse(url) else {
+            return default.cache_key(url, variant);
+        };
+
+        let key = match variant.is_empty() {
+            true => keyer.key(&url),
+            false => keyer.variant_key(&url, variant),
+        };
+
+        CacheKey::from_key(&key, algo, encoding)
+    }
+
+    fn create_path(path: &Path) -> std::io::Result<PathBuf> {
         let absolute_path = if path.is_absolute() {
             path.to_path_buf()
         } else {
@@ -181,29 +1264,37 @@ impl Downloader<UReqFetcher> {
         let fetcher = UReqFetcher::new();
         Downloader::with_fetcher(path, fetcher)
     }
+
+    pub fn builder(path: impl AsRef<Path>) -> DownloaderBuilder<UReqFetcher> {
+        DownloaderBuilder::new(path)
+    }
 }
 
 pub type UreqDownloader = Downloader<UReqFetcher>;
 
-#[cfg(test)]
+const PARTIAL_SUFFIX: &str = ".part";
+
+#[cfg(all(test, not(feature = "test-util")))]
 use fetcher::MockFetcher;
 use url::Url;
 
 #[cfg(test)]
 mod tests {
 
-    use std::{fs::File, io::Read};
+    use std::io::{Cursor, ErrorKind, Read};
 
-    use itertools::Itertools;
+    use sha2::{Digest, Sha256};
+    use url::Url;
 
-    use super::{DownloadError, Downloader, MockFetcher, Response};
+    use super::{
+        fixtures, tee, testing, CacheKey, CachePolicy, DownloadError, DownloaderBuilder,
+        FetchError, FileDownloader, MockFetcher, Response, Storage, UrlProblem,
+    };
 
     #[test]
     fn test_download_file() {
         let url = "https://www.rust-lang.org/logos/rust-logo-512x512.png";
 
-        let files_path = "./images";
-
         let expected_content = mock_file_content();
 
         let response = Response::ok(expected_content.clone(), Some("image/png".to_string()));
@@ -212,7 +1303,8 @@ mod tests {
 
         // Act
 
-        let downloader = Downloader::with_fetcher(files_path, fetcher);
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("download_file"), fetcher).build();
 
         let download = downloader.download(url).unwrap();
 
@@ -220,17 +1312,7 @@ mod tests {
 
         assert_eq!(download.source, url);
 
-        let downloaded_file = File::open(download.file);
-
-        assert!(downloaded_file.is_ok());
-
-        let file_content = downloaded_file
-            .unwrap()
-            .bytes()
-            .map(|b| b.unwrap())
-            .collect_vec();
-
-        assert_eq!(file_content, expected_content);
+        assert_eq!(download.bytes().unwrap(), expected_content);
 
         downloader.clear_cache();
     }
@@ -239,8 +1321,6 @@ mod tests {
     fn test_invalid_url() {
         let url = "rust-logo-512x512.png";
 
-        let files_path = "./images";
-
         let expected_content = mock_file_content();
 
         let response = Response::ok(expected_content.clone(), Some("image/png".to_string()));
@@ -249,28 +1329,28 @@ mod tests {
 
         // Act
 
-        let downloader = Downloader::with_fetcher(files_path, fetcher);
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("invalid_url"), fetcher).build();
 
         let download = downloader.download(url).unwrap_err();
 
         // Assert
 
-        assert_eq!(download, DownloadError::InvalidUrl);
+        assert_eq!(download, DownloadError::InvalidUrl(UrlProblem::RelativeUrl));
     }
 
     #[test]
     fn test_not_found_url() {
         let url = "https://example.com/rust-logo-512x512.png";
 
-        let files_path = "./images";
-
         let response = Response::not_found();
 
         let fetcher = MockFetcher::new(vec![response]);
 
         // Act
 
-        let downloader = Downloader::with_fetcher(files_path, fetcher);
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("not_found_url"), fetcher).build();
 
         let download = downloader.download(url).unwrap_err();
 
@@ -279,6 +1359,389 @@ mod tests {
         assert_eq!(download, DownloadError::NotFound);
     }
 
+    #[test]
+    fn test_extension_from_mimetype_borrows_input() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("extension_borrows_input"),
+            MockFetcher::new(vec![]),
+        )
+        .build();
+
+        let cases = [
+            (Some("image/png"), Some("png")),
+            (Some("application/pdf"), Some("pdf")),
+            (Some("image/svg+xml"), Some("svg")),
+            (Some("image/png; charset=binary"), Some("png")),
+            (Some("image/"), None),
+            (Some("image"), None),
+            (Some("a/b/c"), None),
+            (None, None),
+        ];
+
+        let mut extensions = Vec::with_capacity(cases.len());
+
+        // Act
+
+        let allocations = testing::count_allocations(|| {
+            for (mime, _) in cases {
+                extensions.push(downloader.get_extension_from_mimetype(mime));
+            }
+        });
+
+        // Assert
+
+        assert_eq!(allocations, 0);
+
+        for ((_, expected), extension) in cases.iter().zip(extensions) {
+            assert_eq!(extension, *expected);
+        }
+    }
+
+    #[test]
+    fn test_status_classification() {
+        let url = "https://example.com/status.png";
+
+        let cases = [
+            (
+                Response::new(403).with_body(b"<h1>Forbidden</h1>".to_vec()),
+                DownloadError::HttpStatus(403),
+            ),
+            (Response::new(500), DownloadError::HttpStatus(500)),
+            (Response::new(302), DownloadError::HttpStatus(302)),
+            (Response::not_modified(), DownloadError::InvalidBody),
+            (
+                Response::invalid_body(),
+                DownloadError::TruncatedBody {
+                    received: 0,
+                    kind: ErrorKind::UnexpectedEof,
+                },
+            ),
+        ];
+
+        for (response, expected) in cases {
+            let status = response.status;
+
+            let fetcher = MockFetcher::new(vec![response]);
+
+            // Act
+
+            let downloader = DownloaderBuilder::with_fetcher(
+                testing::cache_dir("status_classification"),
+                fetcher,
+            )
+            .retry_invalid_bodies(0)
+            .build();
+
+            let error = downloader.download(url).unwrap_err();
+
+            // Assert
+
+            assert_eq!(error, expected, "status {status}");
+            assert!(downloader.cached_file(&Url::parse(url).unwrap()).is_none());
+        }
+    }
+
+    // A response whose connection drops halfway through `body`.
+    fn truncated(body: &[u8]) -> Response {
+        struct Dropped;
+
+        impl Read for Dropped {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(ErrorKind::ConnectionReset.into())
+            }
+        }
+
+        let head = Cursor::new(body[..body.len() / 2].to_vec());
+
+        Response::ok(Vec::new(), Some("image/png".to_string())).with_reader(head.chain(Dropped))
+    }
+
+    #[test]
+    fn test_truncated_bodies_are_retried() {
+        let url = "https://example.com/truncated.png";
+
+        let body = mock_file_content();
+
+        let response = Response::ok(body.clone(), Some("image/png".to_string()));
+
+        let fetcher = MockFetcher::new(vec![truncated(&body), truncated(&body), response]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("body_retries"), fetcher).build();
+
+        // Act
+
+        let download = downloader.download(url).unwrap();
+
+        downloader.flush_maintenance();
+
+        // Assert
+
+        assert_eq!(download.bytes().unwrap(), body);
+        assert_eq!(downloader.fetcher().calls(), 3);
+        assert_eq!(downloader.storage().list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_exhausted_body_retries_report_attempts() {
+        let url = "https://example.com/truncated.png";
+
+        let body = mock_file_content();
+
+        let fetcher = MockFetcher::new(vec![truncated(&body), truncated(&body)]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("body_retries_exhausted"), fetcher)
+                .retry_invalid_bodies(1)
+                .build();
+
+        // Act
+
+        let error = downloader.download(url).unwrap_err();
+
+        // Assert
+
+        assert_eq!(error.attempts().len(), 2);
+        assert_eq!(
+            error.last_error(),
+            &DownloadError::TruncatedBody {
+                received: body.len() as u64 / 2,
+                kind: ErrorKind::ConnectionReset,
+            }
+        );
+        assert_eq!(downloader.fetcher().calls(), 2);
+        assert!(downloader.storage().list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_transport_error_mapping() {
+        let url = "https://example.com/transport.png";
+
+        let cases = [
+            (
+                FetchError::dns("no such host"),
+                DownloadError::Dns("no such host".to_string()),
+            ),
+            (
+                FetchError::connect("connection refused"),
+                DownloadError::Connect("connection refused".to_string()),
+            ),
+            (
+                FetchError::tls("unknown issuer"),
+                DownloadError::Tls("unknown issuer".to_string()),
+            ),
+            (FetchError::timeout("too slow"), DownloadError::Timeout),
+            (
+                FetchError::io(ErrorKind::ConnectionReset),
+                DownloadError::NetworkError {
+                    reason: "i/o error: connection reset".to_string(),
+                },
+            ),
+            (
+                FetchError::Other("boom".to_string()),
+                DownloadError::NetworkError {
+                    reason: "boom".to_string(),
+                },
+            ),
+        ];
+
+        for (fetch_error, expected) in cases {
+            let fetcher = MockFetcher::scripted(vec![Err(fetch_error)]);
+
+            // Act
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(testing::cache_dir("transport_errors"), fetcher)
+                    .build();
+
+            let error = downloader.download(url).unwrap_err();
+
+            // Assert
+
+            assert_eq!(error, expected);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_schemes_are_never_fetched() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("unsupported_schemes"),
+            MockFetcher::new(vec![]),
+        )
+        .build();
+
+        let cases = [
+            ("ftp://example.com/logo.png", "ftp"),
+            ("mailto:someone@example.com", "mailto"),
+            ("chrome-extension://abcdef/icon.png", "chrome-extension"),
+            ("javascript:alert(1)", "javascript"),
+            ("FILE:///etc/passwd", "file"),
+        ];
+
+        for (url, scheme) in cases {
+            // Act
+
+            let error = downloader.download(url).unwrap_err();
+
+            // Assert
+
+            assert_eq!(
+                error,
+                DownloadError::InvalidUrl(UrlProblem::UnsupportedScheme(scheme.to_string()))
+            );
+            assert_eq!(error.code(), "unsupported_scheme");
+        }
+
+        assert_eq!(downloader.supported_schemes(), ["http", "https"]);
+        assert_eq!(downloader.fetcher().calls(), 0);
+    }
+
+    #[test]
+    fn test_fetchers_can_add_schemes() {
+        struct GopherFetcher;
+
+        impl FileDownloader for GopherFetcher {
+            fn fetch(&self, _: &str, _: &[(String, String)]) -> Result<Response, FetchError> {
+                Ok(Response::ok(b"hole".to_vec(), None))
+            }
+
+            fn schemes(&self) -> &[&str] {
+                &["http", "https", "gopher"]
+            }
+        }
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("extra_schemes"), GopherFetcher)
+                .build();
+
+        // Act
+
+        let download = downloader.download("gopher://example.com/1/hole").unwrap();
+
+        // Assert
+
+        assert_eq!(download.bytes().unwrap(), b"hole");
+        assert!(downloader.supported_schemes().contains(&"gopher"));
+    }
+
+    #[test]
+    fn test_download_metadata_from_single_pass() {
+        let url = "https://example.com/sniffed";
+
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+
+        let fetcher = MockFetcher::new(vec![
+            Response::new(200).with_reader(Cursor::new(png.clone()))
+        ]);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("metadata"), fetcher)
+            .cache_policy(CachePolicy::CacheFirst)
+            .build();
+
+        // Act
+
+        let download = downloader.download(url).unwrap();
+
+        let cached = downloader.download(url).unwrap();
+
+        // Assert
+
+        assert_eq!(download.metadata.size, Some(png.len() as u64));
+        assert_eq!(
+            download.metadata.sha256,
+            Some(tee::hex(&Sha256::digest(&png)))
+        );
+        assert_eq!(download.metadata.extension.as_deref(), Some("png"));
+        assert_eq!(download.file.extension().unwrap(), "png");
+        assert_eq!(cached, download);
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_directory_urls_are_named_by_hash() {
+        let url = "https://example.com/photos/";
+
+        let page = b"\n<!DOCTYPE html><title>Index of /photos</title>".to_vec();
+
+        let fetcher = MockFetcher::new(vec![Response::new(200).with_body(page)]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("directory_url"), fetcher).build();
+
+        // Act
+
+        let download = downloader.download(url).unwrap();
+
+        // Assert
+
+        assert_eq!(
+            download.file.file_name().unwrap().to_str().unwrap(),
+            format!("{}.html", CacheKey::from_url(url))
+        );
+        assert_eq!(download.metadata.extension.as_deref(), Some("html"));
+    }
+
+    #[test]
+    fn test_reject_html() {
+        let fetcher = MockFetcher::new(vec![
+            Response::ok(
+                b"<html>Sign in</html>".to_vec(),
+                Some("image/png".to_string()),
+            ),
+            Response::ok(
+                b"Sign in".to_vec(),
+                Some("text/html; charset=utf-8".to_string()),
+            ),
+            Response::ok(b"\x89PNG\r\n\x1a\n".to_vec(), Some("image/png".to_string())),
+        ]);
+
+        let dir = testing::cache_dir("reject_html");
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher)
+            .reject_html(true)
+            .build();
+
+        // Act
+
+        let sniffed = downloader.download("https://example.com/a.png");
+
+        let announced = downloader.download("https://example.com/b.png");
+
+        let image = downloader.download("https://example.com/c.png");
+
+        downloader.flush_maintenance();
+
+        // Assert
+
+        assert_eq!(sniffed.unwrap_err(), DownloadError::UnsupportedContent);
+        assert_eq!(announced.unwrap_err(), DownloadError::UnsupportedContent);
+        assert!(image.is_ok());
+        assert_eq!(downloader.storage().list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_svg_is_named_with_and_without_content_type() {
+        let fetcher = MockFetcher::new(vec![
+            Response::ok_svg(),
+            Response::ok(fixtures::SVG.to_vec(), None),
+        ]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("svg_extension"), fetcher).build();
+
+        // Act
+
+        let announced = downloader.download("https://example.com/logo").unwrap();
+
+        let sniffed = downloader.download("https://example.com/icon").unwrap();
+
+        // Assert
+
+        assert_eq!(announced.metadata.extension.as_deref(), Some("svg"));
+        assert_eq!(sniffed.metadata.extension.as_deref(), Some("svg"));
+        assert_eq!(sniffed.mime(), Some("image/svg+xml"));
+    }
+
     fn mock_file_content() -> Vec<u8> {
         "Mocked file content".as_bytes().to_vec()
     }
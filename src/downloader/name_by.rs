@@ -0,0 +1,118 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NameBy {
+    // `<url key>.<ext>`, one file per URL.
+    #[default]
+    UrlHash,
+    // `<sha256 of the body>.<ext>`, so URLs serving identical bytes share a
+    // file. The manifest maps each URL to it, and a file is only removed
+    // once no entry names it anymore.
+    ContentHash,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::NameBy;
+    use crate::downloader::{
+        fetcher::MockFetcher, tee, testing, CachePolicy, Downloader, DownloaderBuilder, Response,
+        Storage,
+    };
+
+    const BANNER: &[u8] = b"weekly banner";
+
+    fn downloader(name: &str, policy: CachePolicy, bodies: &[&[u8]]) -> Downloader<MockFetcher> {
+        let responses = bodies
+            .iter()
+            .map(|body| Response::ok(body.to_vec(), Some("image/png".to_string())))
+            .collect();
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), MockFetcher::new(responses))
+            .name_by(NameBy::ContentHash)
+            .cache_policy(policy)
+            .build()
+    }
+
+    fn data_files(downloader: &Downloader<MockFetcher>) -> Vec<String> {
+        let mut names = downloader.storage().list().unwrap();
+
+        names.sort();
+
+        names
+    }
+
+    #[test]
+    fn test_identical_bodies_share_one_file() {
+        let downloader = downloader(
+            "name_by_content",
+            CachePolicy::CacheFirst,
+            &[BANNER, BANNER],
+        );
+
+        // Act
+
+        let week_1 = downloader
+            .download("https://cdn.example.com/2024-01/banner.png")
+            .unwrap();
+
+        let week_2 = downloader
+            .download("https://cdn.example.com/2024-02/banner.png")
+            .unwrap();
+
+        let hit = downloader
+            .download("https://cdn.example.com/2024-01/banner.png")
+            .unwrap();
+
+        // Assert
+
+        let name = format!("{}.png", tee::BodySummary::of(BANNER).sha256);
+
+        assert_eq!(data_files(&downloader), [name.as_str()]);
+        assert_eq!(week_1.file, week_2.file);
+        assert_eq!(hit.file, week_1.file);
+        assert_eq!(downloader.fetcher().calls(), 2);
+        assert_eq!(
+            downloader
+                .manifest
+                .entries()
+                .iter()
+                .filter(|(_, entry)| entry.file == name)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_files_are_removed_with_their_last_url() {
+        let downloader = downloader(
+            "name_by_released",
+            CachePolicy::NetworkOnly,
+            &[BANNER, BANNER, b"new", b"newer"],
+        );
+
+        let first = "https://cdn.example.com/2024-01/banner.png";
+        let second = "https://cdn.example.com/2024-02/banner.png";
+
+        let shared = downloader.download(first).unwrap();
+
+        downloader.download(second).unwrap();
+
+        // Act
+
+        downloader.download(first).unwrap();
+
+        let kept = shared.file.exists();
+
+        downloader.download(second).unwrap();
+
+        // Assert
+
+        assert!(kept, "still named by the second URL");
+        assert!(!shared.file.exists());
+        assert_eq!(data_files(&downloader).len(), 2);
+        assert_eq!(
+            fs::read(downloader.cached(first).unwrap().file).unwrap(),
+            b"new"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use url::Url;
+
+use super::{
+    manifest::{self, ManifestEntry, NegativeEntry},
+    DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Storage,
+};
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Forgets a remembered failure of `url`, so the next download fetches it.
+    pub fn forget(&self, url: impl IntoDownloadUrl) {
+        let Ok(url) = url.to_download_url() else {
+            return;
+        };
+
+        let key = self.entry_key(url.as_str());
+
+        if let Some(mut entry) = self
+            .manifest
+            .get(&key)
+            .filter(|meta| meta.negative.is_some())
+        {
+            entry.negative = None;
+
+            self.manifest.insert(&key, entry);
+        }
+    }
+
+    // The error a remembered failure of `url` answers with until it expires.
+    pub(crate) fn remembered_failure(&self, url: &Url) -> Option<DownloadError> {
+        self.config.negative_ttl?;
+
+        let negative = self.manifest.get(&self.entry_key(url.as_str()))?.negative?;
+
+        if manifest::from_unix_secs(negative.expires_at) <= self.config.clock.now() {
+            return None;
+        }
+
+        Some(status_error(negative.status))
+    }
+
+    // Only statuses saying the URL itself is wrong are remembered, never
+    // transport errors or 5xx, which say nothing past the moment they happen.
+    pub(crate) fn remember_failure(&self, url: &Url, error: &DownloadError) {
+        let Some(ttl) = self.config.negative_ttl else {
+            return;
+        };
+
+        let status = match error {
+            DownloadError::NotFound => 404,
+            DownloadError::HttpStatus(status) => *status,
+            _ => return,
+        };
+
+        if !(400..500).contains(&status) || !self.config.negative_statuses.contains(&status) {
+            return;
+        }
+
+        let key = self.entry_key(url.as_str());
+
+        let mut entry = self.manifest.get(&key).unwrap_or_else(|| ManifestEntry {
+            url: url.to_string(),
+            ..Default::default()
+        });
+
+        let expires_at = self.config.clock.now() + ttl;
+
+        entry.negative = Some(NegativeEntry {
+            status,
+            expires_at: manifest::unix_secs(expires_at),
+        });
+
+        self.manifest.insert(&key, entry);
+    }
+}
+
+fn status_error(status: u16) -> DownloadError {
+    match status {
+        404 => DownloadError::NotFound,
+        status => DownloadError::HttpStatus(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, DownloadError, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/missing.png";
+
+    #[test]
+    fn test_not_found_is_fetched_once_per_ttl() {
+        let clock = FakeClock::new();
+
+        let fetcher = MockFetcher::new(vec![Response::not_found(), Response::not_found()]);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("negative"), fetcher)
+            .clock(clock.clone())
+            .negative_ttl(Duration::from_secs(60))
+            .build();
+
+        // Act
+
+        let errors: Vec<_> = (0..3)
+            .map(|_| downloader.download(URL).unwrap_err())
+            .collect();
+
+        let calls_within_ttl = downloader.fetcher().calls();
+
+        clock.advance(Duration::from_secs(61));
+
+        let expired = downloader.download(URL).unwrap_err();
+
+        // Assert
+
+        assert_eq!(errors, vec![DownloadError::NotFound; 3]);
+        assert_eq!(calls_within_ttl, 1);
+        assert_eq!(expired, DownloadError::NotFound);
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+
+    #[test]
+    fn test_only_configured_client_errors_are_remembered() {
+        let fetcher = MockFetcher::new(vec![
+            Response::new(500),
+            Response::new(500),
+            Response::new(410),
+            Response::new(403),
+            Response::new(403),
+            Response::not_found(),
+            Response::ok(b"found".to_vec(), Some("image/png".to_string())),
+        ]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("negative_statuses"), fetcher)
+                .negative_ttl(Duration::from_secs(60))
+                .negative_statuses(&[404, 410, 500])
+                .build();
+
+        let url = |path: &str| format!("https://example.com/{path}");
+
+        // Act
+
+        let errors: Vec<_> = ["down", "down", "gone", "gone", "denied", "denied"]
+            .map(|path| downloader.download(&url(path)).unwrap_err())
+            .to_vec();
+
+        downloader.download(URL).unwrap_err();
+
+        downloader.forget(URL);
+
+        let found = downloader.download(URL);
+
+        // Assert
+
+        assert_eq!(
+            errors,
+            [500, 500, 410, 410, 403, 403].map(DownloadError::HttpStatus)
+        );
+        assert_eq!(found.unwrap().bytes().unwrap(), b"found");
+        assert_eq!(downloader.fetcher().calls(), 7);
+    }
+}
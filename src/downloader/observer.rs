@@ -0,0 +1,17 @@
+use std::time::Duration;
+
+use super::{Download, DownloadError};
+
+pub trait Observer: Send + Sync {
+    fn on_download(&self, _url: &str, _result: &Result<Download, DownloadError>) {}
+
+    fn on_refresh(&self, _url: &str, _result: &Result<Download, DownloadError>) {}
+
+    // A retry is about to start, `waited` after it was due, which is only
+    // ever non-zero under `throttle_retries`. Attempts count from 1.
+    fn on_retry(&self, _url: &str, _attempt: u32, _waited: Duration) {}
+
+    // The body is not of the `Content-Type` it was served with. Both types
+    // are MIME essences, such as `image/png`.
+    fn on_content_mismatch(&self, _url: &str, _declared: &str, _detected: &str) {}
+}
@@ -0,0 +1,228 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use super::{
+    builder::Config, deadline::Deadline, headers, CachePolicy, Download, DownloadError, Downloader,
+    FileDownloader, OverwritePolicy, Storage,
+};
+
+// Overrides for a single call. Unset fields keep the downloader's
+// configuration; headers are merged into its own, replacing those with the
+// same name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DownloadOptions {
+    cache_policy: Option<CachePolicy>,
+    ttl: Option<Duration>,
+    overwrite_policy: Option<OverwritePolicy>,
+    headers: Vec<(String, String)>,
+    user_meta: HashMap<String, String>,
+    deadline: Option<Duration>,
+    #[cfg(feature = "image")]
+    retries: Option<u32>,
+}
+
+impl DownloadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = Some(cache_policy);
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn overwrite_policy(mut self, overwrite_policy: OverwritePolicy) -> Self {
+        self.overwrite_policy = Some(overwrite_policy);
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // Bounds the whole call from its start: fetches, redirects, retries and
+    // the waits between them. Fails with `DownloadError::DeadlineExceeded`
+    // once it passes.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    // Stored with the URL's entry and returned with every later download of
+    // it. Keys already stored are replaced, others kept.
+    pub fn user_meta(mut self, user_meta: HashMap<String, String>) -> Self {
+        self.user_meta.extend(user_meta);
+        self
+    }
+
+    #[cfg(feature = "image")]
+    pub fn retry_corrupt_images(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+
+        config.cache_policy = self.cache_policy.unwrap_or(config.cache_policy);
+        config.ttl = self.ttl.or(config.ttl);
+        config.overwrite_policy = self.overwrite_policy.unwrap_or(config.overwrite_policy);
+        if let Some(budget) = self.deadline {
+            config.deadline = Some(Deadline {
+                started: config.clock.now(),
+                budget,
+            });
+        }
+        if !self.headers.is_empty() {
+            config.headers = headers::merge(&config.headers, &self.headers).into();
+        }
+
+        #[cfg(feature = "image")]
+        if let Some(retries) = self.retries {
+            config.image.retries = retries;
+        }
+
+        config
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    pub fn download_with(
+        &self,
+        url: &str,
+        options: &DownloadOptions,
+    ) -> Result<Download, DownloadError> {
+        if *options == DownloadOptions::default() {
+            return self.download_any(url);
+        }
+
+        if options.user_meta.is_empty() {
+            return self.with_options(options).download_any(url);
+        }
+
+        let downloader = self.with_options(options);
+
+        downloader.check_user_meta(url, &options.user_meta)?;
+
+        let download = downloader.download_any(url)?;
+
+        downloader.attach_user_meta(url, download, &options.user_meta)
+    }
+
+    // A handle sharing everything with this one but the configuration.
+    fn with_options(&self, options: &DownloadOptions) -> Self {
+        Downloader {
+            config: Arc::new(options.apply(&self.config)),
+            refresher: self.refresher.clone(),
+            overlay: self
+                .overlay
+                .as_ref()
+                .map(|overlay| Box::new(overlay.with_options(options))),
+            ..self.detached()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DownloadOptions;
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, CachePolicy, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn png() -> Response {
+        Response::ok(b"image".to_vec(), Some("image/png".to_string()))
+    }
+
+    #[test]
+    fn test_call_headers_override_downloader_headers_by_name() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("options_headers"),
+            MockFetcher::new(vec![png()]),
+        )
+        .header("User-Agent", "gallery/1.0")
+        .header("X-Team", "web")
+        .build();
+
+        let options = DownloadOptions::new()
+            .header("x-team", "mobile")
+            .header("X-Request", "42");
+
+        // Act
+
+        downloader.download_with(URL, &options).unwrap();
+
+        // Assert
+
+        assert_eq!(
+            downloader.fetcher().request_headers(0),
+            [
+                ("User-Agent", "gallery/1.0"),
+                ("x-team", "mobile"),
+                ("X-Request", "42")
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_call_options_win_over_the_downloader_for_that_call_only() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("options_cache_policy"),
+            MockFetcher::new(vec![png(), png()]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        downloader.download(URL).unwrap();
+
+        // Act
+
+        downloader
+            .download_with(
+                URL,
+                &DownloadOptions::new().cache_policy(CachePolicy::NetworkOnly),
+            )
+            .unwrap();
+
+        downloader
+            .download_with(URL, &DownloadOptions::new())
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+
+    #[test]
+    fn test_unset_options_fall_back_to_crate_defaults() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("options_defaults"),
+            MockFetcher::new(vec![png(), png()]),
+        )
+        .build();
+
+        let options = DownloadOptions::new().header("X-Request", "42");
+
+        // Act
+
+        downloader.download_with(URL, &options).unwrap();
+        downloader.download_with(URL, &options).unwrap();
+
+        // Assert
+
+        assert_eq!(downloader.cache_policy(), CachePolicy::NetworkOnly);
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+}
@@ -0,0 +1,266 @@
+use super::Download;
+
+// How a download was satisfied, for callers that report more than the file.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    // A new body was fetched and stored.
+    Downloaded(Download),
+    // The server confirmed the cached copy is still current.
+    NotModified(Download),
+    // A new body was fetched but matched the cached copy, which was kept
+    // without rewriting it.
+    Unchanged(Download),
+    // Served from the cache without asking the server, or kept by
+    // `OverwritePolicy::Skip`.
+    CacheHit(Download),
+    // The fetch failed and the cached copy was served instead, see
+    // `CachePolicy::NetworkFirstFallbackStale`.
+    Stale(Download),
+}
+
+impl Outcome {
+    pub fn download(&self) -> &Download {
+        match self {
+            Self::Downloaded(download)
+            | Self::NotModified(download)
+            | Self::Unchanged(download)
+            | Self::CacheHit(download)
+            | Self::Stale(download) => download,
+        }
+    }
+
+    pub fn into_download(self) -> Download {
+        self.into_parts().1
+    }
+
+    pub fn is_fresh_copy(&self) -> bool {
+        matches!(self, Self::Downloaded(_))
+    }
+
+    pub(crate) fn into_parts(self) -> (fn(Download) -> Outcome, Download) {
+        match self {
+            Self::Downloaded(download) => (Self::Downloaded, download),
+            Self::NotModified(download) => (Self::NotModified, download),
+            Self::Unchanged(download) => (Self::Unchanged, download),
+            Self::CacheHit(download) => (Self::CacheHit, download),
+            Self::Stale(download) => (Self::Stale, download),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::Outcome;
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, CachePolicy, DownloadError, Downloader,
+        DownloaderBuilder, FetchError, Response,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn downloader(name: &str, second: Response, policy: CachePolicy) -> Downloader<MockFetcher> {
+        let first = Response::ok(b"v1".to_vec(), Some("image/png".to_string()))
+            .with_header("ETag", "\"v1\"");
+
+        DownloaderBuilder::with_fetcher(
+            testing::cache_dir(name),
+            MockFetcher::new(vec![first, second]),
+        )
+        .cache_policy(policy)
+        .build()
+    }
+
+    #[test]
+    fn test_fetched_bodies_are_downloaded() {
+        let downloader = downloader(
+            "outcome_downloaded",
+            Response::ok(b"v2".to_vec(), Some("image/png".to_string())),
+            CachePolicy::NetworkOnly,
+        );
+
+        downloader.download(URL).unwrap();
+
+        // Act
+
+        let outcome = downloader.download_checked(URL).unwrap();
+
+        // Assert
+
+        assert!(outcome.is_fresh_copy());
+        assert_eq!(outcome.into_download().bytes().unwrap(), b"v2");
+    }
+
+    #[test]
+    fn test_unchanged_resources_are_not_modified() {
+        let downloader = downloader(
+            "outcome_not_modified",
+            Response::not_modified(),
+            CachePolicy::NetworkOnly,
+        );
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let outcome = downloader.download_checked(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(outcome, Outcome::NotModified(first));
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+
+    #[test]
+    fn test_fresh_entries_are_cache_hits() {
+        let downloader = downloader(
+            "outcome_cache_hit",
+            Response::not_found(),
+            CachePolicy::CacheFirst,
+        );
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let outcome = downloader.download_checked(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(outcome, Outcome::CacheHit(first));
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_identical_bodies_are_not_rewritten() {
+        let clock = FakeClock::new();
+
+        let png = |body: &[u8]| Response::ok(body.to_vec(), Some("image/png".to_string()));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("outcome_unchanged"),
+            MockFetcher::new(vec![png(b"v1"), png(b"v1"), png(b"v2")]),
+        )
+        .clock(clock.clone())
+        .cache_policy(CachePolicy::CacheFirst)
+        .ttl(Duration::from_secs(60))
+        .build();
+
+        let first = downloader.download(URL).unwrap();
+
+        let mtime = || fs::metadata(&first.file).unwrap().modified().unwrap();
+
+        let written_at = mtime();
+
+        // Act
+
+        clock.advance(Duration::from_secs(61));
+
+        let unchanged = downloader.download_checked(URL).unwrap();
+
+        let unchanged_at = mtime();
+
+        clock.advance(Duration::from_secs(30));
+
+        let refreshed = downloader.download_checked(URL).unwrap();
+
+        clock.advance(Duration::from_secs(31));
+
+        let changed = downloader.download_checked(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(unchanged, Outcome::Unchanged(first.clone()));
+        assert_eq!(unchanged_at, written_at);
+        assert!(
+            matches!(refreshed, Outcome::CacheHit(_)),
+            "freshness renewed"
+        );
+        assert!(changed.is_fresh_copy());
+        assert_eq!(changed.download().bytes().unwrap(), b"v2");
+        assert_ne!(mtime(), written_at);
+        assert_eq!(downloader.fetcher().calls(), 3);
+    }
+
+    #[test]
+    fn test_failed_fetches_serve_stale_copies() {
+        let png = Response::ok(b"v1".to_vec(), Some("image/png".to_string()));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("outcome_stale"),
+            MockFetcher::scripted(vec![
+                Ok(png),
+                Err(FetchError::timeout("timed out")),
+                Ok(Response::new(500)),
+                Ok(Response::not_found()),
+            ]),
+        )
+        .cache_policy(CachePolicy::NetworkFirstFallbackStale)
+        .ttl(Duration::ZERO)
+        .build();
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let timed_out = downloader.download_checked(URL).unwrap();
+
+        let server_error = downloader.download_checked(URL).unwrap();
+
+        let not_found = downloader.download_checked(URL).unwrap_err();
+
+        // Assert
+
+        assert_eq!(timed_out, Outcome::Stale(first.clone()));
+        assert_eq!(server_error, Outcome::Stale(first));
+        assert!(!server_error.is_fresh_copy());
+        assert_eq!(not_found, DownloadError::NotFound);
+    }
+
+    #[test]
+    fn test_stale_copies_need_a_cached_entry() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("outcome_stale_uncached"),
+            MockFetcher::scripted(vec![Err(FetchError::connect("refused"))]),
+        )
+        .cache_policy(CachePolicy::NetworkFirstFallbackStale)
+        .build();
+
+        // Act
+
+        let error = downloader.download_checked(URL).unwrap_err();
+
+        // Assert
+
+        assert!(matches!(
+            error.last_error(),
+            DownloadError::Connect(_) | DownloadError::NetworkError { .. }
+        ));
+    }
+
+    #[test]
+    fn test_missing_urls_serve_stale_copies_when_allowed() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("outcome_stale_not_found"),
+            MockFetcher::new(vec![
+                Response::ok(b"v1".to_vec(), Some("image/png".to_string())),
+                Response::not_found(),
+            ]),
+        )
+        .cache_policy(CachePolicy::NetworkFirstFallbackStale)
+        .stale_on_not_found(true)
+        .build();
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let outcome = downloader.download_checked(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(outcome, Outcome::Stale(first));
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+}
@@ -0,0 +1,117 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    // Replace whatever is stored at the entry's path.
+    #[default]
+    Overwrite,
+    // Keep the existing file and return it as a cache hit.
+    Skip,
+    // Fail with `DownloadError::AlreadyExists`.
+    Error,
+    // Store the new body next to the existing file as `<name>-1.<ext>`,
+    // `<name>-2.<ext>`, and so on.
+    Rename,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::OverwritePolicy;
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, Downloader, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn downloader(name: &str, policy: OverwritePolicy) -> Downloader<MockFetcher> {
+        let fetcher = MockFetcher::new(vec![
+            Response::ok(b"v1".to_vec(), Some("image/png".to_string())),
+            Response::ok(b"v2".to_vec(), Some("image/png".to_string())),
+        ]);
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), fetcher)
+            .overwrite_policy(policy)
+            .build()
+    }
+
+    #[test]
+    fn test_overwrite_replaces_entry() {
+        let downloader = downloader("overwrite_replace", OverwritePolicy::Overwrite);
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let second = downloader.download(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(second.file, first.file);
+        assert_eq!(second.bytes().unwrap(), b"v2");
+    }
+
+    #[test]
+    fn test_skip_returns_existing_file() {
+        let downloader = downloader("overwrite_skip", OverwritePolicy::Skip);
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let second = downloader.download(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(second, first);
+        assert_eq!(second.bytes().unwrap(), b"v1");
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_error_keeps_existing_file() {
+        let downloader = downloader("overwrite_error", OverwritePolicy::Error);
+
+        let first = downloader.download(URL).unwrap();
+
+        // Act
+
+        let second = downloader.download(URL);
+
+        // Assert
+
+        assert_eq!(second, Err(DownloadError::AlreadyExists));
+        assert_eq!(first.bytes().unwrap(), b"v1");
+    }
+
+    #[test]
+    fn test_rename_rolls_past_existing_suffixes() {
+        let downloader = downloader("overwrite_rename", OverwritePolicy::Rename);
+
+        let first = downloader.download(URL).unwrap();
+
+        let stem = first
+            .file
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+
+        let taken = first.file.with_file_name(format!("{stem}-1.png"));
+
+        fs::write(&taken, b"taken").unwrap();
+
+        // Act
+
+        let second = downloader.download(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(
+            second.file,
+            first.file.with_file_name(format!("{stem}-2.png"))
+        );
+        assert_eq!(second.bytes().unwrap(), b"v2");
+        assert_eq!(first.bytes().unwrap(), b"v1");
+        assert_eq!(fs::read(&taken).unwrap(), b"taken");
+    }
+}
@@ -0,0 +1,36 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use super::CancellationToken;
+
+// Runs `task` for every item on up to `workers` scoped threads. Items not yet
+// started when the token is cancelled are never handed to `task`.
+pub(crate) fn for_each<I, F>(items: &[I], workers: usize, token: &CancellationToken, task: F)
+where
+    I: Sync,
+    F: Fn(usize, &I) + Sync,
+{
+    let next = AtomicUsize::new(0);
+
+    let workers = workers.clamp(1, items.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                if token.is_cancelled() {
+                    break;
+                }
+
+                let index = next.fetch_add(1, Ordering::SeqCst);
+
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+
+                task(index, item);
+            });
+        }
+    });
+}
@@ -0,0 +1,488 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use super::{Downloader, FileDownloader, Storage, PARTIAL_SUFFIX};
+
+static NEXT_STAGING: AtomicU64 = AtomicU64::new(0);
+
+// Where a write of `target` is staged: a name no other writer uses, in this
+// process or another, so concurrent writes of one file never share a partial
+// file and the last rename wins.
+pub(crate) fn staging_path(target: &Path) -> PathBuf {
+    staged_as(target, NEXT_STAGING.fetch_add(1, Ordering::Relaxed))
+}
+
+// The next `count` staging paths of `target`, for tests to plant files at.
+#[cfg(test)]
+pub(crate) fn upcoming_staging_paths(target: &Path, count: u64) -> Vec<PathBuf> {
+    let next = NEXT_STAGING.load(Ordering::Relaxed);
+
+    (next..next + count)
+        .map(|counter| staged_as(target, counter))
+        .collect()
+}
+
+fn staged_as(target: &Path, counter: u64) -> PathBuf {
+    let mut name = OsString::from(target.as_os_str());
+
+    name.push(format!(".{}-{counter}{PARTIAL_SUFFIX}", process::id()));
+
+    PathBuf::from(name)
+}
+
+// An in-progress write. The file is removed when the guard is dropped without
+// being committed, which also covers unwinding out of a panicking body reader.
+pub(crate) struct PartialFile {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl PartialFile {
+    pub fn create(path: PathBuf) -> io::Result<Self> {
+        let file = recreating_parent(&path, || {
+            File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+        })?;
+
+        Ok(Self {
+            path,
+            file: Some(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn set_len(&mut self, size: u64) -> io::Result<()> {
+        self.file().set_len(size)
+    }
+
+    // Reserves the blocks rather than leaving a sparse file, so a full disk
+    // is reported before anything is written.
+    pub fn preallocate(&mut self, size: u64) -> io::Result<()> {
+        fs2::FileExt::allocate(self.file(), size)
+    }
+
+    pub fn read_all(&mut self) -> io::Result<Vec<u8>> {
+        let file = self.file();
+
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    pub fn rewrite(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let file = self.file();
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(bytes)
+    }
+
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.file().rewind()
+    }
+
+    // Unlike creating the file with a mode, this is not narrowed by the umask.
+    #[cfg(unix)]
+    pub fn set_mode(&mut self, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        self.file()
+            .set_permissions(fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    pub fn set_mode(&mut self, _mode: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn commit(mut self, target: &Path, modified: SystemTime) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.set_modified(modified)?;
+        }
+
+        fs::rename(&self.path, target)?;
+
+        self.path = PathBuf::new();
+
+        Ok(())
+    }
+
+    // Leaves the file at `path` when the guard is dropped, replacing what an
+    // earlier writer kept there.
+    pub fn keep_as(mut self, path: &Path) -> io::Result<()> {
+        drop(self.file.take());
+
+        fs::rename(&self.path, path)?;
+
+        self.path = PathBuf::new();
+
+        Ok(())
+    }
+
+    fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("partial file used after commit")
+    }
+}
+
+impl Read for PartialFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file().read(buf)
+    }
+}
+
+impl Write for PartialFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        drop(self.file.take());
+
+        if !self.path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+// Runs a write into `path`, recreating its directory once if it vanished, as
+// it does when a tmp cleaner removes the cache while the process runs.
+pub(crate) fn recreating_parent<R>(
+    path: &Path,
+    mut write: impl FnMut() -> io::Result<R>,
+) -> io::Result<R> {
+    match write() {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            match path.parent().filter(|parent| !parent.exists()) {
+                Some(parent) => {
+                    fs::create_dir_all(parent)?;
+
+                    write()
+                }
+                None => Err(error),
+            }
+        }
+        result => result,
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Sweeps partial files left behind by a previous process that crashed or
+    // was killed mid-write. Completed entries never carry the suffix.
+    pub fn clean_stale_partials(&self, older_than: Duration) -> io::Result<usize> {
+        let now = self.config.clock.now();
+
+        let mut removed = 0;
+
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+
+            let is_partial = entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(PARTIAL_SUFFIX);
+
+            if !is_partial || !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            let modified = entry.metadata()?.modified()?;
+
+            let age = now.duration_since(modified).unwrap_or_default();
+
+            if age >= older_than {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, Read},
+        panic::{self, AssertUnwindSafe},
+        sync::{Arc, Barrier},
+        thread,
+        time::Duration,
+    };
+
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, CachePolicy, Clock, DownloadError,
+        DownloaderBuilder, Response,
+    };
+
+    struct PanickingReader {
+        sent: bool,
+    }
+
+    impl Read for PanickingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.sent {
+                panic!("reader crashed mid-body");
+            }
+
+            self.sent = true;
+            buf[..4].copy_from_slice(b"half");
+            Ok(4)
+        }
+    }
+
+    #[test]
+    fn test_panic_mid_write_leaves_no_partial() {
+        let dir = testing::cache_dir("partial_panic");
+
+        let response = Response::new(200).with_reader(PanickingReader { sent: false });
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![response])).build();
+
+        // Act
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            downloader.download("https://example.com/crash.png")
+        }));
+
+        // Assert
+
+        assert!(result.is_err());
+        assert!(fs::read_dir(&dir)
+            .unwrap()
+            .all(|entry| entry.unwrap().file_name() == "CACHE_FORMAT"));
+    }
+
+    // Sends half the body, then waits for every other reader to get there.
+    struct MeetingReader {
+        halves: Vec<&'static [u8]>,
+        barrier: Arc<Barrier>,
+    }
+
+    impl Read for MeetingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.halves.len() == 1 {
+                self.barrier.wait();
+            }
+
+            let Some(half) = self.halves.pop() else {
+                return Ok(0);
+            };
+
+            buf[..half.len()].copy_from_slice(half);
+
+            Ok(half.len())
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writes_of_an_entry_are_staged_apart() {
+        let barrier = Arc::new(Barrier::new(2));
+
+        let bodies: [[&'static [u8]; 2]; 2] = [
+            [b"second half", b"first half, "],
+            [b"SECOND HALF", b"FIRST HALF, "],
+        ];
+
+        let responses = bodies
+            .iter()
+            .map(|halves| {
+                Response::ok(Vec::new(), Some("application/pdf".to_string())).with_reader(
+                    MeetingReader {
+                        halves: halves.to_vec(),
+                        barrier: Arc::clone(&barrier),
+                    },
+                )
+            })
+            .collect();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("partial_concurrent"),
+            MockFetcher::new(responses),
+        )
+        .cache_policy(CachePolicy::NetworkOnly)
+        .build();
+
+        // Act
+
+        let downloads = thread::scope(|scope| {
+            let writers: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| downloader.download("https://example.com/report.pdf")))
+                .collect();
+
+            writers
+                .into_iter()
+                .map(|writer| writer.join().unwrap().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        // Assert
+
+        let stored = downloads[0].bytes().unwrap();
+
+        assert_eq!(downloads[0].file, downloads[1].file);
+        assert!(
+            [&b"first half, second half"[..], b"FIRST HALF, SECOND HALF"].contains(&&stored[..]),
+            "{stored:?}"
+        );
+    }
+
+    #[test]
+    fn test_truncated_bodies_are_kept_only_when_asked() {
+        let received = vec![7; 10_000];
+
+        let partials = |keep: bool| {
+            let dir = testing::cache_dir(&format!("partial_truncated_{keep}"));
+
+            let response = Response::truncated(received.clone(), io::ErrorKind::ConnectionReset);
+
+            let downloader =
+                DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![response]))
+                    .retry_invalid_bodies(0)
+                    .keep_partial_bodies(keep)
+                    .build();
+
+            let error = downloader
+                .download("https://example.com/large.bin")
+                .unwrap_err();
+
+            let partials: Vec<_> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.to_string_lossy().ends_with(".part"))
+                .map(|path| fs::read(path).unwrap())
+                .collect();
+
+            (error, partials)
+        };
+
+        // Act
+
+        let (kept_error, kept) = partials(true);
+
+        let (discarded_error, discarded) = partials(false);
+
+        // Assert
+
+        let truncated = DownloadError::TruncatedBody {
+            received: 10_000,
+            kind: io::ErrorKind::ConnectionReset,
+        };
+
+        assert_eq!(kept_error, truncated);
+        assert_eq!(discarded_error, truncated);
+        assert_eq!(kept, [received]);
+        assert!(discarded.is_empty());
+    }
+
+    #[test]
+    fn test_sweep_only_removes_old_partials() {
+        let dir = testing::cache_dir("partial_sweep");
+
+        let clock = FakeClock::new();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![Response::ok(b"done".to_vec(), None)]),
+        )
+        .clock(clock.clone())
+        .build();
+
+        let completed = downloader.download("https://example.com/done").unwrap();
+
+        let stale = dir.join("1234.part");
+
+        fs::write(&stale, b"stale").unwrap();
+
+        clock.advance(Duration::from_secs(3600));
+
+        let fresh = dir.join("5678.part");
+
+        fs::write(&fresh, b"fresh").unwrap();
+
+        fs::File::options()
+            .write(true)
+            .open(&fresh)
+            .unwrap()
+            .set_modified(clock.now())
+            .unwrap();
+
+        // Act
+
+        let removed = downloader
+            .clean_stale_partials(Duration::from_secs(60))
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(removed, 1);
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+        assert!(completed.file.exists());
+    }
+
+    #[test]
+    fn test_deleted_cache_dir_is_recreated() {
+        let dir = testing::cache_dir("partial_deleted_dir");
+
+        let url = "https://example.com/report.pdf";
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![
+                Response::ok(b"first".to_vec(), None),
+                Response::ok(b"second".to_vec(), None),
+            ]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .write_sidecars(true)
+        .build();
+
+        let first = downloader.download(url).unwrap();
+
+        downloader.flush_maintenance();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Act
+
+        let second = downloader.download(url).unwrap();
+
+        downloader.flush_maintenance();
+
+        // Assert
+
+        assert_eq!(first.file, second.file);
+        assert_eq!(second.bytes().unwrap(), b"second");
+        assert_eq!(downloader.fetcher().calls(), 2);
+        assert!(dir.join("manifest.json").exists());
+    }
+}
@@ -0,0 +1,227 @@
+use std::{fmt, fs, io, path::Path, sync::Arc, time::SystemTime};
+
+use url::Url;
+
+use super::manifest;
+
+type PartitionFn = dyn Fn(&Url, SystemTime) -> String + Send + Sync;
+
+// Left in every partition, so listings tell them from other directories the
+// cache may hold, such as extracted archives.
+pub(crate) const PARTITION_MARKER: &str = ".partition";
+
+// The subdirectory of the cache new entries are written to. Entries are found
+// in any partition, so changing it, or the day changing, keeps reading the
+// entries already stored.
+#[derive(Clone, Default)]
+pub enum Partitioner {
+    #[default]
+    None,
+    // The UTC date the entry is written, `2024-06-01`.
+    ByDate,
+    // The URL's host, `cdn.example.com`.
+    ByHost,
+    // Characters other than ASCII letters, digits, `.`, `-` and `_` are
+    // replaced by `_`; an empty name stores the entry at the top.
+    Custom(Arc<PartitionFn>),
+}
+
+impl Partitioner {
+    pub fn custom(partition: impl Fn(&Url, SystemTime) -> String + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(partition))
+    }
+
+    pub(crate) fn partition(&self, url: &Url, now: SystemTime) -> Option<String> {
+        let partition = match self {
+            Self::None => return None,
+            Self::ByDate => utc_date(now),
+            Self::ByHost => url.host_str()?.to_string(),
+            Self::Custom(partition) => partition(url, now),
+        };
+
+        let partition: String = partition
+            .chars()
+            .map(|char| match char {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => char,
+                _ => '_',
+            })
+            .collect();
+
+        // `.` and `..` would step out of the partition.
+        (!partition.trim_matches('.').is_empty()).then_some(partition)
+    }
+}
+
+impl fmt::Debug for Partitioner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("None"),
+            Self::ByDate => f.write_str("ByDate"),
+            Self::ByHost => f.write_str("ByHost"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+// Marks the directory an entry named `<partition>/<file>` was written to.
+pub(crate) fn mark(dir: &Path) -> io::Result<()> {
+    let marker = dir.join(PARTITION_MARKER);
+
+    match marker.exists() {
+        true => Ok(()),
+        false => fs::write(marker, ""),
+    }
+}
+
+// Days since the epoch to a proleptic Gregorian date, after Howard Hinnant's
+// `civil_from_days`.
+fn utc_date(time: SystemTime) -> String {
+    let days = (manifest::unix_secs(time) / 86_400) as i64 + 719_468;
+
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    use url::Url;
+
+    use super::{utc_date, Partitioner};
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, CachePolicy, Clock, DownloaderBuilder,
+        Response, Storage,
+    };
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_partition_names() {
+        let url = Url::parse("https://cdn.example.com/a.png").unwrap();
+
+        // Assert
+
+        assert_eq!(utc_date(at(0)), "1970-01-01");
+        assert_eq!(utc_date(at(951_782_400)), "2000-02-29");
+        assert_eq!(utc_date(at(1_717_286_399)), "2024-06-01");
+        assert_eq!(Partitioner::None.partition(&url, at(0)), None);
+        assert_eq!(
+            Partitioner::ByHost.partition(&url, at(0)).as_deref(),
+            Some("cdn.example.com")
+        );
+        assert_eq!(
+            Partitioner::custom(|_, _| "../etc/x y".to_string())
+                .partition(&url, at(0))
+                .as_deref(),
+            Some(".._etc_x_y")
+        );
+        assert_eq!(
+            Partitioner::custom(|_, _| "..".to_string()).partition(&url, at(0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_entries_land_in_dated_partitions() {
+        let dir = testing::cache_dir("partition_by_date");
+
+        let clock = FakeClock::new();
+
+        let png =
+            |body: &str| Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![png("a"), png("b"), png("a again")]),
+        )
+        .clock(clock.clone())
+        .cache_policy(CachePolicy::CacheFirst)
+        .ttl(Duration::from_secs(2 * 86_400))
+        .partition_by(Partitioner::ByDate)
+        .build();
+
+        let today = utc_date(clock.now());
+
+        // Act
+
+        let first = downloader.download("https://example.com/a.png").unwrap();
+
+        clock.advance(Duration::from_secs(86_400));
+
+        let tomorrow = utc_date(clock.now());
+
+        let hit = downloader.download("https://example.com/a.png").unwrap();
+
+        let second = downloader.download("https://example.com/b.png").unwrap();
+
+        clock.advance(Duration::from_secs(2 * 86_400));
+
+        let refreshed = downloader.download("https://example.com/a.png").unwrap();
+
+        // Assert
+
+        assert_eq!(first.file.parent().unwrap(), dir.join(&today));
+        assert_eq!(hit.file, first.file, "found the next day");
+        assert_eq!(second.file.parent().unwrap(), dir.join(&tomorrow));
+        assert_eq!(refreshed.bytes().unwrap(), b"a again");
+        assert!(!first.file.exists(), "replaced across partitions");
+        assert_eq!(downloader.fetcher().calls(), 3);
+
+        downloader.clear_cache();
+
+        assert!(!dir.exists());
+        assert!(fs::metadata(&second.file).is_err());
+    }
+
+    #[test]
+    fn test_only_partitions_are_listed() {
+        let dir = testing::cache_dir("partition_listing");
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![Response::ok(
+                b"image".to_vec(),
+                Some("image/png".to_string()),
+            )]),
+        )
+        .partition_by(Partitioner::ByHost)
+        .build();
+
+        let download = downloader
+            .download("https://cdn.example.com/a.png")
+            .unwrap();
+
+        fs::create_dir_all(dir.join("extracted")).unwrap();
+
+        fs::write(dir.join("extracted/readme.txt"), "kept").unwrap();
+
+        // Act
+
+        let names = downloader.storage().list().unwrap();
+
+        // Assert
+
+        let file = download.file.file_name().unwrap().to_str().unwrap();
+
+        assert_eq!(names, [format!("cdn.example.com/{file}")]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::{self, Read};
+
+use super::{
+    ranges, sniff, Body, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Storage,
+};
+
+// The start of a remote file, fetched without downloading the rest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peek {
+    pub bytes: Vec<u8>,
+    // The size of the whole file, when the server announced it.
+    pub size: Option<u64>,
+    pub mime: Option<String>,
+    pub extension: String,
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Fetches at most `max_bytes` from the start of the body, asking for just
+    // that range. Servers that ignore the range send the whole body, which
+    // is dropped once enough was read. Nothing touches the cache.
+    pub fn peek(&self, url: &str, max_bytes: u64) -> Result<Peek, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        let range = (
+            "Range".to_string(),
+            format!("bytes=0-{}", max_bytes.max(1) - 1),
+        );
+
+        let response = self.fetch(&url, &[range])?;
+
+        let size = match response.status {
+            206 | 416 => ranges::total_size(&response),
+            200..=299 => response.content_length(),
+            404 => return Err(DownloadError::NotFound),
+            status => return Err(DownloadError::HttpStatus(status)),
+        };
+
+        let announced = response.mime().map(str::to_string);
+
+        // Files that are empty cannot satisfy any range.
+        let body = match response.status {
+            416 => Body::Bytes(Vec::new()),
+            _ => response.body,
+        };
+
+        let reader: Box<dyn Read> = match body {
+            Body::Bytes(bytes) => Box::new(io::Cursor::new(bytes)),
+            Body::Reader(reader) => reader,
+        };
+
+        let mut bytes = Vec::new();
+
+        reader
+            .take(max_bytes)
+            .read_to_end(&mut bytes)
+            .map_err(|_| DownloadError::InvalidBody)?;
+
+        Ok(Peek {
+            extension: self.get_extension(announced.as_deref(), &bytes),
+            mime: announced
+                .as_deref()
+                .and_then(sniff::normalize_mime)
+                .or_else(|| sniff::mime_from_magic(&bytes).map(str::to_string)),
+            size,
+            bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+
+    use crate::downloader::{
+        fetcher::MockFetcher, fixtures, testing, DownloaderBuilder, Response, Storage,
+    };
+
+    const URL: &str = "https://example.com/huge.png";
+
+    #[test]
+    fn test_ranged_servers_send_only_the_start() {
+        let head = fixtures::PNG[..16].to_vec();
+
+        let response = Response::new(206)
+            .with_header("Content-Range", "bytes 0-15/5000000")
+            .with_header("Content-Type", "image/png")
+            .with_body(head.clone());
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("peek_ranged"),
+            MockFetcher::new(vec![response]),
+        )
+        .build();
+
+        // Act
+
+        let peek = downloader.peek(URL, 16).unwrap();
+
+        // Assert
+
+        assert_eq!(peek.bytes, head);
+        assert_eq!(peek.size, Some(5_000_000));
+        assert_eq!(peek.mime.as_deref(), Some("image/png"));
+        assert_eq!(peek.extension, "png");
+        assert_eq!(
+            downloader.fetcher().request_headers(0),
+            [("Range".to_string(), "bytes=0-15".to_string())]
+        );
+        assert!(downloader.storage().list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_whole_bodies_are_cut_short() {
+        // Far more than a test could read, so only stopping early finishes.
+        let endless = fixtures::PNG.chain(io::repeat(0));
+
+        let response = Response::new(200)
+            .with_header("Content-Length", "1000000000000")
+            .with_reader(endless);
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("peek_ignored_range"),
+            MockFetcher::new(vec![response]),
+        )
+        .build();
+
+        // Act
+
+        let peek = downloader.peek(URL, 64).unwrap();
+
+        // Assert
+
+        assert_eq!(peek.bytes.len(), 64);
+        assert_eq!(peek.bytes[..8], fixtures::PNG[..8]);
+        assert_eq!(peek.size, Some(1_000_000_000_000));
+        assert_eq!(peek.mime.as_deref(), Some("image/png"), "sniffed");
+        assert_eq!(peek.extension, "png");
+    }
+
+    #[test]
+    fn test_small_files_are_returned_whole() {
+        let responses = vec![
+            Response::new(206)
+                .with_header("Content-Range", "bytes 0-4/5")
+                .with_body(b"hello".to_vec()),
+            Response::ok(b"hello".to_vec(), Some("text/plain".to_string()))
+                .with_header("Content-Length", "5"),
+            Response::new(416).with_header("Content-Range", "bytes */0"),
+        ];
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("peek_small"),
+            MockFetcher::new(responses),
+        )
+        .build();
+
+        // Act
+
+        let peeks: Vec<_> = (0..3)
+            .map(|_| downloader.peek(URL, 1024).unwrap())
+            .map(|peek| (peek.bytes, peek.size))
+            .collect();
+
+        // Assert
+
+        assert_eq!(
+            peeks,
+            [
+                (b"hello".to_vec(), Some(5)),
+                (b"hello".to_vec(), Some(5)),
+                (Vec::new(), Some(0)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{partial, Download};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PersistMode {
+    #[default]
+    Move,
+    Copy,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExistingDestination {
+    #[default]
+    Fail,
+    Overwrite,
+}
+
+impl Download {
+    pub fn persist_to(&self, dest: &Path) -> io::Result<PathBuf> {
+        self.persist_with(dest, PersistMode::Move, ExistingDestination::Fail)
+    }
+
+    // A destination that is an existing directory receives the file under
+    // its cached name, anything else is taken as the full target path.
+    pub fn persist_with(
+        &self,
+        dest: &Path,
+        mode: PersistMode,
+        existing: ExistingDestination,
+    ) -> io::Result<PathBuf> {
+        persist(&self.file, dest, mode, existing, |from, to| {
+            fs::rename(from, to)
+        })
+    }
+}
+
+pub(crate) fn persist(
+    source: &Path,
+    dest: &Path,
+    mode: PersistMode,
+    existing: ExistingDestination,
+    rename: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<PathBuf> {
+    let target = match (dest.is_dir(), source.file_name()) {
+        (true, Some(file_name)) => dest.join(file_name),
+        _ => dest.to_path_buf(),
+    };
+
+    if existing == ExistingDestination::Fail && target.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        ));
+    }
+
+    match mode {
+        PersistMode::Copy => copy_into_place(source, &target)?,
+        PersistMode::Move => match rename(source, &target) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+                copy_into_place(source, &target)?;
+                fs::remove_file(source)?;
+            }
+            Err(error) => return Err(error),
+        },
+    }
+
+    Ok(target)
+}
+
+// Copies land in a partial file on the destination filesystem first, so the
+// target never holds a truncated file if the copy is interrupted.
+fn copy_into_place(source: &Path, target: &Path) -> io::Result<()> {
+    let partial = partial::staging_path(target);
+
+    let copied = fs::copy(source, &partial).and_then(|_| fs::rename(&partial, target));
+
+    if copied.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+
+    copied
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::Path};
+
+    use super::{persist, ExistingDestination, PersistMode};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, Download, DownloadError, DownloaderBuilder, MemoryStorage,
+        Response,
+    };
+
+    fn cached(name: &str, content: &[u8]) -> Download {
+        let dir = testing::cache_dir(name);
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("cached.png");
+
+        fs::write(&file, content).unwrap();
+
+        Download::new("https://example.com/cached.png".to_string(), file)
+    }
+
+    fn cross_device(_from: &Path, _to: &Path) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::CrossesDevices))
+    }
+
+    #[test]
+    fn test_persist_moves_into_directory() {
+        let download = cached("persist_move", b"png");
+
+        let dest = testing::cache_dir("persist_move_dest");
+
+        fs::create_dir_all(&dest).unwrap();
+
+        // Act
+
+        let target = download.persist_to(&dest).unwrap();
+
+        // Assert
+
+        assert_eq!(target, dest.join("cached.png"));
+        assert_eq!(fs::read(&target).unwrap(), b"png");
+        assert!(!download.file.exists());
+    }
+
+    #[test]
+    fn test_persist_falls_back_to_copy_across_devices() {
+        let download = cached("persist_exdev", b"png");
+
+        let dest = testing::cache_dir("persist_exdev_dest");
+
+        fs::create_dir_all(&dest).unwrap();
+
+        // Act
+
+        let target = persist(
+            &download.file,
+            &dest,
+            PersistMode::Move,
+            ExistingDestination::Fail,
+            cross_device,
+        )
+        .unwrap();
+
+        // Assert
+
+        assert_eq!(fs::read(&target).unwrap(), b"png");
+        assert!(!download.file.exists());
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_persist_surfaces_other_rename_errors() {
+        let download = cached("persist_denied", b"png");
+
+        let dest = testing::cache_dir("persist_denied_dest").join("file.png");
+
+        // Act
+
+        let error = persist(
+            &download.file,
+            &dest,
+            PersistMode::Move,
+            ExistingDestination::Fail,
+            |_, _| Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+        )
+        .unwrap_err();
+
+        // Assert
+
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(download.file.exists());
+    }
+
+    #[test]
+    fn test_existing_destination_policies() {
+        let download = cached("persist_existing", b"new");
+
+        let dest = testing::cache_dir("persist_existing_dest");
+
+        fs::create_dir_all(&dest).unwrap();
+
+        let target = dest.join("kept.png");
+
+        fs::write(&target, b"old").unwrap();
+
+        // Act
+
+        let failed = download.persist_with(&target, PersistMode::Copy, ExistingDestination::Fail);
+
+        let content_after_fail = fs::read(&target).unwrap();
+
+        let overwritten =
+            download.persist_with(&target, PersistMode::Copy, ExistingDestination::Overwrite);
+
+        // Assert
+
+        assert_eq!(failed.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(content_after_fail, b"old");
+        assert_eq!(overwritten.unwrap(), target);
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert!(download.file.exists());
+    }
+
+    #[test]
+    fn test_downloader_hands_files_over() {
+        let cache = testing::cache_dir("persist_handover");
+
+        let dest = testing::cache_dir("persist_handover_dest");
+
+        let fetcher = MockFetcher::new(vec![
+            Response::ok(b"v1".to_vec(), Some("image/png".to_string())),
+            Response::ok(b"v2".to_vec(), Some("image/png".to_string())),
+        ]);
+
+        let downloader = DownloaderBuilder::with_fetcher(&cache, fetcher)
+            .persist_to(&dest)
+            .build();
+
+        // Act
+
+        let first = downloader.download("https://example.com/a.png").unwrap();
+
+        let second = downloader.download("https://example.com/a.png").unwrap();
+
+        // Assert
+
+        assert_eq!(first.file, second.file);
+        assert_eq!(first.file.parent().unwrap(), dest);
+        assert_eq!(fs::read(&second.file).unwrap(), b"v2");
+        assert_eq!(downloader.fetcher().calls(), 2);
+        assert!(fs::read_dir(&cache).unwrap().all(|entry| matches!(
+            entry.unwrap().file_name().to_str(),
+            Some("manifest.json" | "CACHE_FORMAT")
+        )));
+    }
+
+    #[test]
+    fn test_failed_hand_overs_are_errors() {
+        let dest = testing::cache_dir("persist_failed_dest");
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("persist_failed"),
+            MockFetcher::new(vec![Response::ok(
+                b"v1".to_vec(),
+                Some("image/png".to_string()),
+            )]),
+        )
+        .persist_to(dest.join("inner"))
+        .build();
+
+        fs::remove_dir_all(&dest).unwrap();
+
+        // Act
+
+        let result = downloader.download("https://example.com/a.png");
+
+        // Assert
+
+        assert!(matches!(result, Err(DownloadError::Io(_))));
+    }
+
+    #[test]
+    fn test_persisting_needs_a_storage_on_disk() {
+        let builder = || {
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir("persist_memory"),
+                MockFetcher::new(Vec::new()),
+            )
+            .storage(MemoryStorage::new())
+            .persist_to(testing::cache_dir("persist_memory_dest"))
+        };
+
+        // Act
+
+        let built = builder().try_build();
+
+        let downloader = builder().build();
+
+        let result = downloader.download("https://example.com/a.png");
+
+        // Assert
+
+        assert!(matches!(built, Err(DownloadError::Io(_))));
+        assert!(matches!(result, Err(DownloadError::Io(_))));
+        assert_eq!(downloader.fetcher().calls(), 0);
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{parallel, Downloader, FileDownloader, IntoDownloadUrl, Storage};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrefetchSummary {
+    pub fetched: usize,
+    pub cached: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    pub fn prefetch<U>(&self, urls: &[U]) -> PrefetchSummary
+    where
+        U: IntoDownloadUrl + Sync,
+    {
+        let fetched = AtomicUsize::new(0);
+        let cached = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+
+        let token = &self.config.cancellation_token;
+
+        parallel::for_each(urls, self.config.max_concurrency, token, |_, url| {
+            let cached_file = url
+                .to_download_url()
+                .ok()
+                .and_then(|url| self.cached_file(&url));
+
+            if cached_file.is_some() {
+                cached.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+
+            match self.download_any(url) {
+                Ok(_) => fetched.fetch_add(1, Ordering::SeqCst),
+                Err(_) => failed.fetch_add(1, Ordering::SeqCst),
+            };
+        });
+
+        let fetched = fetched.into_inner();
+        let cached = cached.into_inner();
+        let failed = failed.into_inner();
+
+        PrefetchSummary {
+            fetched,
+            cached,
+            failed,
+            cancelled: urls.len() - fetched - cached - failed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrefetchSummary;
+
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, CancellationToken, DownloaderBuilder, Response,
+    };
+
+    fn png_response() -> Response {
+        Response::ok(b"image".to_vec(), Some("image/png".to_string()))
+    }
+
+    #[test]
+    fn test_prefetch_skips_cached_urls() {
+        let cached_url = "https://example.com/cached.png";
+        let missing_url = "https://example.com/missing.png";
+
+        let fetcher = MockFetcher::new(vec![png_response(), png_response()]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("prefetch_cached"), fetcher)
+                .max_concurrency(2)
+                .build();
+
+        downloader.download(cached_url).unwrap();
+
+        // Act
+
+        let warm = downloader.prefetch(&[cached_url]);
+
+        let mixed = downloader.prefetch(&[cached_url, missing_url, "not a url"]);
+
+        // Assert
+
+        assert_eq!(
+            warm,
+            PrefetchSummary {
+                cached: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            mixed,
+            PrefetchSummary {
+                fetched: 1,
+                cached: 1,
+                failed: 1,
+                cancelled: 0,
+            }
+        );
+        assert_eq!(downloader.fetcher().calls(), 2);
+
+        downloader.clear_cache();
+    }
+
+    #[test]
+    fn test_prefetch_stops_when_cancelled() {
+        let token = CancellationToken::new();
+
+        let fetcher = MockFetcher::new(vec![png_response()]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("prefetch_cancelled"), fetcher)
+                .cancellation_token(token.clone())
+                .build();
+
+        token.cancel();
+
+        // Act
+
+        let summary = downloader.prefetch(&["https://example.com/a.png"]);
+
+        // Assert
+
+        assert_eq!(summary.cancelled, 1);
+        assert_eq!(downloader.fetcher().calls(), 0);
+
+        downloader.clear_cache();
+    }
+}
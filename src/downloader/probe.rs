@@ -0,0 +1,207 @@
+use std::sync::Mutex;
+
+use url::Url;
+
+use super::{
+    parallel, sniff, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Response, Storage,
+};
+
+// What a server says about a URL, learnt without downloading it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub status: u16,
+    pub size: Option<u64>,
+    pub mime: Option<String>,
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Checks every URL with a HEAD request, or a GET of the first byte where
+    // the server rejects HEAD, without writing anything. Results keep the
+    // order of `urls`, and a URL that does not parse only fails its own slot.
+    pub fn validate_urls<U>(&self, urls: &[U]) -> Vec<(String, Result<Probe, DownloadError>)>
+    where
+        U: IntoDownloadUrl + Sync,
+    {
+        let results = Mutex::new(vec![None; urls.len()]);
+
+        let token = self.config.cancellation_token.child();
+
+        parallel::for_each(urls, self.config.max_concurrency, &token, |index, url| {
+            let probe = self.probe(url);
+
+            results.lock().unwrap()[index] = Some(probe);
+        });
+
+        urls.iter()
+            .zip(results.into_inner().unwrap())
+            .map(|(url, probe)| {
+                let probe = probe.unwrap_or(Err(DownloadError::NetworkError {
+                    reason: "cancelled".to_string(),
+                }));
+
+                (url.as_str().to_string(), probe)
+            })
+            .collect()
+    }
+
+    fn probe(&self, url: &impl IntoDownloadUrl) -> Result<Probe, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        let response = self.head_or_first_byte(&url)?;
+
+        match response.status {
+            200..=299 => Ok(Probe {
+                status: response.status,
+                size: size(&response),
+                mime: response
+                    .header("Content-Type")
+                    .and_then(sniff::normalize_mime),
+            }),
+            404 => Err(DownloadError::NotFound),
+            status => Err(DownloadError::HttpStatus(status)),
+        }
+    }
+
+    pub(crate) fn head_or_first_byte(&self, url: &Url) -> Result<Response, DownloadError> {
+        match self.fetch_head(url, &[])? {
+            response if matches!(response.status, 405 | 501) => {
+                self.fetch(url, &[("Range".to_string(), "bytes=0-0".to_string())])
+            }
+            response => Ok(response),
+        }
+    }
+}
+
+// A ranged answer tells the whole size after the slash of its Content-Range.
+fn size(response: &Response) -> Option<u64> {
+    match response.status {
+        206 => response
+            .header("Content-Range")?
+            .rsplit_once('/')?
+            .1
+            .trim()
+            .parse()
+            .ok(),
+        _ => response.content_length(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::Probe;
+    use crate::downloader::{
+        fetcher::UReqFetcher, testing, DownloadError, DownloaderBuilder, Storage, UrlProblem,
+    };
+
+    // `/logo.png` answers HEAD, `/legacy.pdf` only a ranged GET, anything
+    // else is missing.
+    fn server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = String::new();
+
+                reader.read_line(&mut request).unwrap();
+
+                loop {
+                    let mut line = String::new();
+
+                    reader.read_line(&mut line).unwrap();
+
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+
+                let mut parts = request.split(' ');
+
+                let head = match (parts.next(), parts.next()) {
+                    (Some("HEAD"), Some("/logo.png")) => {
+                        "200 OK\r\nContent-Type: image/png\r\nContent-Length: 2048"
+                    }
+                    (Some("HEAD"), Some("/legacy.pdf")) => {
+                        "405 Method Not Allowed\r\nContent-Length: 0"
+                    }
+                    (Some("GET"), Some("/legacy.pdf")) => {
+                        "206 Partial Content\r\nContent-Type: application/pdf\r\n\
+                         Content-Range: bytes 0-0/5000\r\nContent-Length: 1\r\n\r\n%"
+                    }
+                    _ => "404 Not Found\r\nContent-Length: 0",
+                };
+
+                let (head, body) = head.split_once("\r\n\r\n").unwrap_or((head, ""));
+
+                let _ = write!(stream, "HTTP/1.1 {head}\r\nConnection: close\r\n\r\n{body}");
+            }
+        });
+
+        base
+    }
+
+    #[test]
+    fn test_validate_urls_reports_each_url_in_order() {
+        let base = server();
+
+        let dir = testing::cache_dir("validate_urls");
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, UReqFetcher::new()).build();
+
+        let urls = [
+            format!("{base}/logo.png"),
+            format!("{base}/missing.png"),
+            "not a url".to_string(),
+            format!("{base}/legacy.pdf"),
+        ];
+
+        // Act
+
+        let results = downloader.validate_urls(&urls);
+
+        // Assert
+
+        assert_eq!(
+            results,
+            [
+                (
+                    urls[0].clone(),
+                    Ok(Probe {
+                        status: 200,
+                        size: Some(2048),
+                        mime: Some("image/png".to_string()),
+                    })
+                ),
+                (urls[1].clone(), Err(DownloadError::NotFound)),
+                (
+                    urls[2].clone(),
+                    Err(DownloadError::InvalidUrl(UrlProblem::RelativeUrl))
+                ),
+                (
+                    urls[3].clone(),
+                    Ok(Probe {
+                        status: 206,
+                        size: Some(5000),
+                        mime: Some("application/pdf".to_string()),
+                    })
+                ),
+            ]
+        );
+        assert!(downloader.storage().list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,412 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use url::Url;
+
+use super::{
+    headers, outcome::Outcome, overwrite_policy::OverwritePolicy, parallel, partial::PartialFile,
+    Body, Download, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Response, Storage,
+};
+
+// Each range is attempted this many times before the download fails.
+const PART_ATTEMPTS: u32 = 3;
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Fetches `parts` byte ranges of the body at once into a preallocated
+    // file, then stores that file like `download` would its body. Servers that do not answer
+    // the probe for the first byte with a range get a normal download.
+    pub fn download_parallel_ranges(
+        &self,
+        url: &str,
+        parts: usize,
+    ) -> Result<Download, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        let skipped = self.config.overwrite_policy == OverwritePolicy::Skip
+            && self.cached_entry(&url).is_some();
+
+        if parts <= 1 || self.config.read_only || skipped {
+            return self.download_url(&url);
+        }
+
+        let probe = self.fetch(&url, &[range_header(&(0..1))])?;
+
+        let size = match (probe.status, total_size(&probe)) {
+            (206, Some(size)) if size > 0 => size,
+            (206, _) => return self.download_url(&url),
+            (200..=299, _) => {
+                return self
+                    .store_response(&url, probe, self.config.overwrite_policy)
+                    .map(Outcome::into_download)
+            }
+            (404, _) => return Err(DownloadError::NotFound),
+            (status, _) => return Err(DownloadError::HttpStatus(status)),
+        };
+
+        // The probe's body holds a connection slot until it is dropped, which
+        // the ranges would otherwise wait for under a per-host limit.
+        let Response {
+            headers: probe_headers,
+            redirects,
+            body,
+            ..
+        } = probe;
+
+        drop(body);
+
+        self.check_space(size)?;
+
+        let mut staged =
+            PartialFile::create(self.staging_path(self.entry_key(url.as_str()).as_str()))
+                .map_err(io_error)?;
+
+        staged.set_len(size).map_err(io_error)?;
+
+        // The file has its full length from the start, what arrived is
+        // counted instead.
+        let received = self.fetch_ranges(&url, &staged, split(size, parts))?;
+
+        if received != size {
+            return Err(DownloadError::InvalidBody);
+        }
+
+        let mut headers: Vec<_> = probe_headers
+            .into_iter()
+            .filter(|(name, _)| {
+                !name.eq_ignore_ascii_case("Content-Range")
+                    && !name.eq_ignore_ascii_case("Content-Length")
+            })
+            .collect();
+
+        headers.push(("Content-Length".to_string(), size.to_string()));
+
+        let response = Response {
+            status: 200,
+            headers,
+            body: Body::default(),
+            redirects,
+        };
+
+        self.store_from(&url, response, Some(staged), self.config.overwrite_policy)
+            .map(Outcome::into_download)
+    }
+
+    // How many bytes the ranges brought in together.
+    fn fetch_ranges(
+        &self,
+        url: &Url,
+        staged: &PartialFile,
+        ranges: Vec<Range<u64>>,
+    ) -> Result<u64, DownloadError> {
+        let failure = Mutex::new(None);
+
+        let received = AtomicU64::new(0);
+
+        let token = self.config.cancellation_token.child();
+
+        parallel::for_each(&ranges, ranges.len(), &token, |_, range| {
+            let mut attempts = PART_ATTEMPTS;
+
+            let result = loop {
+                attempts -= 1;
+
+                match self.fetch_range(url, staged, range) {
+                    Err(_) if attempts > 0 => {}
+                    result => break result,
+                }
+            };
+
+            match result {
+                Ok(copied) => {
+                    received.fetch_add(copied, Ordering::SeqCst);
+                }
+                Err(error) => {
+                    failure.lock().unwrap().get_or_insert(error);
+
+                    token.cancel();
+                }
+            }
+        });
+
+        match failure.into_inner().unwrap() {
+            Some(error) => Err(error),
+            None if token.is_cancelled() => Err(DownloadError::InvalidBody),
+            None => Ok(received.into_inner()),
+        }
+    }
+
+    // Every part writes through its own handle, at its own offset.
+    fn fetch_range(
+        &self,
+        url: &Url,
+        staged: &PartialFile,
+        range: &Range<u64>,
+    ) -> Result<u64, DownloadError> {
+        let response = self.fetch(url, &[range_header(range)])?;
+
+        let starts_at_range = headers::find(&response.headers, "Content-Range")
+            .is_some_and(|value| value.starts_with(&format!("bytes {}-", range.start)));
+
+        if response.status != 206 || !starts_at_range {
+            return Err(DownloadError::HttpStatus(response.status));
+        }
+
+        let mut file = File::options()
+            .write(true)
+            .open(staged.path())
+            .map_err(io_error)?;
+
+        file.seek(SeekFrom::Start(range.start)).map_err(io_error)?;
+
+        let length = range.end - range.start;
+
+        let reader: Box<dyn Read> = match response.body {
+            Body::Bytes(bytes) => Box::new(io::Cursor::new(bytes)),
+            Body::Reader(reader) => reader,
+        };
+
+        let copied = io::copy(&mut reader.take(length), &mut file)
+            .map_err(|_| DownloadError::InvalidBody)?;
+
+        if copied != length {
+            return Err(DownloadError::InvalidBody);
+        }
+
+        Ok(copied)
+    }
+}
+
+fn range_header(range: &Range<u64>) -> (String, String) {
+    (
+        "Range".to_string(),
+        format!("bytes={}-{}", range.start, range.end - 1),
+    )
+}
+
+// `Content-Range: bytes 0-0/1234`, unknown when the total is `*`.
+pub(super) fn total_size(response: &Response) -> Option<u64> {
+    let (_, total) = response.header("Content-Range")?.rsplit_once('/')?;
+
+    total.trim().parse().ok()
+}
+
+fn split(size: u64, parts: usize) -> Vec<Range<u64>> {
+    let parts = (parts as u64).clamp(1, size);
+
+    let chunk = size.div_ceil(parts);
+
+    (0..parts)
+        .map(|part| part * chunk..((part + 1) * chunk).min(size))
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+fn io_error(error: io::Error) -> DownloadError {
+    DownloadError::Io(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::split;
+    use crate::downloader::{fetcher::UReqFetcher, testing, DownloaderBuilder, Storage};
+
+    // Serves `body` over HTTP, honouring `Range` when `ranges` is set. The
+    // first request for the range starting at `fail_once_at` gets a 500.
+    struct Server {
+        body: Vec<u8>,
+        ranges: bool,
+        fail_once_at: Mutex<Option<u64>>,
+        requests: Mutex<Vec<Option<String>>>,
+    }
+
+    impl Server {
+        fn start(body: Vec<u8>, ranges: bool, fail_once_at: Option<u64>) -> (Arc<Self>, String) {
+            let server = Arc::new(Self {
+                body,
+                ranges,
+                fail_once_at: Mutex::new(fail_once_at),
+                requests: Mutex::new(Vec::new()),
+            });
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+            let url = format!("http://{}/large.pdf", listener.local_addr().unwrap());
+
+            let handle = Arc::clone(&server);
+
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let server = Arc::clone(&handle);
+
+                    thread::spawn(move || server.respond(stream));
+                }
+            });
+
+            (server, url)
+        }
+
+        fn requests(&self) -> Vec<Option<String>> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn respond(&self, mut stream: TcpStream) {
+            let mut range = None;
+
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            loop {
+                let mut line = String::new();
+
+                reader.read_line(&mut line).unwrap();
+
+                if line.trim().is_empty() {
+                    break;
+                }
+
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                    range = Some(value.trim().to_string());
+                }
+            }
+
+            self.requests.lock().unwrap().push(range.clone());
+
+            let requested = range.filter(|_| self.ranges).map(|range| {
+                let (start, end) = range.split_once('-').unwrap();
+
+                (start.parse::<u64>().unwrap(), end.parse::<u64>().unwrap())
+            });
+
+            let (status, extra, body) = match requested {
+                Some((start, _)) if *self.fail_once_at.lock().unwrap() == Some(start) => {
+                    self.fail_once_at.lock().unwrap().take();
+
+                    ("500 Internal Server Error", String::new(), &[][..])
+                }
+                Some((start, end)) => (
+                    "206 Partial Content",
+                    format!("Content-Range: bytes {start}-{end}/{}\r\n", self.body.len()),
+                    &self.body[start as usize..=end as usize],
+                ),
+                None => ("200 OK", String::new(), &self.body[..]),
+            };
+
+            let head = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/pdf\r\n{extra}\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(body);
+        }
+    }
+
+    fn body() -> Vec<u8> {
+        (0..100_003u32).map(|index| (index % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_ranges_are_stitched_and_failed_parts_retried() {
+        let (server, url) = Server::start(body(), true, Some(25_001));
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("ranges"), UReqFetcher::new())
+                .build();
+
+        // Act
+
+        let download = downloader.download_parallel_ranges(&url, 4).unwrap();
+
+        downloader.flush_maintenance();
+
+        // Assert
+
+        let mut requests = server.requests();
+
+        requests.sort();
+
+        assert_eq!(download.bytes().unwrap(), body());
+        assert_eq!(download.metadata.extension.as_deref(), Some("pdf"));
+        assert_eq!(
+            requests,
+            [
+                "0-0",
+                "0-25000",
+                "25001-50001",
+                "25001-50001",
+                "50002-75002",
+                "75003-100002"
+            ]
+            .map(|range| Some(range.to_string()))
+        );
+        assert_eq!(downloader.storage().list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ranges_fit_in_one_connection_per_host() {
+        let (server, url) = Server::start(body(), true, None);
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("ranges_one_connection"),
+            UReqFetcher::new(),
+        )
+        .max_connections_per_host(1)
+        .build();
+
+        // Act
+
+        let download = downloader.download_parallel_ranges(&url, 3).unwrap();
+
+        // Assert
+
+        assert_eq!(download.bytes().unwrap(), body());
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_servers_without_ranges_get_one_request() {
+        let (server, url) = Server::start(body(), false, None);
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("ranges_unsupported"),
+            UReqFetcher::new(),
+        )
+        .build();
+
+        // Act
+
+        let download = downloader.download_parallel_ranges(&url, 4).unwrap();
+
+        // Assert
+
+        assert_eq!(download.bytes().unwrap(), body());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split(10, 3), vec![0..4, 4..8, 8..10]);
+        assert_eq!(split(2, 4), vec![0..1, 1..2]);
+        assert_eq!(split(7, 1), vec![0..7]);
+    }
+}
@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use url::Url;
+
+use super::{headers, Downloader, FileDownloader, Response, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RateLimitWait {
+    pub max_wait: Duration,
+    pub max_waits: u32,
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // `Retry-After` holds either seconds or an HTTP date. Dates in the past
+    // ask for no wait at all.
+    pub(crate) fn retry_after(&self, response: &Response) -> Option<Duration> {
+        let value = headers::find(&response.headers, "Retry-After")?.trim();
+
+        if let Ok(secs) = value.parse() {
+            return Some(Duration::from_secs(secs));
+        }
+
+        let at = httpdate::parse_http_date(value).ok()?;
+
+        Some(
+            at.duration_since(self.config.clock.now())
+                .unwrap_or_default(),
+        )
+    }
+
+    pub(crate) fn may_wait(&self, wait: Duration, waits: &mut u32) -> bool {
+        let Some(policy) = self.config.rate_limit_wait else {
+            return false;
+        };
+
+        if wait > policy.max_wait || *waits >= policy.max_waits {
+            return false;
+        }
+
+        *waits += 1;
+
+        true
+    }
+
+    // Downloads queued behind this one fail fast until the host's window
+    // passes, rather than each being turned away by the server.
+    pub(crate) fn hold_host(&self, url: &Url, wait: Duration) {
+        let Some(breaker) = &self.circuit_breaker else {
+            return;
+        };
+
+        let until = self.config.clock.now() + wait;
+
+        breaker.open_until(url.host_str().unwrap_or_default(), until);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::downloader::{
+        clock::{Clock, FakeClock},
+        fetcher::MockFetcher,
+        testing, CircuitState, DownloadError, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://origin.example.com/nightly.png";
+
+    fn png() -> Response {
+        Response::ok(b"image".to_vec(), Some("image/png".to_string()))
+    }
+
+    fn unavailable(retry_after: &str) -> Response {
+        Response::new(503).with_header("Retry-After", retry_after)
+    }
+
+    #[test]
+    fn test_short_maintenance_windows_are_waited_out() {
+        let clock = FakeClock::new();
+
+        let start = clock.now();
+
+        let until = httpdate::fmt_http_date(start + Duration::from_secs(30));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("rate_limit_short"),
+            MockFetcher::new(vec![unavailable("20"), unavailable(&until), png()]),
+        )
+        .clock(clock.clone())
+        .wait_on_rate_limit(Duration::from_secs(30), 2)
+        .build();
+
+        // Act
+
+        let download = downloader.download(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(download.bytes().unwrap(), b"image");
+        assert_eq!(downloader.fetcher().calls(), 3);
+
+        // The date is to the second, the fake clock is not.
+        let waited = clock.now().duration_since(start).unwrap();
+
+        assert!(waited > Duration::from_secs(29) && waited <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_waits_past_the_ceiling_or_budget_fail() {
+        let downloader = |name: &str, responses| {
+            DownloaderBuilder::with_fetcher(testing::cache_dir(name), MockFetcher::new(responses))
+                .clock(FakeClock::new())
+                .wait_on_rate_limit(Duration::from_secs(30), 1)
+                .build()
+        };
+
+        let long = downloader("rate_limit_long", vec![unavailable("120")]);
+
+        let budget = downloader(
+            "rate_limit_budget",
+            vec![unavailable("5"), unavailable("5")],
+        );
+
+        let unannounced = downloader("rate_limit_unannounced", vec![Response::new(429)]);
+
+        let unavailable = downloader("rate_limit_unavailable", vec![Response::new(503)]);
+
+        // Act
+
+        let long_error = long.download(URL).unwrap_err();
+
+        let budget_error = budget.download(URL).unwrap_err();
+
+        let unannounced_error = unannounced.download(URL).unwrap_err();
+
+        let unavailable_error = unavailable.download(URL).unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            long_error,
+            DownloadError::RateLimited {
+                retry_after: Some(Duration::from_secs(120))
+            }
+        );
+        assert_eq!(long.fetcher().calls(), 1);
+        assert_eq!(
+            budget_error.last_error(),
+            &DownloadError::RateLimited {
+                retry_after: Some(Duration::from_secs(5))
+            }
+        );
+        assert_eq!(budget_error.attempts().len(), 2);
+        assert_eq!(
+            unannounced_error,
+            DownloadError::RateLimited { retry_after: None }
+        );
+        assert_eq!(unavailable_error, DownloadError::HttpStatus(503));
+    }
+
+    #[test]
+    fn test_long_windows_open_the_circuit_until_they_pass() {
+        let clock = FakeClock::new();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("rate_limit_circuit"),
+            MockFetcher::new(vec![unavailable("120"), png()]),
+        )
+        .clock(clock.clone())
+        .circuit_breaker(5, Duration::from_secs(10))
+        .wait_on_rate_limit(Duration::from_secs(30), 2)
+        .build();
+
+        let reopens = clock.now() + Duration::from_secs(120);
+
+        // Act
+
+        let limited = downloader.download(URL).unwrap_err();
+
+        let queued = downloader.download(URL).unwrap_err();
+
+        clock.advance(Duration::from_secs(120));
+
+        let after = downloader.download(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(limited.code(), "rate_limited");
+        assert_eq!(
+            queued,
+            DownloadError::CircuitOpen {
+                host: "origin.example.com".to_string(),
+                retry_at: reopens,
+            }
+        );
+        assert_eq!(after.bytes().unwrap(), b"image");
+        assert_eq!(downloader.fetcher().calls(), 2);
+        assert_eq!(
+            downloader.circuit_state("origin.example.com"),
+            CircuitState::Closed
+        );
+    }
+}
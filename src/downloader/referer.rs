@@ -0,0 +1,287 @@
+use url::Url;
+
+use super::{Downloader, FileDownloader, Storage};
+
+// Where `Referer` and `Origin` come from, for hosts that refuse requests
+// without a plausible one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefererPolicy {
+    Fixed(String),
+    // The origin of the URL being fetched, `https://cdn.example.com/` as a
+    // `Referer` and without the slash as an `Origin`.
+    SameOrigin,
+}
+
+impl From<&str> for RefererPolicy {
+    fn from(value: &str) -> Self {
+        Self::Fixed(value.to_string())
+    }
+}
+
+impl From<String> for RefererPolicy {
+    fn from(value: String) -> Self {
+        Self::Fixed(value)
+    }
+}
+
+impl RefererPolicy {
+    fn value(&self, url: &Url, trailing_slash: bool) -> Option<String> {
+        match self {
+            Self::Fixed(value) => Some(value.clone()),
+            // Opaque origins, such as `data:` URLs', cannot be sent.
+            Self::SameOrigin => {
+                let origin = url.origin();
+
+                origin.is_tuple().then(|| {
+                    let origin = origin.ascii_serialization();
+
+                    match trailing_slash {
+                        true => format!("{origin}/"),
+                        false => origin,
+                    }
+                })
+            }
+        }
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // `Referer` and `Origin` for `url`, which headers set by name override.
+    // The override of the longest domain `url`'s host ends in wins.
+    pub(crate) fn referer_headers(&self, url: &Url) -> Vec<(String, String)> {
+        let host = url.host_str().unwrap_or_default().trim_end_matches('.');
+
+        let referer = self
+            .config
+            .referer_overrides
+            .iter()
+            .filter(|(domain, _)| is_within(host, domain))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, policy)| policy)
+            .or(self.config.referer.as_ref());
+
+        let mut headers = Vec::new();
+
+        if let Some(referer) = referer.and_then(|policy| policy.value(url, true)) {
+            headers.push(("Referer".to_string(), referer));
+        }
+
+        if let Some(origin) = self
+            .config
+            .origin
+            .as_ref()
+            .and_then(|policy| policy.value(url, false))
+        {
+            headers.push(("Origin".to_string(), origin));
+        }
+
+        headers
+    }
+}
+
+// `cdn.example.com` is within `example.com`, `badexample.com` is not.
+fn is_within(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use url::Url;
+
+    use super::RefererPolicy;
+    use crate::downloader::{
+        fetcher::MockFetcher, headers, testing, Downloader, DownloaderBuilder, FetchError,
+        FileDownloader, Follow, Response,
+    };
+
+    // Redirects once to `hop`, recording the headers of both requests.
+    struct Redirecting {
+        hop: Url,
+        sent: Mutex<Vec<Vec<(String, String)>>>,
+    }
+
+    impl FileDownloader for Redirecting {
+        fn fetch(&self, url: &str, headers: &[(String, String)]) -> Result<Response, FetchError> {
+            self.fetch_following(url, headers, None, &|_| Ok(headers.to_vec()))
+        }
+
+        fn fetch_following(
+            &self,
+            _url: &str,
+            headers: &[(String, String)],
+            _timeout: Option<Duration>,
+            follow: &Follow<'_>,
+        ) -> Result<Response, FetchError> {
+            let hop = follow(&self.hop)?;
+
+            self.sent.lock().unwrap().extend([headers.to_vec(), hop]);
+
+            let mut response = Response::ok(b"image".to_vec(), Some("image/png".to_string()));
+
+            response.redirects = vec![(302, self.hop.to_string())];
+
+            Ok(response)
+        }
+    }
+
+    fn downloader(
+        name: &str,
+        configure: impl FnOnce(DownloaderBuilder<MockFetcher>) -> DownloaderBuilder<MockFetcher>,
+    ) -> Downloader<MockFetcher> {
+        let responses = (0..4)
+            .map(|_| Response::ok(b"image".to_vec(), Some("image/png".to_string())))
+            .collect();
+
+        configure(DownloaderBuilder::with_fetcher(
+            testing::cache_dir(name),
+            MockFetcher::new(responses),
+        ))
+        .build()
+    }
+
+    fn sent(downloader: &Downloader<MockFetcher>, call: usize, name: &str) -> Option<String> {
+        headers::find(&downloader.fetcher().request_headers(call), name).map(str::to_string)
+    }
+
+    #[test]
+    fn test_same_origin_is_derived_from_the_target() {
+        let downloader = downloader("referer_same_origin", |builder| {
+            builder
+                .with_referer(RefererPolicy::SameOrigin)
+                .with_origin(RefererPolicy::SameOrigin)
+        });
+
+        // Act
+
+        downloader
+            .download("https://cdn.example.com:8443/images/logo.png?size=2")
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(
+            sent(&downloader, 0, "Referer").as_deref(),
+            Some("https://cdn.example.com:8443/")
+        );
+        assert_eq!(
+            sent(&downloader, 0, "Origin").as_deref(),
+            Some("https://cdn.example.com:8443")
+        );
+    }
+
+    #[test]
+    fn test_host_overrides_match_by_suffix() {
+        let downloader = downloader("referer_overrides", |builder| {
+            builder
+                .with_referer("https://www.example.org/")
+                .referer_for_host("social.example", RefererPolicy::SameOrigin)
+                .referer_for_host("cdn.social.example", "https://social.example/feed")
+                .header("X-Unrelated", "1")
+        });
+
+        let urls = [
+            "https://social.example/a.png",
+            "https://img.cdn.social.example/b.png",
+            "https://notsocial.example/c.png",
+        ];
+
+        // Act
+
+        for url in urls {
+            downloader.download(url).unwrap();
+        }
+
+        // Assert
+
+        let referers: Vec<_> = (0..urls.len())
+            .map(|call| sent(&downloader, call, "Referer"))
+            .collect();
+
+        assert_eq!(
+            referers,
+            [
+                Some("https://social.example/".to_string()),
+                Some("https://social.example/feed".to_string()),
+                Some("https://www.example.org/".to_string()),
+            ]
+        );
+        assert_eq!(sent(&downloader, 0, "Origin"), None);
+    }
+
+    #[test]
+    fn test_headers_set_by_name_take_precedence() {
+        let downloader = downloader("referer_header_wins", |builder| {
+            builder
+                .with_referer(RefererPolicy::SameOrigin)
+                .header("referer", "https://partner.example/")
+        });
+
+        // Act
+
+        downloader
+            .download("https://cdn.example.com/a.png")
+            .unwrap();
+
+        // Assert
+
+        let referers: Vec<_> = downloader
+            .fetcher()
+            .request_headers(0)
+            .into_iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Referer"))
+            .map(|(_, value)| value)
+            .collect();
+
+        assert_eq!(referers, ["https://partner.example/"]);
+    }
+
+    #[test]
+    fn test_referers_are_worked_out_again_for_every_hop() {
+        let fetcher = Redirecting {
+            hop: Url::parse("https://img.partner.example/a.png").unwrap(),
+            sent: Mutex::default(),
+        };
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("referer_per_hop"), fetcher)
+                .with_referer(RefererPolicy::SameOrigin)
+                .with_origin(RefererPolicy::SameOrigin)
+                .header("X-Client", "1")
+                .build();
+
+        // Act
+
+        downloader
+            .download("https://cdn.example.com/a.png")
+            .unwrap();
+
+        // Assert
+
+        let sent = downloader.fetcher().sent.lock().unwrap();
+
+        let find = |hop: usize, name| headers::find(&sent[hop], name).map(str::to_string);
+
+        assert_eq!(
+            find(0, "Referer").as_deref(),
+            Some("https://cdn.example.com/")
+        );
+        assert_eq!(
+            find(1, "Referer").as_deref(),
+            Some("https://img.partner.example/")
+        );
+        assert_eq!(
+            find(1, "Origin").as_deref(),
+            Some("https://img.partner.example")
+        );
+        assert_eq!(find(1, "X-Client").as_deref(), Some("1"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use url::Url;
+
+use super::{Downloader, FileDownloader, Outcome, OverwritePolicy, Storage};
+
+// Background worker owned by a `Downloader` that re-fetches stale entries.
+// Requests for a URL already queued or in flight are coalesced.
+pub(crate) struct Refresher {
+    sender: Option<Sender<Url>>,
+    in_flight: Arc<Mutex<HashSet<String>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Refresher {
+    pub fn spawn<T: FileDownloader, S: Storage>(downloader: Downloader<T, S>) -> Self {
+        let (sender, receiver) = mpsc::channel::<Url>();
+
+        let in_flight = Arc::new(Mutex::new(HashSet::new()));
+
+        let pending = Arc::clone(&in_flight);
+
+        let worker = thread::spawn(move || {
+            for url in receiver {
+                let cached = downloader.cached_entry(&url);
+
+                // Refreshing an entry always replaces it, whatever the policy for
+                // new downloads is.
+                let result = downloader
+                    .fetch_and_store(
+                        &url,
+                        cached.as_ref(),
+                        OverwritePolicy::Overwrite,
+                        downloader.accept(),
+                    )
+                    .map(Outcome::into_download);
+
+                pending.lock().unwrap().remove(url.as_str());
+
+                if let Some(observer) = &downloader.config.observer {
+                    observer.on_refresh(url.as_str(), &result);
+                }
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            in_flight,
+            worker: Some(worker),
+        }
+    }
+
+    pub fn schedule(&self, url: &Url) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+
+        if !in_flight.insert(url.to_string()) {
+            return;
+        }
+
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(url.clone()).is_ok());
+
+        if !sent {
+            in_flight.remove(url.as_str());
+        }
+    }
+}
+
+impl Drop for Refresher {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::mpsc::{self, Sender},
+        time::{Duration, Instant},
+    };
+
+    use crate::downloader::{
+        clock::FakeClock, fetcher::MockFetcher, testing, CachePolicy, Download, DownloadError,
+        DownloaderBuilder, Observer, Response,
+    };
+
+    struct RefreshListener(Sender<String>);
+
+    impl Observer for RefreshListener {
+        fn on_refresh(&self, url: &str, _result: &Result<Download, DownloadError>) {
+            self.0.send(url.to_string()).unwrap();
+        }
+    }
+
+    fn png_response(body: &str) -> Response {
+        Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()))
+    }
+
+    #[test]
+    fn test_stale_entry_is_served_while_refreshing() {
+        let url = "https://example.com/logo.png";
+
+        let clock = FakeClock::new();
+
+        let delay = Duration::from_millis(300);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let fetcher =
+            MockFetcher::new(vec![png_response("stale"), png_response("fresh")]).with_delay(delay);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("swr"), fetcher)
+            .clock(clock.clone())
+            .cache_policy(CachePolicy::StaleWhileRevalidate)
+            .ttl(Duration::from_secs(60))
+            .observer(RefreshListener(sender))
+            .build();
+
+        let first = downloader.download(url).unwrap();
+
+        clock.advance(Duration::from_secs(120));
+
+        // Act
+
+        let started = Instant::now();
+
+        let stale = downloader.download(url).unwrap();
+
+        let elapsed = started.elapsed();
+
+        let stale_content = fs::read(&stale.file).unwrap();
+
+        let refreshed = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // Assert
+
+        assert!(elapsed < delay);
+        assert_eq!(stale, first);
+        assert_eq!(stale_content, b"stale");
+
+        assert_eq!(refreshed, url);
+        assert_eq!(fs::read(&stale.file).unwrap(), b"fresh");
+        let next = downloader.download(url).unwrap();
+
+        assert_eq!(next.file, first.file);
+        assert_eq!(next.metadata.size, Some(5));
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_refreshes_are_coalesced() {
+        let url = "https://example.com/icon.png";
+
+        let clock = FakeClock::new();
+
+        let (sender, receiver) = mpsc::channel();
+
+        let fetcher = MockFetcher::new(vec![
+            png_response("v1"),
+            png_response("v2"),
+            png_response("v3"),
+        ])
+        .with_delay(Duration::from_millis(100));
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("swr_coalesced"), fetcher)
+                .clock(clock.clone())
+                .cache_policy(CachePolicy::StaleWhileRevalidate)
+                .ttl(Duration::from_secs(60))
+                .observer(RefreshListener(sender))
+                .build();
+
+        downloader.download(url).unwrap();
+
+        clock.advance(Duration::from_secs(120));
+
+        // Act
+
+        for _ in 0..5 {
+            downloader.download(url).unwrap();
+        }
+
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // Assert
+
+        assert!(receiver.recv_timeout(Duration::from_millis(300)).is_err());
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+}
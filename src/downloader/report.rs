@@ -0,0 +1,154 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{BatchOptions, BatchResult, Downloader, FileDownloader, Storage};
+
+// One NDJSON line of a failure report. Fields added later must be optional
+// so older reports still load, and unknown ones are ignored.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Failure {
+    url: String,
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    attempts: u32,
+}
+
+impl BatchResult {
+    // One line per failed URL, repeated URLs included, in `results` order.
+    pub fn write_failures(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut report = String::new();
+
+        for (index, result) in &self.results {
+            let (Err(error), Some((url, attempts))) = (result, self.sources.get(*index)) else {
+                continue;
+            };
+
+            let failure = Failure {
+                url: url.clone(),
+                error: format!("{error:?}"),
+                attempts: *attempts,
+            };
+
+            report.push_str(&serde_json::to_string(&failure)?);
+            report.push('\n');
+        }
+
+        fs::write(path, report)
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Attempts again every URL of a report written by `write_failures`.
+    pub fn retry_from_report(&self, path: impl AsRef<Path>) -> io::Result<BatchResult> {
+        let failures = read_failures(&fs::read_to_string(path)?)?;
+
+        let urls: Vec<_> = failures
+            .iter()
+            .map(|failure| failure.url.as_str())
+            .collect();
+
+        let mut batch = self.download_all(&urls, BatchOptions::default());
+
+        for ((_, attempts), failure) in batch.sources.iter_mut().zip(&failures) {
+            *attempts += failure.attempts;
+        }
+
+        Ok(batch)
+    }
+}
+
+fn read_failures(report: &str) -> io::Result<Vec<Failure>> {
+    report
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{read_failures, Failure};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, BatchOptions, DownloadError, DownloaderBuilder, Response,
+    };
+
+    #[test]
+    fn test_report_tolerates_blank_lines_and_unknown_fields() {
+        let report = "\n{\"url\":\"https://example.com/a.png\",\"error\":\"NotFound\",\"attempts\":2,\"status\":404}\n  \n{\"url\":\"https://example.com/b.png\"}\n";
+
+        // Act
+
+        let failures = read_failures(report).unwrap();
+
+        // Assert
+
+        assert_eq!(
+            failures,
+            [
+                Failure {
+                    url: "https://example.com/a.png".to_string(),
+                    error: "NotFound".to_string(),
+                    attempts: 2,
+                },
+                Failure {
+                    url: "https://example.com/b.png".to_string(),
+                    error: String::new(),
+                    attempts: 0,
+                },
+            ]
+        );
+        assert!(read_failures("not json").is_err());
+    }
+
+    #[test]
+    fn test_failed_batch_is_replayed_from_its_report() {
+        let dir = testing::cache_dir("report_replay");
+
+        let ok = || Response::ok(b"image".to_vec(), Some("image/png".to_string()));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![
+                ok(),
+                Response::not_found(),
+                Response::new(500),
+                ok(),
+                Response::new(500),
+            ]),
+        )
+        .max_concurrency(1)
+        .build();
+
+        let urls = ["a", "b", "c"].map(|name| format!("https://example.com/{name}.png"));
+
+        let report = dir.join("failures.ndjson");
+
+        downloader
+            .download_all(&urls, BatchOptions::default())
+            .write_failures(&report)
+            .unwrap();
+
+        // Act
+
+        let retried = downloader.retry_from_report(&report).unwrap();
+
+        retried.write_failures(&report).unwrap();
+
+        // Assert
+
+        assert!(retried.results[0].1.is_ok());
+        assert_eq!(retried.results[1].1, Err(DownloadError::HttpStatus(500)));
+        assert_eq!(
+            fs::read_to_string(&report).unwrap(),
+            "{\"url\":\"https://example.com/c.png\",\"error\":\"HttpStatus(500)\",\"attempts\":2}\n"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use super::{sidecar, sniff, tee::SNIFF_LIMIT, Downloader, FileDownloader, Storage};
+
+// What `rescan_unknown` did with the entries stored as `.dat`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RescanReport {
+    // Renamed after the type their content is now recognized as.
+    pub fixed: usize,
+    pub unknown: usize,
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Sniffs the entries stored as `.dat` again, so entries saved before a
+    // signature was known get their extension. Files are renamed in place and
+    // their manifest entries remember the old name, see `relocated`. Entries
+    // of storages off the local filesystem cannot be renamed and stay as
+    // they are.
+    pub fn rescan_unknown(&self) -> io::Result<RescanReport> {
+        if self.config.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "a read-only cache cannot be rescanned",
+            ));
+        }
+
+        let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for (key, entry) in self.manifest.entries() {
+            if entry.file.ends_with(".dat") {
+                files.entry(entry.file).or_default().push(key);
+            }
+        }
+
+        let mut report = RescanReport::default();
+
+        for (name, keys) in files {
+            let Some(file) = self.storage.path(&name).filter(|file| file.exists()) else {
+                continue;
+            };
+
+            match self.rename_recognized(&name, &file)? {
+                Some((renamed, mime)) => {
+                    for key in keys {
+                        let Some(mut entry) = self.manifest.get(&key) else {
+                            continue;
+                        };
+
+                        entry.renamed_from.push(name.clone());
+                        entry.file = renamed.clone();
+                        entry.mime = Some(mime.to_string());
+
+                        self.manifest.insert(&key, entry);
+                    }
+
+                    report.fixed += 1;
+                }
+                None => report.unknown += 1,
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Where a file `rescan_unknown` renamed is now.
+    pub fn relocated(&self, previous: impl AsRef<Path>) -> Option<PathBuf> {
+        let name = self.storage_name(previous.as_ref());
+
+        self.manifest
+            .entries()
+            .into_iter()
+            .find(|(_, entry)| entry.renamed_from.contains(&name))
+            .map(|(_, entry)| self.locate(&entry.file))
+    }
+
+    // The new name and MIME type, or `None` when the content is still not
+    // recognized or another file already has the name.
+    fn rename_recognized(
+        &self,
+        name: &str,
+        file: &Path,
+    ) -> io::Result<Option<(String, &'static str)>> {
+        let mut head = Vec::with_capacity(SNIFF_LIMIT);
+
+        fs::File::open(file)?
+            .take(SNIFF_LIMIT as u64)
+            .read_to_end(&mut head)?;
+
+        let (Some(extension), Some(mime)) = (
+            sniff::extension_from_magic(&head),
+            sniff::mime_from_magic(&head),
+        ) else {
+            return Ok(None);
+        };
+
+        let renamed = format!("{}.{extension}", name.strip_suffix(".dat").unwrap_or(name));
+
+        let target = self.locate(&renamed);
+
+        if target.exists() {
+            return Ok(None);
+        }
+
+        fs::rename(file, &target)?;
+
+        let sidecar = sidecar::sidecar_path(file);
+
+        if sidecar.exists() {
+            fs::rename(sidecar, sidecar::sidecar_path(&target))?;
+        }
+
+        Ok(Some((renamed, mime)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::RescanReport;
+    use crate::downloader::{
+        fetcher::MockFetcher, fixtures, sidecar, testing, CachePolicy, DownloaderBuilder, Response,
+    };
+
+    #[test]
+    fn test_dat_entries_are_renamed_once_recognized() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("rescan_unknown"),
+            MockFetcher::new(vec![
+                Response::ok(b"not known yet".to_vec(), None),
+                Response::ok(b"never known".to_vec(), None),
+            ]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .write_sidecars(true)
+        .build();
+
+        let logo = downloader.download("https://example.com/logo").unwrap();
+
+        let other = downloader.download("https://example.com/other").unwrap();
+
+        // As if the signature had been unknown when the body was stored.
+        fs::write(&logo.file, fixtures::PNG).unwrap();
+
+        // Act
+
+        let report = downloader.rescan_unknown().unwrap();
+
+        let again = downloader.rescan_unknown().unwrap();
+
+        // Assert
+
+        let renamed = logo.file.with_extension("png");
+
+        assert_eq!(
+            report,
+            RescanReport {
+                fixed: 1,
+                unknown: 1
+            }
+        );
+        assert_eq!(
+            again,
+            RescanReport {
+                fixed: 0,
+                unknown: 1
+            }
+        );
+        assert!(!logo.file.exists());
+        assert_eq!(fs::read(&renamed).unwrap(), fixtures::PNG);
+        assert!(sidecar::sidecar_path(&renamed).exists());
+        assert_eq!(downloader.relocated(&logo.file), Some(renamed.clone()));
+        assert_eq!(downloader.relocated(&other.file), None);
+        assert!(other.file.exists());
+
+        let hit = downloader.download("https://example.com/logo").unwrap();
+
+        assert_eq!(hit.file, renamed);
+        assert_eq!(hit.metadata.mime.as_deref(), Some("image/png"));
+        assert_eq!(downloader.fetcher().calls(), 2);
+    }
+
+    #[test]
+    fn test_read_only_caches_are_not_rescanned() {
+        let dir = testing::cache_dir("rescan_read_only");
+
+        let writer = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![Response::ok(b"not known yet".to_vec(), None)]),
+        )
+        .build();
+
+        let logo = writer.download("https://example.com/logo").unwrap();
+
+        writer.flush_maintenance();
+
+        fs::write(&logo.file, fixtures::PNG).unwrap();
+
+        let reader = DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![]))
+            .read_only(true)
+            .build();
+
+        // Act
+
+        let error = reader.rescan_unknown().unwrap_err();
+
+        // Assert
+
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(logo.file.exists());
+        assert_eq!(reader.relocated(&logo.file), None);
+    }
+}
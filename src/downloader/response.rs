@@ -0,0 +1,143 @@
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use super::headers;
+
+// Upper bound for files pre-sized from a server supplied Content-Length, so a
+// bogus header cannot make us allocate gigabytes up front.
+pub(crate) const MAX_PREALLOCATION: u64 = 64 * 1024 * 1024;
+
+pub enum Body {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>),
+}
+
+impl Body {
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Bytes(bytes) => Ok(bytes),
+
+            Self::Reader(mut reader) => {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Self::Reader(_) => f.debug_tuple("Reader").finish(),
+        }
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Self::Bytes(Vec::new())
+    }
+}
+
+// A raw HTTP exchange as seen by a fetcher. Deciding what a status means
+// (not found, not modified, error page...) is left to the `Downloader`.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+    // Every redirect followed to get here, as its status and the URL it led
+    // to. The last URL is the one that answered.
+    pub redirects: Vec<(u16, String)>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Body::default(),
+            redirects: Vec::new(),
+        }
+    }
+
+    pub fn ok(body: Vec<u8>, mime: Option<String>) -> Self {
+        let response = Self::new(200).with_body(body);
+
+        match mime {
+            Some(mime) => response.with_header("Content-Type", &mime),
+            None => response,
+        }
+    }
+
+    pub fn not_modified() -> Self {
+        Self::new(304)
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(404)
+    }
+
+    // A successful status whose body fails while being read.
+    pub fn invalid_body() -> Self {
+        Self::truncated(Vec::new(), io::ErrorKind::UnexpectedEof)
+    }
+
+    // A successful status whose body delivers `received` and then fails.
+    pub fn truncated(received: Vec<u8>, kind: io::ErrorKind) -> Self {
+        Self::new(200).with_reader(FailingReader {
+            received: io::Cursor::new(received),
+            kind,
+        })
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: Vec<u8>) -> Self {
+        self.body = Body::Bytes(body);
+        self
+    }
+
+    pub fn with_redirect(mut self, status: u16, url: &str) -> Self {
+        self.redirects.push((status, url.to_string()));
+        self
+    }
+
+    pub fn with_reader(mut self, reader: impl Read + Send + 'static) -> Self {
+        self.body = Body::Reader(Box::new(reader));
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        headers::find(&self.headers, name)
+    }
+
+    pub fn mime(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("Content-Length")?.trim().parse().ok()
+    }
+}
+
+struct FailingReader {
+    received: io::Cursor<Vec<u8>>,
+    kind: io::ErrorKind,
+}
+
+impl Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.received.read(buf)? {
+            0 => Err(io::Error::new(self.kind, "body read failed")),
+            read => Ok(read),
+        }
+    }
+}
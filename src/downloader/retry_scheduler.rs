@@ -0,0 +1,487 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use url::Url;
+
+use super::{
+    cancel::CANCEL_CHECK, clock::Clock, CancellationToken, Downloader, FileDownloader, Storage,
+};
+
+// Why a retry stopped waiting for its turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GaveUp {
+    Deadline,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RetryThrottle {
+    pub min_interval: Duration,
+    pub max_concurrent: usize,
+}
+
+#[derive(Debug, Default)]
+struct HostRetries {
+    // Tickets are served in the order they were taken.
+    issued: u64,
+    serving: u64,
+    running: usize,
+    last_started: Option<SystemTime>,
+    // Tickets given up on before their turn, skipped when it comes.
+    abandoned: Vec<u64>,
+}
+
+impl HostRetries {
+    fn serve_next(&mut self) {
+        self.serving += 1;
+
+        while let Some(index) = self.abandoned.iter().position(|&t| t == self.serving) {
+            self.abandoned.swap_remove(index);
+            self.serving += 1;
+        }
+    }
+
+    // Nothing runs or waits, and the next retry would not have to wait for
+    // the interval either, so forgetting the host changes nothing.
+    fn is_idle(&self, now: SystemTime, min_interval: Duration) -> bool {
+        self.running == 0
+            && self.issued == self.serving
+            && self
+                .last_started
+                .is_none_or(|last| last + min_interval <= now)
+    }
+
+    fn abandon(&mut self, ticket: u64) {
+        match ticket == self.serving {
+            true => self.serve_next(),
+            false => self.abandoned.push(ticket),
+        }
+    }
+}
+
+// Lines up the retries of every download sharing a `Downloader`, per host,
+// so a flaky host sees them trickle in rather than in waves. First attempts
+// never wait here.
+#[derive(Debug)]
+pub(crate) struct RetryScheduler {
+    throttle: RetryThrottle,
+    hosts: Mutex<HashMap<String, HostRetries>>,
+    changed: Condvar,
+}
+
+impl RetryScheduler {
+    pub fn new(throttle: RetryThrottle) -> Self {
+        Self {
+            throttle: RetryThrottle {
+                max_concurrent: throttle.max_concurrent.max(1),
+                ..throttle
+            },
+            hosts: Mutex::new(HashMap::new()),
+            changed: Condvar::new(),
+        }
+    }
+
+    // Blocks until it is the retry's turn, a slot is free and the interval
+    // since the host's last retry started has passed. Returns how long that
+    // took by `clock`, or why the retry was given up on: waiting took longer
+    // than `within`, or `token` was cancelled.
+    pub fn acquire(
+        self: &Arc<Self>,
+        host: &str,
+        clock: &dyn Clock,
+        within: Option<Duration>,
+        token: &CancellationToken,
+    ) -> Result<(RetrySlot, Duration), GaveUp> {
+        let queued_at = clock.now();
+
+        let started = Instant::now();
+
+        let mut hosts = self.hosts.lock().unwrap();
+
+        let min_interval = self.throttle.min_interval;
+
+        hosts.retain(|_, retries| !retries.is_idle(queued_at, min_interval));
+
+        let ticket = {
+            let retries = hosts.entry(host.to_string()).or_default();
+
+            retries.issued += 1;
+            retries.issued - 1
+        };
+
+        loop {
+            let now = clock.now();
+
+            // A fake clock stands still while the condvar waits, so the
+            // longer of the two counts.
+            let waited = now
+                .duration_since(queued_at)
+                .unwrap_or_default()
+                .max(started.elapsed());
+
+            let left = within.map(|within| within.saturating_sub(waited));
+
+            let gave_up = match token.is_cancelled() {
+                true => Some(GaveUp::Cancelled),
+                false => (left == Some(Duration::ZERO)).then_some(GaveUp::Deadline),
+            };
+
+            let retries = hosts.get_mut(host).unwrap();
+
+            if let Some(gave_up) = gave_up {
+                retries.abandon(ticket);
+
+                self.changed.notify_all();
+
+                return Err(gave_up);
+            }
+
+            let slice = left.map_or(CANCEL_CHECK, |left| left.min(CANCEL_CHECK));
+
+            if retries.serving != ticket || retries.running >= self.throttle.max_concurrent {
+                hosts = self.changed.wait_timeout(hosts, slice).unwrap().0;
+
+                continue;
+            }
+
+            let due = retries
+                .last_started
+                .map(|last| last + min_interval)
+                .filter(|due| *due > now);
+
+            if let Some(due) = due {
+                // Later tickets keep waiting for this one meanwhile.
+                drop(hosts);
+
+                clock.sleep(due.duration_since(now).unwrap_or_default().min(slice));
+
+                hosts = self.hosts.lock().unwrap();
+
+                continue;
+            }
+
+            retries.serve_next();
+            retries.running += 1;
+            retries.last_started = Some(now);
+
+            self.changed.notify_all();
+
+            let waited = now.duration_since(queued_at).unwrap_or_default();
+
+            return Ok((
+                RetrySlot {
+                    scheduler: Arc::clone(self),
+                    host: host.to_string(),
+                },
+                waited,
+            ));
+        }
+    }
+
+    fn release(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if let Some(retries) = hosts.get_mut(host) {
+            retries.running -= 1;
+        }
+
+        self.changed.notify_all();
+    }
+}
+
+// Counts against the host's concurrent retries until dropped.
+pub(crate) struct RetrySlot {
+    scheduler: Arc<RetryScheduler>,
+    host: String,
+}
+
+impl Drop for RetrySlot {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.host);
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Waits for the retry's turn when retries are throttled, and tells the
+    // observer about it. `attempt` counts from 1, the first attempt.
+    pub(crate) fn schedule_retry(
+        &self,
+        url: &Url,
+        attempt: u32,
+    ) -> Result<Option<RetrySlot>, GaveUp> {
+        let (slot, waited) = match &self.retry_scheduler {
+            Some(scheduler) => {
+                let host = url.host_str().unwrap_or_default();
+
+                let (slot, waited) = scheduler.acquire(
+                    host,
+                    &*self.config.clock,
+                    self.remaining_budget(),
+                    &self.config.cancellation_token,
+                )?;
+
+                (Some(slot), waited)
+            }
+            None => (None, Duration::ZERO),
+        };
+
+        if let Some(observer) = &self.config.observer {
+            observer.on_retry(url.as_str(), attempt, waited);
+        }
+
+        Ok(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{GaveUp, RetryScheduler, RetryThrottle};
+    use crate::downloader::{
+        clock::{Clock, FakeClock, SystemClock},
+        fetcher::MockFetcher,
+        testing, CancellationToken, DownloaderBuilder, Observer, Response,
+    };
+
+    type Retry = (String, u32, Duration, SystemTime);
+
+    // The URL, attempt and wait of every retry, and when it started.
+    #[derive(Clone)]
+    struct Retries {
+        clock: FakeClock,
+        seen: Arc<Mutex<Vec<Retry>>>,
+    }
+
+    impl Observer for Retries {
+        fn on_retry(&self, url: &str, attempt: u32, waited: Duration) {
+            self.seen
+                .lock()
+                .unwrap()
+                .push((url.to_string(), attempt, waited, self.clock.now()));
+        }
+    }
+
+    #[test]
+    fn test_retries_to_a_host_are_spaced() {
+        let clock = FakeClock::new();
+
+        let retries = Retries {
+            clock: clock.clone(),
+            seen: Arc::default(),
+        };
+
+        let png = || Response::ok(b"image".to_vec(), Some("image/png".to_string()));
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("retry_scheduler_spaced"),
+            MockFetcher::new(vec![
+                Response::invalid_body(),
+                png(),
+                Response::invalid_body(),
+                Response::invalid_body(),
+                png(),
+                Response::invalid_body(),
+                png(),
+            ]),
+        )
+        .clock(clock.clone())
+        .observer(retries.clone())
+        .throttle_retries(Duration::from_secs(10), 1)
+        .build();
+
+        let start = clock.now();
+
+        let urls = [
+            "https://flaky.example.com/a.png",
+            "https://flaky.example.com/b.png",
+            "https://other.example.com/c.png",
+        ];
+
+        // Act
+
+        for url in urls {
+            downloader.download(url).unwrap();
+        }
+
+        // Assert
+
+        let seen: Vec<_> = retries
+            .seen
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, attempt, waited, at)| {
+                (
+                    url.clone(),
+                    *attempt,
+                    waited.as_secs(),
+                    at.duration_since(start).unwrap().as_secs(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            seen,
+            [
+                (urls[0].to_string(), 2, 0, 0),
+                (urls[1].to_string(), 2, 10, 10),
+                (urls[1].to_string(), 3, 10, 20),
+                (urls[2].to_string(), 2, 0, 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_waiting_retries_start_in_turn() {
+        let scheduler = Arc::new(RetryScheduler::new(RetryThrottle {
+            min_interval: Duration::ZERO,
+            max_concurrent: 1,
+        }));
+
+        let started = Arc::new(Mutex::new(Vec::new()));
+
+        let (running, _) = scheduler
+            .acquire("example.com", &SystemClock, None, &CancellationToken::new())
+            .unwrap();
+
+        // Act
+
+        let waiting: Vec<_> = ["second", "third"]
+            .into_iter()
+            .map(|name| {
+                let issued = || scheduler.hosts.lock().unwrap()["example.com"].issued;
+
+                let queued = issued() + 1;
+
+                let waiter = {
+                    let (scheduler, started) = (Arc::clone(&scheduler), Arc::clone(&started));
+
+                    thread::spawn(move || {
+                        let _slot = scheduler.acquire(
+                            "example.com",
+                            &SystemClock,
+                            None,
+                            &CancellationToken::new(),
+                        );
+
+                        started.lock().unwrap().push(name);
+                    })
+                };
+
+                while issued() < queued {
+                    thread::yield_now();
+                }
+
+                waiter
+            })
+            .collect();
+
+        let while_running = started.lock().unwrap().clone();
+
+        drop(running);
+
+        for waiter in waiting {
+            waiter.join().unwrap();
+        }
+
+        // Assert
+
+        assert!(while_running.is_empty());
+        assert_eq!(*started.lock().unwrap(), ["second", "third"]);
+    }
+
+    #[test]
+    fn test_retries_given_up_on_lose_their_turn() {
+        let scheduler = Arc::new(RetryScheduler::new(RetryThrottle {
+            min_interval: Duration::ZERO,
+            max_concurrent: 1,
+        }));
+
+        let token = CancellationToken::new();
+
+        let acquire = |within, token: &CancellationToken| {
+            scheduler
+                .acquire("example.com", &SystemClock, within, token)
+                .map(|_| ())
+        };
+
+        let running = scheduler.acquire("example.com", &SystemClock, None, &token);
+
+        let cancelled = {
+            let (scheduler, token) = (Arc::clone(&scheduler), token.child());
+
+            let waiter = thread::spawn({
+                let token = token.clone();
+
+                move || {
+                    scheduler
+                        .acquire("example.com", &SystemClock, None, &token)
+                        .map(|_| ())
+                }
+            });
+
+            thread::sleep(Duration::from_millis(20));
+
+            token.cancel();
+
+            waiter
+        };
+
+        // Act
+
+        let timed_out = acquire(Some(Duration::from_millis(20)), &token);
+
+        let cancelled = cancelled.join().unwrap();
+
+        drop(running);
+
+        let next = acquire(Some(Duration::from_secs(1)), &token);
+
+        // Assert
+
+        assert_eq!(timed_out, Err(GaveUp::Deadline));
+        assert_eq!(cancelled, Err(GaveUp::Cancelled));
+        assert_eq!(next, Ok(()));
+    }
+
+    #[test]
+    fn test_idle_hosts_are_forgotten() {
+        let scheduler = Arc::new(RetryScheduler::new(RetryThrottle {
+            min_interval: Duration::from_secs(10),
+            max_concurrent: 1,
+        }));
+
+        let clock = FakeClock::new();
+
+        let token = CancellationToken::new();
+
+        for host in ["a.example", "b.example"] {
+            drop(scheduler.acquire(host, &clock, None, &token));
+        }
+
+        // Act
+
+        clock.advance(Duration::from_secs(10));
+
+        let running = scheduler.acquire("c.example", &clock, None, &token);
+
+        // Assert
+
+        let hosts: Vec<_> = scheduler.hosts.lock().unwrap().keys().cloned().collect();
+
+        assert!(running.is_ok());
+        assert_eq!(hosts, ["c.example"]);
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    io::{self, Read},
+    time::SystemTime,
+};
+
+use super::{
+    sign::{self, Target},
+    S3Client, S3Config,
+};
+
+// Talks to the S3 REST API directly, signing every request with SigV4.
+pub struct UreqS3Client {
+    config: S3Config,
+    agent: ureq::Agent,
+}
+
+impl UreqS3Client {
+    pub fn new(config: S3Config) -> Self {
+        Self {
+            config,
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<ureq::Response> {
+        let target = Target::new(&self.config, key)?;
+
+        let headers = sign::sign(
+            &self.config,
+            method,
+            &target,
+            query,
+            body,
+            SystemTime::now(),
+        );
+
+        let request = headers.iter().fold(
+            self.agent
+                .request(method, &target.url(&sign::canonical_query(query))),
+            |request, (name, value)| request.set(name, value),
+        );
+
+        match request.send_bytes(body) {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(404, _)) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{key} not found"),
+            )),
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response.into_string().unwrap_or_default();
+
+                Err(io::Error::other(format!(
+                    "S3 {method} {key} failed with {status}: {message}"
+                )))
+            }
+            Err(error) => Err(io::Error::other(error)),
+        }
+    }
+}
+
+impl S3Client for UreqS3Client {
+    fn put_object(&self, key: &str, body: &[u8]) -> io::Result<()> {
+        self.send("PUT", key, &[], body).map(|_| ())
+    }
+
+    fn create_multipart_upload(&self, key: &str) -> io::Result<String> {
+        let response = self.send("POST", key, &[("uploads", "")], &[])?;
+
+        let xml = response.into_string()?;
+
+        tag_values(&xml, "UploadId")
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::other("S3 returned no upload id"))
+    }
+
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        body: &[u8],
+    ) -> io::Result<String> {
+        let part_number = part_number.to_string();
+
+        let response = self.send(
+            "PUT",
+            key,
+            &[("partNumber", &part_number), ("uploadId", upload_id)],
+            body,
+        )?;
+
+        response
+            .header("ETag")
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::other("S3 returned no ETag for the part"))
+    }
+
+    fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> io::Result<()> {
+        let parts: String = etags
+            .iter()
+            .enumerate()
+            .map(|(index, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    index + 1,
+                    escape(etag)
+                )
+            })
+            .collect();
+
+        let body = format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>");
+
+        let response = self.send("POST", key, &[("uploadId", upload_id)], body.as_bytes())?;
+
+        // Failures found after the upload are reported with a 200 status.
+        let xml = response.into_string()?;
+
+        match tag_values(&xml, "Message").into_iter().next() {
+            Some(message) if xml.contains("<Error>") => Err(io::Error::other(message)),
+            _ => Ok(()),
+        }
+    }
+
+    fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> io::Result<()> {
+        self.send("DELETE", key, &[("uploadId", upload_id)], &[])
+            .map(|_| ())
+    }
+
+    fn head_object(&self, key: &str) -> io::Result<bool> {
+        match self.send("HEAD", key, &[], &[]) {
+            Ok(_) => Ok(true),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn get_object(&self, key: &str) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(self.send("GET", key, &[], &[])?.into_reader()))
+    }
+
+    fn delete_object(&self, key: &str) -> io::Result<()> {
+        self.send("DELETE", key, &[], &[]).map(|_| ())
+    }
+
+    fn list_objects(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+
+            let xml = self.send("GET", "", &query, &[])?.into_string()?;
+
+            keys.extend(tag_values(&xml, "Key"));
+
+            token = tag_values(&xml, "NextContinuationToken").into_iter().next();
+
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+}
+
+// Enough XML for the few flat values the S3 API answers with.
+fn tag_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+
+    let mut values = Vec::new();
+
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+
+        values.push(unescape(&rest[..end]));
+
+        rest = &rest[end + close.len()..];
+    }
+
+    values
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tag_values;
+
+    #[test]
+    fn test_tag_values() {
+        let xml = "<ListBucketResult><Contents><Key>cache/a&amp;b.png</Key></Contents>\
+                   <Contents><Key>cache/c.png</Key></Contents>\
+                   <NextContinuationToken>token</NextContinuationToken></ListBucketResult>";
+
+        // Act
+
+        let keys = tag_values(xml, "Key");
+
+        // Assert
+
+        assert_eq!(keys, vec!["cache/a&b.png", "cache/c.png"]);
+        assert_eq!(tag_values(xml, "NextContinuationToken"), vec!["token"]);
+        assert!(tag_values(xml, "UploadId").is_empty());
+    }
+}
@@ -0,0 +1,466 @@
+mod client;
+mod sign;
+
+use std::{
+    io::{self, Read},
+    time::{Duration, SystemTime},
+};
+
+use super::storage::{Storage, StoredFile};
+
+pub use client::UreqS3Client;
+
+// S3 refuses multipart uploads with parts under 5 MiB, except the last one.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    // Prepended to every object name, `cache/` stores `cache/<hash>.<ext>`.
+    pub prefix: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub virtual_hosted: bool,
+    // Bodies larger than one part are uploaded in parts of this size.
+    pub part_size: usize,
+    pub presign_expiry: Duration,
+}
+
+impl S3Config {
+    pub fn new(
+        endpoint: impl Into<String>,
+        bucket: impl Into<String>,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            region: "us-east-1".to_string(),
+            bucket: bucket.into(),
+            prefix: String::new(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            virtual_hosted: false,
+            part_size: 8 * 1024 * 1024,
+            presign_expiry: Duration::from_secs(3600),
+        }
+    }
+}
+
+// The S3 operations the storage needs, so it can run against a fake bucket.
+pub trait S3Client: Send + Sync + 'static {
+    fn put_object(&self, key: &str, body: &[u8]) -> io::Result<()>;
+
+    fn create_multipart_upload(&self, key: &str) -> io::Result<String>;
+
+    // Returns the ETag of the part.
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        body: &[u8],
+    ) -> io::Result<String>;
+
+    fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> io::Result<()>;
+
+    fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> io::Result<()>;
+
+    fn head_object(&self, key: &str) -> io::Result<bool>;
+
+    fn get_object(&self, key: &str) -> io::Result<Box<dyn Read + Send>>;
+
+    fn delete_object(&self, key: &str) -> io::Result<()>;
+
+    fn list_objects(&self, prefix: &str) -> io::Result<Vec<String>>;
+}
+
+pub struct S3Storage<C: S3Client = UreqS3Client> {
+    config: S3Config,
+    client: C,
+}
+
+impl S3Storage {
+    pub fn new(config: S3Config) -> Self {
+        Self::with_client(config.clone(), UreqS3Client::new(config))
+    }
+}
+
+impl<C: S3Client> S3Storage<C> {
+    pub fn with_client(mut config: S3Config, client: C) -> Self {
+        config.part_size = config.part_size.max(MIN_PART_SIZE);
+
+        Self { config, client }
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    pub fn key(&self, name: &str) -> String {
+        match self.config.prefix.trim_end_matches('/') {
+            "" => name.to_string(),
+            prefix => format!("{prefix}/{name}"),
+        }
+    }
+
+    pub fn presigned_url(&self, name: &str, expires: Duration) -> io::Result<String> {
+        let target = sign::Target::new(&self.config, &self.key(name))?;
+
+        Ok(sign::presign(
+            &self.config,
+            &target,
+            expires,
+            SystemTime::now(),
+        ))
+    }
+
+    fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut part: Vec<u8>,
+        reader: &mut dyn Read,
+    ) -> io::Result<u64> {
+        let mut etags = Vec::new();
+
+        let mut size = 0;
+
+        while !part.is_empty() {
+            let part_number = etags.len() as u32 + 1;
+
+            etags.push(
+                self.client
+                    .upload_part(key, upload_id, part_number, &part)?,
+            );
+
+            size += part.len() as u64;
+
+            part = read_part(reader, self.config.part_size)?;
+        }
+
+        self.client
+            .complete_multipart_upload(key, upload_id, &etags)?;
+
+        Ok(size)
+    }
+}
+
+impl<C: S3Client> Storage for S3Storage<C> {
+    // Holds at most one part in memory, small bodies go up in a single
+    // request.
+    fn put(&self, name: &str, reader: &mut dyn Read) -> io::Result<StoredFile> {
+        let key = self.key(name);
+
+        let first = read_part(reader, self.config.part_size)?;
+
+        let size = if first.len() < self.config.part_size {
+            self.client.put_object(&key, &first)?;
+
+            first.len() as u64
+        } else {
+            let upload_id = self.client.create_multipart_upload(&key)?;
+
+            match self.upload_parts(&key, &upload_id, first, reader) {
+                Ok(size) => size,
+                Err(error) => {
+                    let _ = self.client.abort_multipart_upload(&key, &upload_id);
+
+                    return Err(error);
+                }
+            }
+        };
+
+        Ok(StoredFile {
+            name: name.to_string(),
+            size,
+        })
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.client.head_object(&self.key(name)).unwrap_or(false)
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+        self.client.get_object(&self.key(name))
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.client.delete_object(&self.key(name))
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let prefix = self.key("");
+
+        let keys = self.client.list_objects(&prefix)?;
+
+        Ok(keys
+            .iter()
+            .filter_map(|key| key.strip_prefix(&prefix))
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn remote_url(&self, name: &str) -> Option<String> {
+        self.presigned_url(name, self.config.presign_expiry).ok()
+    }
+}
+
+// Fills a part completely unless the body ends first.
+fn read_part(reader: &mut dyn Read, size: usize) -> io::Result<Vec<u8>> {
+    let mut part = Vec::with_capacity(size);
+
+    reader.take(size as u64).read_to_end(&mut part)?;
+
+    Ok(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{self, Cursor, Read},
+        sync::Mutex,
+    };
+
+    use super::{S3Client, S3Config, S3Storage, MIN_PART_SIZE};
+    use crate::downloader::{
+        fetcher::MockFetcher, storage::Storage, testing, DownloaderBuilder, Response,
+    };
+
+    #[derive(Default)]
+    struct FakeBucket {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+        uploads: Mutex<HashMap<String, Vec<Vec<u8>>>>,
+        calls: Mutex<Vec<String>>,
+        fail_part: Option<u32>,
+    }
+
+    impl FakeBucket {
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        fn record(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    impl S3Client for FakeBucket {
+        fn put_object(&self, key: &str, body: &[u8]) -> io::Result<()> {
+            self.record(format!("put {key}"));
+
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), body.to_vec());
+
+            Ok(())
+        }
+
+        fn create_multipart_upload(&self, key: &str) -> io::Result<String> {
+            self.record(format!("create {key}"));
+
+            self.uploads
+                .lock()
+                .unwrap()
+                .insert("upload".to_string(), Vec::new());
+
+            Ok("upload".to_string())
+        }
+
+        fn upload_part(
+            &self,
+            _key: &str,
+            upload_id: &str,
+            part_number: u32,
+            body: &[u8],
+        ) -> io::Result<String> {
+            self.record(format!("part {part_number} {}", body.len()));
+
+            if self.fail_part == Some(part_number) {
+                return Err(io::Error::other("connection reset"));
+            }
+
+            self.uploads
+                .lock()
+                .unwrap()
+                .get_mut(upload_id)
+                .unwrap()
+                .push(body.to_vec());
+
+            Ok(format!("\"etag-{part_number}\""))
+        }
+
+        fn complete_multipart_upload(
+            &self,
+            key: &str,
+            upload_id: &str,
+            etags: &[String],
+        ) -> io::Result<()> {
+            self.record(format!("complete {key} {}", etags.len()));
+
+            let parts = self.uploads.lock().unwrap().remove(upload_id).unwrap();
+
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), parts.concat());
+
+            Ok(())
+        }
+
+        fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> io::Result<()> {
+            self.record(format!("abort {key}"));
+
+            self.uploads.lock().unwrap().remove(upload_id);
+
+            Ok(())
+        }
+
+        fn head_object(&self, key: &str) -> io::Result<bool> {
+            Ok(self.objects.lock().unwrap().contains_key(key))
+        }
+
+        fn get_object(&self, key: &str) -> io::Result<Box<dyn Read + Send>> {
+            let objects = self.objects.lock().unwrap();
+
+            let object = objects
+                .get(key)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+            Ok(Box::new(Cursor::new(object.clone())))
+        }
+
+        fn delete_object(&self, key: &str) -> io::Result<()> {
+            self.objects.lock().unwrap().remove(key);
+
+            Ok(())
+        }
+
+        fn list_objects(&self, prefix: &str) -> io::Result<Vec<String>> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn storage(bucket: FakeBucket) -> S3Storage<FakeBucket> {
+        let config = S3Config {
+            prefix: "cache/".to_string(),
+            part_size: MIN_PART_SIZE,
+            ..S3Config::new("http://localhost:9000", "downloads", "key", "secret")
+        };
+
+        S3Storage::with_client(config, bucket)
+    }
+
+    #[test]
+    fn test_large_bodies_are_uploaded_in_parts() {
+        let storage = storage(FakeBucket::default());
+
+        let body = vec![7; MIN_PART_SIZE * 2 + 10];
+
+        // Act
+
+        let small = storage.put("small.txt", &mut &b"tiny"[..]).unwrap();
+
+        let large = storage.put("large.bin", &mut Cursor::new(&body)).unwrap();
+
+        // Assert
+
+        let mut stored = Vec::new();
+
+        storage
+            .open("large.bin")
+            .unwrap()
+            .read_to_end(&mut stored)
+            .unwrap();
+
+        assert_eq!(small.size, 4);
+        assert_eq!(large.size, body.len() as u64);
+        assert_eq!(stored, body);
+        assert_eq!(
+            storage.client().calls(),
+            vec![
+                "put cache/small.txt".to_string(),
+                "create cache/large.bin".to_string(),
+                format!("part 1 {MIN_PART_SIZE}"),
+                format!("part 2 {MIN_PART_SIZE}"),
+                "part 3 10".to_string(),
+                "complete cache/large.bin 3".to_string(),
+            ]
+        );
+        assert_eq!(
+            storage.list().unwrap().len(),
+            2,
+            "names are listed without the prefix"
+        );
+    }
+
+    #[test]
+    fn test_failed_part_aborts_the_upload() {
+        let storage = storage(FakeBucket {
+            fail_part: Some(2),
+            ..Default::default()
+        });
+
+        let body = vec![7; MIN_PART_SIZE * 2];
+
+        // Act
+
+        let error = storage
+            .put("large.bin", &mut Cursor::new(&body))
+            .unwrap_err();
+
+        // Assert
+
+        assert_eq!(error.to_string(), "connection reset");
+        assert_eq!(
+            storage.client().calls().last().unwrap(),
+            "abort cache/large.bin"
+        );
+        assert!(!storage.exists("large.bin"));
+    }
+
+    #[test]
+    fn test_downloads_land_in_the_bucket() {
+        let url = "https://example.com/logo.png";
+
+        let fetcher = MockFetcher::new(vec![Response::ok(
+            b"png".to_vec(),
+            Some("image/png".to_string()),
+        )]);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("s3_storage"), fetcher)
+            .storage(storage(FakeBucket::default()))
+            .build();
+
+        // Act
+
+        let download = downloader.download(url).unwrap();
+
+        // Assert
+
+        let name = download.file.to_str().unwrap();
+
+        let remote_url = download.remote_url.clone().unwrap();
+
+        assert_eq!(downloader.storage().list().unwrap(), vec![name.to_string()]);
+        assert!(remote_url.starts_with(&format!("http://localhost:9000/downloads/cache/{name}?")));
+        assert!(remote_url.contains("X-Amz-Signature="));
+    }
+}
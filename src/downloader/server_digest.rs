@@ -0,0 +1,245 @@
+use std::io::{self, Read, Write};
+
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+
+use super::{
+    cache::StoreError,
+    data_uri, headers,
+    partial::PartialFile,
+    tee::{self, BodySummary},
+    DownloadError,
+};
+
+// Weakest first, so the strongest digest a server sends is the greatest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Algorithm {
+    Md5,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn named(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "md5" => Some(Self::Md5),
+            "sha-256" => Some(Self::Sha256),
+            "sha-512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Self::Md5 => 16,
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+
+    // Streams `body` through the hasher rather than holding it whole.
+    fn hash(self, body: &mut impl Read) -> io::Result<Vec<u8>> {
+        fn digest<D: Digest + Write>(body: &mut impl Read, mut hasher: D) -> io::Result<Vec<u8>> {
+            io::copy(body, &mut hasher)?;
+
+            Ok(hasher.finalize().to_vec())
+        }
+
+        match self {
+            Self::Md5 => digest(body, Md5::new()),
+            Self::Sha256 => digest(body, Sha256::new()),
+            Self::Sha512 => digest(body, Sha512::new()),
+        }
+    }
+}
+
+// A digest of the body the server announced.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ServerDigest {
+    algorithm: Algorithm,
+    expected: Vec<u8>,
+}
+
+impl ServerDigest {
+    // The strongest of `Digest` (RFC 3230) and `Content-MD5` that can be
+    // checked. Unknown algorithms and malformed values are skipped.
+    pub fn from_headers(headers: &[(String, String)]) -> Option<Self> {
+        let digests = headers::find(headers, "Digest")
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .filter_map(|item| {
+                let (name, value) = item.split_once('=')?;
+
+                Some((Algorithm::named(name)?, value))
+            });
+
+        let content_md5 =
+            headers::find(headers, "Content-MD5").map(|value| (Algorithm::Md5, value));
+
+        digests
+            .chain(content_md5)
+            .filter_map(|(algorithm, value)| {
+                let expected = data_uri::decode(value.trim())?;
+
+                (expected.len() == algorithm.len()).then_some(Self {
+                    algorithm,
+                    expected,
+                })
+            })
+            .max_by_key(|digest| digest.algorithm)
+    }
+
+    // SHA-256 was computed while the body streamed in; the others take
+    // another pass over the staged file.
+    pub fn check(
+        &self,
+        summary: &BodySummary,
+        partial: &mut PartialFile,
+    ) -> Result<(), StoreError> {
+        let expected = tee::hex(&self.expected);
+
+        let actual = match self.algorithm {
+            Algorithm::Sha256 => summary.sha256.clone(),
+            algorithm => {
+                partial.rewind().map_err(StoreError::Write)?;
+
+                tee::hex(&algorithm.hash(partial).map_err(StoreError::Write)?)
+            }
+        };
+
+        if actual != expected {
+            return Err(StoreError::Rejected(DownloadError::ChecksumMismatch {
+                expected,
+                actual,
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, ServerDigest};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, DownloaderBuilder, Response,
+    };
+
+    const BODY: &[u8] = b"body";
+
+    // base64 of the digests of `BODY`.
+    const MD5: &str = "hBotaJrYa9FhFEdFPCLG/A==";
+    const SHA256: &str = "Iw2DWNyOiJC0xY3utikS7i8gNXrpKlzIYbmOaP4xrLU=";
+    const SHA512: &str =
+        "VRDrvaXtTaAHxVpi/XB1xyLsAx8HOY7z6QubUOD+lQmFR2xHRBTSs4bo8IzVBftQa1KABqMKv+nKDrC2e352Cw==";
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_the_strongest_known_digest_is_picked() {
+        let digest = |pairs: &[(&str, &str)]| {
+            ServerDigest::from_headers(&headers(pairs)).map(|digest| digest.algorithm)
+        };
+
+        // Assert
+
+        assert_eq!(
+            digest(&[
+                ("Digest", &format!("unixsum=30637, SHA-256={SHA256}")),
+                ("Content-MD5", MD5),
+            ]),
+            Some(Algorithm::Sha256)
+        );
+        assert_eq!(digest(&[("content-md5", MD5)]), Some(Algorithm::Md5));
+        assert_eq!(digest(&[("Digest", "crc32c=abc, sha-256=@@@")]), None);
+        assert_eq!(digest(&[("Digest", "sha-512=aGVsbG8=")]), None, "too short");
+    }
+
+    #[test]
+    fn test_bodies_are_checked_against_server_digests() {
+        let response =
+            |name: &str, value: &str| Response::ok(BODY.to_vec(), None).with_header(name, value);
+
+        let downloader = |name: &str, response| {
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir(name),
+                MockFetcher::new(vec![response]),
+            )
+            .verify_server_digests(true)
+            .build()
+        };
+
+        let correct = downloader(
+            "server_digest_correct",
+            response("Digest", &format!("sha-256={SHA256}")),
+        );
+
+        let incorrect = downloader(
+            "server_digest_incorrect",
+            response("Content-MD5", "Iw2DWNyOiJC0xY3utikS7g=="),
+        );
+
+        let unknown = downloader("server_digest_unknown", response("Digest", "unixsum=30637"));
+
+        let unchecked = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("server_digest_off"),
+            MockFetcher::new(vec![response("Content-MD5", "AAAAAAAAAAAAAAAAAAAAAA==")]),
+        )
+        .build();
+
+        let url = "https://example.com/file";
+
+        // Act
+
+        let correct = correct.download(url).unwrap();
+
+        let incorrect = incorrect.download(url).unwrap_err();
+
+        let unknown = unknown.download(url).unwrap();
+
+        let unchecked = unchecked.download(url).unwrap();
+
+        // Assert
+
+        assert_eq!(correct.bytes().unwrap(), BODY);
+        assert_eq!(
+            incorrect,
+            DownloadError::ChecksumMismatch {
+                expected: "230d8358dc8e8890b4c58deeb62912ee".to_string(),
+                actual: "841a2d689ad86bd1611447453c22c6fc".to_string(),
+            }
+        );
+        assert_eq!(unknown.bytes().unwrap(), BODY);
+        assert_eq!(unchecked.bytes().unwrap(), BODY);
+    }
+
+    #[test]
+    fn test_digests_other_than_sha256_are_checked_from_the_staged_file() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("server_digest_staged"),
+            MockFetcher::new(vec![
+                Response::ok(BODY.to_vec(), None).with_header("Content-MD5", MD5),
+                Response::ok(BODY.to_vec(), None)
+                    .with_header("Digest", &format!("sha-512={SHA512}")),
+            ]),
+        )
+        .verify_server_digests(true)
+        .build();
+
+        // Act
+
+        let md5 = downloader.download("https://example.com/md5").unwrap();
+
+        let sha512 = downloader.download("https://example.com/sha512").unwrap();
+
+        // Assert
+
+        assert_eq!(md5.bytes().unwrap(), BODY);
+        assert_eq!(sha512.bytes().unwrap(), BODY);
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use url::Url;
+
+use super::{Download, DownloadError, Downloader, FileDownloader, IntoDownloadUrl};
+
+type FlightResult = Result<Download, DownloadError>;
+
+// One download of a URL that later callers wait for instead of starting
+// their own. Blocking callers wait on the condvar, async ones are woken.
+#[derive(Default)]
+struct Flight {
+    state: Mutex<(Option<FlightResult>, Vec<Waker>)>,
+    landed: Condvar,
+}
+
+impl Flight {
+    fn land(&self, result: FlightResult) {
+        let mut state = self.state.lock().unwrap();
+
+        state.0 = Some(result);
+
+        for waker in state.1.drain(..) {
+            waker.wake();
+        }
+
+        self.landed.notify_all();
+    }
+
+    fn wait(&self) -> FlightResult {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(result) = &state.0 {
+                return result.clone();
+            }
+
+            state = self.landed.wait(state).unwrap();
+        }
+    }
+}
+
+// The flights waiting for a worker, and how many workers run them.
+#[derive(Default)]
+struct Pool {
+    queued: VecDeque<(Url, String, Arc<Flight>)>,
+    workers: usize,
+}
+
+struct Shared<T: FileDownloader> {
+    downloader: Downloader<T>,
+    in_flight: Mutex<HashMap<String, Arc<Flight>>>,
+    pool: Mutex<Pool>,
+}
+
+// Lands the flight however flying it ends, a panicking download included,
+// so that its waiters are not left waiting. `key` is the one it was joined
+// under, which learning a `Vary` while flying can change.
+struct Landing<'a, T: FileDownloader> {
+    shared: &'a Shared<T>,
+    key: &'a str,
+    flight: &'a Flight,
+    result: Option<FlightResult>,
+}
+
+impl<T: FileDownloader> Drop for Landing<'_, T> {
+    fn drop(&mut self) {
+        self.shared.in_flight.lock().unwrap().remove(self.key);
+
+        let result = self
+            .result
+            .take()
+            .unwrap_or_else(|| Err(DownloadError::Io("the download panicked".to_string())));
+
+        self.flight.land(result);
+    }
+}
+
+impl<T: FileDownloader> Shared<T> {
+    // The flight for the URL, and the key it is joined under when the caller
+    // has to fly it.
+    fn join(&self, url: &Url) -> (Arc<Flight>, Option<String>) {
+        let key = self.downloader.entry_key(url.as_str()).as_str().to_string();
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+
+        if let Some(flight) = in_flight.get(&key) {
+            return (Arc::clone(flight), None);
+        }
+
+        let flight = Arc::new(Flight::default());
+
+        in_flight.insert(key.clone(), Arc::clone(&flight));
+
+        (flight, Some(key))
+    }
+
+    fn fly(&self, url: &Url, key: &str, flight: &Flight) {
+        let mut landing = Landing {
+            shared: self,
+            key,
+            flight,
+            result: None,
+        };
+
+        landing.result = Some(self.downloader.download_url(url));
+    }
+
+    // Queues the flight for one of at most `max_concurrency` workers, which
+    // exit once nothing is left to fly.
+    fn dispatch(self: &Arc<Self>, url: Url, key: String, flight: Arc<Flight>) {
+        let mut pool = self.pool.lock().unwrap();
+
+        pool.queued.push_back((url, key, flight));
+
+        if pool.workers >= self.downloader.config.max_concurrency {
+            return;
+        }
+
+        pool.workers += 1;
+
+        let shared = Arc::clone(self);
+
+        thread::spawn(move || loop {
+            // Leaving under the lock that found the queue empty, or a flight
+            // queued meanwhile would find no worker.
+            let next = {
+                let mut pool = shared.pool.lock().unwrap();
+
+                let next = pool.queued.pop_front();
+
+                if next.is_none() {
+                    pool.workers -= 1;
+                }
+
+                next
+            };
+
+            let Some((url, key, flight)) = next else {
+                break;
+            };
+
+            // The landing already told the waiters, the worker carries on.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| shared.fly(&url, &key, &flight)));
+        });
+    }
+}
+
+// One `Downloader` for blocking and async call sites alike, so both share
+// its cache, naming and limits. A URL asked for from either side while it
+// is already downloading waits for that download instead of racing it.
+// There is no async fetcher: async calls run the blocking one on up to
+// `max_concurrency` threads of its own, so they never block the executor.
+pub struct SharedDownloader<T: FileDownloader> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: FileDownloader> Clone for SharedDownloader<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T: FileDownloader> SharedDownloader<T> {
+    pub fn new(downloader: Downloader<T>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                downloader,
+                in_flight: Mutex::new(HashMap::new()),
+                pool: Mutex::default(),
+            }),
+        }
+    }
+
+    // For everything besides downloading, which is not coalesced.
+    pub fn downloader(&self) -> &Downloader<T> {
+        &self.shared.downloader
+    }
+
+    pub fn download(&self, url: impl IntoDownloadUrl) -> Result<Download, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        let (flight, leader) = self.shared.join(&url);
+
+        if let Some(key) = leader {
+            self.shared.fly(&url, &key, &flight);
+        }
+
+        flight.wait()
+    }
+
+    pub fn download_async(&self, url: impl IntoDownloadUrl) -> PendingDownload {
+        let flight = match url.to_download_url() {
+            Ok(url) => {
+                let url = url.into_owned();
+
+                let (flight, leader) = self.shared.join(&url);
+
+                if let Some(key) = leader {
+                    self.shared.dispatch(url, key, Arc::clone(&flight));
+                }
+
+                flight
+            }
+            Err(error) => {
+                let flight = Flight::default();
+
+                flight.land(Err(DownloadError::InvalidUrl(error.into())));
+
+                Arc::new(flight)
+            }
+        };
+
+        PendingDownload(flight)
+    }
+}
+
+// Resolves once the download `download_async` joined or started lands.
+pub struct PendingDownload(Arc<Flight>);
+
+impl Future for PendingDownload {
+    type Output = FlightResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.state.lock().unwrap();
+
+        match &state.0 {
+            Some(result) => Poll::Ready(result.clone()),
+            None => {
+                if !state.1.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    state.1.push(cx.waker().clone());
+                }
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::SharedDownloader;
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, CachePolicy, DownloadError, DownloaderBuilder, FetchError,
+        FileDownloader, Response, UrlProblem,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    // Panics on the first fetch, once the second caller has joined it.
+    struct PanicsOnce(AtomicUsize);
+
+    impl FileDownloader for PanicsOnce {
+        fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                thread::sleep(Duration::from_millis(200));
+
+                panic!("fetcher bug");
+            }
+
+            Ok(Response::ok(b"logo".to_vec(), None))
+        }
+    }
+
+    // Counts how many fetches run at once.
+    #[derive(Default)]
+    struct Overlapping {
+        running: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    impl FileDownloader for Overlapping {
+        fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+
+            self.most.fetch_max(running, Ordering::SeqCst);
+
+            thread::sleep(Duration::from_millis(50));
+
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(Response::ok(b"logo".to_vec(), None))
+        }
+    }
+
+    #[test]
+    fn test_blocking_and_async_callers_share_one_download() {
+        let png = Response::ok(b"logo".to_vec(), Some("image/png".to_string()));
+
+        let shared = SharedDownloader::new(
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir("shared_coalesced"),
+                MockFetcher::new(vec![png]).with_delay(Duration::from_millis(200)),
+            )
+            .build(),
+        );
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap();
+
+        // Act
+
+        let task = runtime.spawn(shared.download_async(URL));
+
+        let blocking = {
+            let shared = shared.clone();
+
+            thread::spawn(move || shared.download(URL))
+        };
+
+        let from_task = runtime.block_on(task).unwrap().unwrap();
+
+        let from_thread = blocking.join().unwrap().unwrap();
+
+        // Assert
+
+        assert_eq!(from_task, from_thread);
+        assert_eq!(from_task.bytes().unwrap(), b"logo");
+        assert_eq!(shared.downloader().fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_finished_downloads_are_not_coalesced() {
+        let png = |body: &str| Response::ok(body.as_bytes().to_vec(), None);
+
+        let shared = SharedDownloader::new(
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir("shared_sequential"),
+                MockFetcher::new(vec![png("v1"), png("v2")]),
+            )
+            .cache_policy(CachePolicy::NetworkOnly)
+            .build(),
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        // Act
+
+        let first = shared.download(URL).unwrap().bytes().unwrap();
+
+        let second = runtime
+            .block_on(shared.download_async(URL))
+            .unwrap()
+            .bytes()
+            .unwrap();
+
+        let invalid = runtime.block_on(shared.download_async("logo.png"));
+
+        // Assert
+
+        assert_eq!(first, b"v1");
+        assert_eq!(second, b"v2");
+        assert_eq!(
+            invalid,
+            Err(DownloadError::InvalidUrl(UrlProblem::RelativeUrl))
+        );
+        assert_eq!(shared.downloader().fetcher().calls(), 2);
+    }
+
+    #[test]
+    fn test_flights_that_learn_a_vary_are_cleared() {
+        let png = |body: &str| {
+            Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()))
+                .with_header("Vary", "Accept")
+        };
+
+        let shared = SharedDownloader::new(
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir("shared_learn_vary"),
+                MockFetcher::new(vec![png("v1"), png("v2")]),
+            )
+            .header("Accept", "image/webp")
+            .learn_vary(true)
+            .cache_policy(CachePolicy::NetworkOnly)
+            .build(),
+        );
+
+        // Act
+
+        let first = shared.download(URL).unwrap().bytes().unwrap();
+
+        let second = shared.download(URL).unwrap().bytes().unwrap();
+
+        // Assert
+
+        assert_eq!(first, b"v1");
+        assert_eq!(second, b"v2");
+        assert!(shared.shared.in_flight.lock().unwrap().is_empty());
+        assert_eq!(shared.downloader().fetcher().calls(), 2);
+    }
+
+    #[test]
+    fn test_a_panicking_download_lands_for_its_waiters() {
+        let shared = SharedDownloader::new(
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir("shared_panic"),
+                PanicsOnce(AtomicUsize::new(0)),
+            )
+            .build(),
+        );
+
+        let leader = {
+            let shared = shared.clone();
+
+            thread::spawn(move || shared.download(URL))
+        };
+
+        thread::sleep(Duration::from_millis(50));
+
+        // Act
+
+        let waited = shared.download(URL);
+
+        let panicked = leader.join();
+
+        let again = shared.download(URL).unwrap().bytes().unwrap();
+
+        // Assert
+
+        assert!(panicked.is_err());
+        assert_eq!(
+            waited,
+            Err(DownloadError::Io("the download panicked".to_string()))
+        );
+        assert_eq!(again, b"logo");
+    }
+
+    #[test]
+    fn test_async_downloads_run_on_at_most_max_concurrency_threads() {
+        let shared = SharedDownloader::new(
+            DownloaderBuilder::with_fetcher(
+                testing::cache_dir("shared_pool"),
+                Overlapping::default(),
+            )
+            .max_concurrency(2)
+            .build(),
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let pending: Vec<_> = (0..6)
+            .map(|index| shared.download_async(format!("https://example.com/{index}.png")))
+            .collect();
+
+        // Act
+
+        let results: Vec<_> = pending
+            .into_iter()
+            .map(|pending| runtime.block_on(pending))
+            .collect();
+
+        // Assert
+
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(shared.downloader().fetcher().most.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{partial, Download};
+
+pub(crate) const SIDECAR_SUFFIX: &str = ".meta.json";
+
+// Written next to a data file when sidecars are enabled, so the cache
+// directory describes itself even without the manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sidecar {
+    pub source: String,
+    // Seconds since the unix epoch.
+    pub fetched_at: u64,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub mime: Option<String>,
+}
+
+impl Download {
+    pub fn load_sidecar(&self) -> io::Result<Sidecar> {
+        read(&self.file)
+    }
+}
+
+pub(crate) fn read(file: &Path) -> io::Result<Sidecar> {
+    let content = fs::read(sidecar_path(file))?;
+
+    Ok(serde_json::from_slice(&content)?)
+}
+
+pub(crate) fn sidecar_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(SIDECAR_SUFFIX);
+
+    PathBuf::from(path)
+}
+
+pub(crate) fn write(file: &Path, sidecar: &Sidecar) -> io::Result<()> {
+    let content = serde_json::to_vec_pretty(sidecar)?;
+
+    let path = sidecar_path(file);
+
+    let partial = partial::staging_path(&path);
+
+    partial::recreating_parent(&partial, || fs::write(&partial, &content))?;
+
+    fs::rename(&partial, &path)
+}
+
+// Sidecars are optional, a missing one is not an error.
+pub(crate) fn remove_sidecar(file: &Path) -> io::Result<()> {
+    match fs::remove_file(sidecar_path(file)) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::ErrorKind};
+
+    use url::Url;
+
+    use super::{sidecar_path, Sidecar};
+    use crate::downloader::{fetcher::MockFetcher, testing, DownloaderBuilder, Response};
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let sidecar = Sidecar {
+            source: "https://example.com/a.png".to_string(),
+            fetched_at: 1_700_000_000,
+            status: 200,
+            headers: vec![("ETag".to_string(), "\"v1\"".to_string())],
+            sha256: Some("abc".to_string()),
+            mime: Some("image/png".to_string()),
+        };
+
+        // Act
+
+        let json = serde_json::to_string(&sidecar).unwrap();
+
+        let decoded: Sidecar = serde_json::from_str(&json).unwrap();
+
+        let minimal: Sidecar =
+            serde_json::from_str(r#"{"source":"s","fetched_at":1,"status":200}"#).unwrap();
+
+        // Assert
+
+        assert_eq!(decoded, sidecar);
+        assert!(minimal.headers.is_empty());
+        assert_eq!(minimal.sha256, None);
+        assert_eq!(minimal.mime, None);
+    }
+
+    #[test]
+    fn test_downloads_write_sidecars() {
+        let url = "https://example.com/a.png";
+
+        let response = Response::ok(b"png".to_vec(), Some("image/png".to_string()))
+            .with_header("ETag", "\"v1\"");
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("sidecar_write"),
+            MockFetcher::new(vec![response]),
+        )
+        .write_sidecars(true)
+        .build();
+
+        // Act
+
+        let download = downloader.download(url).unwrap();
+
+        let sidecar = download.load_sidecar().unwrap();
+
+        // Assert
+
+        assert_eq!(sidecar.source, url);
+        assert_eq!(sidecar.status, 200);
+        assert_eq!(sidecar.sha256, download.metadata.sha256);
+        assert!(sidecar
+            .headers
+            .contains(&("ETag".to_string(), "\"v1\"".to_string())));
+    }
+
+    #[test]
+    fn test_sidecars_follow_their_data_file() {
+        let url = "https://example.com/a";
+
+        let fetcher = MockFetcher::new(vec![
+            Response::ok(b"png".to_vec(), Some("image/png".to_string())),
+            Response::ok(b"gif".to_vec(), Some("image/gif".to_string())),
+        ]);
+
+        let downloader =
+            DownloaderBuilder::with_fetcher(testing::cache_dir("sidecar_unit"), fetcher)
+                .write_sidecars(true)
+                .build();
+
+        let png = downloader.download(url).unwrap();
+
+        let gif = downloader.download(url).unwrap();
+
+        let parsed = Url::parse(url).unwrap();
+
+        let with_sidecar = downloader.cached_file(&parsed);
+
+        fs::remove_file(sidecar_path(&gif.file)).unwrap();
+
+        // Act
+
+        let without_sidecar = downloader.cached_file(&parsed);
+
+        // Assert
+
+        assert!(!png.file.exists());
+        assert!(!sidecar_path(&png.file).exists());
+        assert_eq!(with_sidecar.as_ref(), Some(&gif.file));
+        assert_eq!(without_sidecar.as_ref(), Some(&gif.file));
+        assert_eq!(gif.load_sidecar().unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,140 @@
+// Magic-byte detection that does not depend on any codec crate.
+const SIGNATURES: &[(usize, &[u8], &str, &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "png", "image/png"),
+    (0, b"\xff\xd8\xff", "jpg", "image/jpeg"),
+    (0, b"GIF87a", "gif", "image/gif"),
+    (0, b"GIF89a", "gif", "image/gif"),
+    (8, b"WEBP", "webp", "image/webp"),
+    (4, b"ftypavif", "avif", "image/avif"),
+    (0, b"II*\x00", "tiff", "image/tiff"),
+    (0, b"MM\x00*", "tiff", "image/tiff"),
+    (0, b"\x00\x00\x01\x00", "ico", "image/x-icon"),
+    (0, b"qoif", "qoi", "image/qoi"),
+    (0, b"%PDF-", "pdf", "application/pdf"),
+    (0, b"PK\x03\x04", "zip", "application/zip"),
+    (0, b"\x1f\x8b", "gz", "application/gzip"),
+    (0, b"BM", "bmp", "image/bmp"),
+];
+
+pub(crate) fn extension_from_magic(head: &[u8]) -> Option<&'static str> {
+    signature(head)
+        .map(|(extension, _)| extension)
+        .or_else(|| is_svg(head).then_some("svg"))
+        .or_else(|| is_html(head).then_some("html"))
+}
+
+pub(crate) fn mime_from_magic(head: &[u8]) -> Option<&'static str> {
+    signature(head)
+        .map(|(_, mime)| mime)
+        .or_else(|| is_svg(head).then_some("image/svg+xml"))
+        .or_else(|| is_html(head).then_some("text/html"))
+}
+
+fn signature(head: &[u8]) -> Option<(&'static str, &'static str)> {
+    SIGNATURES
+        .iter()
+        .find_map(|(offset, signature, extension, mime)| {
+            let candidate = head.get(*offset..offset + signature.len())?;
+
+            (candidate == *signature).then_some((*extension, *mime))
+        })
+}
+
+// `Image/PNG; charset=binary` is kept as `image/png`.
+pub(crate) fn normalize_mime(mime: &str) -> Option<String> {
+    let essence = mime.split(';').next()?.trim();
+
+    essence
+        .split_once('/')
+        .is_some_and(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty())
+        .then(|| essence.to_ascii_lowercase())
+}
+
+// Markup has no magic bytes, so look for the opening tag past any BOM and
+// leading whitespace, ignoring case.
+pub(crate) fn is_html(head: &[u8]) -> bool {
+    let text = markup(head);
+
+    [&b"<!doctype html"[..], b"<html"]
+        .iter()
+        .any(|tag| starts_with_tag(text, tag))
+}
+
+// The root element may follow an XML declaration.
+pub(crate) fn is_svg(head: &[u8]) -> bool {
+    let mut text = markup(head);
+
+    if starts_with_tag(text, b"<?xml") {
+        text = match text.windows(2).position(|window| window == b"?>") {
+            Some(end) => markup(&text[end + 2..]),
+            None => return false,
+        };
+    }
+
+    starts_with_tag(text, b"<svg")
+}
+
+fn markup(head: &[u8]) -> &[u8] {
+    let text = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+
+    let start = text
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(text.len());
+
+    &text[start..]
+}
+
+fn starts_with_tag(text: &[u8], tag: &[u8]) -> bool {
+    text.get(..tag.len())
+        .is_some_and(|candidate| candidate.eq_ignore_ascii_case(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extension_from_magic, mime_from_magic, normalize_mime};
+
+    #[test]
+    fn test_magic_signatures() {
+        let cases: &[(&[u8], Option<&str>)] = &[
+            (b"\x89PNG\r\n\x1a\n\x00\x00", Some("png")),
+            (b"\xff\xd8\xff\xe0\x00\x10JFIF", Some("jpg")),
+            (b"GIF89a\x01\x00", Some("gif")),
+            (b"RIFF\x24\x00\x00\x00WEBPVP8 ", Some("webp")),
+            (b"\x00\x00\x00\x1cftypavif", Some("avif")),
+            (b"%PDF-1.7", Some("pdf")),
+            (b"PK\x03\x04\x14\x00", Some("zip")),
+            (b"\x1f\x8b\x08\x00", Some("gz")),
+            (b"RIFF\x24\x00\x00\x00WAVE", None),
+            (b"\x89PN", None),
+            (b"<!DOCTYPE html><html>", Some("html")),
+            (b"\xef\xbb\xbf\n  <HTML lang=\"en\">", Some("html")),
+            (b"<htm", None),
+            (b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>", Some("svg")),
+            (b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<SVG>", Some("svg")),
+            (b"<?xml version=\"1.0\"?><feed>", None),
+            (b"<?xml version=\"1.0\"?>", None),
+            (b"plain text", None),
+            (b"", None),
+        ];
+
+        for (head, expected) in cases {
+            assert_eq!(extension_from_magic(head), *expected, "{head:?}");
+        }
+    }
+
+    #[test]
+    fn test_mime_types() {
+        // Assert
+
+        assert_eq!(mime_from_magic(b"\xff\xd8\xff\xe0"), Some("image/jpeg"));
+        assert_eq!(mime_from_magic(b"<html>"), Some("text/html"));
+        assert_eq!(mime_from_magic(b"plain text"), None);
+        assert_eq!(
+            normalize_mime(" Image/PNG ; charset=binary").as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(normalize_mime("png"), None);
+        assert_eq!(normalize_mime(""), None);
+    }
+}
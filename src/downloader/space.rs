@@ -0,0 +1,151 @@
+use std::{io, path::Path};
+
+use super::{DownloadError, Downloader, FileDownloader, Storage};
+
+pub trait SpaceProvider: Send + Sync {
+    fn available_space(&self, path: &Path) -> io::Result<u64>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FsSpace;
+
+impl SpaceProvider for FsSpace {
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        fs2::available_space(path)
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Bodies are staged in the cache directory whatever the storage, so that
+    // is the filesystem that has to hold them. An unknown amount of free
+    // space does not block the download.
+    pub(crate) fn check_space(&self, size: u64) -> Result<(), DownloadError> {
+        let needed = size.saturating_add(self.config.space_margin);
+
+        match self.config.space.available_space(&self.path) {
+            Ok(available) if available < needed => {
+                Err(DownloadError::InsufficientSpace { needed, available })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn insufficient_space(&self, needed: u64) -> DownloadError {
+        DownloadError::InsufficientSpace {
+            needed,
+            available: self
+                .config
+                .space
+                .available_space(&self.path)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::Path};
+
+    use super::SpaceProvider;
+    use crate::downloader::{
+        cache_key::CacheKey, fetcher::MockFetcher, partial, testing, DownloadError,
+        DownloaderBuilder, Response,
+    };
+
+    struct FakeSpace(u64);
+
+    impl SpaceProvider for FakeSpace {
+        fn available_space(&self, _path: &Path) -> io::Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    fn sized(length: usize) -> Response {
+        Response::ok(vec![0; length], None).with_header("Content-Length", &length.to_string())
+    }
+
+    #[test]
+    fn test_known_sizes_are_checked_before_writing() {
+        let fetcher = MockFetcher::new(vec![sized(1000), sized(900)]);
+
+        let dir = testing::cache_dir("space_check");
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher)
+            .space_provider(FakeSpace(1000))
+            .space_margin(100)
+            .build();
+
+        // Act
+
+        let too_large = downloader.download("https://example.com/a.bin");
+
+        let fitting = downloader.download("https://example.com/b.bin");
+
+        downloader.flush_maintenance();
+
+        // Assert
+
+        assert_eq!(
+            too_large.unwrap_err(),
+            DownloadError::InsufficientSpace {
+                needed: 1100,
+                available: 1000
+            }
+        );
+        assert!(fitting.is_ok());
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            3,
+            "entry, manifest and format"
+        );
+    }
+
+    // /dev/full fails every write with ENOSPC.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_full_disk_while_streaming_is_reported() {
+        let url = "https://example.com/unsized.bin";
+
+        let dir = testing::cache_dir("space_full");
+
+        let fetcher = MockFetcher::new(vec![Response::ok(vec![1; 4096], None)]);
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher)
+            .space_provider(FakeSpace(0))
+            .build();
+
+        // Other tests stage writes meanwhile, so any of the next names may be
+        // the one taken.
+        let partials =
+            partial::upcoming_staging_paths(&dir.join(CacheKey::from_url(url).as_str()), 1000);
+
+        for partial in &partials {
+            std::os::unix::fs::symlink("/dev/full", partial).unwrap();
+        }
+
+        // Act
+
+        let error = downloader.download(url).unwrap_err();
+
+        // Assert
+
+        assert_eq!(
+            error,
+            DownloadError::InsufficientSpace {
+                needed: 0,
+                available: 0
+            }
+        );
+        assert_eq!(
+            partials
+                .iter()
+                .filter(|partial| fs::symlink_metadata(partial).is_err())
+                .count(),
+            1
+        );
+    }
+}
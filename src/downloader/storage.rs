@@ -0,0 +1,446 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use super::{
+    budget::BUDGET_FILE,
+    format::FORMAT_FILE,
+    manifest::MANIFEST_FILE,
+    partial::{self, PartialFile},
+    partition::{self, PARTITION_MARKER},
+    Download, Downloader, FileDownloader, PARTIAL_SUFFIX,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFile {
+    pub name: String,
+    pub size: u64,
+}
+
+// Where cache entries end up. Bodies are still staged, and the manifest kept,
+// in the downloader directory.
+pub trait Storage: Send + Sync + 'static {
+    fn put(&self, name: &str, reader: &mut dyn Read) -> io::Result<StoredFile>;
+
+    fn exists(&self, name: &str) -> bool;
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>>;
+
+    fn delete(&self, name: &str) -> io::Result<()>;
+
+    fn list(&self) -> io::Result<Vec<String>>;
+
+    // Backends on the local filesystem expose their files, which enables the
+    // features that need one: commits by rename, sidecars and thumbnails.
+    fn path(&self, _name: &str) -> Option<PathBuf> {
+        None
+    }
+
+    // Where remote backends serve the entry from, set on `Download::remote_url`.
+    fn remote_url(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+// For storages chosen at runtime.
+impl Storage for Box<dyn Storage> {
+    fn put(&self, name: &str, reader: &mut dyn Read) -> io::Result<StoredFile> {
+        (**self).put(name, reader)
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        (**self).exists(name)
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+        (**self).open(name)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        (**self).delete(name)
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        (**self).list()
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        (**self).path(name)
+    }
+
+    fn remote_url(&self, name: &str) -> Option<String> {
+        (**self).remote_url(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+    dir: PathBuf,
+}
+
+impl FsStorage {
+    // Relative directories are resolved against the working directory once,
+    // here.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+
+        Self {
+            dir: std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf()),
+        }
+    }
+}
+
+impl Storage for FsStorage {
+    fn put(&self, name: &str, reader: &mut dyn Read) -> io::Result<StoredFile> {
+        let target = self.dir.join(name);
+
+        let mut partial = PartialFile::create(partial::staging_path(&target))?;
+
+        let size = io::copy(reader, &mut partial)?;
+
+        partial.commit(&target, SystemTime::now())?;
+
+        if let Some(dir) = target.parent().filter(|_| name.contains('/')) {
+            partition::mark(dir)?;
+        }
+
+        Ok(StoredFile {
+            name: name.to_string(),
+            size,
+        })
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.dir.join(name).exists()
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(BufReader::new(File::open(self.dir.join(name))?)))
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.dir.join(name))
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+
+            // Partitions are one level deep. Directories without a marker,
+            // such as extracted archives, hold no entries.
+            if entry.file_type()?.is_dir() {
+                if !entry.path().join(PARTITION_MARKER).is_file() {
+                    continue;
+                }
+
+                for nested in fs::read_dir(entry.path())? {
+                    let nested = nested?;
+
+                    if let Some(file) = nested.file_name().to_str() {
+                        if nested.file_type()?.is_file()
+                            && !file.ends_with(PARTIAL_SUFFIX)
+                            && file != PARTITION_MARKER
+                        {
+                            names.push(format!("{name}/{file}"));
+                        }
+                    }
+                }
+            } else if entry.file_type()?.is_file()
+                && !name.ends_with(PARTIAL_SUFFIX)
+                // Describe the directory rather than hold entries.
+                && ![FORMAT_FILE, MANIFEST_FILE, BUDGET_FILE].contains(&name.as_str())
+            {
+                names.push(name);
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.dir.join(name))
+    }
+}
+
+// The storage a download reads its entry through, when the entry is not a
+// file on disk. Like `Resident`, it does not make two downloads differ.
+#[derive(Clone, Default)]
+pub(crate) struct Backing(pub Option<(Arc<dyn Storage>, String)>);
+
+impl PartialEq for Backing {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for Backing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some((_, name)) => write!(f, "Backing({name:?})"),
+            None => f.write_str("Backing(None)"),
+        }
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Entries on disk are read from their file, others through the storage.
+    pub(crate) fn backing(&self, file: &Path) -> Backing {
+        let name = self.storage_name(file);
+
+        match self.storage.path(&name) {
+            Some(_) => Backing::default(),
+            None => Backing(Some((Arc::clone(&self.storage) as Arc<dyn Storage>, name))),
+        }
+    }
+
+    // Reads a download whichever storage holds it. Persisted files are read
+    // from their destination.
+    pub fn open(&self, download: &Download) -> io::Result<Box<dyn Read + Send>> {
+        if self.config.persist_dir.is_some() {
+            return Ok(Box::new(download.reader()?));
+        }
+
+        self.storage.open(&self.storage_name(&download.file))
+    }
+}
+
+// Keeps entries in memory, for tests that should not depend on the disk.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn put(&self, name: &str, reader: &mut dyn Read) -> io::Result<StoredFile> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let size = bytes.len() as u64;
+
+        self.files.lock().unwrap().insert(name.to_string(), bytes);
+
+        Ok(StoredFile {
+            name: name.to_string(),
+            size,
+        })
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.files.lock().unwrap().contains_key(name)
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+        let files = self.files.lock().unwrap();
+
+        let bytes = files.get(name).ok_or_else(|| not_found(name))?;
+
+        Ok(Box::new(Cursor::new(bytes.clone())))
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| not_found(name))
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut names: Vec<_> = self.files.lock().unwrap().keys().cloned().collect();
+
+        names.sort();
+
+        Ok(names)
+    }
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{name} is not stored"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, Read},
+    };
+
+    use url::Url;
+
+    use super::{FsStorage, MemoryStorage, Storage, StoredFile};
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, DownloadError, DownloaderBuilder, Response,
+    };
+
+    // Refuses every write, as a full or unreachable bucket would.
+    struct Unwritable;
+
+    impl Storage for Unwritable {
+        fn put(&self, _name: &str, _reader: &mut dyn Read) -> io::Result<StoredFile> {
+            Err(io::Error::other("bucket unreachable"))
+        }
+
+        fn exists(&self, _name: &str) -> bool {
+            false
+        }
+
+        fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+            Err(super::not_found(name))
+        }
+
+        fn delete(&self, name: &str) -> io::Result<()> {
+            Err(super::not_found(name))
+        }
+
+        fn list(&self) -> io::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+    }
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn png_response(body: &str) -> Response {
+        Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()))
+    }
+
+    fn read(storage: &dyn Storage, name: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        storage.open(name).unwrap().read_to_end(&mut bytes).unwrap();
+
+        bytes
+    }
+
+    #[test]
+    fn test_backends_store_list_and_delete() {
+        let dir = testing::cache_dir("storage_backends");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        // Bookkeeping of the downloader, not an entry.
+        fs::write(dir.join("manifest.json"), "{}").unwrap();
+
+        let backends: [Box<dyn Storage>; 2] = [
+            Box::new(FsStorage::new(&dir)),
+            Box::new(MemoryStorage::new()),
+        ];
+
+        for storage in backends {
+            // Act
+
+            let stored = storage.put("a.txt", &mut &b"first"[..]).unwrap();
+
+            storage.put("a.txt", &mut &b"second"[..]).unwrap();
+
+            storage.put("b.txt", &mut &b"other"[..]).unwrap();
+
+            storage.delete("b.txt").unwrap();
+
+            // Assert
+
+            assert_eq!(stored.size, 5);
+            assert_eq!(read(&*storage, "a.txt"), b"second");
+            assert_eq!(storage.list().unwrap(), vec!["a.txt".to_string()]);
+            assert!(!storage.exists("b.txt"));
+            assert!(storage.delete("b.txt").is_err());
+        }
+    }
+
+    fn download_twice(name: &str, storage: impl Storage) {
+        let dir = testing::cache_dir(name);
+
+        fs::create_dir_all(dir.join("entries")).unwrap();
+
+        let fetcher = MockFetcher::new(vec![png_response("first"), png_response("second")]);
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, fetcher)
+            .storage(storage)
+            .build();
+
+        // Act
+
+        let first = downloader.download(URL).unwrap();
+
+        let second = downloader.download(URL).unwrap();
+
+        // Assert
+
+        let file_name = second.file.file_name().unwrap().to_str().unwrap();
+
+        let mut bytes = Vec::new();
+
+        downloader
+            .open(&second)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+
+        assert_eq!(first.file, second.file);
+        assert_eq!(bytes, b"second");
+        assert_eq!(
+            downloader.storage().list().unwrap(),
+            vec![file_name.to_string()]
+        );
+        assert_eq!(
+            downloader.cached_file(&Url::parse(URL).unwrap()),
+            Some(second.file.clone())
+        );
+
+        downloader.clear_cache();
+
+        assert!(downloader.storage().list().unwrap_or_default().is_empty());
+    }
+
+    #[test]
+    fn test_downloads_behave_the_same_on_both_backends() {
+        download_twice(
+            "storage_fs",
+            FsStorage::new(testing::cache_dir("storage_fs").join("entries")),
+        );
+
+        download_twice("storage_memory", MemoryStorage::new());
+    }
+
+    #[test]
+    fn test_storage_write_failures_are_errors() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("storage_unwritable"),
+            MockFetcher::new(vec![png_response("first")]),
+        )
+        .storage(Unwritable)
+        .build();
+
+        // Act
+
+        let result = downloader.download(URL);
+
+        // Assert
+
+        assert!(
+            matches!(result, Err(DownloadError::Io(reason)) if reason.contains("bucket unreachable"))
+        );
+    }
+}
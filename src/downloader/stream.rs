@@ -0,0 +1,397 @@
+use std::{
+    io::{self, Read, Write},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use super::{
+    tee::{self, CopyError, TeeWriter},
+    Body, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Storage,
+};
+
+// Chunks a body may run ahead of a slow writer.
+const TAP_CHUNKS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadInfo {
+    pub size: u64,
+    // The response Content-Type or, for bytes from the cache, the type
+    // recorded with the entry.
+    pub mime: Option<String>,
+    pub sha256: String,
+}
+
+// Hands a copy of the body being staged to `download_into_cached`'s writer,
+// so the bytes are read from the network once. Closed once the call is done,
+// so work it set off in the background sends nothing.
+#[derive(Clone)]
+pub(crate) struct Tap(Arc<TapState>);
+
+struct TapState {
+    chunks: Mutex<Option<SyncSender<Vec<u8>>>>,
+    streamed: AtomicBool,
+}
+
+impl Tap {
+    fn new(chunks: SyncSender<Vec<u8>>) -> Self {
+        Self(Arc::new(TapState {
+            chunks: Mutex::new(Some(chunks)),
+            streamed: AtomicBool::new(false),
+        }))
+    }
+
+    pub fn wrap(&self, body: Body) -> Body {
+        self.0.streamed.store(true, Ordering::Release);
+
+        let inner: Box<dyn Read + Send> = match body {
+            Body::Bytes(bytes) => Box::new(io::Cursor::new(bytes)),
+            Body::Reader(reader) => reader,
+        };
+
+        Body::Reader(Box::new(TapReader {
+            inner,
+            tap: self.clone(),
+        }))
+    }
+
+    // Whether a body went through the tap, even an empty one.
+    pub fn streamed(&self) -> bool {
+        self.0.streamed.load(Ordering::Acquire)
+    }
+
+    fn send(&self, chunk: &[u8]) {
+        let mut chunks = self.0.chunks.lock().unwrap();
+
+        // A writer that failed stops taking chunks; the cache copy goes on.
+        if chunks
+            .as_ref()
+            .is_some_and(|chunks| chunks.send(chunk.to_vec()).is_err())
+        {
+            *chunks = None;
+        }
+    }
+
+    fn close(&self) {
+        self.0.chunks.lock().unwrap().take();
+    }
+}
+
+struct TapReader {
+    inner: Box<dyn Read + Send>,
+    tap: Tap,
+}
+
+impl Read for TapReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if read > 0 {
+            self.tap.send(&buf[..read]);
+        }
+
+        Ok(read)
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Streams the body straight into `writer`, nothing touches the cache.
+    pub fn download_into<W: Write>(
+        &self,
+        url: &str,
+        writer: &mut W,
+    ) -> Result<DownloadInfo, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        let response = self.fetch(&url, &[])?;
+
+        match response.status {
+            200..=299 => {}
+            404 => return Err(DownloadError::NotFound),
+            status => return Err(DownloadError::HttpStatus(status)),
+        }
+
+        let mime = response.mime().map(str::to_string);
+
+        copy_into(
+            response.body,
+            TeeWriter::new(writer),
+            mime,
+            |received, kind| DownloadError::TruncatedBody { received, kind },
+        )
+    }
+
+    // Goes through the cache like `download`. A fetched body streams into
+    // `writer` while it is stored, and so is not fetched again when it turns
+    // out truncated or corrupt; hits play the cached file. On errors `writer`
+    // may hold part of the body.
+    pub fn download_into_cached<W: Write>(
+        &self,
+        url: &str,
+        writer: &mut W,
+    ) -> Result<DownloadInfo, DownloadError> {
+        let (chunks, received) = mpsc::sync_channel(TAP_CHUNKS);
+
+        let tap = Tap::new(chunks);
+
+        let downloader = self.tapped(&tap);
+
+        let mut tee = TeeWriter::new(writer);
+
+        let mut written = Ok(());
+
+        let result = thread::scope(|scope| {
+            let download = scope.spawn(|| {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| downloader.download_any(url)));
+
+                tap.close();
+
+                result
+            });
+
+            for chunk in &received {
+                written = tee.write_all(&chunk);
+
+                if written.is_err() {
+                    tap.close();
+
+                    break;
+                }
+            }
+
+            download.join().unwrap()
+        });
+
+        let download = result.unwrap_or_else(|panic| panic::resume_unwind(panic))?;
+
+        written.map_err(|error| DownloadError::Writer(error.to_string()))?;
+
+        if !tap.streamed() {
+            let file = self
+                .open(&download)
+                .map_err(|error| DownloadError::Io(error.to_string()))?;
+
+            let read_error =
+                DownloadError::Io(format!("Error reading {}", download.file.display()));
+
+            return copy_into(Body::Reader(file), tee, download.metadata.mime, |_, _| {
+                read_error
+            });
+        }
+
+        finish(tee, download.metadata.mime)
+    }
+
+    // A handle sharing everything with this one, its stored bodies going
+    // through `tap` as well.
+    fn tapped(&self, tap: &Tap) -> Self {
+        let mut config = (*self.config).clone();
+
+        config.tap = Some(tap.clone());
+
+        Downloader {
+            config: Arc::new(config),
+            refresher: self.refresher.clone(),
+            overlay: self
+                .overlay
+                .as_ref()
+                .map(|overlay| Box::new(overlay.tapped(tap))),
+            ..self.detached()
+        }
+    }
+}
+
+fn copy_into<W: Write>(
+    body: Body,
+    mut tee: TeeWriter<W>,
+    mime: Option<String>,
+    read_error: impl FnOnce(u64, io::ErrorKind) -> DownloadError,
+) -> Result<DownloadInfo, DownloadError> {
+    match tee::copy_body(body, &mut tee) {
+        Ok(_) => {}
+        Err(CopyError::Read { received, kind }) => return Err(read_error(received, kind)),
+        Err(CopyError::Write(error)) => return Err(DownloadError::Writer(error.to_string())),
+    }
+
+    finish(tee, mime)
+}
+
+fn finish<W: Write>(
+    mut tee: TeeWriter<W>,
+    mime: Option<String>,
+) -> Result<DownloadInfo, DownloadError> {
+    tee.flush()
+        .map_err(|error| DownloadError::Writer(error.to_string()))?;
+
+    let (_, summary) = tee.finish();
+
+    Ok(DownloadInfo {
+        size: summary.size,
+        mime,
+        sha256: summary.sha256,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use sha2::{Digest, Sha256};
+    use url::Url;
+
+    use crate::downloader::{
+        fetcher::MockFetcher, tee, testing, CachePolicy, DownloadError, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/report.pdf";
+
+    #[test]
+    fn test_download_into_vec_skips_cache() {
+        let body = b"%PDF-1.7 report".to_vec();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("stream_vec"),
+            MockFetcher::new(vec![Response::ok(
+                body.clone(),
+                Some("application/pdf".to_string()),
+            )]),
+        )
+        .build();
+
+        let mut writer = Vec::new();
+
+        // Act
+
+        let info = downloader.download_into(URL, &mut writer).unwrap();
+
+        // Assert
+
+        assert_eq!(writer, body);
+        assert_eq!(info.size, body.len() as u64);
+        assert_eq!(info.mime.as_deref(), Some("application/pdf"));
+        assert_eq!(info.sha256, tee::hex(&Sha256::digest(&body)));
+        assert!(downloader.cached_file(&Url::parse(URL).unwrap()).is_none());
+    }
+
+    struct Closed;
+
+    impl Write for Closed {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer_errors_are_not_network_errors() {
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("stream_failing"),
+            MockFetcher::new(vec![
+                Response::ok(b"data".to_vec(), None),
+                Response::invalid_body(),
+            ]),
+        )
+        .build();
+
+        // Act
+
+        let writer = downloader.download_into(URL, &mut Closed).unwrap_err();
+
+        let body = downloader.download_into(URL, &mut Vec::new()).unwrap_err();
+
+        // Assert
+
+        assert!(matches!(writer, DownloadError::Writer(_)), "{writer:?}");
+        assert_eq!(
+            body,
+            DownloadError::TruncatedBody {
+                received: 0,
+                kind: io::ErrorKind::UnexpectedEof
+            }
+        );
+    }
+
+    #[test]
+    fn test_download_into_cached_keeps_a_cache_copy() {
+        let body = b"cached bytes".to_vec();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("stream_cached"),
+            MockFetcher::new(vec![Response::ok(body.clone(), None)]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build();
+
+        let mut first = Vec::new();
+
+        let mut second = Vec::new();
+
+        // Act
+
+        let info = downloader.download_into_cached(URL, &mut first).unwrap();
+
+        downloader.download_into_cached(URL, &mut second).unwrap();
+
+        // Assert
+
+        assert_eq!(first, body);
+        assert_eq!(second, body);
+        assert_eq!(info.size, body.len() as u64);
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_download_into_cached_streams_the_fetch_once() {
+        let body = b"%PDF-1.7 report".to_vec();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("stream_cached_once"),
+            MockFetcher::new(vec![
+                Response::ok(body.clone(), Some("application/pdf".to_string())),
+                Response::invalid_body(),
+                Response::ok(body.clone(), None),
+            ]),
+        )
+        .build();
+
+        let mut streamed = Vec::new();
+
+        // Act
+
+        let info = downloader.download_into_cached(URL, &mut streamed).unwrap();
+
+        let truncated = downloader
+            .download_into_cached("https://example.com/truncated.pdf", &mut Vec::new())
+            .unwrap_err();
+
+        let closed = downloader.download_into_cached("https://example.com/closed.pdf", &mut Closed);
+
+        // Assert
+
+        assert_eq!(streamed, body);
+        assert_eq!(info.mime.as_deref(), Some("application/pdf"));
+        assert_eq!(info.sha256, tee::hex(&Sha256::digest(&body)));
+        assert_eq!(
+            downloader.cached(URL).unwrap().bytes().unwrap(),
+            body,
+            "the cache holds the streamed bytes"
+        );
+        assert!(matches!(truncated, DownloadError::TruncatedBody { .. }));
+        assert!(matches!(closed, Err(DownloadError::Writer(_))));
+        assert!(downloader.is_cached("https://example.com/closed.pdf"));
+        assert_eq!(downloader.fetcher().calls(), 3);
+    }
+}
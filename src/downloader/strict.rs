@@ -0,0 +1,119 @@
+use super::{extension, DownloadError, Downloader, FileDownloader, Storage};
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // `get_extension` for cache entries, which fails instead of falling back
+    // to `dat` when strict.
+    pub(crate) fn entry_extension(
+        &self,
+        mime: Option<&str>,
+        body: &[u8],
+    ) -> Result<String, DownloadError> {
+        if let Some(extension) = self
+            .get_extension_from_mimetype(mime)
+            .and_then(extension::sanitize_extension)
+        {
+            return Ok(extension);
+        }
+
+        if let Some(extension) = self.get_extension_from_content(body) {
+            return Ok(extension.to_string());
+        }
+
+        if self.config.strict_sniff {
+            return Err(DownloadError::SniffFailed);
+        }
+
+        if self.config.strict_extension {
+            return Err(DownloadError::UnknownExtension);
+        }
+
+        Ok("dat".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::downloader::{
+        fetcher::MockFetcher, fixtures, testing, DownloadError, DownloaderBuilder, Response,
+        Storage,
+    };
+
+    const URL: &str = "https://example.com/asset";
+
+    fn download(
+        name: &str,
+        response: Response,
+        strict: impl FnOnce(DownloaderBuilder<MockFetcher>) -> DownloaderBuilder<MockFetcher>,
+    ) -> Result<String, DownloadError> {
+        let downloader = strict(DownloaderBuilder::with_fetcher(
+            testing::cache_dir(name),
+            MockFetcher::new(vec![response]),
+        ))
+        .build();
+
+        let result = downloader.download(URL).map(|download| {
+            download
+                .file
+                .extension()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        });
+
+        if result.is_err() {
+            assert!(downloader.storage().list().unwrap().is_empty());
+        }
+
+        result
+    }
+
+    #[test]
+    fn test_fallbacks_become_errors_when_strict() {
+        let untyped_png = || Response::new(200).with_body(fixtures::PNG.to_vec());
+
+        let untyped_junk = || Response::new(200).with_body(b"\x00\x01junk".to_vec());
+
+        let typed_png = || Response::ok(fixtures::PNG.to_vec(), Some("image/png".to_string()));
+
+        let lenient = |builder| builder;
+
+        let strict = |builder: DownloaderBuilder<MockFetcher>| builder.strict(true);
+
+        // Act
+
+        let outcomes = [
+            download("strict_lenient_png", untyped_png(), lenient),
+            download("strict_lenient_junk", untyped_junk(), lenient),
+            download("strict_strict_png", untyped_png(), strict),
+            download("strict_strict_typed", typed_png(), strict),
+            download("strict_sniff_png", untyped_png(), |builder| {
+                builder.strict_sniff(true)
+            }),
+            download("strict_sniff_junk", untyped_junk(), |builder| {
+                builder.strict_sniff(true)
+            }),
+            download("strict_extension_junk", untyped_junk(), |builder| {
+                builder.strict_extension(true)
+            }),
+        ];
+
+        // Assert
+
+        assert_eq!(
+            outcomes,
+            [
+                Ok("png".to_string()),
+                Ok("dat".to_string()),
+                Err(DownloadError::MissingContentType),
+                Ok("png".to_string()),
+                Ok("png".to_string()),
+                Err(DownloadError::SniffFailed),
+                Err(DownloadError::UnknownExtension),
+            ]
+        );
+    }
+}
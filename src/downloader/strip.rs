@@ -0,0 +1,321 @@
+// Lossless removal of privacy-sensitive metadata (EXIF, XMP, ICC, text
+// chunks) by dropping container segments; pixel data is never re-encoded.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripOutcome {
+    // Metadata was found and removed.
+    Stripped,
+    // The format is supported but carried no metadata.
+    Clean,
+    // The format is not JPEG, PNG or WebP, or could not be parsed; the file
+    // was saved unchanged.
+    Unsupported,
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const PNG_METADATA_CHUNKS: &[&[u8]] = &[b"eXIf", b"iCCP", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+const WEBP_METADATA_CHUNKS: &[&[u8]] = &[b"EXIF", b"XMP ", b"ICCP"];
+
+// VP8X feature flags for ICC, EXIF and XMP.
+const VP8X_METADATA_FLAGS: u8 = 0x20 | 0x08 | 0x04;
+
+pub(crate) fn strip(bytes: &[u8]) -> (StripOutcome, Option<Vec<u8>>) {
+    let stripped = if bytes.starts_with(b"\xff\xd8") {
+        strip_jpeg(bytes)
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        strip_png(bytes)
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        strip_webp(bytes)
+    } else {
+        None
+    };
+
+    match stripped {
+        None => (StripOutcome::Unsupported, None),
+        Some(stripped) if stripped.len() == bytes.len() => (StripOutcome::Clean, None),
+        Some(stripped) => (StripOutcome::Stripped, Some(stripped)),
+    }
+}
+
+// APP1 (EXIF, XMP), APP2 (ICC) and APP13 (IPTC) go; APP0 and APP14 stay
+// because decoders rely on them for colour handling.
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(bytes.len());
+    output.extend_from_slice(&bytes[..2]);
+
+    let mut offset = 2;
+
+    loop {
+        let marker = *bytes.get(offset + 1)?;
+
+        if bytes[offset] != 0xff {
+            return None;
+        }
+
+        // Entropy-coded data follows the start of scan; copy the remainder.
+        if marker == 0xda {
+            output.extend_from_slice(&bytes[offset..]);
+            return Some(output);
+        }
+
+        let length = usize::from(u16::from_be_bytes([
+            *bytes.get(offset + 2)?,
+            *bytes.get(offset + 3)?,
+        ]));
+
+        let end = offset + 2 + length;
+
+        let segment = bytes.get(offset..end)?;
+
+        if !matches!(marker, 0xe1 | 0xe2 | 0xed) {
+            output.extend_from_slice(segment);
+        }
+
+        offset = end;
+    }
+}
+
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(bytes.len());
+    output.extend_from_slice(PNG_SIGNATURE);
+
+    let mut offset = PNG_SIGNATURE.len();
+
+    while offset < bytes.len() {
+        let length = u32::from_be_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?);
+
+        // Length, type, data and CRC.
+        let end = offset + 12 + usize::try_from(length).ok()?;
+
+        let chunk = bytes.get(offset..end)?;
+
+        if !PNG_METADATA_CHUNKS.contains(&&chunk[4..8]) {
+            output.extend_from_slice(chunk);
+        }
+
+        offset = end;
+    }
+
+    Some(output)
+}
+
+fn strip_webp(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(bytes.len());
+    output.extend_from_slice(&bytes[..12]);
+
+    let mut offset = 12;
+
+    while offset < bytes.len() {
+        let size = u32::from_le_bytes(bytes.get(offset + 4..offset + 8)?.try_into().ok()?);
+
+        let size = usize::try_from(size).ok()?;
+
+        // Chunks are padded to an even size.
+        let end = (offset + 8 + size + size % 2).min(bytes.len());
+
+        let chunk = bytes.get(offset..end)?;
+
+        match &chunk[..4] {
+            kind if WEBP_METADATA_CHUNKS.contains(&kind) => {}
+            b"VP8X" => {
+                let start = output.len();
+                output.extend_from_slice(chunk);
+                *output.get_mut(start + 8)? &= !VP8X_METADATA_FLAGS;
+            }
+            _ => output.extend_from_slice(chunk),
+        }
+
+        offset = end;
+    }
+
+    let riff_size = u32::try_from(output.len() - 8).ok()?;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strip, StripOutcome};
+    use crate::downloader::{fetcher::MockFetcher, testing, DownloaderBuilder, Response};
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xff, marker];
+        segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn jpeg_with_exif() -> Vec<u8> {
+        [
+            vec![0xff, 0xd8],
+            segment(0xe0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"),
+            segment(0xe1, b"Exif\0\0MM\0*GPS"),
+            segment(0xe2, b"ICC_PROFILE\0"),
+            segment(0xdb, &[0; 65]),
+            segment(0xda, &[1, 2, 3]),
+            vec![0x12, 0x34, 0xff, 0xd9],
+        ]
+        .concat()
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn webp_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+
+        chunk
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn test_strip_jpeg_app_segments() {
+        let jpeg = jpeg_with_exif();
+
+        // Act
+
+        let (outcome, stripped) = strip(&jpeg);
+
+        // Assert
+
+        let stripped = stripped.unwrap();
+
+        assert_eq!(outcome, StripOutcome::Stripped);
+        assert!(jpeg.windows(4).any(|window| window == b"Exif"));
+        assert!(!stripped.windows(4).any(|window| window == b"Exif"));
+        assert!(!stripped.windows(3).any(|window| window == b"ICC"));
+        assert!(stripped.windows(4).any(|window| window == b"JFIF"));
+        assert!(stripped.ends_with(&[0x12, 0x34, 0xff, 0xd9]));
+    }
+
+    #[test]
+    fn test_strip_png_chunks() {
+        let png = [
+            b"\x89PNG\r\n\x1a\n".to_vec(),
+            png_chunk(b"IHDR", &[0; 13]),
+            png_chunk(b"eXIf", b"MM\0*"),
+            png_chunk(b"tEXt", b"Author\0me"),
+            png_chunk(b"IDAT", &[1, 2, 3]),
+            png_chunk(b"IEND", &[]),
+        ]
+        .concat();
+
+        let expected = [
+            b"\x89PNG\r\n\x1a\n".to_vec(),
+            png_chunk(b"IHDR", &[0; 13]),
+            png_chunk(b"IDAT", &[1, 2, 3]),
+            png_chunk(b"IEND", &[]),
+        ]
+        .concat();
+
+        // Act
+
+        let (outcome, stripped) = strip(&png);
+
+        let (clean, unchanged) = strip(&expected);
+
+        // Assert
+
+        assert_eq!(outcome, StripOutcome::Stripped);
+        assert_eq!(stripped.unwrap(), expected);
+        assert_eq!(clean, StripOutcome::Clean);
+        assert_eq!(unchanged, None);
+    }
+
+    #[test]
+    fn test_strip_webp_chunks_and_flags() {
+        let vp8x = |flags: u8| webp_chunk(b"VP8X", &[flags, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let webp_with_exif = webp(&[
+            vp8x(0x08 | 0x10),
+            webp_chunk(b"VP8 ", &[9; 5]),
+            webp_chunk(b"EXIF", b"MM\0*GPS"),
+        ]);
+
+        // Act
+
+        let (outcome, stripped) = strip(&webp_with_exif);
+
+        // Assert
+
+        assert_eq!(outcome, StripOutcome::Stripped);
+        assert_eq!(
+            stripped.unwrap(),
+            webp(&[vp8x(0x10), webp_chunk(b"VP8 ", &[9; 5])])
+        );
+    }
+
+    #[test]
+    fn test_unsupported_or_malformed_bodies_are_left_alone() {
+        let mut truncated = jpeg_with_exif();
+        truncated.truncate(10);
+
+        let cases: [&[u8]; 3] = [b"GIF89a\x01\x00", b"plain text", &truncated];
+
+        for body in cases {
+            // Act
+
+            let (outcome, stripped) = strip(body);
+
+            // Assert
+
+            assert_eq!(outcome, StripOutcome::Unsupported);
+            assert_eq!(stripped, None);
+        }
+    }
+
+    #[test]
+    fn test_downloader_strips_before_saving() {
+        let jpeg = jpeg_with_exif();
+
+        let fetcher = MockFetcher::new(vec![
+            Response::ok(jpeg.clone(), Some("image/jpeg".to_string())),
+            Response::ok(b"GIF89a\x01\x00".to_vec(), Some("image/gif".to_string())),
+        ]);
+
+        let downloader = DownloaderBuilder::with_fetcher(testing::cache_dir("strip"), fetcher)
+            .strip_metadata(true)
+            .build();
+
+        // Act
+
+        let photo = downloader
+            .download("https://example.com/photo.jpg")
+            .unwrap();
+
+        let gif = downloader.download("https://example.com/anim.gif").unwrap();
+
+        // Assert
+
+        let saved = photo.bytes().unwrap();
+
+        assert_eq!(photo.metadata.stripped, Some(StripOutcome::Stripped));
+        assert!(!saved.windows(4).any(|window| window == b"Exif"));
+        assert_eq!(photo.metadata.size, Some(saved.len() as u64));
+        assert_eq!(gif.metadata.stripped, Some(StripOutcome::Unsupported));
+        assert_eq!(gif.bytes().unwrap(), b"GIF89a\x01\x00");
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
+use super::Body;
+
+// Enough of the body for every content sniffer we run.
+pub(crate) const SNIFF_LIMIT: usize = 8 * 1024;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BodySummary {
+    pub size: u64,
+    pub sha256: String,
+    pub head: Vec<u8>,
+}
+
+impl BodySummary {
+    pub fn of(bytes: &[u8]) -> Self {
+        let mut tee = TeeWriter::new(io::sink());
+
+        // Writing to a sink cannot fail.
+        let _ = tee.write_all(bytes);
+
+        tee.finish().1
+    }
+
+    // For bodies written some other way, read back once.
+    pub fn of_reader(reader: &mut impl Read) -> io::Result<Self> {
+        let mut tee = TeeWriter::new(io::sink());
+
+        io::copy(reader, &mut tee)?;
+
+        Ok(tee.finish().1)
+    }
+}
+
+// Single pass over the body: every chunk is hashed, counted, the first
+// `SNIFF_LIMIT` bytes are kept for sniffing, and then it goes to `inner`.
+pub(crate) struct TeeWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    head: Vec<u8>,
+    written: u64,
+}
+
+impl<W: Write> TeeWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            head: Vec::with_capacity(SNIFF_LIMIT),
+            written: 0,
+        }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn finish(self) -> (W, BodySummary) {
+        let sha256 = hex(&self.hasher.finalize());
+
+        let summary = BodySummary {
+            size: self.written,
+            sha256,
+            head: self.head,
+        };
+
+        (self.inner, summary)
+    }
+}
+
+impl<W: Write> Write for TeeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+
+        let chunk = &buf[..written];
+
+        self.hasher.update(chunk);
+
+        let missing = SNIFF_LIMIT.saturating_sub(self.head.len());
+        self.head.extend_from_slice(&chunk[..missing.min(written)]);
+
+        self.written += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum CopyError {
+    // How many bytes were copied before the body failed.
+    Read { received: u64, kind: io::ErrorKind },
+    Write(io::Error),
+}
+
+// Unlike `io::copy` this keeps failures of the body (network) apart from
+// failures of the destination (disk).
+pub(crate) fn copy_body<W: Write>(body: Body, writer: &mut W) -> Result<u64, CopyError> {
+    match body {
+        Body::Bytes(bytes) => {
+            writer.write_all(&bytes).map_err(CopyError::Write)?;
+
+            Ok(bytes.len() as u64)
+        }
+
+        Body::Reader(mut reader) => {
+            let mut buffer = vec![0; CHUNK_SIZE];
+
+            let mut copied = 0;
+
+            loop {
+                let read = match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(error) => {
+                        return Err(CopyError::Read {
+                            received: copied,
+                            kind: error.kind(),
+                        })
+                    }
+                };
+
+                writer
+                    .write_all(&buffer[..read])
+                    .map_err(CopyError::Write)?;
+
+                copied += read as u64;
+            }
+
+            Ok(copied)
+        }
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Write};
+
+    use super::{copy_body, CopyError, TeeWriter, SNIFF_LIMIT};
+    use crate::downloader::{Body, Response};
+
+    #[test]
+    fn test_tee_summarizes_in_one_pass() {
+        let body = vec![1u8; SNIFF_LIMIT * 3 + 17];
+
+        let mut tee = TeeWriter::new(Vec::new());
+
+        // Act
+
+        let copied = copy_body(Body::Reader(Box::new(Cursor::new(body.clone()))), &mut tee);
+
+        let (written, summary) = tee.finish();
+
+        // Assert
+
+        assert_eq!(copied.unwrap(), body.len() as u64);
+        assert_eq!(written, body);
+        assert_eq!(summary.size, body.len() as u64);
+        assert_eq!(summary.head, &body[..SNIFF_LIMIT]);
+    }
+
+    #[test]
+    fn test_tee_sha256() {
+        let mut tee = TeeWriter::new(io::sink());
+
+        // Act
+
+        tee.write_all(b"a").unwrap();
+        tee.write_all(b"bc").unwrap();
+
+        let (_, summary) = tee.finish();
+
+        // Assert
+
+        assert_eq!(
+            summary.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(summary.head, b"abc");
+    }
+
+    #[test]
+    fn test_copy_body_tells_read_and_write_errors_apart() {
+        struct FullDisk;
+
+        impl Write for FullDisk {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Act
+
+        let read = copy_body(Response::invalid_body().body, &mut Vec::new());
+
+        let write = copy_body(Body::Bytes(b"data".to_vec()), &mut FullDisk);
+
+        // Assert
+
+        assert!(matches!(read, Err(CopyError::Read { received: 0, .. })));
+        assert!(matches!(write, Err(CopyError::Write(_))));
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    env, fs,
+    ops::Deref,
+    path::PathBuf,
+    process,
+    sync::{atomic::AtomicUsize, atomic::Ordering, Arc},
+};
+
+use super::{
+    builder::Config, CachePolicy, Download, DownloadError, Downloader, FileDownloader,
+    OverwritePolicy, Storage,
+};
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+// A download outside the cache, deleted when dropped unless kept.
+#[derive(Debug)]
+pub struct TempDownload {
+    download: Download,
+    dir: Option<PathBuf>,
+}
+
+impl TempDownload {
+    // Leaves the file where it is and hands out its `Download`.
+    pub fn keep(mut self) -> Download {
+        self.dir = None;
+
+        std::mem::replace(
+            &mut self.download,
+            Download::new(String::new(), PathBuf::new()),
+        )
+    }
+}
+
+impl Deref for TempDownload {
+    type Target = Download;
+
+    fn deref(&self) -> &Download {
+        &self.download
+    }
+}
+
+// The file may already have been moved or deleted, which is not an error.
+impl Drop for TempDownload {
+    fn drop(&mut self) {
+        if let Some(dir) = self.dir.take() {
+            let _ = fs::remove_file(&self.download.file);
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Fetches the URL into a directory of its own under the OS temp dir, so
+    // the cache is neither read nor written.
+    pub fn download_temp(&self, url: &str) -> Result<TempDownload, DownloadError> {
+        let dir = env::temp_dir().join(format!(
+            "file-downloader-{}-{}",
+            process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        fs::create_dir_all(&dir).map_err(|error| DownloadError::Io(error.to_string()))?;
+
+        #[cfg_attr(not(feature = "image"), allow(unused_mut))]
+        let mut config = Config {
+            target: Some(dir.clone()),
+            cache_policy: CachePolicy::NetworkOnly,
+            overwrite_policy: OverwritePolicy::Overwrite,
+            persist_dir: None,
+            write_sidecars: false,
+            keep_partial_bodies: false,
+            negative_ttl: None,
+            ..(*self.config).clone()
+        };
+
+        #[cfg(feature = "image")]
+        {
+            config.image.thumbnails = None;
+        }
+
+        // Nothing of a temp file is worth holding in memory, and a cache that
+        // cannot be used does not matter to it.
+        let downloader = Downloader {
+            config: Arc::new(config),
+            memory_cache: None,
+            unusable: None,
+            ..self.detached()
+        };
+
+        match downloader.download_any(url) {
+            Ok(mut download) => {
+                download.remote_url = None;
+
+                Ok(TempDownload {
+                    download,
+                    dir: Some(dir),
+                })
+            }
+            Err(error) => {
+                let _ = fs::remove_dir_all(&dir);
+
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::downloader::{
+        fetcher::MockFetcher, testing, Downloader, DownloaderBuilder, Response, Storage,
+    };
+
+    const URL: &str = "https://example.com/report.pdf";
+
+    fn downloader(name: &str) -> Downloader<MockFetcher> {
+        let responses = (0..2)
+            .map(|_| Response::ok(b"%PDF-1.7".to_vec(), Some("application/pdf".to_string())))
+            .collect();
+
+        DownloaderBuilder::with_fetcher(testing::cache_dir(name), MockFetcher::new(responses))
+            .build()
+    }
+
+    #[test]
+    fn test_temp_file_is_deleted_on_drop() {
+        let downloader = downloader("temp_drop");
+
+        // Act
+
+        let temp = downloader.download_temp(URL).unwrap();
+
+        let file = temp.file.clone();
+
+        let content = temp.bytes().unwrap();
+
+        drop(temp);
+
+        // Assert
+
+        assert_eq!(content, b"%PDF-1.7");
+        assert!(!file.exists());
+        assert!(!file.parent().unwrap().exists());
+        assert!(downloader.storage().list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_kept_temp_file_survives() {
+        let downloader = downloader("temp_keep");
+
+        let gone = downloader.download_temp(URL).unwrap();
+
+        fs::remove_file(&gone.file).unwrap();
+
+        // Act
+
+        drop(gone);
+
+        let kept = downloader.download_temp(URL).unwrap().keep();
+
+        // Assert
+
+        assert_eq!(fs::read(&kept.file).unwrap(), b"%PDF-1.7");
+
+        fs::remove_dir_all(kept.file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_temp_downloads_leave_the_cache_alone() {
+        let dir = testing::cache_dir("temp_cache_alone");
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![
+                Response::ok(b"%PDF-1.7 v1".to_vec(), Some("application/pdf".to_string())),
+                Response::ok(b"%PDF-1.7 v2".to_vec(), Some("application/pdf".to_string())),
+                Response::ok(b"%PDF-1.7 v3".to_vec(), Some("application/pdf".to_string())),
+            ]),
+        )
+        .build();
+
+        let cached = downloader.download(URL).unwrap();
+
+        downloader.flush_maintenance();
+
+        let reader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![Response::ok(
+                b"%PDF-1.7 v4".to_vec(),
+                Some("application/pdf".to_string()),
+            )]),
+        )
+        .read_only(true)
+        .build();
+
+        let before = downloader.storage().list().unwrap();
+
+        // Act
+
+        let temp = downloader.download_temp(URL).unwrap();
+
+        let read_only = reader.download_temp(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(temp.bytes().unwrap(), b"%PDF-1.7 v2");
+        assert_eq!(read_only.bytes().unwrap(), b"%PDF-1.7 v4");
+        assert!(!temp.file.starts_with(&dir));
+        assert_eq!(downloader.cached(URL), Some(cached.clone()));
+        assert_eq!(cached.bytes().unwrap(), b"%PDF-1.7 v1");
+        assert_eq!(downloader.storage().list().unwrap(), before);
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    env, fs,
+    path::PathBuf,
+};
+
+pub fn cache_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join("file-downloader-tests").join(name);
+
+    let _ = fs::remove_dir_all(&dir);
+
+    dir
+}
+
+// Counts allocations per thread so tests running in parallel do not observe
+// each other.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+pub fn count_allocations(task: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+
+    task();
+
+    ALLOCATIONS.with(Cell::get) - before
+}
@@ -0,0 +1,192 @@
+use super::{Body, Download, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PutOrPost {
+    Put,
+    Post,
+}
+
+impl PutOrPost {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Put => "PUT",
+            Self::Post => "POST",
+        }
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Streams a download back out as the body of a request to `url`, typed
+    // with its stored MIME. Statuses are classified like downloads are, and
+    // the URL is normalized as theirs.
+    pub fn upload(
+        &self,
+        download: &Download,
+        url: impl IntoDownloadUrl,
+        method: PutOrPost,
+    ) -> Result<u16, DownloadError> {
+        let url = url
+            .to_download_url()
+            .map_err(|error| DownloadError::InvalidUrl(error.into()))?;
+
+        let body = self
+            .open(download)
+            .map_err(|error| DownloadError::Io(error.to_string()))?;
+
+        let mime = download.mime().unwrap_or("application/octet-stream");
+
+        let mut headers = vec![("Content-Type".to_string(), mime.to_string())];
+
+        if let Some(size) = download.metadata.size {
+            headers.push(("Content-Length".to_string(), size.to_string()));
+        }
+
+        let response = self.send(&url, &headers, |fetcher, url, headers, _| {
+            fetcher.send_body(method.as_str(), url, headers, Body::Reader(body))
+        })?;
+
+        match response.status {
+            status @ 200..=299 => Ok(status),
+            404 => Err(DownloadError::NotFound),
+            status => Err(DownloadError::HttpStatus(status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use url::Url;
+
+    use super::PutOrPost;
+    use crate::downloader::{
+        fetcher::{MockFetcher, UReqFetcher},
+        fixtures, testing, DownloadError, DownloaderBuilder, Response,
+    };
+
+    type Received = Arc<Mutex<Vec<(String, String, Option<String>, Vec<u8>)>>>;
+
+    // Records the method, path, Content-Type and body of every request and
+    // answers 201, or 404 under `/missing`.
+    fn upload_server() -> (Received, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        let received = Received::default();
+
+        let log = Arc::clone(&received);
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = String::new();
+
+                reader.read_line(&mut request).unwrap();
+
+                let (mut content_type, mut length) = (None, 0);
+
+                loop {
+                    let mut line = String::new();
+
+                    reader.read_line(&mut line).unwrap();
+
+                    let Some((name, value)) = line.trim().split_once(": ") else {
+                        break;
+                    };
+
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-type" => content_type = Some(value.to_string()),
+                        "content-length" => length = value.parse().unwrap(),
+                        _ => {}
+                    }
+                }
+
+                let mut body = vec![0; length];
+
+                reader.read_exact(&mut body).unwrap();
+
+                let mut parts = request.split(' ');
+
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+
+                let status = match path {
+                    "/missing" => "404 Not Found",
+                    _ => "201 Created",
+                };
+
+                log.lock().unwrap().push((
+                    method.to_string(),
+                    path.to_string(),
+                    content_type,
+                    body,
+                ));
+
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+            }
+        });
+
+        (received, base)
+    }
+
+    #[test]
+    fn test_upload_streams_the_cached_file() {
+        let (received, base) = upload_server();
+
+        let dir = testing::cache_dir("upload");
+
+        let cached =
+            DownloaderBuilder::with_fetcher(&dir, MockFetcher::new(vec![Response::ok_png()]))
+                .build()
+                .download("https://example.com/logo.png")
+                .unwrap();
+
+        let downloader = DownloaderBuilder::with_fetcher(&dir, UReqFetcher::new()).build();
+
+        // Act
+
+        let status = downloader
+            .upload(&cached, format!("{base}/assets/logo.png"), PutOrPost::Put)
+            .unwrap();
+
+        let missing = downloader.upload(&cached, format!("{base}/missing"), PutOrPost::Post);
+
+        let escaped = Url::parse(&format!("{base}/assets/caf%c3%a9.png")).unwrap();
+
+        downloader
+            .upload(&cached, &escaped, PutOrPost::Put)
+            .unwrap();
+
+        // Assert
+
+        let received = received.lock().unwrap();
+
+        assert_eq!(status, 201);
+        assert_eq!(missing, Err(DownloadError::NotFound));
+        assert_eq!(
+            received[0],
+            (
+                "PUT".to_string(),
+                "/assets/logo.png".to_string(),
+                Some("image/png".to_string()),
+                fixtures::PNG.to_vec()
+            )
+        );
+        assert_eq!(received[1].0, "POST");
+        assert_eq!(received[2].1, "/assets/caf%C3%A9.png");
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use super::{Download, DownloadError, Downloader, FileDownloader, Storage};
+
+// The most an entry's user metadata may take, serialized as JSON.
+pub(crate) const USER_META_LIMIT: usize = 4096;
+
+pub(crate) fn check(user_meta: &HashMap<String, String>) -> Result<(), DownloadError> {
+    let size = serde_json::to_vec(user_meta).map_or(usize::MAX, |json| json.len());
+
+    match size > USER_META_LIMIT {
+        true => Err(DownloadError::UserMetaTooLarge {
+            size,
+            limit: USER_META_LIMIT,
+        }),
+        false => Ok(()),
+    }
+}
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    pub(crate) fn stored_user_meta(&self, url: &str) -> HashMap<String, String> {
+        self.manifest
+            .get(&self.entry_key(url))
+            .map(|entry| entry.user_meta)
+            .unwrap_or_default()
+    }
+
+    // Whether `user_meta` still fits once merged into what `url`'s entry
+    // holds, so an oversized merge costs no fetch.
+    pub(crate) fn check_user_meta(
+        &self,
+        url: &str,
+        user_meta: &HashMap<String, String>,
+    ) -> Result<(), DownloadError> {
+        check(&merged(self.stored_user_meta(url), user_meta))
+    }
+
+    // Merges `user_meta` into what `url`'s entry holds, under the manifest's
+    // lock so concurrent merges all land. Downloads without an entry, such as
+    // persisted ones, only carry it.
+    pub(crate) fn attach_user_meta(
+        &self,
+        url: &str,
+        mut download: Download,
+        user_meta: &HashMap<String, String>,
+    ) -> Result<Download, DownloadError> {
+        let stored: Option<Result<_, DownloadError>> = match self.config.read_only {
+            true => None,
+            false => self.manifest.update(&self.entry_key(url), |entry| {
+                let user_meta = merged(entry.user_meta.clone(), user_meta);
+
+                check(&user_meta)?;
+
+                entry.user_meta = user_meta.clone();
+
+                Ok(user_meta)
+            }),
+        };
+
+        download.user_meta = match stored {
+            Some(stored) => stored?,
+            None => {
+                let user_meta = merged(download.user_meta, user_meta);
+
+                check(&user_meta)?;
+
+                user_meta
+            }
+        };
+
+        Ok(download)
+    }
+}
+
+fn merged(
+    mut stored: HashMap<String, String>,
+    user_meta: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    stored.extend(user_meta.clone());
+
+    stored
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use super::USER_META_LIMIT;
+    use crate::downloader::{
+        fetcher::MockFetcher, manifest::ManifestEntry, testing, CachePolicy, DownloadError,
+        DownloadOptions, Downloader, DownloaderBuilder, Response,
+    };
+
+    const URL: &str = "https://example.com/logo.png";
+
+    fn meta(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn downloader(dir: &Path) -> Downloader<MockFetcher> {
+        DownloaderBuilder::with_fetcher(
+            dir,
+            MockFetcher::new(vec![Response::ok(
+                b"image".to_vec(),
+                Some("image/png".to_string()),
+            )]),
+        )
+        .cache_policy(CachePolicy::CacheFirst)
+        .build()
+    }
+
+    #[test]
+    fn test_user_meta_round_trips_through_the_manifest() {
+        let entry = ManifestEntry {
+            url: URL.to_string(),
+            user_meta: meta(&[("product", "42"), ("campaign", "spring")]),
+            ..Default::default()
+        };
+
+        // Act
+
+        let json = serde_json::to_string(&entry).unwrap();
+
+        let parsed: ManifestEntry = serde_json::from_str(&json).unwrap();
+
+        let older: ManifestEntry =
+            serde_json::from_str(r#"{"url": "", "file": "", "fetched_at": 0}"#).unwrap();
+
+        // Assert
+
+        assert_eq!(parsed, entry);
+        assert!(older.user_meta.is_empty());
+    }
+
+    #[test]
+    fn test_user_meta_survives_reopening_the_cache() {
+        let dir = testing::cache_dir("user_meta_reopened");
+
+        let first = downloader(&dir);
+
+        first
+            .download_with(
+                URL,
+                &DownloadOptions::new()
+                    .user_meta(meta(&[("product", "42"), ("campaign", "spring")])),
+            )
+            .unwrap();
+
+        first.flush_maintenance();
+
+        drop(first);
+
+        let reopened = downloader(&dir);
+
+        // Act
+
+        let merged = reopened
+            .download_with(
+                URL,
+                &DownloadOptions::new().user_meta(meta(&[("campaign", "summer")])),
+            )
+            .unwrap();
+
+        let hit = reopened.download(URL).unwrap();
+
+        // Assert
+
+        assert_eq!(reopened.fetcher().calls(), 0);
+        assert_eq!(
+            merged.user_meta,
+            meta(&[("product", "42"), ("campaign", "summer")])
+        );
+        assert_eq!(hit.user_meta, merged.user_meta);
+    }
+
+    #[test]
+    fn test_oversized_user_meta_is_refused() {
+        let downloader = downloader(&testing::cache_dir("user_meta_oversized"));
+
+        let value = "x".repeat(USER_META_LIMIT);
+
+        // Act
+
+        let result = downloader.download_with(
+            URL,
+            &DownloadOptions::new().user_meta(meta(&[("note", &value)])),
+        );
+
+        // Assert
+
+        assert_eq!(
+            result,
+            Err(DownloadError::UserMetaTooLarge {
+                size: USER_META_LIMIT + 11,
+                limit: USER_META_LIMIT,
+            })
+        );
+        assert_eq!(downloader.fetcher().calls(), 0);
+    }
+
+    #[test]
+    fn test_merges_past_the_limit_cost_no_fetch() {
+        let dir = testing::cache_dir("user_meta_merged_oversized");
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            &dir,
+            MockFetcher::new(vec![
+                Response::ok(b"image".to_vec(), Some("image/png".to_string())),
+                Response::ok(b"image".to_vec(), Some("image/png".to_string())),
+            ]),
+        )
+        .cache_policy(CachePolicy::NetworkOnly)
+        .build();
+
+        let value = "x".repeat(USER_META_LIMIT / 2);
+
+        downloader
+            .download_with(
+                URL,
+                &DownloadOptions::new().user_meta(meta(&[("first", &value)])),
+            )
+            .unwrap();
+
+        // Act
+
+        let result = downloader.download_with(
+            URL,
+            &DownloadOptions::new().user_meta(meta(&[("second", &value)])),
+        );
+
+        // Assert
+
+        assert!(matches!(
+            result,
+            Err(DownloadError::UserMetaTooLarge { .. })
+        ));
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_merges_all_land() {
+        let downloader = downloader(&testing::cache_dir("user_meta_concurrent"));
+
+        downloader.download(URL).unwrap();
+
+        let keys: Vec<String> = (0..8).map(|key| format!("key{key}")).collect();
+
+        // Act
+
+        std::thread::scope(|scope| {
+            for key in &keys {
+                let downloader = &downloader;
+
+                scope.spawn(move || {
+                    downloader
+                        .download_with(URL, &DownloadOptions::new().user_meta(meta(&[(key, "1")])))
+                        .unwrap();
+                });
+            }
+        });
+
+        // Assert
+
+        let hit = downloader.download(URL).unwrap();
+
+        let expected: HashMap<_, _> = keys
+            .iter()
+            .map(|key| (key.clone(), "1".to_string()))
+            .collect();
+
+        assert_eq!(hit.user_meta, expected);
+        assert_eq!(downloader.fetcher().calls(), 1);
+    }
+}
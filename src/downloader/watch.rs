@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use super::{
+    cancel::CANCEL_CHECK, DownloadError, Downloader, FileDownloader, IntoDownloadUrl, Outcome,
+    Storage,
+};
+
+impl<T, S> Downloader<T, S>
+where
+    T: FileDownloader,
+    S: Storage,
+{
+    // Downloads `url` again every `interval` until the cancellation token is
+    // cancelled, handing each cycle's result to `on_cycle`. Entries with an
+    // `ETag` or `Last-Modified` are asked for conditionally, so content that
+    // did not change comes back as `Outcome::NotModified` without a body.
+    pub fn watch<F>(&self, url: impl IntoDownloadUrl, interval: Duration, mut on_cycle: F)
+    where
+        F: FnMut(Result<Outcome, DownloadError>),
+    {
+        let token = &self.config.cancellation_token;
+
+        while !token.is_cancelled() {
+            on_cycle(self.download_outcome(&url));
+
+            let mut remaining = interval;
+
+            while !remaining.is_zero() && !token.is_cancelled() {
+                let wait = remaining.min(CANCEL_CHECK);
+
+                self.config.clock.sleep(wait);
+
+                remaining -= wait;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::downloader::{
+        clock::{Clock, FakeClock},
+        fetcher::MockFetcher,
+        testing, CancellationToken, DownloaderBuilder, Outcome, Response,
+    };
+
+    #[test]
+    fn test_watching_refetches_conditionally_until_cancelled() {
+        let png = |body: &str| {
+            Response::ok(body.as_bytes().to_vec(), Some("image/png".to_string()))
+                .with_header("ETag", &format!("\"{body}\""))
+        };
+
+        let clock = FakeClock::new();
+
+        let token = CancellationToken::new();
+
+        let downloader = DownloaderBuilder::with_fetcher(
+            testing::cache_dir("watch"),
+            MockFetcher::new(vec![png("v1"), Response::new(304), png("v2")]),
+        )
+        .clock(clock.clone())
+        .cancellation_token(token.clone())
+        .build();
+
+        let start = clock.now();
+
+        let mut cycles = Vec::new();
+
+        // Act
+
+        downloader.watch(
+            "https://example.com/dashboard.png",
+            Duration::from_secs(60),
+            |result| {
+                let outcome = result.unwrap();
+
+                cycles.push((
+                    matches!(outcome, Outcome::NotModified(_)),
+                    outcome.into_download().bytes().unwrap(),
+                ));
+
+                if cycles.len() == 3 {
+                    token.cancel();
+                }
+            },
+        );
+
+        // Assert
+
+        assert_eq!(
+            cycles,
+            [
+                (false, b"v1".to_vec()),
+                (true, b"v1".to_vec()),
+                (false, b"v2".to_vec())
+            ]
+        );
+        assert!(downloader
+            .fetcher()
+            .request_headers(1)
+            .contains(&("If-None-Match".to_string(), "\"v1\"".to_string())));
+        assert_eq!(
+            clock.now().duration_since(start).unwrap(),
+            Duration::from_secs(120)
+        );
+    }
+}
@@ -1,3 +1,39 @@
+#[cfg(feature = "cli")]
+pub mod cli;
 mod downloader;
 
-pub use downloader::{Download, DownloadError, Downloader, UreqDownloader};
+pub use downloader::{
+    validate_url, AdoptReport, AttemptRecord, BatchEvent, BatchOptions, BatchResult, Body,
+    CacheKeyer, CachePolicy, CancellationToken, ChecksumReport, CircuitBreakerConfig, CircuitState,
+    Clock, Download, DownloadError, DownloadInfo, DownloadIter, DownloadMetadata, DownloadOptions,
+    Downloader, DownloaderBuilder, ExistingDestination, FetchError, FileDownloader, Follow,
+    FsSpace, FsStorage, HashAlgo, IntoDownloadUrl, KeyEncoding, MemoryStorage, NameBy, Observer,
+    Outcome, OverwritePolicy, Partitioner, Peek, PendingDownload, PersistMode, PrefetchSummary,
+    Probe, PutOrPost, RefererPolicy, RescanReport, Response, SaltedKeyer, SharedDownloader,
+    Sidecar, SpaceProvider, Storage, StoredFile, StripOutcome, SystemClock, TempDownload, TlsRoots,
+    UReqFetcher, UreqDownloader, UrlProblem, DATA_URI_LIMIT,
+};
+
+#[cfg(feature = "http2")]
+pub use downloader::HyperFetcher;
+#[cfg(feature = "test-util")]
+pub use downloader::{fixtures, Chaos, ChaosFetcher, MockFetcher};
+#[cfg(feature = "image")]
+pub use downloader::{AnimatedPolicy, ThumbSpec, VerifyLevel};
+#[cfg(feature = "archives")]
+pub use downloader::{ArchiveLimits, Extraction};
+#[cfg(feature = "s3")]
+pub use downloader::{S3Client, S3Config, S3Storage, UreqS3Client};
+#[cfg(feature = "image")]
+pub use image::ImageFormat;
+
+pub use url::Url;
+
+// The types and traits most callers need, for a single glob import.
+pub mod prelude {
+    pub use crate::{
+        CachePolicy, Clock, Download, DownloadError, DownloadOptions, Downloader,
+        DownloaderBuilder, FetchError, FileDownloader, IntoDownloadUrl, Observer, Response,
+        SpaceProvider, Storage, UreqDownloader,
+    };
+}
@@ -0,0 +1,175 @@
+#![cfg(feature = "cli")]
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+    thread,
+};
+
+// Serves `body` at `/doc.bin` and 404 everywhere else.
+fn serve(body: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            respond(stream, &body);
+        }
+    });
+
+    url
+}
+
+fn respond(mut stream: TcpStream, body: &[u8]) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut request_line = String::new();
+
+    reader.read_line(&mut request_line).unwrap();
+
+    loop {
+        let mut line = String::new();
+
+        reader.read_line(&mut line).unwrap();
+
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let (status, body) = match request_line.split(' ').nth(1) {
+        Some("/doc.bin") => ("200 OK", body),
+        _ => ("404 Not Found", &[][..]),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/octet-stream\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(body);
+}
+
+fn work_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join("file-downloader-cli").join(name);
+
+    let _ = fs::remove_dir_all(&dir);
+
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+fn run(dir: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_file-downloader"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_stdout_streams_the_body() {
+    let body: Vec<u8> = (0..70_000u32).map(|index| (index % 251) as u8).collect();
+
+    let url = format!("{}/doc.bin", serve(body.clone()));
+
+    let dir = work_dir("stdout");
+
+    // Act
+
+    let output = run(&dir, &["--stdout", &url]);
+
+    // Assert
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, body);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0, "nothing written");
+}
+
+#[test]
+fn test_stdout_exit_codes() {
+    let server = serve(b"body".to_vec());
+
+    let dir = work_dir("stdout_exit_codes");
+
+    let missing = format!("{server}/missing.bin");
+
+    let found = format!("{server}/doc.bin");
+
+    // Act
+
+    let code = |args: &[&str]| run(&dir, args).status.code();
+
+    // Assert
+
+    assert_eq!(code(&["--stdout", "not a url"]), Some(2));
+    assert_eq!(code(&["--stdout", &found, &found]), Some(2));
+    assert_eq!(code(&["--stdout", &missing]), Some(3));
+    assert_eq!(code(&["--stdout", "http://127.0.0.1:9/doc.bin"]), Some(4));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_watch_stops_cleanly_on_ctrl_c() {
+    let url = format!("{}/doc.bin", serve(b"body".to_vec()));
+
+    let dir = work_dir("watch");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_file-downloader"))
+        .args(["--watch", "1", &url])
+        .current_dir(&dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    let mut first = String::new();
+
+    stdout.read_line(&mut first).unwrap();
+
+    // Act
+
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    let status = child.wait().unwrap();
+
+    // Assert
+
+    assert!(first.starts_with("Downloaded file: "), "{first}");
+    assert!(status.success(), "{status:?}");
+    assert!(dir.join("images").join("manifest.json").exists());
+}
+
+#[test]
+fn test_watch_needs_an_interval() {
+    let dir = work_dir("watch_interval");
+
+    // Act
+
+    let code = |args: &[&str]| run(&dir, args).status.code();
+
+    // Assert
+
+    assert_eq!(
+        code(&["--watch", "soon", "http://127.0.0.1:9/doc.bin"]),
+        Some(2)
+    );
+    assert_eq!(
+        code(&["--watch", "0", "http://127.0.0.1:9/doc.bin"]),
+        Some(2)
+    );
+    assert_eq!(code(&["--watch", "1"]), Some(2));
+}
@@ -0,0 +1,318 @@
+#![cfg(feature = "http2")]
+
+use std::{
+    convert::Infallible,
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use file_downloader::{
+    BatchOptions, Download, DownloadError, DownloadOptions, Downloader, DownloaderBuilder,
+    HyperFetcher,
+};
+use http_body_util::Full;
+use hyper::{body::Bytes, service::service_fn, Request, Response, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rcgen::CertifiedKey;
+use rustls::{pki_types::PrivateKeyDer, ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+const PDF: &[u8] = b"%PDF-1.4\n% file-downloader\n";
+
+// Serves `/doc.pdf` over HTTP/2 only, with `/a` redirecting to it.
+#[derive(Default)]
+struct H2Server {
+    connections: AtomicUsize,
+    versions: Mutex<Vec<Version>>,
+}
+
+impl H2Server {
+    fn start() -> (Arc<Self>, String, ClientConfig) {
+        let CertifiedKey { cert, signing_key } =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let mut server_config = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.der().clone()],
+                PrivateKeyDer::try_from(signing_key.serialize_der()).unwrap(),
+            )
+            .unwrap();
+
+        server_config.alpn_protocols = vec![b"h2".to_vec()];
+
+        let mut roots = RootCertStore::empty();
+
+        roots.add(cert.der().clone()).unwrap();
+
+        let client_config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        listener.set_nonblocking(true).unwrap();
+
+        let base = format!(
+            "https://localhost:{}",
+            listener.local_addr().unwrap().port()
+        );
+
+        let server = Arc::new(Self::default());
+
+        let handle = Arc::clone(&server);
+
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+
+                    handle.connections.fetch_add(1, Ordering::SeqCst);
+
+                    let acceptor = acceptor.clone();
+
+                    let server = Arc::clone(&handle);
+
+                    tokio::spawn(async move {
+                        let Ok(stream) = acceptor.accept(stream).await else {
+                            return;
+                        };
+
+                        let service = service_fn(move |request| {
+                            let server = Arc::clone(&server);
+
+                            async move { Ok::<_, Infallible>(server.respond(request)) }
+                        });
+
+                        let _ = hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                            .serve_connection(TokioIo::new(stream), service)
+                            .await;
+                    });
+                }
+            });
+        });
+
+        (server, base, client_config)
+    }
+
+    fn respond<B>(&self, request: Request<B>) -> Response<Full<Bytes>> {
+        self.versions.lock().unwrap().push(request.version());
+
+        let response = Response::builder();
+
+        match request.uri().path() {
+            "/a" => response
+                .status(302)
+                .header("Location", "/doc.pdf")
+                .body(Full::default()),
+            path if path.ends_with(".pdf") => response
+                .header("Content-Type", "application/pdf")
+                .body(Full::new(Bytes::from_static(PDF))),
+            _ => response.status(404).body(Full::default()),
+        }
+        .unwrap()
+    }
+}
+
+// The same routes over plain HTTP/1.1, which both fetchers speak.
+fn http1_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let base = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+
+            reader.read_line(&mut request_line).unwrap();
+
+            loop {
+                let mut line = String::new();
+
+                reader.read_line(&mut line).unwrap();
+
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+
+            let (head, body) = match request_line.split(' ').nth(1) {
+                Some("/a") => ("302 Found\r\nLocation: /doc.pdf", &[][..]),
+                _ => ("200 OK\r\nContent-Type: application/pdf", PDF),
+            };
+
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {head}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(body);
+        }
+    });
+
+    base
+}
+
+// Accepts connections and never answers on them.
+fn stalled_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let base = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        let held: Vec<_> = listener.incoming().flatten().collect();
+
+        drop(held);
+    });
+
+    base
+}
+
+fn cache_dir(name: &str) -> PathBuf {
+    let path = env::temp_dir().join("file-downloader-tests").join(name);
+
+    let _ = fs::remove_dir_all(&path);
+
+    path
+}
+
+fn file_name(download: &Download) -> &Path {
+    Path::new(download.file.file_name().unwrap())
+}
+
+#[test]
+fn test_http2_streams_share_one_connection() {
+    let (server, base, config) = H2Server::start();
+
+    let downloader =
+        DownloaderBuilder::with_fetcher(cache_dir("http2"), HyperFetcher::with_tls_config(config))
+            .build();
+
+    let urls: Vec<_> = (0..4).map(|index| format!("{base}/{index}.pdf")).collect();
+
+    // Act
+
+    let redirected = downloader.download(&format!("{base}/a")).unwrap();
+
+    let batch = downloader.download_all(&urls, BatchOptions::default());
+
+    // Assert
+
+    assert_eq!(redirected.bytes().unwrap(), PDF);
+    assert_eq!(redirected.redirects, [(302, format!("{base}/doc.pdf"))]);
+    assert_eq!(batch.error(), None);
+    assert_eq!(batch.results.len(), urls.len());
+    assert!(server
+        .versions
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|version| *version == Version::HTTP_2));
+    assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_hyper_and_ureq_downloads_are_identical() {
+    let base = http1_server();
+
+    let url = format!("{base}/a");
+
+    let hyper =
+        DownloaderBuilder::with_fetcher(cache_dir("parity_hyper"), HyperFetcher::new()).build();
+
+    let ureq = Downloader::builder(cache_dir("parity_ureq")).build();
+
+    // Act
+
+    let expected = ureq.download(&url).unwrap();
+
+    let download = hyper.download(&url).unwrap();
+
+    // Assert
+
+    assert_eq!(download.source, expected.source);
+    assert_eq!(download.metadata, expected.metadata);
+    assert_eq!(download.redirects, expected.redirects);
+    assert_eq!(download.remote_url, expected.remote_url);
+    assert_eq!(file_name(&download), file_name(&expected));
+    assert_eq!(download.bytes().unwrap(), expected.bytes().unwrap());
+}
+
+#[test]
+fn test_downloads_work_from_inside_a_runtime() {
+    let base = http1_server();
+
+    let downloader =
+        DownloaderBuilder::with_fetcher(cache_dir("hyper_in_runtime"), HyperFetcher::new()).build();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    // Act
+
+    let download = runtime
+        .block_on(async { downloader.download(&format!("{base}/a")) })
+        .unwrap();
+
+    // Assert
+
+    assert_eq!(download.bytes().unwrap(), PDF);
+}
+
+#[test]
+fn test_stalled_responses_time_out() {
+    let base = stalled_server();
+
+    let url = format!("{base}/doc.pdf");
+
+    let timing_out = DownloaderBuilder::with_fetcher(
+        cache_dir("hyper_read_timeout"),
+        HyperFetcher::new().timeouts(Duration::from_secs(5), Duration::from_millis(100)),
+    )
+    .build();
+
+    let deadlined =
+        DownloaderBuilder::with_fetcher(cache_dir("hyper_deadline"), HyperFetcher::new()).build();
+
+    // Act
+
+    let timed_out = timing_out.download(&url).unwrap_err();
+
+    let past_deadline = deadlined
+        .download_with(
+            &url,
+            &DownloadOptions::new().deadline(Duration::from_millis(300)),
+        )
+        .unwrap_err();
+
+    // Assert
+
+    assert_eq!(timed_out.last_error(), &DownloadError::Timeout);
+    assert!(
+        matches!(past_deadline, DownloadError::DeadlineExceeded { .. }),
+        "{past_deadline:?}"
+    );
+}
@@ -0,0 +1,32 @@
+#![cfg(not(feature = "image"))]
+
+use std::{env, fs};
+
+use file_downloader::{DownloaderBuilder, FetchError, FileDownloader, Response};
+
+struct StaticFetcher(&'static [u8]);
+
+impl FileDownloader for StaticFetcher {
+    fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+        Ok(Response::ok(self.0.to_vec(), None))
+    }
+}
+
+#[test]
+fn test_download_sniffs_extension_without_image_feature() {
+    let path = env::temp_dir().join("file-downloader-tests/no_image");
+
+    let _ = fs::remove_dir_all(&path);
+
+    let downloader =
+        DownloaderBuilder::with_fetcher(&path, StaticFetcher(b"GIF89a\x01\x00")).build();
+
+    // Act
+
+    let download = downloader.download("https://example.com/spinner").unwrap();
+
+    // Assert
+
+    assert_eq!(download.metadata.extension.as_deref(), Some("gif"));
+    assert_eq!(download.file.extension().unwrap(), "gif");
+}
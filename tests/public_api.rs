@@ -0,0 +1,68 @@
+// Names every public item at the crate root, so removing or renaming one
+// fails to compile here rather than in a dependent crate.
+
+#[allow(unused_imports)]
+use file_downloader::{
+    validate_url, AdoptReport, AttemptRecord, BatchEvent, BatchOptions, BatchResult, Body,
+    CacheKeyer, CachePolicy, CancellationToken, ChecksumReport, CircuitBreakerConfig, CircuitState,
+    Clock, Download, DownloadError, DownloadInfo, DownloadIter, DownloadMetadata, DownloadOptions,
+    Downloader, DownloaderBuilder, ExistingDestination, FetchError, FileDownloader, Follow,
+    FsSpace, FsStorage, HashAlgo, IntoDownloadUrl, KeyEncoding, MemoryStorage, NameBy, Observer,
+    Outcome, OverwritePolicy, Partitioner, Peek, PendingDownload, PersistMode, PrefetchSummary,
+    Probe, PutOrPost, RefererPolicy, RescanReport, Response, SaltedKeyer, SharedDownloader,
+    Sidecar, SpaceProvider, Storage, StoredFile, StripOutcome, SystemClock, TempDownload, TlsRoots,
+    UReqFetcher, UreqDownloader, Url, UrlProblem, DATA_URI_LIMIT,
+};
+
+#[cfg(feature = "http2")]
+#[allow(unused_imports)]
+use file_downloader::HyperFetcher;
+#[cfg(feature = "test-util")]
+#[allow(unused_imports)]
+use file_downloader::{fixtures, Chaos, ChaosFetcher, MockFetcher};
+#[cfg(feature = "image")]
+#[allow(unused_imports)]
+use file_downloader::{AnimatedPolicy, ImageFormat, ThumbSpec, VerifyLevel};
+#[cfg(feature = "archives")]
+#[allow(unused_imports)]
+use file_downloader::{ArchiveLimits, Extraction};
+#[cfg(feature = "s3")]
+#[allow(unused_imports)]
+use file_downloader::{S3Client, S3Config, S3Storage, UreqS3Client};
+
+mod prelude {
+    use std::env;
+
+    use file_downloader::prelude::*;
+
+    struct Fixed;
+
+    impl FileDownloader for Fixed {
+        fn fetch(&self, _url: &str, _headers: &[(String, String)]) -> Result<Response, FetchError> {
+            Ok(Response::ok(b"body".to_vec(), None))
+        }
+    }
+
+    fn download(downloader: &Downloader<impl FileDownloader>) -> Result<Download, DownloadError> {
+        downloader.download_with("https://example.com/body", &DownloadOptions::new())
+    }
+
+    #[test]
+    fn test_prelude_covers_a_custom_fetcher() {
+        let downloader: Downloader<Fixed> = DownloaderBuilder::with_fetcher(
+            env::temp_dir().join("file-downloader-tests/prelude"),
+            Fixed,
+        )
+        .cache_policy(CachePolicy::NetworkOnly)
+        .build();
+
+        // Act
+
+        let download = download(&downloader).unwrap();
+
+        // Assert
+
+        assert_eq!(download.bytes().unwrap(), b"body");
+        assert!("https://example.com".to_download_url().is_ok());
+    }
+}